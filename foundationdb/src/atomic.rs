@@ -0,0 +1,124 @@
+use crate::{Error, Result};
+use crate::storage::Transaction;
+use foundationdb::options::MutationType;
+
+/// Length of the versionstamp FoundationDB writes at commit time.
+pub const VERSIONSTAMP_LEN: usize = 10;
+
+/// An atomic mutation of a single key.
+///
+/// Mutations are applied by the storage servers at commit time without
+/// reading the key, so concurrent writers never conflict on it. They operate
+/// on raw records, not on chunked values written with `FoundationDB::set`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mutation {
+    /// Add a signed integer to a little-endian counter.
+    Add(i64),
+    /// Keep the smaller of the stored value and this one, both read as
+    /// unsigned little-endian integers.
+    Min(u64),
+    /// Keep the larger of the stored value and this one, both read as
+    /// unsigned little-endian integers.
+    Max(u64),
+    BitAnd(Vec<u8>),
+    BitOr(Vec<u8>),
+    BitXor(Vec<u8>),
+    /// Keep the lexicographically smaller of the stored value and this one.
+    ByteMin(Vec<u8>),
+    /// Keep the lexicographically larger of the stored value and this one.
+    ByteMax(Vec<u8>),
+    /// Clear the key if it holds exactly this value.
+    CompareAndClear(Vec<u8>),
+    /// Append to the stored value unless the result would exceed the value
+    /// size limit, in which case the value is left untouched.
+    AppendIfFits(Vec<u8>),
+}
+
+impl Mutation {
+    /// Apply the mutation to `key` within `trx`.
+    pub fn apply(&self, trx: &Transaction, key: &[u8]) {
+        let (param, op) = match self {
+            Mutation::Add(delta) => (delta.to_le_bytes().to_vec(), MutationType::Add),
+            Mutation::Min(value) => (value.to_le_bytes().to_vec(), MutationType::Min),
+            Mutation::Max(value) => (value.to_le_bytes().to_vec(), MutationType::Max),
+            Mutation::BitAnd(param) => (param.clone(), MutationType::BitAnd),
+            Mutation::BitOr(param) => (param.clone(), MutationType::BitOr),
+            Mutation::BitXor(param) => (param.clone(), MutationType::BitXor),
+            Mutation::ByteMin(param) => (param.clone(), MutationType::ByteMin),
+            Mutation::ByteMax(param) => (param.clone(), MutationType::ByteMax),
+            Mutation::CompareAndClear(param) => (param.clone(), MutationType::CompareAndClear),
+            Mutation::AppendIfFits(param) => (param.clone(), MutationType::AppendIfFits),
+        };
+        trx.atomic_op(key, &param, op);
+    }
+}
+
+/// Write `value` at `key`, with the commit versionstamp substituted for the
+/// placeholder at `offset` in the key.
+pub fn set_versionstamped_key(
+    trx: &Transaction,
+    key: &[u8],
+    offset: usize,
+    value: &[u8],
+) -> Result<()> {
+    let key = with_offset(key, offset)?;
+    trx.atomic_op(&key, value, MutationType::SetVersionstampedKey);
+    Ok(())
+}
+
+/// Write `value` at `key`, with the commit versionstamp substituted for the
+/// placeholder at `offset` in the value.
+pub fn set_versionstamped_value(
+    trx: &Transaction,
+    key: &[u8],
+    value: &[u8],
+    offset: usize,
+) -> Result<()> {
+    let value = with_offset(value, offset)?;
+    trx.atomic_op(key, &value, MutationType::SetVersionstampedValue);
+    Ok(())
+}
+
+/// Append the little-endian position of the versionstamp placeholder, as
+/// FoundationDB expects it.
+fn with_offset(bytes: &[u8], offset: usize) -> Result<Vec<u8>> {
+    if offset + VERSIONSTAMP_LEN > bytes.len() {
+        return Err(Error::InvalidVersionstampOffset(offset));
+    }
+    let mut out = bytes.to_vec();
+    out.extend_from_slice(&(offset as u32).to_le_bytes());
+    Ok(out)
+}
+
+/// Read a counter maintained with `Mutation::Add`. Missing bytes read as
+/// zero, as they do for FoundationDB; longer values are truncated.
+pub fn decode_i64(bytes: &[u8]) -> i64 {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    i64::from_le_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_i64() {
+        assert_eq!(decode_i64(&[]), 0);
+        assert_eq!(decode_i64(&[5]), 5);
+        assert_eq!(decode_i64(&(-3i64).to_le_bytes()), -3);
+        assert_eq!(decode_i64(&[1, 0, 0, 0, 0, 0, 0, 0, 9]), 1);
+    }
+
+    #[test]
+    fn test_versionstamp_offset() {
+        let key = [b"prefix".as_slice(), &[0xFF; VERSIONSTAMP_LEN]].concat();
+        let encoded = with_offset(&key, 6).expect("offset");
+        assert_eq!(&encoded[key.len()..], &6u32.to_le_bytes());
+        assert!(matches!(
+            with_offset(&key, 7),
+            Err(Error::InvalidVersionstampOffset(7))
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{Error, Result};
+use std::borrow::Cow;
+use std::fmt;
+use std::str::FromStr;
+
+/// Compression level used for zstd.
+const ZSTD_LEVEL: i32 = 3;
+
+/// Default size below which values are stored uncompressed.
+pub const DEFAULT_MIN_SIZE: usize = 4 * 1024; // 4KB
+
+/// Compression applied to a value before it is chunked.
+/// The codec is recorded in the value's manifest, so values written with
+/// different settings can be read side by side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    None = 0,
+    Zstd = 1,
+    Lz4 = 2,
+}
+
+impl Codec {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Codec::None),
+            1 => Some(Codec::Zstd),
+            2 => Some(Codec::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn compress<'a>(self, data: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        Ok(match self {
+            Codec::None => Cow::Borrowed(data),
+            Codec::Zstd => Cow::Owned(zstd::bulk::compress(data, ZSTD_LEVEL)?),
+            Codec::Lz4 => Cow::Owned(lz4_flex::compress(data)),
+        })
+    }
+
+    /// Restore the `value_len` bytes compressed into `data`.
+    pub fn decompress(self, data: Vec<u8>, value_len: u64) -> Result<Vec<u8>> {
+        let value = match self {
+            Codec::None => data,
+            Codec::Zstd => zstd::bulk::decompress(&data, value_len as usize)
+                .map_err(|_| Error::corrupt("unable to decompress value"))?,
+            Codec::Lz4 => lz4_flex::decompress(&data, value_len as usize)
+                .map_err(|_| Error::corrupt("unable to decompress value"))?,
+        };
+        if value.len() as u64 != value_len {
+            return Err(Error::corrupt("decompressed length mismatch"));
+        }
+        Ok(value)
+    }
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Codec::None => "none",
+            Codec::Zstd => "zstd",
+            Codec::Lz4 => "lz4",
+        })
+    }
+}
+
+impl FromStr for Codec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Codec::None),
+            "zstd" => Ok(Codec::Zstd),
+            "lz4" => Ok(Codec::Lz4),
+            _ => Err(format!("unknown codec '{}', expected none, zstd or lz4", s)),
+        }
+    }
+}
+
+/// Compression settings applied by `FoundationDB::set`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compression {
+    pub codec: Codec,
+    /// Values shorter than this are stored as is.
+    pub min_size: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            codec: Codec::None,
+            min_size: DEFAULT_MIN_SIZE,
+        }
+    }
+}
+
+impl Compression {
+    /// Encode `value` for storage, returning the codec actually used.
+    /// Values that are too short or do not shrink are kept uncompressed.
+    pub fn encode<'a>(&self, value: &'a [u8]) -> Result<(Codec, Cow<'a, [u8]>)> {
+        if self.codec == Codec::None || value.len() < self.min_size {
+            return Ok((Codec::None, Cow::Borrowed(value)));
+        }
+        let compressed = self.codec.compress(value)?;
+        if compressed.len() >= value.len() {
+            return Ok((Codec::None, Cow::Borrowed(value)));
+        }
+        Ok((self.codec, compressed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_roundtrip() {
+        let value = b"{\"cached\": \"payload\"}".repeat(500);
+        for codec in [Codec::None, Codec::Zstd, Codec::Lz4] {
+            let compressed = codec.compress(&value).expect("compress").into_owned();
+            if codec != Codec::None {
+                assert!(compressed.len() < value.len());
+            }
+            let restored = codec
+                .decompress(compressed, value.len() as u64)
+                .expect("decompress");
+            assert_eq!(restored, value);
+            assert_eq!(Codec::from_byte(codec as u8), Some(codec));
+            assert_eq!(codec.to_string().parse::<Codec>(), Ok(codec));
+        }
+    }
+
+    #[test]
+    fn test_compression_threshold() {
+        let compression = Compression {
+            codec: Codec::Zstd,
+            min_size: 1024,
+        };
+        let short = vec![b'a'; 100];
+        let (codec, stored) = compression.encode(&short).expect("encode");
+        assert_eq!(codec, Codec::None);
+        assert_eq!(stored.as_ref(), short.as_slice());
+
+        let long = vec![b'a'; 10_000];
+        let (codec, stored) = compression.encode(&long).expect("encode");
+        assert_eq!(codec, Codec::Zstd);
+        assert!(stored.len() < long.len());
+
+        // incompressible data is kept as is
+        let random = (0..10_000).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+        let (codec, _) = compression.encode(&random).expect("encode");
+        assert_eq!(codec, Codec::None);
+    }
+
+    #[test]
+    fn test_corrupt_compressed_value() {
+        let res = Codec::Lz4.decompress(vec![0xFF; 16], 1000);
+        assert!(matches!(res, Err(Error::CorruptValue(_))));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{Error, Result};
+use aes_gcm::Aes256Gcm;
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::ChaCha20Poly1305;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Length of the per-value nonce stored in the manifest.
+pub const NONCE_LEN: usize = 12;
+
+/// Length of an encryption key.
+const KEY_LEN: usize = 32;
+
+/// Authentication tag appended to every encrypted chunk.
+const TAG_LEN: usize = 16;
+
+/// Cipher protecting the chunks of a value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Cipher {
+    #[default]
+    None = 0,
+    Aes256Gcm = 1,
+    ChaCha20Poly1305 = 2,
+}
+
+impl Cipher {
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Cipher::None),
+            1 => Some(Cipher::Aes256Gcm),
+            2 => Some(Cipher::ChaCha20Poly1305),
+            _ => None,
+        }
+    }
+
+    /// Bytes added to each chunk by this cipher.
+    pub fn overhead(self) -> usize {
+        match self {
+            Cipher::None => 0,
+            Cipher::Aes256Gcm | Cipher::ChaCha20Poly1305 => TAG_LEN,
+        }
+    }
+}
+
+impl fmt::Display for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cipher::None => "none",
+            Cipher::Aes256Gcm => "aes-256-gcm",
+            Cipher::ChaCha20Poly1305 => "chacha20-poly1305",
+        })
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(Cipher::None),
+            "aes-256-gcm" => Ok(Cipher::Aes256Gcm),
+            "chacha20-poly1305" => Ok(Cipher::ChaCha20Poly1305),
+            _ => Err(format!(
+                "unknown cipher '{}', expected aes-256-gcm or chacha20-poly1305",
+                s
+            )),
+        }
+    }
+}
+
+struct Key {
+    cipher: Cipher,
+    material: [u8; KEY_LEN],
+}
+
+/// Encryption keys loaded from a key file.
+///
+/// Each non-empty line of the file reads `<id> <cipher> <64 hex digits>`;
+/// lines starting with `#` are comments. New values are encrypted with the
+/// key of highest id, while older keys stay available to read values written
+/// before a rotation.
+#[derive(Default)]
+pub struct Keyring {
+    keys: BTreeMap<u32, Key>,
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print key material
+        f.debug_struct("Keyring")
+            .field("key_ids", &self.keys.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Keyring {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut keys = BTreeMap::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |reason: &str| Error::InvalidKeyFile(format!("line {}: {}", n + 1, reason));
+            let fields = line.split_whitespace().collect::<Vec<_>>();
+            let [id, cipher, hex] = fields[..] else {
+                return Err(invalid("expected `<id> <cipher> <hex key>`"));
+            };
+            let id = id.parse::<u32>().map_err(|_| invalid("invalid key id"))?;
+            let cipher = cipher.parse::<Cipher>().map_err(|e| invalid(&e))?;
+            if cipher == Cipher::None {
+                return Err(invalid("a key needs a cipher"));
+            }
+            let material = decode_hex(hex).ok_or_else(|| invalid("expected 64 hex digits"))?;
+            if keys.insert(id, Key { cipher, material }).is_some() {
+                return Err(invalid("duplicate key id"));
+            }
+        }
+        Ok(Self { keys })
+    }
+
+    /// Id and cipher of the key new values are encrypted with.
+    pub fn active(&self) -> Option<(u32, Cipher)> {
+        self.keys.last_key_value().map(|(id, key)| (*id, key.cipher))
+    }
+
+    /// Encrypt chunk `index` of the value stored at `root`.
+    pub(crate) fn seal(
+        &self,
+        key_id: u32,
+        nonce: &[u8; NONCE_LEN],
+        root: &[u8],
+        index: usize,
+        chunk: &[u8],
+    ) -> Result<Vec<u8>> {
+        let key = self.key(key_id)?;
+        let nonce = chunk_nonce(nonce, index);
+        let aad = chunk_aad(root, index);
+        let payload = Payload {
+            msg: chunk,
+            aad: &aad,
+        };
+        let sealed = match key.cipher {
+            Cipher::Aes256Gcm => Aes256Gcm::new(GenericArray::from_slice(&key.material))
+                .encrypt(GenericArray::from_slice(&nonce), payload),
+            Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(GenericArray::from_slice(&key.material))
+                .encrypt(GenericArray::from_slice(&nonce), payload),
+            Cipher::None => unreachable!("keys always have a cipher"),
+        };
+        sealed.map_err(|_| Error::Encryption(format!("unable to encrypt chunk {}", index)))
+    }
+
+    /// Decrypt chunk `index` of the value stored at `root`, checking that it
+    /// was sealed for this very position.
+    pub(crate) fn open(
+        &self,
+        key_id: u32,
+        nonce: &[u8; NONCE_LEN],
+        root: &[u8],
+        index: usize,
+        chunk: &[u8],
+    ) -> Result<Vec<u8>> {
+        let key = self.key(key_id)?;
+        let nonce = chunk_nonce(nonce, index);
+        let aad = chunk_aad(root, index);
+        let payload = Payload {
+            msg: chunk,
+            aad: &aad,
+        };
+        let opened = match key.cipher {
+            Cipher::Aes256Gcm => Aes256Gcm::new(GenericArray::from_slice(&key.material))
+                .decrypt(GenericArray::from_slice(&nonce), payload),
+            Cipher::ChaCha20Poly1305 => ChaCha20Poly1305::new(GenericArray::from_slice(&key.material))
+                .decrypt(GenericArray::from_slice(&nonce), payload),
+            Cipher::None => unreachable!("keys always have a cipher"),
+        };
+        opened.map_err(|_| Error::corrupt(format!("chunk {} failed authentication", index)))
+    }
+
+    fn key(&self, key_id: u32) -> Result<&Key> {
+        self.keys.get(&key_id).ok_or(Error::UnknownKey(key_id))
+    }
+}
+
+/// Draw the nonce of a new value.
+pub(crate) fn new_nonce() -> [u8; NONCE_LEN] {
+    rand::random()
+}
+
+/// Nonce of chunk `index`: the value nonce with the index folded into its
+/// last four bytes, so no two chunks of a value share a nonce.
+fn chunk_nonce(nonce: &[u8; NONCE_LEN], index: usize) -> [u8; NONCE_LEN] {
+    let mut out = *nonce;
+    for (b, i) in out[NONCE_LEN - 4..].iter_mut().zip((index as u32).to_le_bytes()) {
+        *b ^= i;
+    }
+    out
+}
+
+/// Associated data binding a chunk to its key and position, so chunks cannot
+/// be swapped between values or reordered.
+fn chunk_aad(root: &[u8], index: usize) -> Vec<u8> {
+    let mut aad = root.to_vec();
+    aad.extend_from_slice(&(index as u32).to_le_bytes());
+    aad
+}
+
+fn decode_hex(hex: &str) -> Option<[u8; KEY_LEN]> {
+    if hex.len() != KEY_LEN * 2 {
+        return None;
+    }
+    let mut out = [0u8; KEY_LEN];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(hex.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY_FILE: &str = "
+        # rotated keys
+        1 aes-256-gcm 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f
+        2 chacha20-poly1305 1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100
+    ";
+
+    #[test]
+    fn test_keyring_parse() {
+        let keyring = Keyring::parse(KEY_FILE).expect("parse");
+        assert_eq!(keyring.active(), Some((2, Cipher::ChaCha20Poly1305)));
+        assert!(!format!("{:?}", keyring).contains("0001"));
+
+        assert!(Keyring::parse("1 aes-256-gcm 00").is_err());
+        assert!(Keyring::parse("x aes-256-gcm 00").is_err());
+        assert!(Keyring::parse("1 rot13 00").is_err());
+        assert_eq!(Keyring::parse("").expect("parse").active(), None);
+    }
+
+    #[test]
+    fn test_seal_open_roundtrip() {
+        let keyring = Keyring::parse(KEY_FILE).expect("parse");
+        let nonce = new_nonce();
+        for key_id in [1, 2] {
+            let sealed = keyring
+                .seal(key_id, &nonce, b"root", 3, b"payload")
+                .expect("seal");
+            assert_eq!(sealed.len(), b"payload".len() + TAG_LEN);
+            let opened = keyring
+                .open(key_id, &nonce, b"root", 3, &sealed)
+                .expect("open");
+            assert_eq!(opened, b"payload");
+
+            // bound to the key and position it was sealed for
+            assert!(keyring.open(key_id, &nonce, b"other", 3, &sealed).is_err());
+            assert!(keyring.open(key_id, &nonce, b"root", 4, &sealed).is_err());
+        }
+        assert!(matches!(
+            keyring.seal(9, &nonce, b"root", 0, b"payload"),
+            Err(Error::UnknownKey(9))
+        ));
+    }
+}
@@ -1,13 +1,38 @@
-use crate::FoundationDB;
+use crate::codec::Codec;
+use crate::crypto::{self, Cipher, Keyring};
+use crate::manifest::Manifest;
+use crate::scan::ScanOptions;
+use crate::stream::ValueStream;
+use crate::{Error, FoundationDB, MAX_VALUE_LEN, Result};
+use bytes::Bytes;
+use foundationdb::options::MutationType;
+use crate::storage::Transaction;
+use foundationdb::{FdbBindingError, RangeOption};
 use foundationdb_tuple::Subspace;
 use futures::StreamExt;
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::Mutex;
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 pub const MAX_VALUE_SIZE: usize = 100 * 1000; // 100KB
 pub const MAX_TRANSACTION_SIZE: usize = 9 * 1000 * 1000; // 9MB
-pub const MAX_RETRIES: usize = 3; // Maximum number of retries for a transaction
+pub const READ_BATCH_CHUNKS: usize = 50; // Chunks fetched per read transaction (5MB)
+pub const CLEAR_BATCH_CHUNKS: usize = 1000; // Chunks cleared per transaction of a detached value
 
 pub struct DataModel {}
 
+/// Condition on the currently stored manifest for `commit_manifest` to write.
+#[derive(Clone)]
+pub enum Expected {
+    /// Overwrite whatever is stored.
+    Any,
+    /// Only write when the key has no manifest.
+    Absent,
+    /// Only write when the key still holds this manifest.
+    Current(Manifest),
+}
+
 impl DataModel {
     // Function to split data into chunks
     pub fn split_into_chunks(data: &[u8], chunk_size: Option<usize>) -> Vec<Vec<u8>> {
@@ -20,7 +45,7 @@ impl DataModel {
         fdb: &FoundationDB,
         key: &[u8],
         chunks: Vec<Vec<u8>>,
-    ) -> std::result::Result<(), foundationdb::FdbBindingError> {
+    ) -> Result<()> {
         let mut current_batch_size = 0;
         let mut batch = Vec::new();
         let mut batches = Vec::new();
@@ -44,7 +69,7 @@ impl DataModel {
         // Spawn all batch futures
         let futures = batches
             .into_iter()
-            .map(|b| Self::store_batch_with_retry(fdb, key, b))
+            .map(|b| Self::store_batch_in_fdb(fdb, key, b))
             .collect::<Vec<_>>();
 
         // Await all in parallel
@@ -58,79 +83,1102 @@ impl DataModel {
         Ok(())
     }
 
-    // Helper function to store a batch of chunks with retry logic
-    async fn store_batch_with_retry(
+    // Function to store a batch of chunks
+    async fn store_batch_in_fdb(
         fdb: &FoundationDB,
         key: &[u8],
         batch: Vec<(usize, Vec<u8>)>,
-    ) -> std::result::Result<(), foundationdb::FdbBindingError> {
-        let mut retries = 0;
-        // Clone the batch for each retry since Vec is moved into the async closure
-        while retries < MAX_RETRIES {
-            match Self::store_batch_in_fdb(fdb, key, batch.clone()).await {
-                Ok(_) => {
-                    return Ok(());
+    ) -> Result<()> {
+        let subspace = Subspace::from_bytes(key);
+        fdb.run(move |trx, _| {
+            let batch = batch.to_vec();
+            let subspace = subspace.clone();
+            async move {
+                    for (i, chunk) in batch {
+                        // pack a single-element tuple consistently with retrieval (i,)
+                        let chunk_key = subspace.pack(&(i,));
+                        trx.set(&chunk_key, &chunk);
+                    }
+                Ok(())
+            }
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Function to clean up a value: its manifest and the chunks it references.
+    // Generations staged by in-flight writers are left alone.
+    pub async fn clean_chunks(
+        fdb: &FoundationDB,
+        key: &[u8],
+    ) -> Result<()> {
+        let key = key.to_vec();
+        fdb.run(move |trx, _| {
+            let key = key.clone();
+            async move { Self::clear_in_trx(&trx, &key).await }
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Function to encode a value the way `fdb` is configured: compress it,
+    // split it into chunks and encrypt each chunk, under a fresh generation.
+    pub fn prepare(fdb: &FoundationDB, key: &[u8], value: &[u8]) -> Result<(Manifest, Vec<Vec<u8>>)> {
+        let (codec, stored) = fdb.compression.encode(value)?;
+        let mut manifest = Self::new_manifest(fdb);
+        let mut chunks = Vec::new();
+        for (i, chunk) in stored.chunks(MAX_VALUE_SIZE).enumerate() {
+            let chunk = Self::seal_chunk(fdb, &manifest, key, i, chunk)?;
+            manifest.push_chunk(&chunk);
+            chunks.push(chunk);
+        }
+        Ok((manifest.with_codec(codec, value.len() as u64), chunks))
+    }
+
+    // Function to start the manifest of a new value, encrypted with the
+    // active key when `fdb` has a keyring
+    fn new_manifest(fdb: &FoundationDB) -> Manifest {
+        let manifest =
+            Manifest::for_chunks(&[], MAX_VALUE_SIZE).with_generation(Manifest::new_generation());
+        match fdb.keyring.as_ref().and_then(|k| k.active()) {
+            Some((key_id, cipher)) => manifest.with_encryption(cipher, key_id, crypto::new_nonce()),
+            None => manifest,
+        }
+    }
+
+    fn seal_chunk(
+        fdb: &FoundationDB,
+        manifest: &Manifest,
+        key: &[u8],
+        index: usize,
+        chunk: &[u8],
+    ) -> Result<Vec<u8>> {
+        match (&fdb.keyring, manifest.cipher) {
+            (_, Cipher::None) => Ok(chunk.to_vec()),
+            (Some(keyring), _) => keyring.seal(manifest.key_id, &manifest.nonce, key, index, chunk),
+            (None, _) => Err(Error::UnknownKey(manifest.key_id)),
+        }
+    }
+
+    fn open_chunk(
+        keyring: Option<&Keyring>,
+        manifest: &Manifest,
+        key: &[u8],
+        index: usize,
+        chunk: Vec<u8>,
+    ) -> Result<Vec<u8>> {
+        if manifest.cipher == Cipher::None {
+            return Ok(chunk);
+        }
+        keyring
+            .ok_or(Error::UnknownKey(manifest.key_id))?
+            .open(manifest.key_id, &manifest.nonce, key, index, &chunk)
+    }
+
+    // Function to turn the stored chunks of a value back into the value
+    fn decode_chunks(
+        keyring: Option<&Keyring>,
+        manifest: &Manifest,
+        key: &[u8],
+        chunks: Vec<Vec<u8>>,
+    ) -> Result<Vec<u8>> {
+        Self::verify_chunks(manifest, &chunks)?;
+        let mut stored = Vec::with_capacity(manifest.total_len as usize);
+        for (i, chunk) in chunks.into_iter().enumerate() {
+            stored.extend_from_slice(&Self::open_chunk(keyring, manifest, key, i, chunk)?);
+        }
+        manifest.codec.decompress(stored, manifest.value_len)
+    }
+
+    // Function to stage the chunks of a value under a fresh generation.
+    // Staged chunks are invisible to readers until `commit_manifest` points
+    // the key at them; a failed staging is cleaned up best-effort.
+    pub async fn stage_value(fdb: &FoundationDB, key: &[u8], value: &[u8]) -> Result<Manifest> {
+        let (manifest, chunks) = Self::prepare(fdb, key, value)?;
+        Self::stage_chunks(fdb, key, &manifest, chunks).await?;
+        Ok(manifest)
+    }
+
+    // Function to stage the prepared chunks of a value
+    pub async fn stage_chunks(
+        fdb: &FoundationDB,
+        key: &[u8],
+        manifest: &Manifest,
+        chunks: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let root = manifest.chunk_root(key);
+        if let Err(e) = Self::store_chunks_in_fdb(fdb, &root, chunks).await {
+            let _ = Self::clear_chunks(fdb, key, manifest).await;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Function to stage a value read from `reader` under a fresh generation.
+    // Chunks are written one transaction-sized batch at a time, so at most
+    // one batch is held in memory.
+    pub async fn stage_stream<R: AsyncRead + Unpin>(
+        fdb: &FoundationDB,
+        key: &[u8],
+        mut reader: R,
+    ) -> Result<Manifest> {
+        let mut manifest = Self::new_manifest(fdb);
+        let root = manifest.chunk_root(key);
+        let mut batch = Vec::new();
+        let mut batch_size = 0;
+
+        let staged: Result<()> = async {
+            loop {
+                let mut chunk = Vec::with_capacity(MAX_VALUE_SIZE);
+                (&mut reader)
+                    .take(MAX_VALUE_SIZE as u64)
+                    .read_to_end(&mut chunk)
+                    .await?;
+                let last = chunk.len() < MAX_VALUE_SIZE;
+
+                if batch_size + chunk.len() > MAX_TRANSACTION_SIZE {
+                    Self::store_batch_in_fdb(fdb, &root, std::mem::take(&mut batch)).await?;
+                    batch_size = 0;
                 }
-                Err(e) => {
-                    retries += 1;
-                    if retries == MAX_RETRIES {
-                        return Err(e);
+                if !chunk.is_empty() {
+                    let size = manifest.value_len as usize + chunk.len();
+                    if size > MAX_VALUE_LEN {
+                        return Err(Error::ValueTooLarge {
+                            size,
+                            max: MAX_VALUE_LEN,
+                        });
                     }
+                    let index = manifest.chunk_count as usize;
+                    let chunk = Self::seal_chunk(fdb, &manifest, key, index, &chunk)?;
+                    manifest.push_chunk(&chunk);
+                    batch_size += chunk.len();
+                    batch.push((index, chunk));
+                }
+                if last {
+                    break;
                 }
             }
+            if !batch.is_empty() {
+                Self::store_batch_in_fdb(fdb, &root, batch).await?;
+            }
+            Ok(())
         }
-        Ok(())
+        .await;
+
+        if let Err(e) = staged {
+            let _ = Self::clear_chunks(fdb, key, &manifest).await;
+            return Err(e);
+        }
+        Ok(manifest)
     }
 
-    // Function to store a batch of chunks
-    async fn store_batch_in_fdb(
+    // Function to atomically point `key` at a staged generation, then
+    // garbage-collect the generation it replaced.
+    // Nothing is written unless the stored manifest meets `expected`;
+    // returns whether the manifest was written.
+    pub async fn commit_manifest(
         fdb: &FoundationDB,
         key: &[u8],
-        batch: Vec<(usize, Vec<u8>)>,
-    ) -> std::result::Result<(), foundationdb::FdbBindingError> {
-        let subspace = Subspace::from_bytes(key);
-        fdb.database
-            .run(move |trx, _| {
-                let batch = batch.to_vec();
-                let subspace = subspace.clone();
+        manifest: &Manifest,
+        expected: Expected,
+    ) -> Result<bool> {
+        let root = key.to_vec();
+        let encoded = manifest.encode_versionstamped();
+        let generation = manifest.generation;
+        // What the last attempt to write the manifest replaced: an attempt
+        // finding it already written after an unknown outcome has nothing
+        // left to replace, and collects what that one did
+        let replaced: Mutex<Option<Option<Manifest>>> = Mutex::new(None);
+        let flipped = fdb
+            .run(|trx, maybe_committed| {
+                let root = root.clone();
+                let encoded = encoded.clone();
+                let expected = expected.clone();
+                let replaced = &replaced;
                 async move {
-                        for (i, chunk) in batch {
-                            // pack a single-element tuple consistently with retrieval (i,)
-                            let chunk_key = subspace.pack(&(i,));
-                            trx.set(&chunk_key, &chunk);
-                        }
-                    Ok(())
+                    let previous = match trx.get(&root, false).await? {
+                        Some(raw) => match Manifest::decode(&raw) {
+                            // A previous attempt committed before reporting an error
+                            Some(m) if bool::from(maybe_committed) && m.generation == generation => {
+                                return Ok(true);
+                            }
+                            m => Some(m),
+                        },
+                        None => None,
+                    };
+                    let allowed = match &expected {
+                        Expected::Any => true,
+                        Expected::Absent => previous.is_none(),
+                        Expected::Current(m) => previous.as_ref().is_some_and(|p| p.as_ref() == Some(m)),
+                    };
+                    if !allowed {
+                        return Ok(false);
+                    }
+                    trx.atomic_op(&root, &encoded, MutationType::SetVersionstampedValue);
+                    *replaced.lock().expect("replaced lock") = Some(previous.flatten());
+                    Ok(true)
                 }
             })
-            .await
+            .await?;
+
+        if !flipped {
+            return Ok(false);
+        }
+        // The replaced generation is unreachable from now on. Readers pinned to
+        // an older read version still see it through MVCC.
+        let gc = match replaced.into_inner().expect("replaced lock") {
+            Some(Some(old)) => Self::clear_chunks(fdb, key, &old).await,
+            Some(None) => Self::clear_legacy_chunks(fdb, key).await,
+            None => Ok(()),
+        };
+        if let Err(e) = gc {
+            log::warn!("unable to collect replaced generation: {}", e);
+        }
+        Ok(true)
     }
 
-    // Function to clean up failed chunks
-    pub async fn clean_chunks(
+    // Function to clear the chunks referenced by `manifest`
+    async fn clear_chunks(fdb: &FoundationDB, key: &[u8], manifest: &Manifest) -> Result<()> {
+        let (begin, end) = manifest.chunk_range(key);
+        Self::clear_range(fdb, begin, end).await
+    }
+
+    // Function to clear the chunks of a value written without a manifest
+    async fn clear_legacy_chunks(fdb: &FoundationDB, key: &[u8]) -> Result<()> {
+        let (begin, end) = Manifest::legacy_chunk_range(key);
+        Self::clear_range(fdb, begin, end).await
+    }
+
+    async fn clear_range(fdb: &FoundationDB, begin: Vec<u8>, end: Vec<u8>) -> Result<()> {
+        fdb.run(move |trx, _| {
+            let (begin, end) = (begin.clone(), end.clone());
+            async move {
+                trx.clear_range(&begin, &end);
+                Ok(())
+            }
+        })
+        .await?;
+        Ok(())
+    }
+
+    // Function to read the manifest of a value
+    pub async fn read_manifest(fdb: &FoundationDB, key: &[u8]) -> Result<Option<Manifest>> {
+        let key = key.to_vec();
+        let raw = fdb
+            .run(move |trx, _| {
+                let key = key.clone();
+                async move { Ok(trx.get(&key, false).await?) }
+            })
+            .await?;
+        raw.map(|m| Manifest::decode(&m).ok_or_else(|| Self::corrupt("unknown manifest layout")))
+            .transpose()
+    }
+
+    // Function to read a whole value through its manifest.
+    // Values written before manifests existed are reassembled by range scan.
+    // Retryable failures of any of the transactions involved back off and
+    // count against the retry limit of the one reading the manifest.
+    pub async fn read_value(fdb: &FoundationDB, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut trx = fdb.create_trx()?;
+        loop {
+            let e = match Self::read_value_once(fdb, &trx, key).await {
+                Err(e) => e,
+                res => return res,
+            };
+            let Some(cause) = e.retryable_cause() else {
+                return Err(e);
+            };
+            trx = trx.on_error(cause).await?;
+        }
+    }
+
+    async fn read_value_once(
         fdb: &FoundationDB,
+        trx: &Transaction,
         key: &[u8],
-    ) -> std::result::Result<(), foundationdb::FdbBindingError> {
-        let mut end = key.to_vec();
-        end.push(0xFF);
+    ) -> Result<Option<Vec<u8>>> {
+        let read_version = trx.get_read_version().await?;
+        Self::read_value_at(fdb, trx, key, read_version).await
+    }
 
-        let kvs = fdb.full_scan(key, &end).await.collect::<Vec<_>>().await;
-        let mut keys_to_clear = Vec::new();
-        for res in kvs {
-            let (k, _) = res?;
-            keys_to_clear.push(k);
+    // Function to read several values and raw records at a single read version.
+    // Values are read in parallel, each fetching its chunks in parallel.
+    pub async fn read_many(
+        fdb: &FoundationDB,
+        keys: &[Vec<u8>],
+        records: &[Vec<u8>],
+    ) -> Result<(Vec<Option<Vec<u8>>>, Vec<Option<Vec<u8>>>)> {
+        let mut trx = fdb.create_trx()?;
+        loop {
+            let attempt = async {
+                let read_version = trx.get_read_version().await?;
+                let values = keys
+                    .iter()
+                    .map(|key| Self::read_value_at(fdb, &trx, key, read_version));
+                let records = records.iter().map(|key| trx.get(key, true));
+                let (values, records) = futures::future::join(
+                    futures::future::try_join_all(values),
+                    futures::future::try_join_all(records),
+                )
+                .await;
+                Ok::<_, Error>((values?, records?))
+            };
+            let e = match attempt.await {
+                Err(e) => e,
+                res => return res,
+            };
+            let Some(cause) = e.retryable_cause() else {
+                return Err(e);
+            };
+            trx = trx.on_error(cause).await?;
         }
+    }
 
-        fdb.database
-            .run(move |trx, _| {
-                let keys_to_clear = keys_to_clear.clone();
-                async move {
-                    for k in keys_to_clear {
-                        trx.clear(&k);
+    // Function to read a whole value with the manifest read in `trx` and the
+    // chunks read at `read_version`
+    async fn read_value_at(
+        fdb: &FoundationDB,
+        trx: &Transaction,
+        key: &[u8],
+        read_version: i64,
+    ) -> Result<Option<Vec<u8>>> {
+        let Some(raw) = trx.get(key, true).await? else {
+            return Self::read_legacy_value(fdb, key).await;
+        };
+        let manifest =
+            Manifest::decode(&raw).ok_or_else(|| Self::corrupt("unknown manifest layout"))?;
+
+        // Fetch every batch in parallel, all pinned to the manifest's read version
+        let root = manifest.chunk_root(key);
+        let count = manifest.chunk_count as usize;
+        let batches = (0..count)
+            .step_by(READ_BATCH_CHUNKS)
+            .map(|start| Self::read_chunk_range(fdb, &root, start..(start + READ_BATCH_CHUNKS).min(count), read_version));
+        let chunks = futures::future::try_join_all(batches)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        let value = Self::decode_chunks(fdb.keyring.as_deref(), &manifest, key, chunks)?;
+        Ok(Some(value))
+    }
+
+    // Function to read chunks `range` below `root` at a fixed read version
+    pub async fn read_chunk_range(
+        fdb: &FoundationDB,
+        root: &[u8],
+        range: Range<usize>,
+        read_version: i64,
+    ) -> Result<Vec<Vec<u8>>> {
+        let trx = fdb.create_trx()?;
+        trx.set_read_version(read_version);
+        let subspace = Subspace::from_bytes(root);
+        let futs = range
+            .map(|i| trx.get(&subspace.pack(&(i,)), true))
+            .collect::<Vec<_>>();
+        futures::future::try_join_all(futs)
+            .await?
+            .into_iter()
+            .map(|chunk| chunk.map(|c| c.to_vec()).ok_or_else(|| Self::corrupt("missing chunk")))
+            .collect()
+    }
+
+    // Function to read the length of a value from its manifest.
+    // Values written before manifests existed are reassembled to be measured.
+    pub async fn value_len(fdb: &FoundationDB, key: &[u8]) -> Result<Option<u64>> {
+        if let Some(manifest) = Self::read_manifest(fdb, key).await? {
+            return Ok(Some(manifest.value_len));
+        }
+        let legacy = Self::read_legacy_value(fdb, key).await?;
+        Ok(legacy.map(|value| value.len() as u64))
+    }
+
+    // Function to reassemble a value written before manifests existed.
+    // Once the handle is told no such value is left, a key without a
+    // manifest holds no value and nothing is read.
+    async fn read_legacy_value(fdb: &FoundationDB, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        if !fdb.legacy_values {
+            return Ok(None);
+        }
+        let legacy = Self::reconstruct_bloc(fdb, key).await?;
+        Ok(if legacy.is_empty() { None } else { Some(legacy) })
+    }
+
+    // Function to read part of a value, `range` mapping the length of the
+    // value to the bytes wanted.
+    // Only the chunks holding those bytes are fetched, except for compressed
+    // values and values without per-chunk checksums, which are read whole.
+    pub async fn read_range<F>(fdb: &FoundationDB, key: &[u8], range: F) -> Result<Option<Vec<u8>>>
+    where
+        F: Fn(u64) -> Range<u64>,
+    {
+        let mut trx = fdb.create_trx()?;
+        loop {
+            let e = match Self::read_range_once(fdb, &trx, key, &range).await {
+                Err(e) => e,
+                res => return res,
+            };
+            let Some(cause) = e.retryable_cause() else {
+                return Err(e);
+            };
+            trx = trx.on_error(cause).await?;
+        }
+    }
+
+    async fn read_range_once<F>(
+        fdb: &FoundationDB,
+        trx: &Transaction,
+        key: &[u8],
+        range: &F,
+    ) -> Result<Option<Vec<u8>>>
+    where
+        F: Fn(u64) -> Range<u64>,
+    {
+        let read_version = trx.get_read_version().await?;
+        let manifest = trx
+            .get(key, true)
+            .await?
+            .map(|raw| Manifest::decode(&raw).ok_or_else(|| Self::corrupt("unknown manifest layout")))
+            .transpose()?;
+        let Some(manifest) =
+            manifest.filter(|m| m.codec == Codec::None && m.chunk_checksums.len() == m.chunk_count as usize)
+        else {
+            let value = Self::read_value_at(fdb, trx, key, read_version).await?;
+            return Ok(value.map(|value| {
+                let wanted = range(value.len() as u64);
+                let end = (wanted.end as usize).min(value.len());
+                value[(wanted.start as usize).min(end)..end].to_vec()
+            }));
+        };
+
+        let wanted = range(manifest.value_len);
+        let end = wanted.end.min(manifest.value_len);
+        let start = wanted.start.min(end);
+        if start == end {
+            return Ok(Some(Vec::new()));
+        }
+        let size = manifest.chunk_size as u64;
+        let (first, last) = ((start / size) as usize, ((end - 1) / size) as usize);
+        let root = manifest.chunk_root(key);
+        let batches = (first..=last).step_by(READ_BATCH_CHUNKS).map(|from| {
+            Self::read_chunk_range(
+                fdb,
+                &root,
+                from..(from + READ_BATCH_CHUNKS).min(last + 1),
+                read_version,
+            )
+        });
+        let chunks = futures::future::try_join_all(batches)
+            .await?
+            .into_iter()
+            .flatten();
+
+        let mut bytes = Vec::with_capacity((end - first as u64 * size) as usize);
+        for (i, chunk) in (first..).zip(chunks) {
+            if !manifest.verify_chunk(i, &chunk) {
+                return Err(Self::corrupt(&format!("chunk {} failed its checksum", i)));
+            }
+            let chunk = Self::open_chunk(fdb.keyring.as_deref(), &manifest, key, i, chunk)?;
+            bytes.extend_from_slice(&chunk);
+        }
+        bytes.truncate((end - first as u64 * size) as usize);
+        bytes.drain(..(start - first as u64 * size) as usize);
+        Ok(Some(bytes))
+    }
+
+    // Function to read bytes `range` of a value inside an existing
+    // transaction, clamped to its end. Only the chunks holding them are
+    // fetched, except for compressed values and values without per-chunk
+    // checksums, which are read whole.
+    // A missing value reads as empty. Returns `None` when the value is too
+    // large to be read whole within one transaction.
+    async fn read_range_in_trx(
+        fdb: &FoundationDB,
+        trx: &Transaction,
+        key: &[u8],
+        range: Range<u64>,
+    ) -> std::result::Result<Option<Vec<u8>>, FdbBindingError> {
+        let custom = |e: Error| FdbBindingError::CustomError(Box::new(e));
+        let manifest = trx
+            .get(key, false)
+            .await?
+            .map(|raw| Manifest::decode(&raw).ok_or_else(|| custom(Self::corrupt("unknown manifest layout"))))
+            .transpose()?;
+        let manifest = match manifest {
+            Some(m) if m.codec == Codec::None && m.chunk_checksums.len() == m.chunk_count as usize => m,
+            Some(m) if m.value_len as usize > MAX_TRANSACTION_SIZE => return Ok(None),
+            _ => {
+                let value = Self::get_in_trx(fdb, trx, key).await?.unwrap_or_default();
+                let end = (range.end as usize).min(value.len());
+                return Ok(Some(value[(range.start as usize).min(end)..end].to_vec()));
+            }
+        };
+
+        let end = range.end.min(manifest.value_len);
+        let start = range.start.min(end);
+        if start == end {
+            return Ok(Some(Vec::new()));
+        }
+        let size = manifest.chunk_size as u64;
+        let (first, last) = ((start / size) as usize, ((end - 1) / size) as usize);
+        let subspace = Subspace::from_bytes(manifest.chunk_root(key));
+        let reads = (first..=last)
+            .map(|i| trx.get(&subspace.pack(&(i,)), false))
+            .collect::<Vec<_>>();
+        let mut bytes = Vec::with_capacity((end - first as u64 * size) as usize);
+        for (i, chunk) in (first..).zip(futures::future::try_join_all(reads).await?) {
+            let chunk = chunk.ok_or_else(|| custom(Self::corrupt("missing chunk")))?;
+            if !manifest.verify_chunk(i, &chunk) {
+                return Err(custom(Self::corrupt(&format!("chunk {} failed its checksum", i))));
+            }
+            let chunk = Self::open_chunk(fdb.keyring.as_deref(), &manifest, key, i, chunk.to_vec())
+                .map_err(custom)?;
+            bytes.extend_from_slice(&chunk);
+        }
+        bytes.truncate((end - first as u64 * size) as usize);
+        bytes.drain(..(start - first as u64 * size) as usize);
+        Ok(Some(bytes))
+    }
+
+    // Function to stream a value one batch of chunks at a time.
+    // Each batch is read at its own read version, together with the manifest,
+    // so a long transfer never hits the 5s transaction limit; the generation
+    // cannot be collected while the manifest still names it.
+    // Compressed values are decoded in memory before being streamed.
+    pub async fn read_stream(fdb: &FoundationDB, key: &[u8]) -> Result<Option<ValueStream>> {
+        let Some(manifest) = Self::read_manifest(fdb, key).await? else {
+            let Some(legacy) = Self::read_legacy_value(fdb, key).await? else {
+                return Ok(None);
+            };
+            let len = legacy.len() as u64;
+            let chunk = futures::stream::once(async move { Ok(Bytes::from(legacy)) });
+            return Ok(Some(ValueStream::new(len, chunk)));
+        };
+
+        let fdb = fdb.clone();
+        let key = key.to_vec();
+        let len = manifest.value_len;
+        let keyring = fdb.keyring.clone();
+        let chunks = async_stream::try_stream! {
+            let count = manifest.chunk_count as usize;
+            let mut checksum = 0;
+            let mut streamed = 0;
+            // Compressed values can only be decoded once every chunk is in
+            let mut compressed = Vec::new();
+            for start in (0..count).step_by(READ_BATCH_CHUNKS) {
+                let range = start..(start + READ_BATCH_CHUNKS).min(count);
+                let batch = Self::read_streamed_batch(&fdb, &key, &manifest, range).await?;
+                for (i, chunk) in (start..).zip(batch) {
+                    // Checked before it leaves, since a sent chunk cannot be taken back
+                    if !manifest.verify_chunk(i, &chunk) {
+                        let reason = format!("chunk {} failed its checksum", i);
+                        Err::<(), Error>(Self::corrupt(&reason))?;
                     }
-                    Ok(())
+                    checksum = crc32c::crc32c_append(checksum, &chunk);
+                    streamed += chunk.len() as u64;
+                    let chunk = Self::open_chunk(keyring.as_deref(), &manifest, &key, i, chunk)?;
+                    if manifest.codec == Codec::None {
+                        yield Bytes::from(chunk);
+                    } else {
+                        compressed.extend_from_slice(&chunk);
+                    }
+                }
+            }
+            if checksum != manifest.checksum || streamed != manifest.total_len {
+                Err::<(), Error>(Self::corrupt("checksum mismatch"))?;
+            }
+            if manifest.codec != Codec::None {
+                let value = Bytes::from(manifest.codec.decompress(compressed, manifest.value_len)?);
+                for start in (0..value.len()).step_by(MAX_VALUE_SIZE) {
+                    yield value.slice(start..(start + MAX_VALUE_SIZE).min(value.len()));
+                }
+            }
+        };
+        Ok(Some(ValueStream::new(len, chunks)))
+    }
+
+    // Function to read chunks `range` of a streamed value, checking that
+    // `manifest` is still the one stored at `key`
+    async fn read_streamed_batch(
+        fdb: &FoundationDB,
+        key: &[u8],
+        manifest: &Manifest,
+        range: Range<usize>,
+    ) -> Result<Vec<Vec<u8>>> {
+        let mut trx = fdb.create_trx()?;
+        loop {
+            let attempt = async {
+                let read_version = trx.get_read_version().await?;
+                let current = trx.get(key, true).await?.and_then(|m| Manifest::decode(&m));
+                if current.as_ref() != Some(manifest) {
+                    return Err(Error::ValueChanged);
                 }
+                let root = manifest.chunk_root(key);
+                Self::read_chunk_range(fdb, &root, range.clone(), read_version).await
+            };
+            let e = match attempt.await {
+                Err(e) => e,
+                res => return res,
+            };
+            let Some(cause) = e.retryable_cause() else {
+                return Err(e);
+            };
+            trx = trx.on_error(cause).await?;
+        }
+    }
+
+    // Function to read a whole value inside an existing transaction.
+    // Only suited to values that can be read within a single transaction.
+    pub async fn get_in_trx(
+        fdb: &FoundationDB,
+        trx: &Transaction,
+        key: &[u8],
+    ) -> std::result::Result<Option<Vec<u8>>, FdbBindingError> {
+        let Some(raw) = trx.get(key, false).await? else {
+            if !fdb.legacy_values {
+                return Ok(None);
+            }
+            let range = RangeOption::from(Manifest::legacy_chunk_range(key));
+            let legacy = trx
+                .get_range(&range, false)
+                .await?
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>()
+                .concat();
+            return Ok(if legacy.is_empty() { None } else { Some(legacy) });
+        };
+        let manifest = Manifest::decode(&raw).ok_or_else(|| {
+            FdbBindingError::CustomError(Box::new(Self::corrupt("unknown manifest layout")))
+        })?;
+
+        let subspace = Subspace::from_bytes(manifest.chunk_root(key));
+        let futs = (0..manifest.chunk_count as usize)
+            .map(|i| trx.get(&subspace.pack(&(i,)), false))
+            .collect::<Vec<_>>();
+        let chunks = futures::future::try_join_all(futs)
+            .await?
+            .into_iter()
+            .map(|chunk| chunk.map(|c| c.to_vec()))
+            .collect::<Option<Vec<_>>>();
+        chunks
+            .ok_or_else(|| Self::corrupt("missing chunk"))
+            .and_then(|chunks| Self::decode_chunks(fdb.keyring.as_deref(), &manifest, key, chunks))
+            .map(Some)
+            .map_err(|e| FdbBindingError::CustomError(Box::new(e)))
+    }
+
+    // Function to write a whole value inside an existing transaction.
+    // The encoded value must fit within the transaction size limit.
+    pub async fn set_in_trx(
+        fdb: &FoundationDB,
+        trx: &Transaction,
+        key: &[u8],
+        value: &[u8],
+    ) -> std::result::Result<(), FdbBindingError> {
+        let (manifest, chunks) =
+            Self::prepare(fdb, key, value).map_err(|e| FdbBindingError::CustomError(Box::new(e)))?;
+        Self::write_in_trx(trx, key, &manifest, &chunks).await
+    }
+
+    // Function to write a prepared value inside an existing transaction
+    pub async fn write_in_trx(
+        trx: &Transaction,
+        key: &[u8],
+        manifest: &Manifest,
+        chunks: &[Vec<u8>],
+    ) -> std::result::Result<(), FdbBindingError> {
+        Self::clear_in_trx(trx, key).await?;
+        let subspace = Subspace::from_bytes(manifest.chunk_root(key));
+        for (i, chunk) in chunks.iter().enumerate() {
+            trx.set(&subspace.pack(&(i,)), chunk);
+        }
+        trx.atomic_op(key, &manifest.encode_versionstamped(), MutationType::SetVersionstampedValue);
+        Ok(())
+    }
+
+    // Function to clear a value inside an existing transaction
+    pub async fn clear_in_trx(
+        trx: &Transaction,
+        key: &[u8],
+    ) -> std::result::Result<(), FdbBindingError> {
+        let previous = trx.get(key, false).await?.and_then(|m| Manifest::decode(&m));
+        let (begin, end) = match previous {
+            Some(m) => m.chunk_range(key),
+            None => Manifest::legacy_chunk_range(key),
+        };
+        trx.clear(key);
+        trx.clear_range(&begin, &end);
+        Ok(())
+    }
+
+    // Function to write several values so that they all become visible in
+    // one transaction, in which `guard` runs first and may refuse the write.
+    // Values that fit together are written in that transaction; otherwise
+    // each is staged first and only the manifests are flipped together.
+    // A key given twice keeps its last value.
+    // Returns whether the values were written.
+    pub async fn write_many<F, Fut>(
+        fdb: &FoundationDB,
+        pairs: &[(Vec<u8>, Vec<u8>)],
+        guard: F,
+    ) -> Result<bool>
+    where
+        F: Fn(Transaction) -> Fut,
+        Fut: Future<Output = std::result::Result<bool, FdbBindingError>>,
+    {
+        let mut seen = HashSet::new();
+        let mut writes = Vec::new();
+        let mut total_len = 0;
+        for (key, value) in pairs.iter().rev() {
+            if !seen.insert(key.as_slice()) {
+                continue;
+            }
+            let (manifest, chunks) = Self::prepare(fdb, key, value)?;
+            total_len += manifest.total_len as usize;
+            writes.push((key.clone(), manifest, chunks));
+        }
+
+        let staged = total_len > MAX_TRANSACTION_SIZE;
+        if staged {
+            for i in 0..writes.len() {
+                let (key, manifest, chunks) = &mut writes[i];
+                let chunks = std::mem::take(chunks);
+                if let Err(e) = Self::stage_chunks(fdb, key, manifest, chunks).await {
+                    for (key, manifest, _) in &writes[..i] {
+                        let _ = Self::clear_chunks(fdb, key, manifest).await;
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        let (writes, guard) = (&writes, &guard);
+        let written = fdb
+            .run(move |trx, maybe_committed| async move {
+                // Generations are fresh, so finding one in place proves a
+                // previous attempt committed before reporting an error
+                if let Some((key, manifest, _)) = writes.first().filter(|_| bool::from(maybe_committed)) {
+                    let current = trx.get(key, false).await?.and_then(|m| Manifest::decode(&m));
+                    if current.is_some_and(|m| m.generation == manifest.generation) {
+                        return Ok(true);
+                    }
+                }
+                if !guard(trx.clone()).await? {
+                    return Ok(false);
+                }
+                // Staged values have no chunks left to write
+                for (key, manifest, chunks) in writes {
+                    Self::write_in_trx(&trx, key, manifest, chunks).await?;
+                }
+                Ok(true)
             })
-            .await
+            .await?;
+
+        if !written && staged {
+            for (key, manifest, _) in writes {
+                Self::clear_chunks(fdb, key, manifest).await?;
+            }
+        }
+        Ok(written)
+    }
+
+    // Function to remove a value inside an existing transaction, leaving its
+    // chunks in place when it has more than `max_chunks` of them. The value
+    // is gone either way; the manifest of the chunks left behind is returned
+    // for `clear_detached` to finish the job.
+    pub async fn detach_in_trx(
+        trx: &Transaction,
+        key: &[u8],
+        max_chunks: u64,
+    ) -> std::result::Result<Option<Manifest>, FdbBindingError> {
+        match trx.get(key, false).await?.and_then(|m| Manifest::decode(&m)) {
+            Some(manifest) if manifest.chunk_count > max_chunks => {
+                trx.clear(key);
+                Ok(Some(manifest))
+            }
+            _ => {
+                Self::clear_in_trx(trx, key).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    // Function to clear the chunks of a value detached by `detach_in_trx`,
+    // `CLEAR_BATCH_CHUNKS` at a time so no transaction grows with the value
+    pub async fn clear_detached(fdb: &FoundationDB, key: &[u8], manifest: &Manifest) -> Result<()> {
+        let subspace = Subspace::from_bytes(manifest.chunk_root(key));
+        let count = manifest.chunk_count as usize;
+        for start in (0..count).step_by(CLEAR_BATCH_CHUNKS) {
+            let end = (start + CLEAR_BATCH_CHUNKS).min(count);
+            Self::clear_range(fdb, subspace.pack(&(start,)), subspace.pack(&(end,))).await?;
+        }
+        // Whatever else is left below the generation
+        Self::clear_chunks(fdb, key, manifest).await
+    }
+
+    // Function to write `data` into the value at `key` from byte `offset`,
+    // or after its last byte when `offset` is `None`, zero-padding any gap.
+    // The write takes effect exactly once. When the chunks it touches do not
+    // fit in one transaction, the whole value is read, patched and written
+    // back as `set` would, so concurrent writers must be kept out by the caller.
+    // Returns the new length of the value.
+    pub async fn write_range(
+        fdb: &FoundationDB,
+        key: &[u8],
+        offset: Option<u64>,
+        data: &[u8],
+    ) -> Result<u64> {
+        let written = fdb
+            .run_once(move |trx| async move { Self::write_range_in_trx(fdb, &trx, key, offset, data).await })
+            .await?;
+        if let Some(len) = written {
+            return Ok(len);
+        }
+
+        let mut value = Self::read_value(fdb, key).await?.unwrap_or_default();
+        let at = offset.unwrap_or(value.len() as u64);
+        Self::check_len(at + data.len() as u64)?;
+        Self::splice(&mut value, at as usize, data);
+        fdb.set(key, &value).await?;
+        Ok(value.len() as u64)
+    }
+
+    // Function to write `data` into a value inside an existing transaction.
+    // Uncompressed, unencrypted values only get the chunks overlapping the
+    // write rewritten, in place under their generation; readers pinned to
+    // an older version still see the old chunks through MVCC. Any other
+    // value is rewritten whole.
+    // Returns the new length, or `None` when the write is too large for one
+    // transaction.
+    async fn write_range_in_trx(
+        fdb: &FoundationDB,
+        trx: &Transaction,
+        key: &[u8],
+        offset: Option<u64>,
+        data: &[u8],
+    ) -> std::result::Result<Option<u64>, FdbBindingError> {
+        let custom = |e: Error| FdbBindingError::CustomError(Box::new(e));
+        let manifest = trx
+            .get(key, false)
+            .await?
+            .map(|raw| Manifest::decode(&raw).ok_or_else(|| custom(Self::corrupt("unknown manifest layout"))))
+            .transpose()?;
+        let mut manifest = match manifest {
+            Some(m)
+                if m.codec == Codec::None
+                    && m.cipher == Cipher::None
+                    && m.chunk_checksums.len() == m.chunk_count as usize =>
+            {
+                m
+            }
+            other => return Self::rewrite_in_trx(fdb, trx, key, other, offset, data).await,
+        };
+
+        let len = manifest.value_len;
+        let at = offset.unwrap_or(len);
+        if data.is_empty() {
+            return Ok(Some(len));
+        }
+        let end = at + data.len() as u64;
+        Self::check_len(end).map_err(custom)?;
+        // From the chunk holding the first byte written, or the current end
+        // when padding, to the chunk holding the last byte written
+        let size = manifest.chunk_size as u64;
+        let first = (at.min(len) / size) as usize;
+        let last = ((end - 1) / size) as usize;
+        if (last + 1 - first) * size as usize > MAX_TRANSACTION_SIZE {
+            return Ok(None);
+        }
+
+        let subspace = Subspace::from_bytes(manifest.chunk_root(key));
+        let reads = (first..(last + 1).min(manifest.chunk_count as usize))
+            .map(|i| trx.get(&subspace.pack(&(i,)), false))
+            .collect::<Vec<_>>();
+        let mut window = Vec::new();
+        for (i, chunk) in (first..).zip(futures::future::try_join_all(reads).await?) {
+            let chunk = chunk.ok_or_else(|| custom(Self::corrupt("missing chunk")))?;
+            if !manifest.verify_chunk(i, &chunk) {
+                return Err(custom(Self::corrupt(&format!("chunk {} failed its checksum", i))));
+            }
+            window.extend_from_slice(&chunk);
+        }
+        Self::splice(&mut window, (at - first as u64 * size) as usize, data);
+
+        let chunks = Self::split_into_chunks(&window, Some(size as usize));
+        for (i, chunk) in (first..).zip(&chunks) {
+            trx.set(&subspace.pack(&(i,)), chunk);
+        }
+        manifest.replace_chunks(first, &chunks);
+        trx.atomic_op(key, &manifest.encode_versionstamped(), MutationType::SetVersionstampedValue);
+        Ok(Some(manifest.value_len))
+    }
+
+    // Function to write `data` into a value that cannot be patched, by
+    // reading it whole and writing it back
+    async fn rewrite_in_trx(
+        fdb: &FoundationDB,
+        trx: &Transaction,
+        key: &[u8],
+        manifest: Option<Manifest>,
+        offset: Option<u64>,
+        data: &[u8],
+    ) -> std::result::Result<Option<u64>, FdbBindingError> {
+        if manifest.is_some_and(|m| m.value_len as usize + data.len() > MAX_TRANSACTION_SIZE) {
+            return Ok(None);
+        }
+        let current = Self::get_in_trx(fdb, trx, key).await?;
+        let exists = current.is_some();
+        let mut value = current.unwrap_or_default();
+        let at = offset.unwrap_or(value.len() as u64);
+        if data.is_empty() && (exists || offset.is_some()) {
+            return Ok(Some(value.len() as u64));
+        }
+        Self::check_len(at + data.len() as u64).map_err(|e| FdbBindingError::CustomError(Box::new(e)))?;
+        if at as usize + data.len() > MAX_TRANSACTION_SIZE {
+            return Ok(None);
+        }
+        Self::splice(&mut value, at as usize, data);
+        Self::set_in_trx(fdb, trx, key, &value).await?;
+        Ok(Some(value.len() as u64))
+    }
+
+    // Function to update bytes `range` of the value at `key` in one go.
+    // `update` gets the bytes the value holds in `range`, fewer past its end,
+    // and may change or extend them; they are written back from
+    // `range.start` as `write_range` would, only when changed, so updates
+    // that change nothing never extend the value.
+    // The update takes effect exactly once. When the chunks it touches do not
+    // fit in one transaction, the whole value is read, updated and written
+    // back, so concurrent writers must be kept out by the caller.
+    // Returns what `update` returned.
+    pub async fn update_range<F, T>(fdb: &FoundationDB, key: &[u8], range: Range<u64>, update: F) -> Result<T>
+    where
+        F: Fn(&mut Vec<u8>) -> T,
+        T: Clone,
+    {
+        let (range, update) = (&range, &update);
+        let updated = fdb
+            .run_once(move |trx| async move {
+                let Some(mut window) = Self::read_range_in_trx(fdb, &trx, key, range.clone()).await? else {
+                    return Ok(None);
+                };
+                let original = window.clone();
+                let out = update(&mut window);
+                if window != original
+                    && Self::write_range_in_trx(fdb, &trx, key, Some(range.start), &window)
+                        .await?
+                        .is_none()
+                {
+                    return Ok(None);
+                }
+                Ok(Some(out))
+            })
+            .await?;
+        if let Some(out) = updated {
+            return Ok(out);
+        }
+
+        let mut value = Self::read_value(fdb, key).await?.unwrap_or_default();
+        let end = (range.end as usize).min(value.len());
+        let mut window = value[(range.start as usize).min(end)..end].to_vec();
+        let original = window.clone();
+        let out = update(&mut window);
+        if window != original {
+            Self::check_len(range.start + window.len() as u64)?;
+            Self::splice(&mut value, range.start as usize, &window);
+            fdb.set(key, &value).await?;
+        }
+        Ok(out)
+    }
+
+    // Function to write `data` into `value` from byte `at`, zero-padding any gap
+    fn splice(value: &mut Vec<u8>, at: usize, data: &[u8]) {
+        let end = at + data.len();
+        if value.len() < end {
+            value.resize(end, 0);
+        }
+        value[at..end].copy_from_slice(data);
+    }
+
+    fn check_len(len: u64) -> Result<()> {
+        if len > MAX_VALUE_LEN as u64 {
+            return Err(Error::ValueTooLarge {
+                size: len as usize,
+                max: MAX_VALUE_LEN,
+            });
+        }
+        Ok(())
+    }
+
+    // Function to adopt a value written before manifests existed.
+    // The value is re-chunked under a fresh manifest unless a writer got there first.
+    pub async fn adopt_legacy_value(fdb: &FoundationDB, key: &[u8]) -> Result<bool> {
+        if Self::read_manifest(fdb, key).await?.is_some() {
+            return Ok(false);
+        }
+        let value = Self::reconstruct_bloc(fdb, key).await?;
+        let manifest = Self::stage_value(fdb, key, &value).await?;
+        if !Self::commit_manifest(fdb, key, &manifest, Expected::Absent).await? {
+            Self::clear_chunks(fdb, key, &manifest).await?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    // Function to write a value unless the key already has a manifest.
+    // Returns whether the value was written.
+    pub async fn write_if_absent(fdb: &FoundationDB, key: &[u8], value: &[u8]) -> Result<bool> {
+        let manifest = Self::stage_value(fdb, key, value).await?;
+        if !Self::commit_manifest(fdb, key, &manifest, Expected::Absent).await? {
+            Self::clear_chunks(fdb, key, &manifest).await?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    // Function to rewrite a value under the active key of `fdb`'s keyring,
+    // or in clear when it has none. Values already stored that way are left
+    // alone, as is a value overwritten while it is being rewritten.
+    // Returns whether the value was rewritten.
+    pub async fn reencrypt_value(fdb: &FoundationDB, key: &[u8]) -> Result<bool> {
+        let Some(current) = Self::read_manifest(fdb, key).await? else {
+            return Ok(false);
+        };
+        let target = fdb.keyring.as_ref().and_then(|k| k.active());
+        let up_to_date = match target {
+            Some((key_id, cipher)) => current.cipher == cipher && current.key_id == key_id,
+            None => current.cipher == Cipher::None,
+        };
+        if up_to_date {
+            return Ok(false);
+        }
+        let Some(value) = Self::read_value(fdb, key).await? else {
+            return Ok(false);
+        };
+        let manifest = Self::stage_value(fdb, key, &value).await?;
+        if !Self::commit_manifest(fdb, key, &manifest, Expected::Current(current)).await? {
+            Self::clear_chunks(fdb, key, &manifest).await?;
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn corrupt(reason: &str) -> Error {
+        Error::corrupt(reason)
+    }
+
+    // Function to check `chunks` against their manifest, naming the first
+    // chunk that fails its own checksum
+    fn verify_chunks(manifest: &Manifest, chunks: &[Vec<u8>]) -> Result<()> {
+        if let Some(i) = (0..chunks.len()).find(|&i| !manifest.verify_chunk(i, &chunks[i])) {
+            return Err(Self::corrupt(&format!("chunk {} failed its checksum", i)));
+        }
+        if !manifest.verify(chunks) {
+            return Err(Self::corrupt("checksum mismatch"));
+        }
+        Ok(())
     }
 
     // Function to retrieve and combine chunks from FoundationDB
@@ -139,40 +1187,41 @@ impl DataModel {
         fdb: &FoundationDB,
         key: &[u8],
         num_chunks: usize,
-    ) -> std::result::Result<Vec<u8>, foundationdb::FdbBindingError> {
+    ) -> Result<Vec<u8>> {
         let subspace = Subspace::from_bytes(key);
-        fdb.database
-            .run(move |trx, _| {
-                let mut futs = Vec::with_capacity(num_chunks);
-                let subspace = subspace.clone();
-                for i in 0..num_chunks {
-                    let chunk_key = subspace.pack(&(i,));
-                    futs.push(trx.get(&chunk_key, false));
-                }
-                async move {
-                    let mut result = Vec::new();
-                    for fut in futs {
-                        if let Some(chunk) = fut.await? {
-                            result.extend_from_slice(&chunk);
-                        }
+        fdb.run(move |trx, _| {
+            let mut futs = Vec::with_capacity(num_chunks);
+            let subspace = subspace.clone();
+            for i in 0..num_chunks {
+                let chunk_key = subspace.pack(&(i,));
+                futs.push(trx.get(&chunk_key, false));
+            }
+            async move {
+                let mut result = Vec::new();
+                for fut in futs {
+                    if let Some(chunk) = fut.await? {
+                        result.extend_from_slice(&chunk);
                     }
-                    Ok(result)
                 }
-            })
-            .await
+                Ok(result)
+            }
+        })
+        .await
+            .map_err(Into::into)
     }
 
     pub async fn reconstruct_bloc(
         fdb: &FoundationDB,
         key_prefix: &[u8],
-    ) -> Result<Vec<u8>, foundationdb::FdbBindingError> {
-        let mut end = key_prefix.to_vec();
-        end.push(0xFF);
+    ) -> Result<Vec<u8>> {
+        // Only bare chunk indexes: generations staged by writers are skipped
+        let (begin, end) = Manifest::legacy_chunk_range(key_prefix);
 
+        // Chunks are large: fetch as many per transaction as reads of
+        // manifested values do
         let mut bloc = Vec::new();
         let result = fdb
-            .full_scan(key_prefix, &end)
-            .await
+            .scan_stream(&begin, &end, ScanOptions::bulk(READ_BATCH_CHUNKS))
             .collect::<Vec<_>>()
             .await;
         for chunk in result {
@@ -257,4 +1306,144 @@ mod tests {
             .expect("reconstruct_bloc failed");
         assert_eq!(reconstructed, data);
     }
+
+    #[tokio::test]
+    async fn test_stale_trailing_chunks_ignored() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        let key = Subspace::from_bytes("subspace_manifest").pack(&("shrinking_key"));
+
+        let long = vec![1u8; 3 * MAX_VALUE_SIZE];
+        db.set(&key, &long).await.expect("Unable to set long value");
+        let short = vec![2u8; 10];
+        db.set(&key, &short).await.expect("Unable to set short value");
+
+        let manifest = db
+            .manifest(&key)
+            .await
+            .expect("Unable to read manifest")
+            .expect("missing manifest");
+        assert_eq!(manifest.chunk_count, 1);
+        assert_eq!(manifest.total_len, 10);
+        assert_ne!(manifest.write_version, [0; 10]);
+
+        let value = db.get(&key).await.expect("Unable to get value");
+        assert_eq!(value, Some(short));
+    }
+
+    #[tokio::test]
+    async fn test_torn_value_detected() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        let key = Subspace::from_bytes("subspace_manifest").pack(&("torn_key"));
+
+        db.set(&key, &vec![1u8; 2 * MAX_VALUE_SIZE])
+            .await
+            .expect("Unable to set value");
+        // overwrite the first chunk behind the manifest's back
+        let manifest = db
+            .manifest(&key)
+            .await
+            .expect("Unable to read manifest")
+            .expect("missing manifest");
+        let root = manifest.chunk_root(&key);
+        DataModel::store_chunks_in_fdb(&db, &root, vec![vec![2u8; MAX_VALUE_SIZE]])
+            .await
+            .expect("Unable to store chunk");
+
+        let before = crate::metrics::corrupt_values();
+        let res = db.get(&key).await;
+        assert!(matches!(res, Err(Error::CorruptValue(_))));
+        assert!(crate::metrics::corrupt_values() > before);
+        assert!(matches!(db.verify(&key).await, Err(Error::CorruptValue(_))));
+    }
+
+    #[tokio::test]
+    async fn test_adopt_legacy_value() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        let key = Subspace::from_bytes("subspace_manifest").pack(&("legacy_key"));
+
+        // chunks written without a manifest, as before manifests existed
+        let mut data = vec![0; 2 * MAX_VALUE_SIZE + 10];
+        rand::thread_rng().fill(&mut data[..]);
+        let chunks = DataModel::split_into_chunks(&data, None);
+        DataModel::store_chunks_in_fdb(&db, &key, chunks)
+            .await
+            .expect("Unable to store chunks");
+        assert_eq!(db.get(&key).await.expect("legacy get"), Some(data.clone()));
+
+        assert!(db.adopt_legacy_value(&key).await.expect("adopt failed"));
+        assert!(!db.adopt_legacy_value(&key).await.expect("second adopt failed"));
+        let manifest = db
+            .manifest(&key)
+            .await
+            .expect("Unable to read manifest")
+            .expect("missing manifest");
+        assert_eq!(manifest.chunk_count, 3);
+        assert_eq!(db.get(&key).await.expect("get"), Some(data));
+    }
+
+    #[tokio::test]
+    async fn test_staged_generation_invisible_until_commit() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        let key = Subspace::from_bytes("subspace_manifest").pack(&("staged_key"));
+
+        let old = vec![1u8; 2 * MAX_VALUE_SIZE];
+        db.set(&key, &old).await.expect("Unable to set old value");
+        let old_manifest = db
+            .manifest(&key)
+            .await
+            .expect("Unable to read manifest")
+            .expect("missing manifest");
+
+        let new = vec![2u8; 3 * MAX_VALUE_SIZE];
+        let staged = DataModel::stage_value(&db, &key, &new)
+            .await
+            .expect("Unable to stage value");
+        assert_ne!(staged.generation, old_manifest.generation);
+        assert_eq!(db.get(&key).await.expect("get"), Some(old));
+
+        let flipped = DataModel::commit_manifest(&db, &key, &staged, Expected::Any)
+            .await
+            .expect("Unable to commit manifest");
+        assert!(flipped);
+        assert_eq!(db.get(&key).await.expect("get"), Some(new));
+
+        // the replaced generation is collected
+        let (begin, end) = old_manifest.chunk_range(&key);
+        let leftovers = db.scan(&begin, &end).await.expect("Unable to scan");
+        assert!(leftovers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_large_writes_are_atomic() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        let key = Subspace::from_bytes("subspace_manifest").pack(&("contended_key"));
+
+        // larger than a transaction, so every write is staged
+        let payloads = (0..4u8)
+            .map(|i| vec![i; MAX_TRANSACTION_SIZE + MAX_VALUE_SIZE])
+            .collect::<Vec<_>>();
+        let writes = payloads.iter().map(|p| db.set(&key, p));
+        let reads = (0..4).map(|_| db.get(&key));
+        let (writes, reads) = futures::future::join(
+            futures::future::join_all(writes),
+            futures::future::join_all(reads),
+        )
+        .await;
+        for res in writes {
+            res.expect("Unable to set value");
+        }
+        for res in reads {
+            if let Some(value) = res.expect("Unable to get value") {
+                assert!(payloads.contains(&value), "read a torn value");
+            }
+        }
+
+        let value = db.get(&key).await.expect("get").expect("missing value");
+        assert!(payloads.contains(&value));
+    }
 }
@@ -0,0 +1,105 @@
+use crate::storage;
+use foundationdb::{FdbBindingError, FdbError};
+
+/// FoundationDB error code raised when a transaction outlives the 5s MVCC window.
+const TRANSACTION_TOO_OLD: i32 = 1007;
+
+/// Errors returned by the FoundationDB wrapper.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The value exceeds the maximum size accepted by the wrapper.
+    #[error("value of {size} bytes exceeds the maximum of {max} bytes")]
+    ValueTooLarge { size: usize, max: usize },
+
+    /// The stored value does not match its manifest: chunks are missing,
+    /// stale or corrupted.
+    #[error("value is incomplete or corrupted: {0}")]
+    CorruptValue(String),
+
+    /// The value was replaced while it was being streamed.
+    #[error("value was overwritten while being streamed")]
+    ValueChanged,
+
+    /// Reading the value from its source failed.
+    #[error("unable to read value from its source")]
+    Io(#[from] std::io::Error),
+
+    /// The value was encrypted with a key missing from the keyring.
+    #[error("no encryption key with id {0}")]
+    UnknownKey(u32),
+
+    /// The key file could not be parsed.
+    #[error("invalid key file: {0}")]
+    InvalidKeyFile(String),
+
+    /// A chunk could not be encrypted.
+    #[error("encryption failed: {0}")]
+    Encryption(String),
+
+    /// A scan continuation token could not be decoded.
+    #[error("invalid scan continuation token")]
+    InvalidContinuation,
+
+    /// A versionstamp placeholder does not fit at the given offset.
+    #[error("no room for a versionstamp at offset {0}")]
+    InvalidVersionstampOffset(usize),
+
+    /// The transaction ran for longer than FoundationDB allows.
+    #[error("transaction too old")]
+    TransactionTooOld(#[source] FdbError),
+
+    /// A retryable FoundationDB error that outlasted the retry loop.
+    #[error("retryable FoundationDB error")]
+    Retryable(#[source] FdbError),
+
+    /// Any other FoundationDB or binding error.
+    #[error("FoundationDB error")]
+    Fdb(#[source] FdbBindingError),
+}
+
+impl Error {
+    /// A value failing its checksums or manifest, counted in
+    /// `metrics::corrupt_values`.
+    pub(crate) fn corrupt(reason: impl Into<String>) -> Self {
+        crate::metrics::record_corrupt_value();
+        Error::CorruptValue(reason.into())
+    }
+
+    /// Whether the caller may retry the whole operation.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::TransactionTooOld(_) | Error::Retryable(_))
+    }
+
+    /// FoundationDB error to hand to `on_error` when retrying the operation.
+    pub(crate) fn retryable_cause(&self) -> Option<FdbError> {
+        match self {
+            Error::TransactionTooOld(e) | Error::Retryable(e) => Some(*e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FdbBindingError> for Error {
+    fn from(e: FdbBindingError) -> Self {
+        match e.get_fdb_error() {
+            Some(err) if err.code() == TRANSACTION_TOO_OLD => Error::TransactionTooOld(err),
+            Some(err) if storage::is_retryable(err.code()) => Error::Retryable(err),
+            _ => match e {
+                // Errors raised by our own code inside a transaction closure
+                FdbBindingError::CustomError(custom) => match custom.downcast::<Error>() {
+                    Ok(err) => *err,
+                    Err(custom) => Error::Fdb(FdbBindingError::CustomError(custom)),
+                },
+                e => Error::Fdb(e),
+            },
+        }
+    }
+}
+
+impl From<FdbError> for Error {
+    fn from(e: FdbError) -> Self {
+        Error::from(FdbBindingError::from(e))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
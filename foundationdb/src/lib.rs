@@ -1,110 +1,697 @@
-use foundationdb::future::FdbValue;
-use foundationdb::{FdbBindingError, RangeOption};
 use futures::Stream;
-use futures_util::TryStreamExt;
 use futures_util::stream::StreamExt;
-use std::result::Result;
 use std::sync::Arc;
+pub mod atomic;
+pub mod codec;
+pub mod crypto;
 pub(crate) mod datamodel;
-use datamodel::DataModel;
+pub mod error;
+pub mod manifest;
+pub mod metrics;
+pub mod scan;
+pub mod storage;
+pub mod stream;
+pub mod transaction;
+pub mod watch;
+use atomic::Mutation;
+use codec::Compression;
+use crypto::Keyring;
+use datamodel::{DataModel, Expected, MAX_TRANSACTION_SIZE};
+pub use error::{Error, Result};
+use foundationdb::FdbBindingError;
+use manifest::Manifest;
+use scan::{Continuation, ScanOptions, ScanPage};
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::Duration;
+use storage::fault::{FaultInjector, Faults};
+use storage::{Backend, MaybeCommitted, MemoryStore, Transaction};
+use stream::ValueStream;
+use tokio::io::AsyncRead;
+use tokio::sync::Semaphore;
+use transaction::{Priority, TransactionOptions};
+
+/// Largest value accepted by `set`, matching Redis' `proto-max-bulk-len`.
+pub const MAX_VALUE_LEN: usize = 512 * 1024 * 1024; // 512MB
 
 #[derive(Clone)]
 pub struct FoundationDB {
-    pub database: Arc<foundationdb::Database>,
+    backend: Backend,
+    compression: Compression,
+    keyring: Option<Arc<Keyring>>,
+    watches: Arc<Semaphore>,
+    options: TransactionOptions,
+    /// Whether values written before manifests existed may be left.
+    legacy_values: bool,
 }
 
 impl FoundationDB {
     pub fn new(db: Arc<foundationdb::Database>) -> Self {
-        Self { database: db }
+        Self::with_backend(Backend::FoundationDB(db))
+    }
+
+    /// Store records in memory instead of FoundationDB, with the same
+    /// transactional guarantees. Clones of the handle share the records;
+    /// they are lost when the last one is dropped.
+    pub fn in_memory() -> Self {
+        Self::with_backend(Backend::Memory(MemoryStore::default()))
+    }
+
+    /// Inject the faults drawn by `injector` into every transaction of this
+    /// handle. Other clones of the handle keep reaching the same records
+    /// without faults.
+    pub fn with_faults(mut self, injector: Arc<FaultInjector>) -> Self {
+        self.backend = Backend::Faulty(Arc::new(Faults::new(self.backend, injector)));
+        self
     }
 
-    pub async fn set(&self, key: &[u8], value: &[u8]) -> Result<(), FdbBindingError> {
-        let chunks = DataModel::split_into_chunks(value, None);
-        if (self.get(key).await?).is_some() {
-            self.delete(key).await?;
+    fn with_backend(backend: Backend) -> Self {
+        Self {
+            backend,
+            compression: Compression::default(),
+            keyring: None,
+            watches: Arc::new(Semaphore::new(watch::DEFAULT_MAX_WATCHES)),
+            options: TransactionOptions::default(),
+            legacy_values: true,
         }
-        DataModel::store_chunks_in_fdb(self, key, chunks).await?;
+    }
+
+    /// Compress values written by `set` from now on.
+    /// Values already stored keep the codec they were written with.
+    pub fn with_compression(mut self, compression: Compression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Encrypt values written from now on with the active key of `keyring`.
+    /// Values are read with whichever key of the keyring they were written with.
+    pub fn with_encryption(mut self, keyring: Keyring) -> Self {
+        self.keyring = Some(Arc::new(keyring));
+        self
+    }
+
+    /// Treat keys without a manifest as missing instead of looking for chunks
+    /// written before manifests existed, once every value has been given a
+    /// manifest. Saves a range read on every miss.
+    pub fn without_legacy_values(mut self) -> Self {
+        self.legacy_values = false;
+        self
+    }
+
+    /// Allow at most `max` outstanding watches through this handle; further
+    /// watches wait for one to resolve. The connection-wide limit is set with
+    /// `DatabaseOption::MaxWatches` by whoever opens the database.
+    pub fn with_max_watches(mut self, max: usize) -> Self {
+        self.watches = Arc::new(Semaphore::new(max));
+        self
+    }
+
+    /// Cancel transactions still running after `timeout`, retries included.
+    ///
+    /// Every setter returns a new handle, so a single call can be given
+    /// different options with `db.clone().with_timeout(...)`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = Some(timeout);
+        self
+    }
+
+    /// Give up on a transaction after `limit` retries, or never with `None`.
+    pub fn with_retry_limit(mut self, limit: Option<u32>) -> Self {
+        self.options.retry_limit = limit;
+        self
+    }
+
+    /// Cap the backoff between two retries of a transaction.
+    pub fn with_max_retry_delay(mut self, delay: Duration) -> Self {
+        self.options.max_retry_delay = Some(delay);
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.options.priority = priority;
+        self
+    }
+
+    /// Replace all transaction options at once.
+    pub fn with_transaction_options(mut self, options: TransactionOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Options applied to the transactions of this handle.
+    pub fn transaction_options(&self) -> &TransactionOptions {
+        &self.options
+    }
+
+    /// Create a transaction with the options of this handle.
+    pub fn create_trx(&self) -> Result<Transaction> {
+        self.create_trx_with(&self.options)
+    }
+
+    /// Create a transaction with `options` instead of those of this handle.
+    pub fn create_trx_with(&self, options: &TransactionOptions) -> Result<Transaction> {
+        let trx = self.backend.create_trx()?;
+        options.apply(&trx)?;
+        Ok(trx)
+    }
+
+    /// Run `closure` in a transaction with the options of this handle,
+    /// retrying it on retryable errors.
+    ///
+    /// When a commit outcome is unknown, the closure runs again with
+    /// `MaybeCommitted` set; it must then either be idempotent or check
+    /// whether its previous attempt landed. `run_once` does the latter.
+    pub async fn run<F, Fut, T>(&self, closure: F) -> std::result::Result<T, FdbBindingError>
+    where
+        F: Fn(Transaction, MaybeCommitted) -> Fut,
+        Fut: Future<Output = std::result::Result<T, FdbBindingError>>,
+    {
+        self.run_with(&self.options, closure).await
+    }
+
+    /// `run` with `options` instead of those of this handle.
+    pub async fn run_with<F, Fut, T>(
+        &self,
+        options: &TransactionOptions,
+        closure: F,
+    ) -> std::result::Result<T, FdbBindingError>
+    where
+        F: Fn(Transaction, MaybeCommitted) -> Fut,
+        Fut: Future<Output = std::result::Result<T, FdbBindingError>>,
+    {
+        let mut trx = self.backend.create_trx()?;
+        options.apply(&trx)?;
+        let mut maybe_committed = false;
+        loop {
+            // The attempt must be dropped, with its handle on `trx`, before committing
+            let attempt = closure(trx.clone(), MaybeCommitted(maybe_committed)).await;
+            let error = match attempt {
+                Ok(value) => match trx.commit().await {
+                    Ok(()) => return Ok(value),
+                    Err(e) => {
+                        maybe_committed = storage::is_maybe_committed(e.code());
+                        trx = e.on_error().await?;
+                        continue;
+                    }
+                },
+                Err(e) => e,
+            };
+            let Some(cause) = error.get_fdb_error() else {
+                return Err(error);
+            };
+            maybe_committed = storage::is_maybe_committed(cause.code());
+            trx = trx.on_error(cause).await?;
+        }
+    }
+
+    /// Run a transaction that must take effect exactly once, such as an
+    /// atomic add, even when a commit outcome is unknown.
+    ///
+    /// Each attempt also writes a random commit marker. After an unknown
+    /// outcome, the retry looks for the markers of earlier attempts and
+    /// returns the result of the one that committed instead of running again.
+    /// Markers are cleared once the transaction is done.
+    pub async fn run_once<F, Fut, T>(&self, closure: F) -> Result<T>
+    where
+        F: Fn(Transaction) -> Fut,
+        Fut: Future<Output = std::result::Result<T, FdbBindingError>>,
+        T: Clone,
+    {
+        let attempts: Mutex<Vec<(Vec<u8>, T)>> = Mutex::new(Vec::new());
+        let result = self
+            .run(|trx, maybe_committed| {
+                let (attempts, closure) = (&attempts, &closure);
+                async move {
+                    if bool::from(maybe_committed) {
+                        let previous = attempts.lock().expect("attempts lock").clone();
+                        for (marker, value) in previous {
+                            if trx.get(&marker, false).await?.is_some() {
+                                return Ok(value);
+                            }
+                        }
+                    }
+                    let marker = transaction::new_marker();
+                    trx.set(&marker, &[]);
+                    let value = closure(trx).await?;
+                    attempts.lock().expect("attempts lock").push((marker, value.clone()));
+                    Ok(value)
+                }
+            })
+            .await;
+
+        let markers = attempts
+            .into_inner()
+            .expect("attempts lock")
+            .into_iter()
+            .map(|(marker, _)| marker)
+            .collect::<Vec<_>>();
+        if !markers.is_empty() {
+            let cleared = self
+                .run(|trx, _| {
+                    let markers = &markers;
+                    async move {
+                        for marker in markers {
+                            trx.clear(marker);
+                        }
+                        Ok(())
+                    }
+                })
+                .await;
+            if let Err(e) = cleared {
+                log::warn!("unable to clear commit markers: {}", e);
+            }
+        }
+        Ok(result?)
+    }
+
+    /// Run a transaction as `run_once` does, and return the versionstamp it
+    /// committed at along with its result.
+    ///
+    /// Each attempt writes its versionstamp under a commit marker of its
+    /// own, read back and cleared in a second transaction once the first
+    /// one committed.
+    pub async fn run_once_versionstamped<F, Fut, T>(&self, closure: F) -> Result<(T, [u8; atomic::VERSIONSTAMP_LEN])>
+    where
+        F: Fn(Transaction) -> Fut,
+        Fut: Future<Output = std::result::Result<T, FdbBindingError>>,
+        T: Clone,
+    {
+        let (value, marker) = self
+            .run_once(|trx| {
+                let closure = &closure;
+                async move {
+                    let marker = transaction::new_marker();
+                    atomic::set_versionstamped_value(&trx, &marker, &[0; atomic::VERSIONSTAMP_LEN], 0)
+                        .map_err(|e| FdbBindingError::CustomError(Box::new(e)))?;
+                    let value = closure(trx).await?;
+                    Ok((value, marker))
+                }
+            })
+            .await?;
+
+        let stamp = self
+            .run(|trx, _| {
+                let marker = &marker;
+                async move {
+                    let stamp = trx.get(marker, false).await?;
+                    trx.clear(marker);
+                    Ok(stamp)
+                }
+            })
+            .await?;
+        let Some(stamp) = stamp.and_then(|s| <[u8; atomic::VERSIONSTAMP_LEN]>::try_from(s).ok()) else {
+            return Err(Error::Fdb(FdbBindingError::CustomError("commit marker is gone".into())));
+        };
+        Ok((value, stamp))
+    }
+
+    pub async fn set(&self, key: &[u8], value: &[u8]) -> Result<()> {
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::ValueTooLarge {
+                size: value.len(),
+                max: MAX_VALUE_LEN,
+            });
+        }
+        let (manifest, chunks) = DataModel::prepare(self, key, value)?;
+        if manifest.total_len as usize <= MAX_TRANSACTION_SIZE {
+            let (key, manifest, chunks) = (key.to_vec(), Arc::new(manifest), Arc::new(chunks));
+            self.run(move |trx, _| {
+                let (key, manifest, chunks) = (key.clone(), manifest.clone(), chunks.clone());
+                async move { DataModel::write_in_trx(&trx, &key, &manifest, &chunks).await }
+            })
+            .await?;
+            return Ok(());
+        }
+
+        // Too large for one transaction: stage the chunks under a fresh
+        // generation, then flip the manifest so readers see either the
+        // complete old value or the complete new one.
+        DataModel::stage_chunks(self, key, &manifest, chunks).await?;
+        DataModel::commit_manifest(self, key, &manifest, Expected::Any).await?;
         Ok(())
     }
 
-    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, FdbBindingError> {
-        let result = DataModel::reconstruct_bloc(self, key).await?;
-        if result.is_empty() {
-            Ok(None)
-        } else {
-            Ok(Some(result))
+    /// Write `value` at `key` unless a value is already stored there.
+    /// Returns whether the value was written.
+    pub async fn set_if_absent(&self, key: &[u8], value: &[u8]) -> Result<bool> {
+        if value.len() > MAX_VALUE_LEN {
+            return Err(Error::ValueTooLarge {
+                size: value.len(),
+                max: MAX_VALUE_LEN,
+            });
         }
+        DataModel::write_if_absent(self, key, value).await
+    }
+
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        DataModel::read_value(self, key).await
+    }
+
+    /// Write the value read from `reader` at `key` without holding it in memory.
+    /// Readers keep seeing the previous value until the whole stream is stored.
+    /// Streamed values are stored uncompressed.
+    /// Returns the number of bytes written.
+    pub async fn set_stream<R: AsyncRead + Unpin>(&self, key: &[u8], reader: R) -> Result<u64> {
+        let manifest = DataModel::stage_stream(self, key, reader).await?;
+        DataModel::commit_manifest(self, key, &manifest, Expected::Any).await?;
+        Ok(manifest.value_len)
+    }
+
+    /// Read the values stored at `keys` along with the raw records at
+    /// `records`, all at a single read version. Values are fetched in
+    /// parallel, and so are the chunks of each.
+    pub async fn get_many(
+        &self,
+        keys: &[Vec<u8>],
+        records: &[Vec<u8>],
+    ) -> Result<(Vec<Option<Vec<u8>>>, Vec<Option<Vec<u8>>>)> {
+        DataModel::read_many(self, keys, records).await
     }
 
-    pub async fn delete(&self, key: &[u8]) -> Result<i64, FdbBindingError> {
+    /// Write every pair of `pairs` so that they all become visible at once.
+    ///
+    /// `guard` runs first in the transaction writing them, where it may add
+    /// writes of its own; nothing is written when it returns false. Values
+    /// too large to share one transaction are staged beforehand.
+    /// Returns whether the pairs were written.
+    pub async fn set_many<F, Fut>(&self, pairs: &[(Vec<u8>, Vec<u8>)], guard: F) -> Result<bool>
+    where
+        F: Fn(Transaction) -> Fut,
+        Fut: Future<Output = std::result::Result<bool, FdbBindingError>>,
+    {
+        if let Some((_, value)) = pairs.iter().find(|(_, v)| v.len() > MAX_VALUE_LEN) {
+            return Err(Error::ValueTooLarge {
+                size: value.len(),
+                max: MAX_VALUE_LEN,
+            });
+        }
+        DataModel::write_many(self, pairs, guard).await
+    }
+
+    /// Read the bytes of the value stored at `key` that `range` picks given
+    /// the length of the value, fetching only the chunks holding them.
+    /// Ends past the value are clamped to it.
+    pub async fn get_range<F>(&self, key: &[u8], range: F) -> Result<Option<Vec<u8>>>
+    where
+        F: Fn(u64) -> Range<u64>,
+    {
+        DataModel::read_range(self, key, range).await
+    }
+
+    /// Length of the value stored at `key`, read from its manifest.
+    pub async fn value_len(&self, key: &[u8]) -> Result<Option<u64>> {
+        DataModel::value_len(self, key).await
+    }
+
+    /// Overwrite the value stored at `key` from byte `offset` with `data`,
+    /// zero-padding it up to `offset` when shorter, and creating it when missing.
+    /// Only the chunks the write overlaps are rewritten. Writes too large for
+    /// one transaction rewrite the whole value instead, and must not race
+    /// with other writers of `key`.
+    /// Returns the new length of the value.
+    pub async fn set_range(&self, key: &[u8], offset: u64, data: &[u8]) -> Result<u64> {
+        DataModel::write_range(self, key, Some(offset), data).await
+    }
+
+    /// Append `data` to the value stored at `key`, creating it when missing.
+    /// Only the last chunk is rewritten, under the same caveats as `set_range`.
+    /// Returns the new length of the value.
+    pub async fn append(&self, key: &[u8], data: &[u8]) -> Result<u64> {
+        DataModel::write_range(self, key, None, data).await
+    }
+
+    /// Update bytes `range` of the value stored at `key` in one go: `update`
+    /// gets the bytes the value holds there, fewer past its end, and may
+    /// change or extend them. Changed bytes are written back from
+    /// `range.start` under the same caveats as `set_range`; a missing value
+    /// reads as empty.
+    /// Returns what `update` returned.
+    pub async fn update_range<F, T>(&self, key: &[u8], range: Range<u64>, update: F) -> Result<T>
+    where
+        F: Fn(&mut Vec<u8>) -> T,
+        T: Clone,
+    {
+        DataModel::update_range(self, key, range, update).await
+    }
+
+    /// Read the value stored at `key` chunk by chunk.
+    pub async fn get_stream(&self, key: &[u8]) -> Result<Option<ValueStream>> {
+        DataModel::read_stream(self, key).await
+    }
+
+    /// Read the whole value stored at `key`, checking every chunk against its
+    /// manifest without keeping the value in memory.
+    /// Returns the length of the value, or `None` when there is no value.
+    pub async fn verify(&self, key: &[u8]) -> Result<Option<u64>> {
+        let Some(mut stream) = self.get_stream(key).await? else {
+            return Ok(None);
+        };
+        while let Some(chunk) = stream.next().await {
+            chunk?;
+        }
+        Ok(Some(stream.len()))
+    }
+
+    /// Read the manifest of the value stored at `key`.
+    pub async fn manifest(&self, key: &[u8]) -> Result<Option<Manifest>> {
+        DataModel::read_manifest(self, key).await
+    }
+
+    /// Rewrite the value stored at `key` under the active encryption key,
+    /// or in clear when encryption is disabled.
+    /// Returns whether the value needed rewriting.
+    pub async fn reencrypt(&self, key: &[u8]) -> Result<bool> {
+        DataModel::reencrypt_value(self, key).await
+    }
+
+    /// Read the value stored at `key` inside an existing transaction.
+    pub async fn get_in_trx(
+        &self,
+        trx: &Transaction,
+        key: &[u8],
+    ) -> std::result::Result<Option<Vec<u8>>, FdbBindingError> {
+        DataModel::get_in_trx(self, trx, key).await
+    }
+
+    /// Write `value` at `key` inside an existing transaction.
+    /// The encoded value must fit within the transaction size limit.
+    pub async fn set_in_trx(
+        &self,
+        trx: &Transaction,
+        key: &[u8],
+        value: &[u8],
+    ) -> std::result::Result<(), FdbBindingError> {
+        DataModel::set_in_trx(self, trx, key, value).await
+    }
+
+    /// Clear the value stored at `key` inside an existing transaction.
+    pub async fn clear_in_trx(
+        trx: &Transaction,
+        key: &[u8],
+    ) -> std::result::Result<(), FdbBindingError> {
+        DataModel::clear_in_trx(trx, key).await
+    }
+
+    /// Remove the value stored at `key` inside an existing transaction.
+    ///
+    /// A value of more than `max_chunks` chunks only loses its manifest, so
+    /// the transaction stays small; its manifest is returned and the chunks
+    /// left behind must be cleared with `clear_detached` once it committed.
+    /// Chunks never cleared are collected like any unreferenced generation.
+    pub async fn detach_in_trx(
+        trx: &Transaction,
+        key: &[u8],
+        max_chunks: u64,
+    ) -> std::result::Result<Option<Manifest>, FdbBindingError> {
+        DataModel::detach_in_trx(trx, key, max_chunks).await
+    }
+
+    /// Clear the chunks of a value detached with `detach_in_trx`, in
+    /// transactions of a bounded size.
+    pub async fn clear_detached(&self, key: &[u8], manifest: &Manifest) -> Result<()> {
+        DataModel::clear_detached(self, key, manifest).await
+    }
+
+    /// Give a manifest to a value written before manifests existed.
+    /// Returns whether the value was rewritten.
+    pub async fn adopt_legacy_value(&self, key: &[u8]) -> Result<bool> {
+        DataModel::adopt_legacy_value(self, key).await
+    }
+
+    pub async fn delete(&self, key: &[u8]) -> Result<i64> {
         DataModel::clean_chunks(self, key).await?;
         Ok(1)
     }
 
+    /// Apply an atomic mutation to the raw record at `key`.
+    pub async fn mutate(&self, key: &[u8], mutation: Mutation) -> Result<()> {
+        self.mutate_many(&[(key.to_vec(), mutation)]).await
+    }
+
+    /// Apply several atomic mutations in one transaction.
+    pub async fn mutate_many(&self, mutations: &[(Vec<u8>, Mutation)]) -> Result<()> {
+        self.run_once(|trx| async move {
+            for (key, mutation) in mutations {
+                mutation.apply(&trx, key);
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Add `delta` to the counter at `key` without conflicting with other writers.
+    pub async fn add(&self, key: &[u8], delta: i64) -> Result<()> {
+        self.mutate(key, Mutation::Add(delta)).await
+    }
+
+    /// Read the counter at `key`, zero when it was never written.
+    pub async fn counter(&self, key: &[u8]) -> Result<i64> {
+        let raw = self
+            .run(|trx, _| async move { Ok(trx.get(key, false).await?) })
+            .await?;
+        Ok(raw.map(|v| atomic::decode_i64(&v)).unwrap_or(0))
+    }
+
+    /// Write `value` at `key`, with the commit versionstamp substituted for the
+    /// placeholder at `offset` in the key.
+    pub async fn set_versionstamped_key(&self, key: &[u8], offset: usize, value: &[u8]) -> Result<()> {
+        self.run_once(|trx| async move {
+            atomic::set_versionstamped_key(&trx, key, offset, value)
+                .map_err(|e| FdbBindingError::CustomError(Box::new(e)))
+        })
+        .await
+    }
+
+    /// Write `value` at `key`, with the commit versionstamp substituted for the
+    /// placeholder at `offset` in the value.
+    pub async fn set_versionstamped_value(&self, key: &[u8], value: &[u8], offset: usize) -> Result<()> {
+        self.run_once(|trx| async move {
+            atomic::set_versionstamped_value(&trx, key, value, offset)
+                .map_err(|e| FdbBindingError::CustomError(Box::new(e)))
+        })
+        .await
+    }
+
+    /// Read the first page of `[start, end)` with the default scan options.
     pub async fn scan(
         &self,
         start: &[u8],
         end: &[u8],
-    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, FdbBindingError> {
-        let kvs = self
-            .database
-            .run(|trx, _| async move {
-                let range = RangeOption::from((start, end));
-                let stream = trx.get_ranges_keyvalues(range, false);
-                collect_stream(stream).await
-            })
-            .await?;
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let page = self.scan_page(start, end, &ScanOptions::default(), None).await?;
+        Ok(page.kvs)
+    }
+
+    /// Read one page of `[start, end)`, resuming after `from` when given.
+    /// A token from a scan run at one read version reads at that version.
+    pub async fn scan_page(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        options: &ScanOptions,
+        from: Option<&Continuation>,
+    ) -> Result<ScanPage> {
+        let mut trx = self.create_trx()?;
+        let pinned = from.and_then(|c| c.read_version());
+        if let Some(version) = pinned {
+            trx.set_read_version(version);
+        }
+        loop {
+            let read_version = match (pinned, options.single_read_version) {
+                (Some(version), _) => Some(version),
+                (None, true) => Some(trx.get_read_version().await?),
+                (None, false) => None,
+            };
+            match scan::read_page(&trx, start, end, options, from, read_version).await {
+                Ok(page) => return Ok(page),
+                // A pinned version cannot be retried: it only gets older
+                Err(e) if pinned.is_some() => return Err(e.into()),
+                Err(e) => trx = trx.on_error(e).await?,
+            }
+        }
+    }
+
+    /// Read one page of `[start, end)` inside an existing transaction.
+    pub async fn scan_in_trx(
+        trx: &Transaction,
+        start: &[u8],
+        end: &[u8],
+        options: &ScanOptions,
+        from: Option<&Continuation>,
+    ) -> std::result::Result<ScanPage, FdbBindingError> {
+        Ok(scan::read_page(trx, start, end, options, from, None).await?)
+    }
 
-        Ok(kvs)
+    /// Wait until the raw record at `key` changes and return its new value.
+    ///
+    /// Like atomic mutations, watches see raw records: a value written with
+    /// `set` changes whenever its manifest at `key` is replaced. Several
+    /// changes in quick succession may be reported as one, and a change
+    /// reverted before the watch fires may go unnoticed.
+    pub async fn watch(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        watch::changed(self, key, None).await
     }
 
+    /// Stream the successive values of the raw record at `key`, starting with
+    /// the first change after the stream is polled.
+    pub fn watch_stream(
+        &self,
+        key: &[u8],
+    ) -> impl Stream<Item = Result<Option<Vec<u8>>>> + Send + 'static {
+        let fdb = self.clone();
+        let key = key.to_vec();
+        async_stream::try_stream! {
+            let mut baseline = None;
+            loop {
+                let value = watch::changed(&fdb, &key, baseline).await?;
+                baseline = Some(value.clone());
+                yield value;
+            }
+        }
+    }
+
+    /// Stream every record of `[start, end)`, `scan::BULK_LIMIT` records per
+    /// transaction.
     pub async fn full_scan(
         &self,
         start: &[u8],
         end: &[u8],
-    ) -> impl Stream<Item = Result<(Vec<u8>, Vec<u8>), FdbBindingError>> {
-        let mut start = start.to_vec();
+    ) -> impl Stream<Item = Result<(Vec<u8>, Vec<u8>)>> {
+        self.scan_stream(start, end, ScanOptions::bulk(scan::BULK_LIMIT))
+    }
+
+    /// Stream every record of `[start, end)`, one page at a time.
+    pub fn scan_stream(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        options: ScanOptions,
+    ) -> impl Stream<Item = Result<(Vec<u8>, Vec<u8>)>> + Send + 'static {
+        let fdb = self.clone();
+        let (start, end) = (start.to_vec(), end.to_vec());
         async_stream::try_stream! {
+            let mut from = None;
             loop {
-                let kvs = self.scan(&start, end).await?;
-                if kvs.is_empty() {
-                    break;
-                }
-                if let Some(kv) = kvs.last() {
-                    start = kv.0.to_vec();
-                    start.push(0xff);
-                }
-                for kv in kvs {
+                let page = fdb.scan_page(&start, &end, &options, from.as_ref()).await?;
+                for kv in page.kvs {
                     yield kv;
                 }
+                match page.continuation {
+                    Some(next) => from = Some(next),
+                    None => break,
+                }
             }
         }
     }
 }
 
-async fn collect_stream<S>(stream: S) -> Result<Vec<(Vec<u8>, Vec<u8>)>, FdbBindingError>
-where
-    S: futures_util::Stream<Item = foundationdb::FdbResult<FdbValue>>,
-{
-    let records = stream
-        .map(|x| match x {
-            Ok(value) => {
-                let data = (value.key().to_vec(), value.value().to_vec());
-                Ok(data)
-            }
-            Err(err) => Err(err),
-        })
-        .take(20)
-        .try_collect::<Vec<(Vec<u8>, Vec<u8>)>>()
-        .await?;
-    Ok(records)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use fdb_testcontainer::get_db_once;
+    use foundationdb::options::StreamingMode;
     use foundationdb_tuple::pack;
+    use futures_util::TryStreamExt;
 
     const MAX_SCAN_SIZE: usize = 20;
 
@@ -123,6 +710,328 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[tokio::test]
+    async fn test_set_stream_get_stream() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+
+        // spans several write transactions and read batches
+        let data = (0..MAX_TRANSACTION_SIZE + 3 * 1000 * 1000)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let written = db
+            .set_stream(b"stream_key", &data[..])
+            .await
+            .expect("Unable to stream value");
+        assert_eq!(written, data.len() as u64);
+        assert_eq!(db.get(b"stream_key").await.expect("get"), Some(data.clone()));
+
+        let stream = db
+            .get_stream(b"stream_key")
+            .await
+            .expect("Unable to open stream")
+            .expect("missing value");
+        assert_eq!(stream.len(), data.len() as u64);
+        let chunks = stream
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("Unable to read stream");
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), data);
+
+        db.delete(b"stream_key").await.expect("Unable to delete key");
+        assert!(db.get_stream(b"stream_key").await.expect("get_stream").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compressed_values() {
+        let _guard = get_db_once().await;
+        let value = b"<html><body>cached page</body></html>".repeat(100_000);
+        for codec in [codec::Codec::Zstd, codec::Codec::Lz4] {
+            let db = FoundationDB::new(_guard.clone()).with_compression(Compression {
+                codec,
+                min_size: 1024,
+            });
+            let key = format!("compressed_{}", codec);
+            db.set(key.as_bytes(), &value).await.expect("Unable to set value");
+
+            let manifest = db
+                .manifest(key.as_bytes())
+                .await
+                .expect("Unable to read manifest")
+                .expect("missing manifest");
+            assert_eq!(manifest.codec, codec);
+            assert_eq!(manifest.value_len, value.len() as u64);
+            assert!(manifest.total_len < manifest.value_len);
+
+            // readers without compression configured still decode it
+            let plain = FoundationDB::new(_guard.clone());
+            let read = plain.get(key.as_bytes()).await.expect("Unable to get value");
+            assert_eq!(read, Some(value.clone()));
+            let stream = plain
+                .get_stream(key.as_bytes())
+                .await
+                .expect("Unable to open stream")
+                .expect("missing value");
+            assert_eq!(stream.len(), value.len() as u64);
+            let chunks = stream
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("Unable to read stream");
+            assert_eq!(chunks.concat(), value);
+            db.delete(key.as_bytes()).await.expect("Unable to delete key");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_encrypted_values() {
+        let _guard = get_db_once().await;
+        const KEY_1: &str = "1 aes-256-gcm 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        const KEY_2: &str =
+            "2 chacha20-poly1305 1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+        let keyring = |text: &str| Keyring::parse(text).expect("Unable to parse keyring");
+
+        // spans several chunks and is staged across transactions
+        let value = (0..MAX_TRANSACTION_SIZE + 250_000)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        let db = FoundationDB::new(_guard.clone()).with_encryption(keyring(KEY_1));
+        db.set(b"encrypted", &value).await.expect("Unable to set value");
+        db.set(b"encrypted_small", b"secret").await.expect("Unable to set value");
+
+        let manifest = db
+            .manifest(b"encrypted")
+            .await
+            .expect("Unable to read manifest")
+            .expect("missing manifest");
+        assert_eq!(manifest.cipher, crypto::Cipher::Aes256Gcm);
+        assert_eq!(manifest.key_id, 1);
+        assert_eq!(manifest.value_len, value.len() as u64);
+        assert_eq!(db.get(b"encrypted").await.expect("get"), Some(value.clone()));
+        assert_eq!(db.verify(b"encrypted").await.expect("verify"), Some(value.len() as u64));
+        assert_eq!(
+            db.get(b"encrypted_small").await.expect("get"),
+            Some(b"secret".to_vec())
+        );
+
+        // readers without the key cannot decrypt
+        let plain = FoundationDB::new(_guard.clone());
+        assert!(matches!(plain.get(b"encrypted").await, Err(Error::UnknownKey(1))));
+
+        // after a rotation, values are readable with the old key and rewritten
+        // under the new one
+        let rotated =
+            FoundationDB::new(_guard.clone()).with_encryption(keyring(&format!("{}\n{}", KEY_1, KEY_2)));
+        assert!(rotated.reencrypt(b"encrypted").await.expect("reencrypt"));
+        assert!(!rotated.reencrypt(b"encrypted").await.expect("reencrypt"));
+        let manifest = rotated
+            .manifest(b"encrypted")
+            .await
+            .expect("Unable to read manifest")
+            .expect("missing manifest");
+        assert_eq!(manifest.cipher, crypto::Cipher::ChaCha20Poly1305);
+        assert_eq!(manifest.key_id, 2);
+        let only_new = FoundationDB::new(_guard.clone()).with_encryption(keyring(KEY_2));
+        assert_eq!(only_new.get(b"encrypted").await.expect("get"), Some(value));
+
+        // rewriting needs the key a value was written with
+        assert!(plain.reencrypt(b"encrypted_small").await.is_err());
+        assert!(!plain.reencrypt(b"missing").await.expect("reencrypt"));
+
+        db.delete(b"encrypted").await.expect("Unable to delete key");
+        db.delete(b"encrypted_small").await.expect("Unable to delete key");
+    }
+
+    #[tokio::test]
+    async fn test_atomic_mutations() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        let raw = |key: &'static [u8]| {
+            let db = db.clone();
+            async move {
+                db
+                    .run(move |trx, _| async move { Ok(trx.get(key, false).await?) })
+                    .await
+                    .expect("Unable to read record")
+            }
+        };
+
+        // concurrent adds never conflict
+        let adds = (0..20).map(|_| db.add(b"atomic_counter", 3));
+        futures::future::try_join_all(adds).await.expect("Unable to add");
+        db.add(b"atomic_counter", -10).await.expect("Unable to add");
+        assert_eq!(db.counter(b"atomic_counter").await.expect("counter"), 50);
+        assert_eq!(db.counter(b"atomic_missing").await.expect("counter"), 0);
+
+        db.mutate_many(&[
+            (b"atomic_min".to_vec(), Mutation::Max(7)),
+            (b"atomic_max".to_vec(), Mutation::Max(7)),
+        ])
+        .await
+        .expect("Unable to mutate");
+        db.mutate(b"atomic_min", Mutation::Min(3)).await.expect("min");
+        db.mutate(b"atomic_max", Mutation::Max(5)).await.expect("max");
+        assert_eq!(raw(b"atomic_min").await, Some(3u64.to_le_bytes().to_vec()));
+        assert_eq!(raw(b"atomic_max").await, Some(7u64.to_le_bytes().to_vec()));
+
+        db.mutate(b"atomic_bits", Mutation::BitOr(vec![0b1100])).await.expect("or");
+        db.mutate(b"atomic_bits", Mutation::BitAnd(vec![0b0110])).await.expect("and");
+        db.mutate(b"atomic_bits", Mutation::BitXor(vec![0b0011])).await.expect("xor");
+        assert_eq!(raw(b"atomic_bits").await, Some(vec![0b0111]));
+
+        db.mutate(b"atomic_bytes", Mutation::ByteMax(b"m".to_vec())).await.expect("byte_max");
+        db.mutate(b"atomic_bytes", Mutation::ByteMin(b"b".to_vec())).await.expect("byte_min");
+        db.mutate(b"atomic_bytes", Mutation::ByteMax(b"a".to_vec())).await.expect("byte_max");
+        assert_eq!(raw(b"atomic_bytes").await, Some(b"b".to_vec()));
+
+        db.mutate(b"atomic_log", Mutation::AppendIfFits(b"ab".to_vec())).await.expect("append");
+        db.mutate(b"atomic_log", Mutation::AppendIfFits(b"cd".to_vec())).await.expect("append");
+        assert_eq!(raw(b"atomic_log").await, Some(b"abcd".to_vec()));
+        db.mutate(b"atomic_log", Mutation::CompareAndClear(b"ab".to_vec())).await.expect("cac");
+        assert_eq!(raw(b"atomic_log").await, Some(b"abcd".to_vec()));
+        db.mutate(b"atomic_log", Mutation::CompareAndClear(b"abcd".to_vec())).await.expect("cac");
+        assert_eq!(raw(b"atomic_log").await, None);
+
+        // versionstamps are substituted at commit time
+        let placeholder = [0xFFu8; atomic::VERSIONSTAMP_LEN];
+        let key = [b"atomic_vs_".as_slice(), &placeholder].concat();
+        db.set_versionstamped_key(&key, 10, b"stamped").await.expect("versionstamped key");
+        let (start, end) = (b"atomic_vs_".to_vec(), b"atomic_vs`".to_vec());
+        let stamped = db.scan(&start, &end).await.expect("Unable to scan");
+        assert_eq!(stamped.len(), 1);
+        assert_ne!(&stamped[0].0[10..], &placeholder);
+        db.set_versionstamped_value(b"atomic_vs_value", &placeholder, 0)
+            .await
+            .expect("versionstamped value");
+        let value = raw(b"atomic_vs_value").await.expect("missing value");
+        assert_eq!(value.len(), atomic::VERSIONSTAMP_LEN);
+        assert_ne!(value, placeholder);
+        assert!(matches!(
+            db.set_versionstamped_value(b"atomic_vs_value", b"short", 0).await,
+            Err(Error::InvalidVersionstampOffset(0))
+        ));
+        let (_, committed) = db
+            .run_once_versionstamped(|trx| async move {
+                atomic::set_versionstamped_value(&trx, b"atomic_vs_run", &placeholder, 0)
+                    .map_err(|e| FdbBindingError::CustomError(Box::new(e)))
+            })
+            .await
+            .expect("versionstamped run");
+        assert_eq!(raw(b"atomic_vs_run").await, Some(committed.to_vec()));
+
+        db.run(|trx, _| async move {
+            trx.clear_range(b"atomic_", b"atomic`");
+            Ok(())
+        })
+        .await
+        .expect("Unable to clean up");
+    }
+
+    #[tokio::test]
+    async fn test_transaction_options() {
+        use foundationdb::FdbError;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+
+        // only retryable errors are retried, up to the limit
+        let attempts = AtomicUsize::new(0);
+        let limited = db.clone().with_retry_limit(Some(2));
+        let res = limited
+            .run(|_, _| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err::<(), _>(FdbError::from_code(1020).into()) }
+            })
+            .await;
+        assert!(Error::from(res.unwrap_err()).is_retryable());
+        assert_eq!(attempts.swap(0, Ordering::SeqCst), 3);
+
+        let res = db
+            .run(|_, _| {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                async { Err::<(), _>(FdbError::from_code(2000).into()) }
+            })
+            .await;
+        assert!(!Error::from(res.unwrap_err()).is_retryable());
+        assert_eq!(attempts.swap(0, Ordering::SeqCst), 1);
+
+        // the timeout covers the whole transaction
+        let res = db
+            .clone()
+            .with_timeout(std::time::Duration::from_millis(50))
+            .run(|trx, _| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                trx.set(b"options_timeout", b"value");
+                Ok(())
+            })
+            .await;
+        assert!(res.is_err());
+
+        // an unknown outcome that did not commit is run again, once
+        db.run(|trx, _| async move {
+            trx.clear(b"options_once");
+            Ok(())
+        })
+        .await
+        .expect("Unable to reset record");
+        db.run_once(|trx| {
+            let attempt = attempts.fetch_add(1, Ordering::SeqCst);
+            async move {
+                Mutation::Add(5).apply(&trx, b"options_once");
+                if attempt == 0 {
+                    return Err(FdbError::from_code(1021).into());
+                }
+                Ok(())
+            }
+        })
+        .await
+        .expect("Unable to add");
+        assert_eq!(attempts.load(Ordering::SeqCst), 2);
+        assert_eq!(db.counter(b"options_once").await.expect("counter"), 5);
+    }
+
+    #[tokio::test]
+    async fn test_watch() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone()).with_max_watches(1);
+        db.run(|trx, _| async move {
+            trx.set(b"watch_key", &1i64.to_le_bytes());
+            Ok(())
+        })
+        .await
+        .expect("Unable to reset record");
+
+        let watcher = db.clone();
+        let fired = tokio::spawn(async move { watcher.watch(b"watch_key").await });
+        tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+        assert!(!fired.is_finished());
+        db.add(b"watch_key", 1).await.expect("add");
+        let value = fired.await.expect("join").expect("watch");
+        assert_eq!(value, Some(2i64.to_le_bytes().to_vec()));
+
+        // a cancelled watch gives its permit back
+        let cancelled = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            db.watch(b"watch_key"),
+        )
+        .await;
+        assert!(cancelled.is_err());
+
+        let mut changes = Box::pin(db.watch_stream(b"watch_key"));
+        let writer = db.clone();
+        tokio::spawn(async move {
+            for _ in 0..2 {
+                tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+                writer.add(b"watch_key", 1).await.expect("add");
+            }
+        });
+        let first = changes.next().await.expect("change").expect("watch");
+        assert_eq!(first, Some(3i64.to_le_bytes().to_vec()));
+        let second = changes.next().await.expect("change").expect("watch");
+        assert_eq!(second, Some(4i64.to_le_bytes().to_vec()));
+    }
+
     #[tokio::test]
     async fn test_scan() {
         let _guard = get_db_once().await;
@@ -138,7 +1047,8 @@ mod tests {
             .await
             .expect("Unable to set key3");
         let result = db.scan(b"key1", b"key3").await.expect("Unable to scan");
-        assert_eq!(result.len(), 2);
+        // each value is a manifest followed by its single chunk
+        assert_eq!(result.len(), 4);
     }
 
     #[tokio::test]
@@ -160,6 +1070,117 @@ mod tests {
         assert_eq!(result.len(), MAX_SCAN_SIZE);
     }
 
+    #[tokio::test]
+    async fn test_scan_pages() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        db.run(|trx, _| async move {
+            for i in 0..25u64 {
+                trx.set(&pack(&("scan_pages", i)), &i.to_be_bytes());
+            }
+            Ok(())
+        })
+        .await
+        .expect("Unable to write records");
+        let (start, end) = (pack(&("scan_pages", 0u64)), pack(&("scan_pages", 25u64)));
+        let read = |kvs: &[(Vec<u8>, Vec<u8>)]| {
+            kvs.iter()
+                .map(|(_, v)| u64::from_be_bytes(v.as_slice().try_into().unwrap()))
+                .collect::<Vec<_>>()
+        };
+
+        for (reverse, mode) in [(false, StreamingMode::WantAll), (true, StreamingMode::Iterator)] {
+            let options = ScanOptions {
+                limit: 10,
+                reverse,
+                mode,
+                snapshot: true,
+                ..ScanOptions::default()
+            };
+            let mut seen = Vec::new();
+            let mut from: Option<Continuation> = None;
+            let mut pages = 0;
+            loop {
+                let page = db
+                    .scan_page(&start, &end, &options, from.as_ref())
+                    .await
+                    .expect("Unable to scan");
+                assert!(page.kvs.len() <= 10);
+                seen.extend(read(&page.kvs));
+                pages += 1;
+                match page.continuation {
+                    // tokens survive a round trip through a client
+                    Some(next) => from = Some(Continuation::decode(&next.encode()).expect("decode")),
+                    None => break,
+                }
+            }
+            let mut expected = (0..25).collect::<Vec<_>>();
+            if reverse {
+                expected.reverse();
+            }
+            assert_eq!(seen, expected);
+            assert_eq!(pages, 3);
+
+            let streamed = db
+                .scan_stream(&start, &end, options)
+                .try_collect::<Vec<_>>()
+                .await
+                .expect("Unable to stream scan");
+            assert_eq!(read(&streamed), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_single_read_version() {
+        let _guard = get_db_once().await;
+        let db = FoundationDB::new(_guard.clone());
+        let write = |i: u64| {
+            let db = db.clone();
+            async move {
+                db
+                    .run(move |trx, _| async move {
+                        trx.set(&pack(&("scan_version", i)), b"v");
+                        Ok(())
+                    })
+                    .await
+            }
+        };
+        for i in 0..4 {
+            write(i * 2).await.expect("Unable to write record");
+        }
+        let (start, end) = (pack(&("scan_version", 0u64)), pack(&("scan_version", 100u64)));
+
+        let options = ScanOptions {
+            limit: 2,
+            single_read_version: true,
+            ..ScanOptions::default()
+        };
+        let first = db
+            .scan_page(&start, &end, &options, None)
+            .await
+            .expect("Unable to scan");
+        let from = first.continuation.expect("missing continuation");
+        assert!(from.read_version().is_some());
+
+        // written after the scan started, past the first page
+        write(5).await.expect("Unable to write record");
+        let second = db
+            .scan_page(&start, &end, &options, Some(&from))
+            .await
+            .expect("Unable to scan");
+        assert_eq!(second.kvs.len(), 2);
+        assert_eq!(second.kvs[0].0, pack(&("scan_version", 4u64)));
+        assert_eq!(second.kvs[1].0, pack(&("scan_version", 6u64)));
+
+        // an unpinned scan sees it
+        let all = db
+            .scan_stream(&start, &end, ScanOptions::default())
+            .try_collect::<Vec<_>>()
+            .await
+            .expect("Unable to stream scan");
+        assert_eq!(all.len(), 5);
+    }
+
     #[tokio::test]
     async fn test_full_scan() {
         let _guard = get_db_once().await;
@@ -176,6 +1197,7 @@ mod tests {
         let end = pack(&("key", &100));
 
         let result = db.full_scan(&start, &end).await.collect::<Vec<_>>().await;
-        assert_eq!(result.len(), 100);
+        // each value is a manifest followed by its single chunk
+        assert_eq!(result.len(), 200);
     }
 }
@@ -0,0 +1,476 @@
+use crate::codec::Codec;
+use crate::crypto::{Cipher, NONCE_LEN};
+use foundationdb_tuple::Subspace;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Layout revision of the encoded manifest.
+/// - 1: chunks stored directly below the root key.
+/// - 2: chunks stored below a generation subspace.
+/// - 3: chunks may hold a compressed value.
+/// - 4: the checksum of every chunk follows the versionstamp.
+/// - 5: chunks may be encrypted.
+const MANIFEST_VERSION: u8 = 5;
+
+/// Size of a FoundationDB versionstamp.
+const VERSIONSTAMP_LEN: usize = 10;
+
+/// Offset of the versionstamp in each encoded manifest revision.
+const V1_VERSIONSTAMP_OFFSET: usize = 1 + 4 + 4 + 8 + 4;
+const V2_VERSIONSTAMP_OFFSET: usize = V1_VERSIONSTAMP_OFFSET + 8;
+const V3_VERSIONSTAMP_OFFSET: usize = V2_VERSIONSTAMP_OFFSET + 1 + 8;
+const VERSIONSTAMP_OFFSET: usize = V3_VERSIONSTAMP_OFFSET + 1 + 4 + NONCE_LEN;
+
+/// Tuple element separating generation subspaces from legacy chunk indexes.
+const GENERATION_TAG: &str = "g";
+
+/// Low bits of a generation id drawn at random; the high bits hold its
+/// creation time in milliseconds.
+const GENERATION_RANDOM_BITS: u32 = 20;
+
+/// Header stored at the root key of every chunked value.
+///
+/// Chunks live at `key + ("g", generation, i)` for `i` in `0..chunk_count`;
+/// everything but the last chunk holds exactly `chunk_size` bytes of the
+/// value, plus the cipher's overhead when encrypted. Values
+/// written before generations existed have generation 0 and keep their
+/// chunks at `key + (i,)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub chunk_size: u32,
+    pub chunk_count: u32,
+    /// Length of the stored, possibly compressed, bytes.
+    pub total_len: u64,
+    /// CRC32C of the stored bytes.
+    pub checksum: u32,
+    /// Generation holding the chunks of this value.
+    pub generation: u64,
+    /// Compression applied to the value before chunking.
+    pub codec: Codec,
+    /// Length of the value as seen by clients.
+    pub value_len: u64,
+    /// Cipher protecting each chunk.
+    pub cipher: Cipher,
+    /// Id of the key the chunks were encrypted with.
+    pub key_id: u32,
+    /// Nonce from which the nonce of each chunk is derived.
+    pub nonce: [u8; NONCE_LEN],
+    /// Commit versionstamp of the transaction that wrote the manifest.
+    pub write_version: [u8; VERSIONSTAMP_LEN],
+    /// CRC32C of each chunk; empty for manifests written before revision 4.
+    pub chunk_checksums: Vec<u32>,
+}
+
+impl Manifest {
+    pub fn for_chunks(chunks: &[Vec<u8>], chunk_size: usize) -> Self {
+        let mut manifest = Self {
+            chunk_size: chunk_size as u32,
+            chunk_count: 0,
+            total_len: 0,
+            checksum: 0,
+            generation: 0,
+            codec: Codec::None,
+            value_len: 0,
+            cipher: Cipher::None,
+            key_id: 0,
+            nonce: [0; NONCE_LEN],
+            write_version: [0; VERSIONSTAMP_LEN],
+            chunk_checksums: Vec::new(),
+        };
+        for chunk in chunks {
+            manifest.push_chunk(chunk);
+        }
+        manifest
+    }
+
+    /// Account for the stored bytes of the chunk appended after the chunks
+    /// already listed.
+    pub fn push_chunk(&mut self, chunk: &[u8]) {
+        self.checksum = crc32c::crc32c_append(self.checksum, chunk);
+        self.chunk_checksums.push(crc32c::crc32c(chunk));
+        self.total_len += chunk.len() as u64;
+        self.chunk_count += 1;
+        if self.codec == Codec::None {
+            self.value_len += chunk.len().saturating_sub(self.cipher.overhead()) as u64;
+        }
+    }
+
+    /// Account for `chunks` taking the place of the stored chunks from index
+    /// `first` on, one for one, those past the last chunk being appended.
+    /// The checksum of the whole value is combined from the per-chunk ones,
+    /// so the chunks left in place need not be read. Only meant for
+    /// uncompressed, unencrypted values with per-chunk checksums.
+    pub fn replace_chunks(&mut self, first: usize, chunks: &[Vec<u8>]) {
+        let size = self.chunk_size as u64;
+        let count = self.chunk_count as usize;
+        let mut lens = vec![size; count];
+        if let Some(last) = lens.last_mut() {
+            *last = self.total_len - (count as u64 - 1) * size;
+        }
+        for (i, chunk) in (first..).zip(chunks) {
+            let checksum = crc32c::crc32c(chunk);
+            match self.chunk_checksums.get_mut(i) {
+                Some(stored) => {
+                    *stored = checksum;
+                    lens[i] = chunk.len() as u64;
+                }
+                None => {
+                    self.chunk_checksums.push(checksum);
+                    lens.push(chunk.len() as u64);
+                }
+            }
+        }
+        self.chunk_count = lens.len() as u32;
+        self.total_len = lens.iter().sum();
+        self.value_len = self.total_len;
+        self.checksum = self
+            .chunk_checksums
+            .iter()
+            .zip(&lens)
+            .fold(0, |acc, (checksum, len)| crc32c::crc32c_combine(acc, *checksum, *len as usize));
+    }
+
+    pub fn with_generation(mut self, generation: u64) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// Record that the chunks hold `value_len` bytes compressed with `codec`.
+    pub fn with_codec(mut self, codec: Codec, value_len: u64) -> Self {
+        self.codec = codec;
+        self.value_len = value_len;
+        self
+    }
+
+    /// Record that chunks are encrypted with key `key_id` under `nonce`.
+    /// Must be set before any chunk is pushed.
+    pub fn with_encryption(mut self, cipher: Cipher, key_id: u32, nonce: [u8; NONCE_LEN]) -> Self {
+        self.cipher = cipher;
+        self.key_id = key_id;
+        self.nonce = nonce;
+        self
+    }
+
+    /// Draw a fresh, non-legacy generation id.
+    /// Its creation time is kept in the high bits so garbage collection can
+    /// tell generations still being staged from orphaned ones.
+    pub fn new_generation() -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let random = rand::random::<u64>() >> (64 - GENERATION_RANDOM_BITS);
+        (now << GENERATION_RANDOM_BITS) | random | 1
+    }
+
+    /// Creation time of `generation`, in milliseconds since the unix epoch.
+    pub fn generation_time_ms(generation: u64) -> u64 {
+        generation >> GENERATION_RANDOM_BITS
+    }
+
+    /// Subspace bytes under which chunk `i` is stored as `(i,)`.
+    pub fn chunk_root(&self, key: &[u8]) -> Vec<u8> {
+        if self.generation == 0 {
+            key.to_vec()
+        } else {
+            Self::generation_root(key, self.generation)
+        }
+    }
+
+    /// Subspace bytes of generation `generation` of `key`.
+    pub fn generation_root(key: &[u8], generation: u64) -> Vec<u8> {
+        Subspace::from_bytes(key)
+            .subspace(&(GENERATION_TAG, generation))
+            .bytes()
+            .to_vec()
+    }
+
+    /// Range holding chunks of values written before generations existed.
+    pub fn legacy_chunk_range(key: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let subspace = Subspace::from_bytes(key);
+        (subspace.pack(&(0,)), subspace.pack(&(u32::MAX,)))
+    }
+
+    /// Range holding the chunks of this value.
+    pub fn chunk_range(&self, key: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        if self.generation == 0 {
+            Self::legacy_chunk_range(key)
+        } else {
+            Subspace::from_bytes(Self::generation_root(key, self.generation)).range()
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(VERSIONSTAMP_OFFSET + VERSIONSTAMP_LEN);
+        out.push(MANIFEST_VERSION);
+        out.extend_from_slice(&self.chunk_size.to_le_bytes());
+        out.extend_from_slice(&self.chunk_count.to_le_bytes());
+        out.extend_from_slice(&self.total_len.to_le_bytes());
+        out.extend_from_slice(&self.checksum.to_le_bytes());
+        out.extend_from_slice(&self.generation.to_le_bytes());
+        out.push(self.codec as u8);
+        out.extend_from_slice(&self.value_len.to_le_bytes());
+        out.push(self.cipher as u8);
+        out.extend_from_slice(&self.key_id.to_le_bytes());
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.write_version);
+        for checksum in &self.chunk_checksums {
+            out.extend_from_slice(&checksum.to_le_bytes());
+        }
+        out
+    }
+
+    /// Encode as a `SetVersionstampedValue` parameter, letting FoundationDB
+    /// fill `write_version` at commit time.
+    pub(crate) fn encode_versionstamped(&self) -> Vec<u8> {
+        let mut out = self.encode();
+        out.extend_from_slice(&(VERSIONSTAMP_OFFSET as u32).to_le_bytes());
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        // Offsets of the optional fields, the versionstamp, and whether
+        // per-chunk checksums follow it
+        let (generation, codec, crypto, stamp_at, per_chunk) = match bytes.first() {
+            Some(1) => (None, None, None, V1_VERSIONSTAMP_OFFSET, false),
+            Some(2) => (Some(V1_VERSIONSTAMP_OFFSET), None, None, V2_VERSIONSTAMP_OFFSET, false),
+            Some(version @ (3 | 4)) => (
+                Some(V1_VERSIONSTAMP_OFFSET),
+                Some(V2_VERSIONSTAMP_OFFSET),
+                None,
+                V3_VERSIONSTAMP_OFFSET,
+                *version == 4,
+            ),
+            Some(5) => (
+                Some(V1_VERSIONSTAMP_OFFSET),
+                Some(V2_VERSIONSTAMP_OFFSET),
+                Some(V3_VERSIONSTAMP_OFFSET),
+                VERSIONSTAMP_OFFSET,
+                true,
+            ),
+            _ => return None,
+        };
+        if bytes.len() < stamp_at + VERSIONSTAMP_LEN {
+            return None;
+        }
+        let u32_at = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let chunk_count = u32_at(5);
+        let tail = &bytes[stamp_at + VERSIONSTAMP_LEN..];
+        let expected_tail = if per_chunk { chunk_count as usize * 4 } else { 0 };
+        if tail.len() != expected_tail {
+            return None;
+        }
+        let chunk_checksums = tail
+            .chunks_exact(4)
+            .map(|c| u32::from_le_bytes(c.try_into().unwrap()))
+            .collect();
+        let mut write_version = [0; VERSIONSTAMP_LEN];
+        write_version.copy_from_slice(&bytes[stamp_at..stamp_at + VERSIONSTAMP_LEN]);
+        let total_len = u64_at(9);
+        let (codec, value_len) = match codec {
+            Some(at) => (Codec::from_byte(bytes[at])?, u64_at(at + 1)),
+            None => (Codec::None, total_len),
+        };
+        let (cipher, key_id, nonce) = match crypto {
+            Some(at) => (
+                Cipher::from_byte(bytes[at])?,
+                u32_at(at + 1),
+                bytes[at + 5..at + 5 + NONCE_LEN].try_into().unwrap(),
+            ),
+            None => (Cipher::None, 0, [0; NONCE_LEN]),
+        };
+        Some(Self {
+            chunk_size: u32_at(1),
+            chunk_count,
+            total_len,
+            checksum: u32_at(17),
+            generation: generation.map(u64_at).unwrap_or(0),
+            codec,
+            value_len,
+            cipher,
+            key_id,
+            nonce,
+            write_version,
+            chunk_checksums,
+        })
+    }
+
+    /// Check chunk `index` on its own, before the whole value is available.
+    /// Manifests without per-chunk checksums only get checked by `verify`.
+    pub fn verify_chunk(&self, index: usize, chunk: &[u8]) -> bool {
+        if index >= self.chunk_count as usize {
+            return false;
+        }
+        let is_last = index + 1 == self.chunk_count as usize;
+        if !is_last && chunk.len() != self.chunk_size as usize + self.cipher.overhead() {
+            return false;
+        }
+        match self.chunk_checksums.get(index) {
+            Some(checksum) => *checksum == crc32c::crc32c(chunk),
+            None => self.chunk_checksums.is_empty(),
+        }
+    }
+
+    /// Check that `chunks` are exactly the chunks described by this manifest.
+    pub fn verify(&self, chunks: &[Vec<u8>]) -> bool {
+        if chunks.len() != self.chunk_count as usize {
+            return false;
+        }
+        let mut checksum = 0;
+        let mut total_len = 0;
+        for (i, chunk) in chunks.iter().enumerate() {
+            if !self.verify_chunk(i, chunk) {
+                return false;
+            }
+            checksum = crc32c::crc32c_append(checksum, chunk);
+            total_len += chunk.len() as u64;
+        }
+        total_len == self.total_len && checksum == self.checksum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_roundtrip() {
+        let chunks = vec![vec![1u8; 4], vec![2u8; 4], vec![3u8; 2]];
+        let mut manifest = Manifest::for_chunks(&chunks, 4).with_generation(42);
+        manifest.write_version = [7; VERSIONSTAMP_LEN];
+        assert_eq!(manifest.chunk_count, 3);
+        assert_eq!(manifest.total_len, 10);
+        assert_eq!(manifest.value_len, 10);
+
+        let decoded = Manifest::decode(&manifest.encode()).expect("decode");
+        assert_eq!(decoded, manifest);
+        assert!(decoded.verify(&chunks));
+    }
+
+    #[test]
+    fn test_manifest_v1_has_legacy_generation() {
+        let chunks = vec![vec![1u8; 4]];
+        let manifest = Manifest::for_chunks(&chunks, 4).with_generation(42);
+        let mut v1 = manifest.encode();
+        v1[0] = 1;
+        v1.truncate(VERSIONSTAMP_OFFSET + VERSIONSTAMP_LEN);
+        v1.drain(V1_VERSIONSTAMP_OFFSET..VERSIONSTAMP_OFFSET);
+
+        let decoded = Manifest::decode(&v1).expect("decode");
+        assert_eq!(decoded.generation, 0);
+        assert_eq!(decoded.codec, Codec::None);
+        assert_eq!(decoded.value_len, 4);
+        assert!(decoded.chunk_checksums.is_empty());
+        assert_eq!(decoded.chunk_root(b"key"), b"key".to_vec());
+        assert!(decoded.verify(&chunks));
+    }
+
+    #[test]
+    fn test_manifest_records_codec() {
+        let chunks = vec![vec![1u8; 4]];
+        let manifest = Manifest::for_chunks(&chunks, 4).with_codec(Codec::Zstd, 100);
+        let decoded = Manifest::decode(&manifest.encode()).expect("decode");
+        assert_eq!(decoded.codec, Codec::Zstd);
+        assert_eq!(decoded.total_len, 4);
+        assert_eq!(decoded.value_len, 100);
+
+        let mut unknown = manifest.encode();
+        unknown[V2_VERSIONSTAMP_OFFSET] = 0xFF;
+        assert!(Manifest::decode(&unknown).is_none());
+    }
+
+    #[test]
+    fn test_manifest_checks_each_chunk() {
+        let chunks = vec![vec![1u8; 4], vec![2u8; 4], vec![3u8; 2]];
+        let manifest = Manifest::for_chunks(&chunks, 4);
+        let decoded = Manifest::decode(&manifest.encode()).expect("decode");
+        assert_eq!(decoded.chunk_checksums.len(), 3);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert!(decoded.verify_chunk(i, chunk));
+        }
+        assert!(!decoded.verify_chunk(1, &[2u8, 2, 2, 9]));
+        // only the last chunk may be short
+        assert!(!decoded.verify_chunk(0, &[1u8; 2]));
+        assert!(!decoded.verify_chunk(3, &[]));
+
+        // a checksum list that does not match the chunk count is rejected
+        let mut truncated = manifest.encode();
+        truncated.pop();
+        assert!(Manifest::decode(&truncated).is_none());
+    }
+
+    #[test]
+    fn test_manifest_replaces_chunks() {
+        let chunks = vec![vec![1u8; 4], vec![2u8; 4], vec![3u8; 2]];
+        let mut manifest = Manifest::for_chunks(&chunks, 4);
+
+        // a chunk in the middle, the others left in place
+        manifest.replace_chunks(1, &[vec![9u8; 4]]);
+        let patched = vec![vec![1u8; 4], vec![9u8; 4], vec![3u8; 2]];
+        assert_eq!(manifest, Manifest::for_chunks(&patched, 4));
+        assert!(manifest.verify(&patched));
+
+        // the short last chunk filled and new chunks appended
+        manifest.replace_chunks(2, &[vec![3u8; 4], vec![4u8; 4], vec![5u8; 1]]);
+        let grown = vec![vec![1u8; 4], vec![9u8; 4], vec![3u8; 4], vec![4u8; 4], vec![5u8; 1]];
+        assert_eq!(manifest, Manifest::for_chunks(&grown, 4));
+        assert_eq!(manifest.value_len, 17);
+
+        let mut empty = Manifest::for_chunks(&[], 4);
+        empty.replace_chunks(0, &[vec![0u8; 3]]);
+        assert_eq!(empty, Manifest::for_chunks(&[vec![0u8; 3]], 4));
+    }
+
+    #[test]
+    fn test_manifest_records_encryption() {
+        let mut manifest =
+            Manifest::for_chunks(&[], 4).with_encryption(Cipher::Aes256Gcm, 7, [9; NONCE_LEN]);
+        manifest.push_chunk(&[1u8; 4 + 16]);
+        manifest.push_chunk(&[2u8; 2 + 16]);
+        assert_eq!(manifest.total_len, 38);
+        assert_eq!(manifest.value_len, 6);
+
+        let decoded = Manifest::decode(&manifest.encode()).expect("decode");
+        assert_eq!(decoded, manifest);
+        assert!(decoded.verify_chunk(0, &[1u8; 4 + 16]));
+        assert!(!decoded.verify_chunk(0, &[1u8; 4]));
+
+        // revision 4 manifests are read as unencrypted
+        let mut v4 = manifest.encode();
+        v4[0] = 4;
+        v4.drain(V3_VERSIONSTAMP_OFFSET..VERSIONSTAMP_OFFSET);
+        let decoded = Manifest::decode(&v4).expect("decode");
+        assert_eq!(decoded.cipher, Cipher::None);
+        assert_eq!(decoded.chunk_checksums, manifest.chunk_checksums);
+    }
+
+    #[test]
+    fn test_generation_carries_creation_time() {
+        let before = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let generation = Manifest::new_generation();
+        let after = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        assert_ne!(generation, 0);
+        assert!((before..=after).contains(&Manifest::generation_time_ms(generation)));
+    }
+
+    #[test]
+    fn test_generations_do_not_overlap_legacy_chunks() {
+        let (begin, end) = Manifest::legacy_chunk_range(b"key");
+        let manifest = Manifest::for_chunks(&[], 4).with_generation(3);
+        let (gen_begin, gen_end) = manifest.chunk_range(b"key");
+        assert!(gen_end <= begin || gen_begin >= end);
+    }
+
+    #[test]
+    fn test_manifest_detects_torn_values() {
+        let chunks = vec![vec![1u8; 4], vec![2u8; 4]];
+        let manifest = Manifest::for_chunks(&chunks, 4);
+
+        // missing trailing chunk
+        assert!(!manifest.verify(&chunks[..1]));
+        // stale chunk from another write
+        assert!(!manifest.verify(&[vec![1u8; 4], vec![9u8; 4]]));
+        // unknown layout
+        assert!(Manifest::decode(&[0u8; 3]).is_none());
+    }
+}
@@ -0,0 +1,13 @@
+//! Process-wide counters kept by the storage layer.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static CORRUPT_VALUES: AtomicU64 = AtomicU64::new(0);
+
+/// Number of reads that found a value failing its checksums since startup.
+pub fn corrupt_values() -> u64 {
+    CORRUPT_VALUES.load(Ordering::Relaxed)
+}
+
+pub(crate) fn record_corrupt_value() {
+    CORRUPT_VALUES.fetch_add(1, Ordering::Relaxed);
+}
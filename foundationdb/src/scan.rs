@@ -0,0 +1,171 @@
+use crate::{Error, Result};
+use foundationdb::options::StreamingMode;
+use crate::storage::Transaction;
+use foundationdb::{FdbError, KeySelector, RangeOption};
+
+/// Default number of records returned per page.
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// Number of records per page of a scan that reads its range whole.
+pub const BULK_LIMIT: usize = 1_000;
+
+/// How a range is read.
+#[derive(Debug, Clone, Copy)]
+pub struct ScanOptions {
+    /// Maximum number of records per page.
+    pub limit: usize,
+    /// Walk the range from its end.
+    pub reverse: bool,
+    /// How eagerly FoundationDB ships records: `WantAll` for pages that are
+    /// consumed whole, `Iterator` for pages that may be abandoned early.
+    pub mode: StreamingMode,
+    /// Read without adding read conflicts, so concurrent writes to the range
+    /// do not abort the enclosing transaction.
+    pub snapshot: bool,
+    /// Read every page at the read version of the first one. The scan then
+    /// sees a consistent view, but fails with `Error::TransactionTooOld` when
+    /// it outlasts the 5 seconds FoundationDB keeps old versions around.
+    pub single_read_version: bool,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            limit: DEFAULT_LIMIT,
+            reverse: false,
+            mode: StreamingMode::Iterator,
+            snapshot: false,
+            single_read_version: false,
+        }
+    }
+}
+
+impl ScanOptions {
+    /// Options of a scan that reads its range whole: large pages, shipped
+    /// eagerly.
+    pub fn bulk(limit: usize) -> Self {
+        Self {
+            limit,
+            mode: StreamingMode::WantAll,
+            ..Self::default()
+        }
+    }
+}
+
+/// Where a paginated scan resumes: a key selector relative to the last key
+/// returned, and the read version to pin when the scan runs at one version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Continuation {
+    key: Vec<u8>,
+    reverse: bool,
+    read_version: Option<i64>,
+}
+
+const REVERSE: u8 = 1;
+const PINNED: u8 = 2;
+
+impl Continuation {
+    /// Serialize the token so it can be handed to a client.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut flags = 0;
+        if self.reverse {
+            flags |= REVERSE;
+        }
+        if self.read_version.is_some() {
+            flags |= PINNED;
+        }
+        let mut out = vec![flags];
+        out.extend_from_slice(&self.read_version.unwrap_or(0).to_be_bytes());
+        out.extend_from_slice(&self.key);
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        let invalid = || Error::InvalidContinuation;
+        let (&flags, rest) = bytes.split_first().ok_or_else(invalid)?;
+        if flags & !(REVERSE | PINNED) != 0 || rest.len() < 8 {
+            return Err(invalid());
+        }
+        let (version, key) = rest.split_at(8);
+        let version = i64::from_be_bytes(version.try_into().map_err(|_| invalid())?);
+        Ok(Self {
+            key: key.to_vec(),
+            reverse: flags & REVERSE != 0,
+            read_version: (flags & PINNED != 0).then_some(version),
+        })
+    }
+
+    /// Read version the scan is pinned to, if it runs at one version.
+    pub fn read_version(&self) -> Option<i64> {
+        self.read_version
+    }
+
+    /// Range left to read once the records up to this token were returned.
+    fn resume<'a>(&self, mut range: RangeOption<'a>) -> RangeOption<'a> {
+        if self.reverse {
+            range.end = KeySelector::first_greater_or_equal(self.key.clone());
+        } else {
+            range.begin = KeySelector::first_greater_than(self.key.clone());
+        }
+        range
+    }
+}
+
+/// One page of a scan.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScanPage {
+    pub kvs: Vec<(Vec<u8>, Vec<u8>)>,
+    /// Token to read the next page, `None` once the range is exhausted.
+    pub continuation: Option<Continuation>,
+}
+
+/// Read one page of `[begin, end)` inside `trx`, resuming after `from`.
+pub(crate) async fn read_page(
+    trx: &Transaction,
+    begin: &[u8],
+    end: &[u8],
+    options: &ScanOptions,
+    from: Option<&Continuation>,
+    read_version: Option<i64>,
+) -> std::result::Result<ScanPage, FdbError> {
+    let limit = options.limit.max(1);
+    let mut range = RangeOption {
+        limit: Some(limit),
+        reverse: options.reverse,
+        mode: options.mode,
+        ..RangeOption::from((begin, end))
+    };
+    if let Some(from) = from {
+        range = from.resume(range);
+    }
+    let kvs = trx.get_range(&range, options.snapshot).await?;
+    let continuation = match kvs.last() {
+        Some((key, _)) if kvs.len() == limit => Some(Continuation {
+            key: key.clone(),
+            reverse: options.reverse,
+            read_version,
+        }),
+        _ => None,
+    };
+    Ok(ScanPage { kvs, continuation })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_continuation_roundtrip() {
+        for (reverse, read_version) in [(false, None), (true, Some(42)), (false, Some(-1))] {
+            let token = Continuation {
+                key: b"some\x00key".to_vec(),
+                reverse,
+                read_version,
+            };
+            assert_eq!(Continuation::decode(&token.encode()).expect("decode"), token);
+        }
+        assert!(Continuation::decode(b"").is_err());
+        assert!(Continuation::decode(&[0, 1, 2]).is_err());
+        assert!(Continuation::decode(&[8; 16]).is_err());
+    }
+}
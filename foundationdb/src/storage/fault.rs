@@ -0,0 +1,396 @@
+//! Faults injected into a backend, to exercise the retry and recovery paths
+//! FoundationDB rarely takes on a healthy cluster.
+//!
+//! A `FaultInjector` draws every fault from a generator seeded by its
+//! `FaultConfig`: the same seed replays the same faults over the same
+//! sequence of operations. Each read and each commit is one step. Reads may
+//! fail with `transaction_too_old`; commits may fail with `not_committed`
+//! without applying their writes, or with `commit_unknown_result` having
+//! applied them or not. From the crash step on, every operation fails with
+//! `operation_cancelled` as if the process had died, leaving whatever a
+//! multi-transaction write had committed so far for recovery to find.
+use super::{Backend, Transaction, code, is_retryable, memory};
+use foundationdb::options::TransactionOption;
+use foundationdb::{FdbError, FdbResult};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Which faults to inject, and how often.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaultConfig {
+    pub seed: u64,
+    /// Probability of a commit failing with `not_committed`.
+    pub not_committed: f64,
+    /// Probability of a read failing with `transaction_too_old`.
+    pub transaction_too_old: f64,
+    /// Probability of a commit failing with `commit_unknown_result`; half of
+    /// those commits are applied nonetheless.
+    pub commit_unknown_result: f64,
+    /// Upper bound of the latency added to every step.
+    pub max_latency: Duration,
+    /// Step from which every operation fails, as after a crash.
+    pub crash_at_step: Option<u64>,
+}
+
+impl Default for FaultConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0,
+            not_committed: 0.0,
+            transaction_too_old: 0.0,
+            commit_unknown_result: 0.0,
+            max_latency: Duration::ZERO,
+            crash_at_step: None,
+        }
+    }
+}
+
+/// Faults injected so far by a `FaultInjector`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FaultCounts {
+    pub not_committed: u64,
+    pub transaction_too_old: u64,
+    pub commit_unknown_result: u64,
+    /// Commits reported as `commit_unknown_result` that were applied.
+    pub applied_unknown_result: u64,
+    /// Operations refused after the crash step.
+    pub cancelled: u64,
+}
+
+/// Outcome of a commit decided by the injector.
+pub(crate) enum CommitFault {
+    None,
+    NotCommitted,
+    UnknownResult { applied: bool },
+}
+
+/// Seeded source of faults, shared by every transaction of the backends it
+/// wraps.
+pub struct FaultInjector {
+    config: FaultConfig,
+    rng: Mutex<StdRng>,
+    steps: AtomicU64,
+    counts: Mutex<FaultCounts>,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig) -> Self {
+        Self {
+            rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+            config,
+            steps: AtomicU64::new(0),
+            counts: Mutex::new(FaultCounts::default()),
+        }
+    }
+
+    pub fn config(&self) -> &FaultConfig {
+        &self.config
+    }
+
+    /// Steps taken so far, those refused after a crash included.
+    pub fn steps(&self) -> u64 {
+        self.steps.load(Ordering::SeqCst)
+    }
+
+    /// Whether the crash step was reached.
+    pub fn crashed(&self) -> bool {
+        self.config.crash_at_step.is_some_and(|step| self.steps() >= step)
+    }
+
+    pub fn counts(&self) -> FaultCounts {
+        *self.counts.lock().expect("fault counts lock")
+    }
+
+    /// Take a step, failing it once the crash step is reached.
+    fn step(&self) -> FdbResult<()> {
+        let step = self.steps.fetch_add(1, Ordering::SeqCst) + 1;
+        if self.config.crash_at_step.is_some_and(|crash| step >= crash) {
+            self.count(|c| c.cancelled += 1);
+            return Err(FdbError::from_code(code::OPERATION_CANCELLED));
+        }
+        Ok(())
+    }
+
+    fn chance(&self, probability: f64) -> bool {
+        probability > 0.0
+            && self
+                .rng
+                .lock()
+                .expect("fault rng lock")
+                .gen_bool(probability.min(1.0))
+    }
+
+    fn latency(&self) -> Duration {
+        if self.config.max_latency.is_zero() {
+            return Duration::ZERO;
+        }
+        let max = self.config.max_latency.as_micros().min(u64::MAX as u128) as u64;
+        Duration::from_micros(self.rng.lock().expect("fault rng lock").gen_range(0..=max))
+    }
+
+    fn count(&self, f: impl FnOnce(&mut FaultCounts)) {
+        f(&mut self.counts.lock().expect("fault counts lock"));
+    }
+
+    /// Decide the fate of a read, then wait for its latency.
+    pub(crate) async fn read(&self) -> FdbResult<()> {
+        self.step()?;
+        let too_old = self.chance(self.config.transaction_too_old);
+        tokio::time::sleep(self.latency()).await;
+        if too_old {
+            self.count(|c| c.transaction_too_old += 1);
+            return Err(FdbError::from_code(code::TRANSACTION_TOO_OLD));
+        }
+        Ok(())
+    }
+
+    /// Decide the fate of a commit, then wait for its latency.
+    pub(crate) async fn commit(&self) -> FdbResult<CommitFault> {
+        self.step()?;
+        let fault = if self.chance(self.config.not_committed) {
+            self.count(|c| c.not_committed += 1);
+            CommitFault::NotCommitted
+        } else if self.chance(self.config.commit_unknown_result) {
+            let applied = self.chance(0.5);
+            self.count(|c| {
+                c.commit_unknown_result += 1;
+                c.applied_unknown_result += applied as u64;
+            });
+            CommitFault::UnknownResult { applied }
+        } else {
+            CommitFault::None
+        };
+        tokio::time::sleep(self.latency()).await;
+        Ok(fault)
+    }
+}
+
+/// A backend whose transactions go through a `FaultInjector`.
+pub struct Faults {
+    pub(crate) backend: Backend,
+    pub(crate) injector: Arc<FaultInjector>,
+}
+
+impl Faults {
+    pub(crate) fn new(backend: Backend, injector: Arc<FaultInjector>) -> Self {
+        Self { backend, injector }
+    }
+
+    /// A transaction of the wrapped backend, subject to the faults.
+    pub(crate) fn create_trx(self: &Arc<Self>) -> FdbResult<Transaction> {
+        Ok(self.backend.create_trx()?.with_faults(self.clone()))
+    }
+}
+
+/// Options set on a transaction subject to faults and the retries it went
+/// through.
+///
+/// FoundationDB cannot reset a transaction once committed, so one failed
+/// after committing is replaced by a fresh transaction, which resumes from
+/// this history: same options, the time and retries already spent counted
+/// against its timeout and retry limit.
+#[derive(Debug, Clone)]
+pub struct History {
+    options: Vec<TransactionOption>,
+    started: Instant,
+    retries: u32,
+}
+
+impl History {
+    pub(crate) fn new() -> Self {
+        Self {
+            options: Vec::new(),
+            started: Instant::now(),
+            retries: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, option: TransactionOption) {
+        self.options.push(option);
+    }
+
+    pub(crate) fn retried(&mut self) {
+        self.retries += 1;
+    }
+
+    /// A fresh transaction of `faults` taking over from the one that failed
+    /// with `error`, after the backoff `on_error` would have waited; or the
+    /// error back when it must not be retried.
+    pub(crate) async fn resume(mut self, faults: &Arc<Faults>, error: FdbError) -> FdbResult<Transaction> {
+        if !is_retryable(error.code()) {
+            return Err(error);
+        }
+        let millis = |ms: i32| (ms > 0).then(|| Duration::from_millis(ms as u64));
+        let (mut timeout, mut retry_limit, mut max_retry_delay) = (None, None, None);
+        for option in &self.options {
+            match *option {
+                TransactionOption::Timeout(ms) => timeout = millis(ms),
+                TransactionOption::RetryLimit(limit) => retry_limit = u32::try_from(limit).ok(),
+                TransactionOption::MaxRetryDelay(ms) => max_retry_delay = millis(ms),
+                _ => {}
+            }
+        }
+        let remaining = timeout.map(|timeout| timeout.saturating_sub(self.started.elapsed()));
+        if remaining.is_some_and(|remaining| remaining.is_zero()) {
+            return Err(FdbError::from_code(code::TRANSACTION_TIMED_OUT));
+        }
+        if retry_limit.is_some_and(|limit| self.retries >= limit) {
+            return Err(error);
+        }
+        tokio::time::sleep(memory::backoff(self.retries, max_retry_delay)).await;
+        self.retries += 1;
+
+        let trx = faults.backend.create_trx()?;
+        for option in &self.options {
+            let option = match *option {
+                TransactionOption::Timeout(ms) if ms > 0 => {
+                    let remaining = remaining.unwrap_or(Duration::from_millis(ms as u64));
+                    TransactionOption::Timeout(remaining.as_millis().clamp(1, i32::MAX as u128) as i32)
+                }
+                TransactionOption::RetryLimit(limit) if limit >= 0 => {
+                    TransactionOption::RetryLimit(limit - self.retries.min(limit as u32) as i32)
+                }
+                ref option => option.clone(),
+            };
+            trx.set_option(option)?;
+        }
+        Ok(trx.with_faults(faults.clone()).with_history(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStore;
+
+    fn faulty(config: FaultConfig) -> (MemoryStore, Arc<Faults>) {
+        let store = MemoryStore::default();
+        let faults = Faults::new(
+            Backend::Memory(store.clone()),
+            Arc::new(FaultInjector::new(config)),
+        );
+        (store, Arc::new(faults))
+    }
+
+    #[test]
+    fn test_same_seed_same_faults() {
+        let config = FaultConfig {
+            seed: 7,
+            not_committed: 0.3,
+            transaction_too_old: 0.3,
+            commit_unknown_result: 0.3,
+            max_latency: Duration::from_millis(5),
+            ..FaultConfig::default()
+        };
+        let draw = |injector: &FaultInjector| {
+            (0..64)
+                .map(|_| (injector.chance(0.5), injector.latency()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            draw(&FaultInjector::new(config)),
+            draw(&FaultInjector::new(config))
+        );
+        let other = FaultInjector::new(FaultConfig { seed: 8, ..config });
+        assert_ne!(draw(&FaultInjector::new(config)), draw(&other));
+    }
+
+    #[tokio::test]
+    async fn test_resume_history() {
+        let (_store, faults) = faulty(FaultConfig::default());
+        let unknown = || FdbError::from_code(code::COMMIT_UNKNOWN_RESULT);
+        let trx = faults.create_trx().unwrap();
+        trx.set_option(TransactionOption::RetryLimit(2)).unwrap();
+        trx.set_option(TransactionOption::MaxRetryDelay(1)).unwrap();
+
+        // The fresh transaction takes over the options and the retries so far
+        let trx = trx.history().unwrap().resume(&faults, unknown()).await.unwrap();
+        let history = trx.history().unwrap();
+        assert_eq!(history.retries, 1);
+        assert_eq!(history.options.len(), 2);
+        let trx = history.resume(&faults, unknown()).await.unwrap();
+        let exhausted = trx.history().unwrap().resume(&faults, unknown()).await;
+        assert_eq!(exhausted.err().map(|e| e.code()), Some(code::COMMIT_UNKNOWN_RESULT));
+
+        // The timeout runs from the first attempt
+        let trx = faults.create_trx().unwrap();
+        trx.set_option(TransactionOption::Timeout(1)).unwrap();
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        let timed_out = trx.history().unwrap().resume(&faults, unknown()).await;
+        assert_eq!(timed_out.err().map(|e| e.code()), Some(code::TRANSACTION_TIMED_OUT));
+
+        let trx = faults.create_trx().unwrap();
+        let refused = trx
+            .history()
+            .unwrap()
+            .resume(&faults, FdbError::from_code(code::KEY_TOO_LARGE))
+            .await;
+        assert_eq!(refused.err().map(|e| e.code()), Some(code::KEY_TOO_LARGE));
+    }
+
+    #[tokio::test]
+    async fn test_injected_commit_errors() {
+        let (store, faults) = faulty(FaultConfig {
+            not_committed: 1.0,
+            ..FaultConfig::default()
+        });
+        let trx = faults.create_trx().unwrap();
+        trx.set(b"key", b"value");
+        let error = trx.commit().await.err().unwrap();
+        assert_eq!(error.code(), code::NOT_COMMITTED);
+        assert_eq!(store.create_trx().get(b"key", false).unwrap(), None);
+
+        // The reset transaction is still subject to the faults
+        let trx = error.on_error().await.unwrap();
+        trx.set(b"key", b"value");
+        assert_eq!(trx.commit().await.err().unwrap().code(), code::NOT_COMMITTED);
+        assert_eq!(faults.injector.counts().not_committed, 2);
+
+        let (store, faults) = faulty(FaultConfig {
+            commit_unknown_result: 1.0,
+            ..FaultConfig::default()
+        });
+        let mut applied = 0;
+        for i in 0..32u8 {
+            let trx = faults.create_trx().unwrap();
+            trx.set(&[i], b"value");
+            let error = trx.commit().await.err().unwrap();
+            assert_eq!(error.code(), code::COMMIT_UNKNOWN_RESULT);
+            applied += store.create_trx().get(&[i], false).unwrap().is_some() as u64;
+        }
+        let counts = faults.injector.counts();
+        assert_eq!(counts.commit_unknown_result, 32);
+        assert_eq!(counts.applied_unknown_result, applied);
+        assert!(applied > 0 && applied < 32);
+    }
+
+    #[tokio::test]
+    async fn test_crash() {
+        let (store, faults) = faulty(FaultConfig {
+            crash_at_step: Some(3),
+            ..FaultConfig::default()
+        });
+        let trx = faults.create_trx().unwrap();
+        assert_eq!(trx.get(b"key", false).await.unwrap(), None);
+        trx.set(b"key", b"value");
+        assert!(trx.commit().await.is_ok());
+
+        // The third step never completes, nor does any later one
+        let trx = faults.create_trx().unwrap();
+        let error = trx.get(b"key", false).await.map_err(|e| e.code());
+        assert_eq!(error, Err(code::OPERATION_CANCELLED));
+        trx.set(b"key", b"other");
+        let error = trx.commit().await.err().unwrap();
+        assert_eq!(error.code(), code::OPERATION_CANCELLED);
+        assert!(error.on_error().await.is_err());
+        assert!(faults.injector.crashed());
+        assert_eq!(faults.injector.counts().cancelled, 2);
+        assert_eq!(
+            store.create_trx().get(b"key", false).unwrap(),
+            Some(b"value".to_vec())
+        );
+    }
+}
@@ -0,0 +1,837 @@
+//! In-memory storage backend.
+//!
+//! Every committed version of a record is kept for as long as a transaction
+//! may read it, so transactions see a consistent snapshot at their read
+//! version, with their own writes on top. Commits are checked optimistically
+//! the way FoundationDB's resolvers do: a transaction fails with
+//! `not_committed` when a record it read without `snapshot` was written by a
+//! transaction that committed after its read version. Versions superseded
+//! for longer than the MVCC window are discarded, and reading them fails
+//! with `transaction_too_old`. Keys, values and transactions are held to the
+//! size limits of FoundationDB.
+use super::{code, is_retryable};
+use foundationdb::options::{MutationType, TransactionOption};
+use foundationdb::{FdbError, FdbResult, KeySelector, RangeOption};
+use futures::future::{BoxFuture, FutureExt};
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::Bound;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+
+/// How long a version stays readable once superseded.
+const MVCC_WINDOW: Duration = Duration::from_secs(5);
+
+const MAX_KEY_SIZE: usize = 10_000;
+const MAX_VALUE_SIZE: usize = 100_000;
+const MAX_TRANSACTION_SIZE: usize = 10_000_000;
+
+/// Backoff before the first retry, doubled on each retry after it.
+const INITIAL_BACKOFF: Duration = Duration::from_millis(10);
+const DEFAULT_MAX_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Half-open range of keys.
+type KeyRange = (Vec<u8>, Vec<u8>);
+
+/// Records of an in-memory database. Clones share the same records.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Version of the last commit.
+    version: i64,
+    /// Readable versions of every record, oldest first; `None` is a clear.
+    records: BTreeMap<Vec<u8>, Vec<(i64, Option<Vec<u8>>)>>,
+    /// Commits within the MVCC window, oldest first.
+    commits: VecDeque<Commit>,
+    watches: Vec<Watch>,
+}
+
+struct Commit {
+    version: i64,
+    at: Instant,
+    /// Write conflict ranges, checked against the reads of later commits.
+    writes: Vec<KeyRange>,
+    /// Records written, whose older versions are discarded once this commit
+    /// leaves the window.
+    keys: Vec<Vec<u8>>,
+}
+
+struct Watch {
+    key: Vec<u8>,
+    value: Option<Vec<u8>>,
+    fire: oneshot::Sender<FdbResult<()>>,
+}
+
+impl MemoryStore {
+    pub(crate) fn create_trx(&self) -> MemoryTransaction {
+        MemoryTransaction {
+            store: self.clone(),
+            state: Arc::new(Mutex::new(TrxState::new(Options::default(), Instant::now(), 0))),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("memory store lock")
+    }
+}
+
+impl State {
+    fn latest(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.records
+            .get(key)
+            .and_then(|history| history.last()?.1.clone())
+    }
+
+    /// Fail unless `version` is still readable.
+    fn check_version(&self, version: i64) -> FdbResult<()> {
+        if version > self.version {
+            return Err(FdbError::from_code(code::FUTURE_VERSION));
+        }
+        if version == self.version {
+            return Ok(());
+        }
+        // Readable until the commit superseding it left the window
+        match self.commits.iter().find(|c| c.version > version) {
+            Some(c) if c.version == version + 1 && c.at.elapsed() <= MVCC_WINDOW => Ok(()),
+            _ => Err(FdbError::from_code(code::TRANSACTION_TOO_OLD)),
+        }
+    }
+
+    /// Oldest version a transaction may still read at.
+    fn oldest_readable(&self) -> i64 {
+        self.commits.front().map_or(self.version, |c| c.version - 1)
+    }
+
+    /// Drop the commits that left the window and the versions only they
+    /// could still read.
+    fn expire(&mut self) {
+        let mut expired = Vec::new();
+        while self.commits.front().is_some_and(|c| c.at.elapsed() > MVCC_WINDOW) {
+            expired.extend(self.commits.pop_front().expect("front commit").keys);
+        }
+        let oldest = self.oldest_readable();
+        for key in expired {
+            self.trim(&key, oldest);
+        }
+        self.watches.retain(|w| !w.fire.is_closed());
+    }
+
+    fn trim(&mut self, key: &[u8], oldest: i64) {
+        let Some(history) = self.records.get_mut(key) else {
+            return;
+        };
+        // The last version at or below `oldest` hides every one before it
+        if let Some(visible) = history.iter().rposition(|(v, _)| *v <= oldest) {
+            history.drain(..visible);
+        }
+        if matches!(history.as_slice(), [(v, None)] if *v <= oldest) {
+            self.records.remove(key);
+        }
+    }
+}
+
+fn value_at(history: &[(i64, Option<Vec<u8>>)], version: i64) -> Option<Vec<u8>> {
+    history
+        .iter()
+        .rev()
+        .find(|(v, _)| *v <= version)
+        .and_then(|(_, value)| value.clone())
+}
+
+/// A transaction of a `MemoryStore`. Clones share the transaction.
+#[derive(Clone)]
+pub struct MemoryTransaction {
+    store: MemoryStore,
+    state: Arc<Mutex<TrxState>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Options {
+    timeout: Option<Duration>,
+    retry_limit: Option<u32>,
+    max_retry_delay: Option<Duration>,
+}
+
+enum Write {
+    Set(Vec<u8>, Vec<u8>),
+    Clear(KeyRange),
+    Atomic(Vec<u8>, Vec<u8>, MutationType),
+}
+
+struct TrxState {
+    options: Options,
+    /// When the first attempt started, for the timeout.
+    started: Instant,
+    retries: u32,
+    read_version: Option<i64>,
+    writes: Vec<Write>,
+    /// Read conflict ranges.
+    reads: Vec<KeyRange>,
+    watches: Vec<(Vec<u8>, oneshot::Sender<FdbResult<()>>)>,
+    size: usize,
+    /// First invalid operation, reported at commit like FoundationDB does.
+    error: Option<FdbError>,
+}
+
+impl TrxState {
+    fn new(options: Options, started: Instant, retries: u32) -> Self {
+        Self {
+            options,
+            started,
+            retries,
+            read_version: None,
+            writes: Vec::new(),
+            reads: Vec::new(),
+            watches: Vec::new(),
+            size: 0,
+            error: None,
+        }
+    }
+
+    fn check_timeout(&self) -> FdbResult<()> {
+        match self.options.timeout {
+            Some(timeout) if self.started.elapsed() >= timeout => {
+                Err(FdbError::from_code(code::TRANSACTION_TIMED_OUT))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn read_version(&mut self, store: &State) -> FdbResult<i64> {
+        self.check_timeout()?;
+        let version = *self.read_version.get_or_insert(store.version);
+        store.check_version(version)?;
+        Ok(version)
+    }
+
+    fn fail(&mut self, code: i32) {
+        self.error.get_or_insert(FdbError::from_code(code));
+    }
+
+    fn push(&mut self, write: Write) {
+        self.size += match &write {
+            Write::Set(key, value) | Write::Atomic(key, value, _) => key.len() + value.len(),
+            Write::Clear((begin, end)) => begin.len() + end.len(),
+        };
+        self.writes.push(write);
+    }
+
+    /// Value of `key` as seen by this transaction.
+    fn read(&self, store: &State, key: &[u8], version: i64) -> FdbResult<Option<Vec<u8>>> {
+        let mut value = store.records.get(key).and_then(|h| value_at(h, version));
+        for write in &self.writes {
+            match write {
+                Write::Set(k, v) if k == key => value = Some(v.clone()),
+                Write::Clear((begin, end)) if begin.as_slice() <= key && key < end.as_slice() => value = None,
+                Write::Atomic(k, param, op) if k == key => value = mutate(op, value.as_deref(), param)?,
+                _ => {}
+            }
+        }
+        Ok(value)
+    }
+
+    /// Records of `[lower, upper]` as seen by this transaction.
+    fn read_range(
+        &self,
+        store: &State,
+        lower: &Bound<Vec<u8>>,
+        upper: &Bound<Vec<u8>>,
+        version: i64,
+    ) -> FdbResult<BTreeMap<Vec<u8>, Vec<u8>>> {
+        let (start, end) = (range_start(lower), range_end(upper));
+        if start >= end {
+            return Ok(BTreeMap::new());
+        }
+        let mut view = store
+            .records
+            .range(start.clone()..end.clone())
+            .filter_map(|(k, h)| Some((k.clone(), value_at(h, version)?)))
+            .collect::<BTreeMap<_, _>>();
+        let within = |key: &[u8]| start.as_slice() <= key && key < end.as_slice();
+        for write in &self.writes {
+            match write {
+                Write::Set(k, v) if within(k) => {
+                    view.insert(k.clone(), v.clone());
+                }
+                Write::Clear((begin, end)) => view.retain(|k, _| k < begin || k >= end),
+                Write::Atomic(k, param, op) if within(k) => {
+                    match mutate(op, view.get(k).map(Vec::as_slice), param)? {
+                        Some(v) => view.insert(k.clone(), v),
+                        None => view.remove(k),
+                    };
+                }
+                _ => {}
+            }
+        }
+        Ok(view)
+    }
+}
+
+impl MemoryTransaction {
+    fn lock(&self) -> MutexGuard<'_, TrxState> {
+        self.state.lock().expect("memory transaction lock")
+    }
+
+    pub(crate) fn get(&self, key: &[u8], snapshot: bool) -> FdbResult<Option<Vec<u8>>> {
+        let mut trx = self.lock();
+        let store = self.store.lock();
+        let version = trx.read_version(&store)?;
+        let value = trx.read(&store, key, version)?;
+        if !snapshot {
+            trx.reads.push(single_key(key));
+        }
+        Ok(value)
+    }
+
+    pub(crate) fn get_key(&self, selector: &KeySelector<'_>, snapshot: bool) -> FdbResult<Vec<u8>> {
+        let lower = lower_bound(selector)?;
+        let mut trx = self.lock();
+        let store = self.store.lock();
+        let version = trx.read_version(&store)?;
+
+        let start = range_start(&lower);
+        let stored = store
+            .records
+            .range(start.clone()..)
+            .map(|(k, _)| k)
+            .find(|k| matches!(trx.read(&store, k, version), Ok(Some(_))));
+        let written = trx
+            .writes
+            .iter()
+            .filter_map(|w| match w {
+                Write::Set(k, _) | Write::Atomic(k, _, _) if *k >= start => Some(k),
+                _ => None,
+            })
+            .filter(|k| matches!(trx.read(&store, k, version), Ok(Some(_))))
+            .min();
+        let key = match (stored, written) {
+            (Some(a), Some(b)) => a.min(b).clone(),
+            (Some(k), None) | (None, Some(k)) => k.clone(),
+            (None, None) => vec![0xFF],
+        };
+        if !snapshot {
+            let mut end = key.clone();
+            end.push(0x00);
+            trx.reads.push((selector.key().to_vec(), end));
+        }
+        Ok(key)
+    }
+
+    pub(crate) fn get_range(
+        &self,
+        range: &RangeOption<'_>,
+        snapshot: bool,
+    ) -> FdbResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        let (lower, upper) = (lower_bound(&range.begin)?, upper_bound(&range.end)?);
+        let mut trx = self.lock();
+        let store = self.store.lock();
+        let version = trx.read_version(&store)?;
+        let view = trx.read_range(&store, &lower, &upper, version)?;
+
+        let limit = range.limit.filter(|l| *l > 0).unwrap_or(usize::MAX);
+        let kvs: Vec<(Vec<u8>, Vec<u8>)> = if range.reverse {
+            view.into_iter().rev().take(limit).collect()
+        } else {
+            view.into_iter().take(limit).collect()
+        };
+        if !snapshot {
+            // Only the part of the range actually read conflicts
+            let (mut start, mut end) = (range_start(&lower), range_end(&upper));
+            match kvs.last() {
+                Some((key, _)) if kvs.len() == limit && range.reverse => start = key.clone(),
+                Some((key, _)) if kvs.len() == limit => end = single_key(key).1,
+                _ => {}
+            }
+            if start < end {
+                trx.reads.push((start, end));
+            }
+        }
+        Ok(kvs)
+    }
+
+    pub(crate) fn set(&self, key: &[u8], value: &[u8]) {
+        let mut trx = self.lock();
+        if key.len() > MAX_KEY_SIZE {
+            trx.fail(code::KEY_TOO_LARGE);
+        } else if value.len() > MAX_VALUE_SIZE {
+            trx.fail(code::VALUE_TOO_LARGE);
+        }
+        trx.push(Write::Set(key.to_vec(), value.to_vec()));
+    }
+
+    pub(crate) fn clear(&self, key: &[u8]) {
+        let mut trx = self.lock();
+        if key.len() > MAX_KEY_SIZE {
+            trx.fail(code::KEY_TOO_LARGE);
+        }
+        trx.push(Write::Clear(single_key(key)));
+    }
+
+    pub(crate) fn clear_range(&self, begin: &[u8], end: &[u8]) {
+        if begin < end {
+            self.lock().push(Write::Clear((begin.to_vec(), end.to_vec())));
+        }
+    }
+
+    pub(crate) fn atomic_op(&self, key: &[u8], param: &[u8], op: MutationType) {
+        let mut trx = self.lock();
+        if key.len() > MAX_KEY_SIZE + 4 {
+            trx.fail(code::KEY_TOO_LARGE);
+        } else if param.len() > MAX_VALUE_SIZE + 4 {
+            trx.fail(code::VALUE_TOO_LARGE);
+        }
+        trx.push(Write::Atomic(key.to_vec(), param.to_vec(), op));
+    }
+
+    pub(crate) fn watch(&self, key: &[u8]) -> BoxFuture<'static, FdbResult<()>> {
+        let (fire, fired) = oneshot::channel();
+        self.lock().watches.push((key.to_vec(), fire));
+        async move {
+            fired
+                .await
+                .unwrap_or_else(|_| Err(FdbError::from_code(code::OPERATION_CANCELLED)))
+        }
+        .boxed()
+    }
+
+    pub(crate) fn get_read_version(&self) -> FdbResult<i64> {
+        let mut trx = self.lock();
+        let store = self.store.lock();
+        trx.read_version(&store)
+    }
+
+    pub(crate) fn set_read_version(&self, version: i64) {
+        self.lock().read_version = Some(version);
+    }
+
+    pub(crate) fn set_option(&self, option: TransactionOption) {
+        let millis = |ms: i32| (ms > 0).then(|| Duration::from_millis(ms as u64));
+        let mut trx = self.lock();
+        match option {
+            TransactionOption::Timeout(ms) => trx.options.timeout = millis(ms),
+            TransactionOption::RetryLimit(limit) => trx.options.retry_limit = u32::try_from(limit).ok(),
+            TransactionOption::MaxRetryDelay(ms) => trx.options.max_retry_delay = millis(ms),
+            _ => {}
+        }
+    }
+
+    pub(crate) fn commit(&self) -> FdbResult<()> {
+        let mut trx = self.lock();
+        let committed = self.try_commit(&mut trx);
+        if let Err(e) = committed {
+            for (_, fire) in trx.watches.drain(..) {
+                let _ = fire.send(Err(e));
+            }
+        }
+        committed
+    }
+
+    fn try_commit(&self, trx: &mut TrxState) -> FdbResult<()> {
+        trx.check_timeout()?;
+        if let Some(e) = trx.error {
+            return Err(e);
+        }
+        if trx.size > MAX_TRANSACTION_SIZE {
+            return Err(FdbError::from_code(code::TRANSACTION_TOO_LARGE));
+        }
+        let mut store = self.store.lock();
+        store.expire();
+
+        if let (Some(version), false) = (trx.read_version, trx.reads.is_empty()) {
+            store.check_version(version)?;
+            let conflicting = store
+                .commits
+                .iter()
+                .filter(|c| c.version > version)
+                .flat_map(|c| &c.writes)
+                .any(|(begin, end)| trx.reads.iter().any(|(b, e)| begin < e && b < end));
+            if conflicting {
+                return Err(FdbError::from_code(code::NOT_COMMITTED));
+            }
+        }
+
+        let version = store.version + 1;
+        let mut changes: BTreeMap<Vec<u8>, Option<Vec<u8>>> = BTreeMap::new();
+        let mut conflicts = Vec::new();
+        for write in std::mem::take(&mut trx.writes) {
+            match write {
+                Write::Set(key, value) => {
+                    conflicts.push(single_key(&key));
+                    changes.insert(key, Some(value));
+                }
+                Write::Clear((begin, end)) => {
+                    let stored = store
+                        .records
+                        .range(begin.clone()..end.clone())
+                        .filter(|(_, h)| h.last().is_some_and(|(_, v)| v.is_some()))
+                        .map(|(k, _)| k.clone());
+                    let written = changes.range(begin.clone()..end.clone()).map(|(k, _)| k.clone());
+                    for key in stored.chain(written).collect::<Vec<_>>() {
+                        changes.insert(key, None);
+                    }
+                    conflicts.push((begin, end));
+                }
+                Write::Atomic(key, param, MutationType::SetVersionstampedKey) => {
+                    let key = stamp(&key, version)?;
+                    conflicts.push(single_key(&key));
+                    changes.insert(key, Some(param));
+                }
+                Write::Atomic(key, param, MutationType::SetVersionstampedValue) => {
+                    conflicts.push(single_key(&key));
+                    changes.insert(key, Some(stamp(&param, version)?));
+                }
+                Write::Atomic(key, param, op) => {
+                    let current = match changes.get(&key) {
+                        Some(value) => value.clone(),
+                        None => store.latest(&key),
+                    };
+                    let value = mutate(&op, current.as_deref(), &param)?;
+                    conflicts.push(single_key(&key));
+                    changes.insert(key, value);
+                }
+            }
+        }
+
+        if changes.is_empty() && conflicts.is_empty() && trx.watches.is_empty() {
+            // Read-only transactions commit without a version
+            return Ok(());
+        }
+        for (key, value) in &changes {
+            let history = store.records.entry(key.clone()).or_default();
+            history.push((version, value.clone()));
+        }
+        store.version = version;
+        store.commits.push_back(Commit {
+            version,
+            at: Instant::now(),
+            writes: conflicts,
+            keys: changes.keys().cloned().collect(),
+        });
+
+        store.watches.retain_mut(|watch| match changes.get(&watch.key) {
+            Some(value) if *value != watch.value => {
+                // Taking the sender fires the watch once
+                let (fire, _) = oneshot::channel();
+                let _ = std::mem::replace(&mut watch.fire, fire).send(Ok(()));
+                false
+            }
+            _ => true,
+        });
+        for (key, fire) in trx.watches.drain(..) {
+            let value = store.latest(&key);
+            store.watches.push(Watch { key, value, fire });
+        }
+        Ok(())
+    }
+
+    /// Reset the transaction after `error` and back off, unless the error
+    /// must not be retried or the retries are exhausted.
+    pub(crate) async fn on_error(&self, error: FdbError) -> FdbResult<MemoryTransaction> {
+        let (options, started, retries) = {
+            let trx = self.lock();
+            if !is_retryable(error.code()) {
+                return Err(error);
+            }
+            trx.check_timeout()?;
+            if trx.options.retry_limit.is_some_and(|limit| trx.retries >= limit) {
+                return Err(error);
+            }
+            (trx.options, trx.started, trx.retries)
+        };
+        tokio::time::sleep(backoff(retries, options.max_retry_delay)).await;
+        Ok(MemoryTransaction {
+            store: self.store.clone(),
+            state: Arc::new(Mutex::new(TrxState::new(options, started, retries + 1))),
+        })
+    }
+}
+
+/// Delay before retry `retries + 1`, doubling from `INITIAL_BACKOFF` up to
+/// `max_retry_delay`, one second when unset.
+pub(crate) fn backoff(retries: u32, max_retry_delay: Option<Duration>) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(1 << retries.min(16))
+        .min(max_retry_delay.unwrap_or(DEFAULT_MAX_RETRY_DELAY))
+}
+
+/// Range holding `key` alone.
+fn single_key(key: &[u8]) -> KeyRange {
+    let mut end = key.to_vec();
+    end.push(0x00);
+    (key.to_vec(), end)
+}
+
+/// Bound of the first key selected by a range's begin selector. Only the
+/// `first_greater_*` selectors ranges are built from are supported.
+fn lower_bound(selector: &KeySelector<'_>) -> FdbResult<Bound<Vec<u8>>> {
+    match (selector.or_equal(), selector.offset()) {
+        (false, 1) => Ok(Bound::Included(selector.key().to_vec())),
+        (true, 1) => Ok(Bound::Excluded(selector.key().to_vec())),
+        _ => Err(FdbError::from_code(code::CLIENT_INVALID_OPERATION)),
+    }
+}
+
+/// Bound of the last key before a range's end selector.
+fn upper_bound(selector: &KeySelector<'_>) -> FdbResult<Bound<Vec<u8>>> {
+    match (selector.or_equal(), selector.offset()) {
+        (false, 1) => Ok(Bound::Excluded(selector.key().to_vec())),
+        (true, 1) => Ok(Bound::Included(selector.key().to_vec())),
+        _ => Err(FdbError::from_code(code::CLIENT_INVALID_OPERATION)),
+    }
+}
+
+fn range_start(bound: &Bound<Vec<u8>>) -> Vec<u8> {
+    match bound {
+        Bound::Included(key) => key.clone(),
+        Bound::Excluded(key) => single_key(key).1,
+        Bound::Unbounded => Vec::new(),
+    }
+}
+
+fn range_end(bound: &Bound<Vec<u8>>) -> Vec<u8> {
+    match bound {
+        Bound::Included(key) => single_key(key).1,
+        Bound::Excluded(key) => key.clone(),
+        Bound::Unbounded => vec![0xFF],
+    }
+}
+
+/// Substitute the versionstamp of `version` at the offset given by the last
+/// four bytes of `param`, little-endian, and drop them.
+fn stamp(param: &[u8], version: i64) -> FdbResult<Vec<u8>> {
+    let invalid = || FdbError::from_code(code::CLIENT_INVALID_OPERATION);
+    let split = param.len().checked_sub(4).ok_or_else(invalid)?;
+    let (body, offset) = param.split_at(split);
+    let offset = u32::from_le_bytes(offset.try_into().expect("4 bytes")) as usize;
+    let mut stamped = body.to_vec();
+    let target = stamped.get_mut(offset..offset + 10).ok_or_else(invalid)?;
+    // Transaction batches hold a single commit: the batch order is zero
+    target[..8].copy_from_slice(&version.to_be_bytes());
+    target[8..].copy_from_slice(&[0, 0]);
+    Ok(stamped)
+}
+
+/// Result of the atomic operation `op` on the `current` value of a record,
+/// following FoundationDB's semantics for a missing record.
+fn mutate(op: &MutationType, current: Option<&[u8]>, param: &[u8]) -> FdbResult<Option<Vec<u8>>> {
+    if matches!(
+        op,
+        MutationType::SetVersionstampedKey | MutationType::SetVersionstampedValue
+    ) {
+        // The versionstamp is only known once committed
+        return Err(FdbError::from_code(code::ACCESSED_UNREADABLE));
+    }
+    if matches!(op, MutationType::CompareAndClear) {
+        return Ok(current.filter(|c| *c != param).map(<[u8]>::to_vec));
+    }
+    let Some(current) = current else {
+        return match op {
+            MutationType::Add => Ok(Some(add(&[], param))),
+            _ => Ok(Some(param.to_vec())),
+        };
+    };
+    // Integer and bitwise operations read the stored value at the length of `param`
+    let mut resized = current.to_vec();
+    resized.resize(param.len(), 0);
+    let bytes =
+        |f: fn(u8, u8) -> u8| -> Vec<u8> { resized.iter().zip(param).map(|(a, b)| f(*a, *b)).collect() };
+    let value = match op {
+        MutationType::Add => add(current, param),
+        MutationType::BitAnd => bytes(|a, b| a & b),
+        MutationType::BitOr => bytes(|a, b| a | b),
+        MutationType::BitXor => bytes(|a, b| a ^ b),
+        MutationType::Max if le_greater(param, &resized) => param.to_vec(),
+        MutationType::Min if le_greater(&resized, param) => param.to_vec(),
+        MutationType::Max | MutationType::Min => resized,
+        MutationType::ByteMax => current.max(param).to_vec(),
+        MutationType::ByteMin => current.min(param).to_vec(),
+        MutationType::AppendIfFits if current.len() + param.len() > MAX_VALUE_SIZE => current.to_vec(),
+        MutationType::AppendIfFits => [current, param].concat(),
+        _ => return Err(FdbError::from_code(code::CLIENT_INVALID_OPERATION)),
+    };
+    Ok(Some(value))
+}
+
+/// Sum of two little-endian integers, truncated to the length of `param`.
+fn add(current: &[u8], param: &[u8]) -> Vec<u8> {
+    let mut carry = 0u16;
+    param
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            let sum = *b as u16 + *current.get(i).unwrap_or(&0) as u16 + carry;
+            carry = sum >> 8;
+            sum as u8
+        })
+        .collect()
+}
+
+/// Whether little-endian integer `a` is greater than `b`, of the same length.
+fn le_greater(a: &[u8], b: &[u8]) -> bool {
+    a.iter().rev().cmp(b.iter().rev()).is_gt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_commit(store: &MemoryStore, f: impl FnOnce(&MemoryTransaction)) -> FdbResult<()> {
+        let trx = store.create_trx();
+        f(&trx);
+        trx.commit()
+    }
+
+    #[test]
+    fn test_snapshot_isolation() {
+        let store = MemoryStore::default();
+        run_commit(&store, |trx| trx.set(b"a", b"1")).expect("commit");
+
+        let reader = store.create_trx();
+        assert_eq!(reader.get(b"a", false).expect("get"), Some(b"1".to_vec()));
+        run_commit(&store, |trx| trx.set(b"a", b"2")).expect("commit");
+        // still reading at its own version, with its writes on top
+        assert_eq!(reader.get(b"a", true).expect("get"), Some(b"1".to_vec()));
+        reader.set(b"b", b"x");
+        assert_eq!(reader.get(b"b", true).expect("get"), Some(b"x".to_vec()));
+
+        // it read a record written since: the commit conflicts
+        let error = reader.commit().expect_err("conflict");
+        assert_eq!(error.code(), code::NOT_COMMITTED);
+        assert!(is_retryable(error.code()));
+
+        // snapshot reads and blind writes never conflict
+        let blind = store.create_trx();
+        blind.get(b"a", true).expect("get");
+        run_commit(&store, |trx| trx.set(b"a", b"3")).expect("commit");
+        blind.set(b"a", b"4");
+        blind.commit().expect("commit");
+        assert_eq!(
+            store.create_trx().get(b"a", true).expect("get"),
+            Some(b"4".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_range_reads() {
+        let store = MemoryStore::default();
+        run_commit(&store, |trx| {
+            for i in 0..10u8 {
+                trx.set(&[b'k', i], &[i]);
+            }
+        })
+        .expect("commit");
+
+        let trx = store.create_trx();
+        trx.clear_range(&[b'k', 2], &[b'k', 4]);
+        trx.set(&[b'k', 20], &[20]);
+        let all = trx
+            .get_range(&RangeOption::from((b"k".to_vec(), b"l".to_vec())), true)
+            .expect("range");
+        let keys = all.iter().map(|(k, _)| k[1]).collect::<Vec<_>>();
+        assert_eq!(keys, vec![0, 1, 4, 5, 6, 7, 8, 9, 20]);
+
+        let range = RangeOption {
+            limit: Some(2),
+            reverse: true,
+            ..RangeOption::from((vec![b'k', 1], vec![b'k', 9]))
+        };
+        let last = trx.get_range(&range, false).expect("range");
+        assert_eq!(last.iter().map(|(k, _)| k[1]).collect::<Vec<_>>(), vec![8, 7]);
+
+        // a write past the part of the range read does not conflict
+        run_commit(&store, |trx| trx.set(&[b'k', 1], b"new")).expect("commit");
+        trx.commit().expect("commit");
+        assert_eq!(
+            store
+                .create_trx()
+                .get_key(&KeySelector::first_greater_than(vec![b'k', 1]), true)
+                .map_err(|e| e.code()),
+            Ok(vec![b'k', 4])
+        );
+    }
+
+    #[test]
+    fn test_atomic_operations() {
+        let store = MemoryStore::default();
+        run_commit(&store, |trx| {
+            trx.atomic_op(b"add", &u16::MAX.to_le_bytes(), MutationType::Add);
+            trx.atomic_op(b"add", &1u32.to_le_bytes(), MutationType::Add);
+            trx.atomic_op(b"min", &7u64.to_le_bytes(), MutationType::Min);
+            trx.atomic_op(b"min", &300u64.to_le_bytes(), MutationType::Min);
+            trx.set(b"cac", b"x");
+            trx.atomic_op(b"cac", b"x", MutationType::CompareAndClear);
+        })
+        .expect("commit");
+        let trx = store.create_trx();
+        assert_eq!(
+            trx.get(b"add", true).expect("get"),
+            Some(65536u32.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            trx.get(b"min", true).expect("get"),
+            Some(7u64.to_le_bytes().to_vec())
+        );
+        assert_eq!(trx.get(b"cac", true).expect("get"), None);
+
+        let placeholder = [b"vs".as_slice(), &[0xFF; 10], &2u32.to_le_bytes()].concat();
+        run_commit(&store, |trx| {
+            trx.atomic_op(&placeholder, b"v", MutationType::SetVersionstampedKey)
+        })
+        .expect("commit");
+        let trx = store.create_trx();
+        let version = trx.get_read_version().expect("version");
+        let key = [b"vs".as_slice(), &version.to_be_bytes(), &[0, 0]].concat();
+        assert_eq!(trx.get(&key, true).expect("get"), Some(b"v".to_vec()));
+    }
+
+    #[test]
+    fn test_limits() {
+        let store = MemoryStore::default();
+        let error =
+            run_commit(&store, |trx| trx.set(b"big", &[0; MAX_VALUE_SIZE + 1])).expect_err("value too large");
+        assert_eq!(error.code(), code::VALUE_TOO_LARGE);
+        let error =
+            run_commit(&store, |trx| trx.set(&[0; MAX_KEY_SIZE + 1], b"")).expect_err("key too large");
+        assert_eq!(error.code(), code::KEY_TOO_LARGE);
+        let error = run_commit(&store, |trx| {
+            for i in 0..110u32 {
+                trx.set(&i.to_be_bytes(), &[0; MAX_VALUE_SIZE]);
+            }
+        })
+        .expect_err("transaction too large");
+        assert_eq!(error.code(), code::TRANSACTION_TOO_LARGE);
+
+        let future = store.create_trx();
+        future.set_read_version(100);
+        assert_eq!(
+            future.get(b"a", true).map_err(|e| e.code()),
+            Err(code::FUTURE_VERSION)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_watches() {
+        let store = MemoryStore::default();
+        let trx = store.create_trx();
+        let watch = trx.watch(b"w");
+        trx.commit().expect("commit");
+
+        // rewriting the same value does not fire it
+        run_commit(&store, |trx| trx.clear(b"w")).expect("commit");
+        let watch = tokio::spawn(watch);
+        tokio::task::yield_now().await;
+        assert!(!watch.is_finished());
+        run_commit(&store, |trx| trx.set(b"w", b"1")).expect("commit");
+        assert_eq!(watch.await.expect("join").map_err(|e| e.code()), Ok(()));
+
+        // nor does a watch whose transaction never commits
+        let trx = store.create_trx();
+        let watch = trx.watch(b"w");
+        drop(trx);
+        assert_eq!(watch.await.map_err(|e| e.code()), Err(code::OPERATION_CANCELLED));
+    }
+}
@@ -0,0 +1,449 @@
+//! Storage backends of the wrapper.
+//!
+//! Everything above this module reads and writes through `Transaction`,
+//! which is either a FoundationDB transaction or one of `MemoryStore`, an
+//! in-memory backend with the same transactional guarantees for tests and
+//! local development. Transactions are dispatched on an enum rather than a
+//! trait object: their operations return futures and `FoundationDB::run`
+//! takes generic closures, neither of which a trait object can carry.
+//!
+//! `Faults` wraps either backend to inject the errors FoundationDB raises
+//! under load or failure, so the retry paths can be tested.
+pub mod fault;
+pub mod memory;
+
+use fault::{CommitFault, Faults, History};
+use foundationdb::options::{MutationType, TransactionOption};
+use foundationdb::{Database, FdbError, FdbResult, KeySelector, RangeOption, TransactionCommitError};
+use futures::future::{BoxFuture, FutureExt, TryFutureExt};
+use futures_util::TryStreamExt;
+pub use memory::MemoryStore;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+/// Error codes of FoundationDB the backends raise themselves.
+pub mod code {
+    pub const TRANSACTION_TOO_OLD: i32 = 1007;
+    pub const FUTURE_VERSION: i32 = 1009;
+    pub const NOT_COMMITTED: i32 = 1020;
+    pub const COMMIT_UNKNOWN_RESULT: i32 = 1021;
+    pub const TRANSACTION_TIMED_OUT: i32 = 1031;
+    pub const ACCESSED_UNREADABLE: i32 = 1036;
+    pub const OPERATION_CANCELLED: i32 = 1101;
+    pub const CLIENT_INVALID_OPERATION: i32 = 2000;
+    pub const USED_DURING_COMMIT: i32 = 2017;
+    pub const TRANSACTION_TOO_LARGE: i32 = 2101;
+    pub const KEY_TOO_LARGE: i32 = 2102;
+    pub const VALUE_TOO_LARGE: i32 = 2103;
+}
+
+/// Codes of the errors after which a transaction may be retried, as
+/// classified by FoundationDB's `retryable` predicate.
+const RETRYABLE: [i32; 11] = [1007, 1009, 1020, 1021, 1037, 1038, 1039, 1042, 1051, 1078, 1213];
+
+/// Codes of the errors after which the transaction may still have committed.
+const MAYBE_COMMITTED: [i32; 2] = [1021, 1039];
+
+/// Whether a transaction failing with `code` may be retried.
+pub fn is_retryable(code: i32) -> bool {
+    RETRYABLE.contains(&code)
+}
+
+/// Whether a transaction failing with `code` may nonetheless have committed.
+pub fn is_maybe_committed(code: i32) -> bool {
+    MAYBE_COMMITTED.contains(&code)
+}
+
+/// Storage backend selected at startup.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Engine {
+    #[default]
+    FoundationDB,
+    /// Records kept in process memory, lost when the process exits.
+    Memory,
+}
+
+impl fmt::Display for Engine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Engine::FoundationDB => "fdb",
+            Engine::Memory => "memory",
+        })
+    }
+}
+
+impl FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fdb" | "foundationdb" => Ok(Engine::FoundationDB),
+            "memory" => Ok(Engine::Memory),
+            _ => Err(format!("unknown storage '{}', expected fdb or memory", s)),
+        }
+    }
+}
+
+/// Where the data of a `FoundationDB` handle lives.
+#[derive(Clone)]
+pub(crate) enum Backend {
+    FoundationDB(Arc<Database>),
+    Memory(MemoryStore),
+    Faulty(Arc<Faults>),
+}
+
+impl Backend {
+    pub(crate) fn create_trx(&self) -> FdbResult<Transaction> {
+        let inner = match self {
+            Backend::FoundationDB(db) => Inner::FoundationDB(Arc::new(db.create_trx()?)),
+            Backend::Memory(store) => Inner::Memory(store.create_trx()),
+            Backend::Faulty(faults) => return faults.create_trx(),
+        };
+        Ok(Transaction {
+            inner,
+            faults: None,
+            history: None,
+        })
+    }
+}
+
+/// Whether a previous attempt of a transaction run by `FoundationDB::run`
+/// failed with an unknown outcome and may have committed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaybeCommitted(pub bool);
+
+impl From<MaybeCommitted> for bool {
+    fn from(m: MaybeCommitted) -> bool {
+        m.0
+    }
+}
+
+/// A transaction of either backend.
+///
+/// Clones share the transaction, so one can be handed to each attempt of a
+/// `run` closure. It must no longer be shared once committed or reset.
+#[derive(Clone)]
+pub struct Transaction {
+    inner: Inner,
+    faults: Option<Arc<Faults>>,
+    /// Kept for transactions subject to faults, which may have to hand it
+    /// over to a fresh transaction.
+    history: Option<Arc<Mutex<History>>>,
+}
+
+#[derive(Clone)]
+enum Inner {
+    FoundationDB(Arc<foundationdb::Transaction>),
+    Memory(memory::MemoryTransaction),
+}
+
+impl Transaction {
+    pub(crate) fn with_faults(mut self, faults: Arc<Faults>) -> Self {
+        self.faults = Some(faults);
+        self.history = Some(Arc::new(Mutex::new(History::new())));
+        self
+    }
+
+    pub(crate) fn with_history(mut self, history: History) -> Self {
+        self.history = Some(Arc::new(Mutex::new(history)));
+        self
+    }
+
+    /// Carry on `history`, that of the transaction this one was reset from.
+    fn continuing(mut self, history: Arc<Mutex<History>>) -> Self {
+        history.lock().expect("history lock").retried();
+        self.history = Some(history);
+        self
+    }
+
+    /// Copy of the history of a transaction subject to faults.
+    fn history(&self) -> Option<History> {
+        let history = self.history.as_ref()?;
+        Some(history.lock().expect("history lock").clone())
+    }
+
+    /// Let the injector, if any, delay or fail `read` first.
+    fn faulty<T: Send + 'static>(
+        &self,
+        read: BoxFuture<'static, FdbResult<T>>,
+    ) -> BoxFuture<'static, FdbResult<T>> {
+        match &self.faults {
+            Some(faults) => {
+                let injector = faults.injector.clone();
+                async move {
+                    injector.read().await?;
+                    read.await
+                }
+                .boxed()
+            }
+            None => read,
+        }
+    }
+
+    pub fn get(&self, key: &[u8], snapshot: bool) -> BoxFuture<'static, FdbResult<Option<Vec<u8>>>> {
+        let read = match &self.inner {
+            Inner::FoundationDB(trx) => trx
+                .get(key, snapshot)
+                .map_ok(|value| value.map(|v| v.to_vec()))
+                .boxed(),
+            Inner::Memory(trx) => futures::future::ready(trx.get(key, snapshot)).boxed(),
+        };
+        self.faulty(read)
+    }
+
+    /// Resolve `selector` to a key. Past the end of the keyspace, the key is
+    /// `\xff`.
+    pub fn get_key(
+        &self,
+        selector: &KeySelector<'_>,
+        snapshot: bool,
+    ) -> BoxFuture<'static, FdbResult<Vec<u8>>> {
+        let read = match &self.inner {
+            Inner::FoundationDB(trx) => trx.get_key(selector, snapshot).map_ok(|key| key.to_vec()).boxed(),
+            Inner::Memory(trx) => futures::future::ready(trx.get_key(selector, snapshot)).boxed(),
+        };
+        self.faulty(read)
+    }
+
+    /// Read the records selected by `range`, honouring its limit and direction.
+    pub async fn get_range(
+        &self,
+        range: &RangeOption<'_>,
+        snapshot: bool,
+    ) -> FdbResult<Vec<(Vec<u8>, Vec<u8>)>> {
+        if let Some(faults) = &self.faults {
+            faults.injector.read().await?;
+        }
+        match &self.inner {
+            Inner::FoundationDB(trx) => {
+                trx.get_ranges_keyvalues(range.clone(), snapshot)
+                    .map_ok(|kv| (kv.key().to_vec(), kv.value().to_vec()))
+                    .try_collect()
+                    .await
+            }
+            Inner::Memory(trx) => trx.get_range(range, snapshot),
+        }
+    }
+
+    pub fn set(&self, key: &[u8], value: &[u8]) {
+        match &self.inner {
+            Inner::FoundationDB(trx) => trx.set(key, value),
+            Inner::Memory(trx) => trx.set(key, value),
+        }
+    }
+
+    pub fn clear(&self, key: &[u8]) {
+        match &self.inner {
+            Inner::FoundationDB(trx) => trx.clear(key),
+            Inner::Memory(trx) => trx.clear(key),
+        }
+    }
+
+    pub fn clear_range(&self, begin: &[u8], end: &[u8]) {
+        match &self.inner {
+            Inner::FoundationDB(trx) => trx.clear_range(begin, end),
+            Inner::Memory(trx) => trx.clear_range(begin, end),
+        }
+    }
+
+    pub fn atomic_op(&self, key: &[u8], param: &[u8], op: MutationType) {
+        match &self.inner {
+            Inner::FoundationDB(trx) => trx.atomic_op(key, param, op),
+            Inner::Memory(trx) => trx.atomic_op(key, param, op),
+        }
+    }
+
+    /// Resolves once the record at `key` changes after the transaction
+    /// commits. Fails when the transaction does not commit.
+    pub fn watch(&self, key: &[u8]) -> BoxFuture<'static, FdbResult<()>> {
+        match &self.inner {
+            Inner::FoundationDB(trx) => trx.watch(key).boxed(),
+            Inner::Memory(trx) => trx.watch(key),
+        }
+    }
+
+    pub fn get_read_version(&self) -> BoxFuture<'static, FdbResult<i64>> {
+        let read = match &self.inner {
+            Inner::FoundationDB(trx) => trx.get_read_version().boxed(),
+            Inner::Memory(trx) => futures::future::ready(trx.get_read_version()).boxed(),
+        };
+        self.faulty(read)
+    }
+
+    pub fn set_read_version(&self, version: i64) {
+        match &self.inner {
+            Inner::FoundationDB(trx) => trx.set_read_version(version),
+            Inner::Memory(trx) => trx.set_read_version(version),
+        }
+    }
+
+    /// Set a transaction option. The memory backend honours the timeout,
+    /// the retry limit and the retry delay, and ignores the others.
+    pub fn set_option(&self, option: TransactionOption) -> FdbResult<()> {
+        if let Some(history) = &self.history {
+            history.lock().expect("history lock").record(option.clone());
+        }
+        match &self.inner {
+            Inner::FoundationDB(trx) => trx.set_option(option),
+            Inner::Memory(trx) => {
+                trx.set_option(option);
+                Ok(())
+            }
+        }
+    }
+
+    pub async fn commit(self) -> Result<(), CommitError> {
+        let Some(faults) = self.faults.clone() else {
+            return self.commit_now().await;
+        };
+        let injected = |trx: Transaction, error: i32| CommitError::Injected {
+            retry: Retry::Reset(Box::new(trx)),
+            faults: faults.clone(),
+            error: FdbError::from_code(error),
+        };
+        let fault = match faults.injector.commit().await {
+            Ok(fault) => fault,
+            Err(e) => return Err(injected(self, e.code())),
+        };
+        match fault {
+            CommitFault::None => {
+                let history = self.history.clone();
+                self.commit_now()
+                    .await
+                    .map_err(|e| CommitError::Faulty(Box::new(e), faults.clone(), history))
+            }
+            CommitFault::NotCommitted => Err(injected(self, code::NOT_COMMITTED)),
+            CommitFault::UnknownResult { applied: false } => Err(injected(self, code::COMMIT_UNKNOWN_RESULT)),
+            CommitFault::UnknownResult { applied: true } => {
+                // A memory transaction can still be reset once committed; a
+                // FoundationDB one cannot, so its retry starts afresh
+                let retry = match &self.inner {
+                    Inner::Memory(_) => Retry::Reset(Box::new(self.clone())),
+                    Inner::FoundationDB(_) => Retry::Resume(self.history().unwrap_or_else(History::new)),
+                };
+                let history = self.history.clone();
+                if let Err(e) = self.commit_now().await {
+                    return Err(CommitError::Faulty(Box::new(e), faults, history));
+                }
+                Err(CommitError::Injected {
+                    retry,
+                    faults,
+                    error: FdbError::from_code(code::COMMIT_UNKNOWN_RESULT),
+                })
+            }
+        }
+    }
+
+    async fn commit_now(self) -> Result<(), CommitError> {
+        match self.inner {
+            Inner::FoundationDB(trx) => {
+                let Ok(trx) = Arc::try_unwrap(trx) else {
+                    return Err(CommitError::Shared);
+                };
+                trx.commit().await.map(|_| ()).map_err(CommitError::FoundationDB)
+            }
+            Inner::Memory(trx) => match trx.commit() {
+                Ok(()) => Ok(()),
+                Err(error) => Err(CommitError::Memory(trx, error)),
+            },
+        }
+    }
+
+    /// Reset the transaction after `error`, backing off before a retry, or
+    /// give the error back when it must not be retried.
+    pub async fn on_error(self, error: FdbError) -> FdbResult<Transaction> {
+        let (faults, history) = (self.faults, self.history);
+        let inner = match self.inner {
+            Inner::FoundationDB(trx) => {
+                let Ok(trx) = Arc::try_unwrap(trx) else {
+                    return Err(FdbError::from_code(code::USED_DURING_COMMIT));
+                };
+                Inner::FoundationDB(Arc::new(trx.on_error(error).await?))
+            }
+            Inner::Memory(trx) => Inner::Memory(trx.on_error(error).await?),
+        };
+        if let Some(history) = &history {
+            history.lock().expect("history lock").retried();
+        }
+        Ok(Transaction {
+            inner,
+            faults,
+            history,
+        })
+    }
+}
+
+/// A failed commit, holding the transaction to retry.
+pub enum CommitError {
+    FoundationDB(TransactionCommitError),
+    Memory(memory::MemoryTransaction, FdbError),
+    /// The transaction was still shared when it was committed.
+    Shared,
+    /// A failed commit of a transaction subject to faults, with its history.
+    Faulty(Box<CommitError>, Arc<Faults>, Option<Arc<Mutex<History>>>),
+    /// A commit failed by the fault injector.
+    Injected {
+        retry: Retry,
+        faults: Arc<Faults>,
+        error: FdbError,
+    },
+}
+
+/// How to retry a transaction whose commit the fault injector failed.
+pub enum Retry {
+    /// Reset the transaction, as after any failed commit.
+    Reset(Box<Transaction>),
+    /// Replace a transaction that did commit with a fresh one resuming its
+    /// history.
+    Resume(History),
+}
+
+impl CommitError {
+    pub fn code(&self) -> i32 {
+        match self {
+            CommitError::FoundationDB(e) => e.code(),
+            CommitError::Memory(_, e) => e.code(),
+            CommitError::Shared => code::USED_DURING_COMMIT,
+            CommitError::Faulty(e, _, _) => e.code(),
+            CommitError::Injected { error, .. } => error.code(),
+        }
+    }
+
+    /// Reset the transaction for a retry, as `Transaction::on_error` does.
+    pub async fn on_error(self) -> FdbResult<Transaction> {
+        let inner = match self {
+            CommitError::FoundationDB(e) => Inner::FoundationDB(Arc::new(e.on_error().await?)),
+            CommitError::Memory(trx, e) => Inner::Memory(trx.on_error(e).await?),
+            CommitError::Shared => return Err(FdbError::from_code(code::USED_DURING_COMMIT)),
+            CommitError::Faulty(e, faults, history) => {
+                let trx = Box::pin(e.on_error()).await?.with_faults(faults);
+                return Ok(match history {
+                    Some(history) => trx.continuing(history),
+                    None => trx,
+                });
+            }
+            CommitError::Injected {
+                retry: Retry::Reset(trx),
+                error,
+                ..
+            } => return trx.on_error(error).await,
+            CommitError::Injected {
+                retry: Retry::Resume(history),
+                faults,
+                error,
+            } => return history.resume(&faults, error).await,
+        };
+        Ok(Transaction {
+            inner,
+            faults: None,
+            history: None,
+        })
+    }
+}
+
+impl From<CommitError> for FdbError {
+    fn from(e: CommitError) -> FdbError {
+        FdbError::from_code(e.code())
+    }
+}
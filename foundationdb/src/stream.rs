@@ -0,0 +1,47 @@
+use crate::Result;
+use bytes::Bytes;
+use futures::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A value read chunk by chunk.
+///
+/// The length is known upfront from the manifest, so it can be announced
+/// before the first chunk arrives. A checksum mismatch or a concurrent
+/// overwrite surfaces as the last item of the stream.
+pub struct ValueStream {
+    len: u64,
+    chunks: Pin<Box<dyn Stream<Item = Result<Bytes>> + Send>>,
+}
+
+impl ValueStream {
+    pub(crate) fn new(len: u64, chunks: impl Stream<Item = Result<Bytes>> + Send + 'static) -> Self {
+        Self {
+            len,
+            chunks: Box::pin(chunks),
+        }
+    }
+
+    /// Stream a value already held in memory as a single chunk.
+    pub fn from_bytes(value: Vec<u8>) -> Self {
+        let len = value.len() as u64;
+        Self::new(len, futures::stream::once(async move { Ok(Bytes::from(value)) }))
+    }
+
+    /// Total length of the value in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Stream for ValueStream {
+    type Item = Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.chunks.as_mut().poll_next(cx)
+    }
+}
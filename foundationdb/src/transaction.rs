@@ -0,0 +1,79 @@
+use crate::storage::Transaction;
+use foundationdb::FdbResult;
+use foundationdb::options::TransactionOption;
+use foundationdb_tuple::pack;
+use std::time::Duration;
+
+/// Retries allowed per transaction unless configured otherwise.
+pub const DEFAULT_RETRY_LIMIT: u32 = 10;
+
+/// Scheduling class of a transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    #[default]
+    Default,
+    /// Background work that yields to everything else under load.
+    Batch,
+    /// Bypasses admission control; for work that must never queue.
+    SystemImmediate,
+}
+
+/// Options applied to every transaction the wrapper runs.
+///
+/// Only errors FoundationDB reports as retryable are retried, with the
+/// exponential backoff of `on_error`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransactionOptions {
+    /// Cancel a transaction still running after this long, retries included.
+    pub timeout: Option<Duration>,
+    /// Give up after this many retries; `None` retries until the timeout.
+    pub retry_limit: Option<u32>,
+    /// Cap on the backoff between retries, one second when unset.
+    pub max_retry_delay: Option<Duration>,
+    pub priority: Priority,
+}
+
+impl Default for TransactionOptions {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retry_limit: Some(DEFAULT_RETRY_LIMIT),
+            max_retry_delay: None,
+            priority: Priority::Default,
+        }
+    }
+}
+
+impl TransactionOptions {
+    /// Set the options on `trx`. FoundationDB keeps them across `on_error`,
+    /// and setting them again on each attempt is harmless.
+    pub(crate) fn apply(&self, trx: &Transaction) -> FdbResult<()> {
+        if let Some(timeout) = self.timeout {
+            trx.set_option(TransactionOption::Timeout(millis(timeout)))?;
+        }
+        if let Some(limit) = self.retry_limit {
+            trx.set_option(TransactionOption::RetryLimit(limit.min(i32::MAX as u32) as i32))?;
+        }
+        if let Some(delay) = self.max_retry_delay {
+            trx.set_option(TransactionOption::MaxRetryDelay(millis(delay)))?;
+        }
+        match self.priority {
+            Priority::Default => {}
+            Priority::Batch => trx.set_option(TransactionOption::PriorityBatch)?,
+            Priority::SystemImmediate => trx.set_option(TransactionOption::PrioritySystemImmediate)?,
+        }
+        Ok(())
+    }
+}
+
+fn millis(duration: Duration) -> i32 {
+    duration.as_millis().min(i32::MAX as u128) as i32
+}
+
+/// Key of a fresh commit marker, written alongside a non-idempotent
+/// transaction so a retry can tell whether an attempt whose outcome was
+/// unknown actually committed.
+pub(crate) fn new_marker() -> Vec<u8> {
+    let id: [u8; 16] = rand::random();
+    pack(&("fdb", "commit_marker", id.as_slice()))
+}
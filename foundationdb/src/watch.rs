@@ -0,0 +1,68 @@
+use crate::transaction::TransactionOptions;
+use crate::{FoundationDB, Result};
+use std::time::Duration;
+
+/// Watches a client may have outstanding, FoundationDB's own default.
+pub const DEFAULT_MAX_WATCHES: usize = 10_000;
+
+/// FoundationDB error code raised when a client exceeds its watch limit.
+const TOO_MANY_WATCHES: i32 = 1032;
+
+/// Pause before re-arming a watch refused for exceeding the limit, which
+/// happens when other handles share the database connection.
+const REARM_DELAY: Duration = Duration::from_millis(100);
+
+/// Wait until the raw record at `key` differs from `baseline` and return its
+/// new value. Without a baseline, the value read when the watch is first
+/// armed is the one to change from.
+///
+/// The watch holds one of the permits of `fdb` while armed, so the wrapper
+/// never asks FoundationDB for more watches than it allows. It is re-armed,
+/// after checking the record did not change in between, whenever FoundationDB
+/// fails it with a retryable error. Dropping the future cancels the watch.
+///
+/// The transaction timeout would cancel the watch as well, so it is left out.
+pub(crate) async fn changed(
+    fdb: &FoundationDB,
+    key: &[u8],
+    mut baseline: Option<Option<Vec<u8>>>,
+) -> Result<Option<Vec<u8>>> {
+    let _permit = fdb
+        .watches
+        .acquire()
+        .await
+        .expect("watch permits are never closed");
+    let options = TransactionOptions {
+        timeout: None,
+        ..*fdb.transaction_options()
+    };
+    let mut trx = fdb.create_trx_with(&options)?;
+    loop {
+        let current = match trx.get(key, false).await {
+            Ok(value) => value,
+            Err(e) => {
+                trx = trx.on_error(e).await?;
+                continue;
+            }
+        };
+        match &baseline {
+            Some(expected) if *expected != current => return Ok(current),
+            Some(_) => {}
+            None => baseline = Some(current),
+        }
+
+        // The watch only starts reporting changes once its transaction committed
+        let watch = trx.watch(key);
+        if let Err(e) = trx.commit().await {
+            trx = e.on_error().await?;
+            continue;
+        }
+        trx = fdb.create_trx_with(&options)?;
+        match watch.await {
+            // Read the new value; a change reverted in the meantime re-arms
+            Ok(()) => {}
+            Err(e) if e.code() == TOO_MANY_WATCHES => tokio::time::sleep(REARM_DELAY).await,
+            Err(e) => trx = trx.on_error(e).await?,
+        }
+    }
+}
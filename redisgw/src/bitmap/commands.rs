@@ -0,0 +1,103 @@
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+use crate::command::CmdMap;
+use crate::bitmap::operations::{
+    BitmapOperations, parse_bit, parse_bit_offset, parse_bitcount_args, parse_bitfield_args, parse_bitop,
+    parse_bitpos_args,
+};
+
+crate::command_handler_static!(SETBIT, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'SETBIT' command".into());
+    }
+    let key = args[0].as_slice();
+    let offset = match parse_bit_offset(&args[1]) {
+        Ok(offset) => offset,
+        Err(e) => return e.into(),
+    };
+    match parse_bit(&args[2]) {
+        Ok(bit) => gw.setbit(key, offset, bit).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(GETBIT, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'GETBIT' command".into());
+    }
+    let key = args[0].as_slice();
+    match parse_bit_offset(&args[1]) {
+        Ok(offset) => gw.getbit(key, offset).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(BITCOUNT, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'BITCOUNT' command".into());
+    }
+    let key = args[0].as_slice();
+
+    let extra_refs: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    match parse_bitcount_args(&extra_refs) {
+        Ok(range) => gw.bitcount(key, range).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(BITPOS, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'BITPOS' command".into());
+    }
+    let key = args[0].as_slice();
+
+    let extra_refs: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    match parse_bitpos_args(&extra_refs) {
+        Ok((bit, range)) => gw.bitpos(key, bit, range).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(BITOP, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'BITOP' command".into());
+    }
+    let op = match parse_bitop(&args[0]) {
+        Ok(op) => op,
+        Err(e) => return e.into(),
+    };
+    let destkey = args[1].as_slice();
+    let keys: Vec<&[u8]> = args.iter().skip(2).map(|v| v.as_slice()).collect();
+    gw.bitop(op, destkey, &keys).await
+});
+
+crate::command_handler_static!(BITFIELD, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'BITFIELD' command".into());
+    }
+    let key = args[0].as_slice();
+
+    let extra_refs: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    match parse_bitfield_args(&extra_refs) {
+        Ok(ops) => gw.bitfield(key, &ops).await,
+        Err(e) => e.into(),
+    }
+});
+
+/// Return handlers for bitmap commands (SETBIT, GETBIT, BITCOUNT, BITPOS, BITOP, BITFIELD)
+pub fn commands() -> CmdMap {
+    let mut m: CmdMap = CmdMap::new();
+
+    m.insert("SETBIT".to_string(), SETBIT.clone());
+
+    m.insert("GETBIT".to_string(), GETBIT.clone());
+
+    m.insert("BITCOUNT".to_string(), BITCOUNT.clone());
+
+    m.insert("BITPOS".to_string(), BITPOS.clone());
+
+    m.insert("BITOP".to_string(), BITOP.clone());
+
+    m.insert("BITFIELD".to_string(), BITFIELD.clone());
+
+    m
+}
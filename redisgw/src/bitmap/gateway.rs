@@ -0,0 +1,167 @@
+//! Bitmap commands, over string values.
+//!
+//! Bitmaps are plain strings, so that GET and SET see the bytes SETBIT
+//! wrote: each command reads or rewrites only the chunks holding the bits it
+//! addresses.
+//!
+//! SETBIT and BITFIELD read the bytes they change and write them back with
+//! `update_range`, under the lock of the key, rather than with FoundationDB's
+//! BitOr and BitAnd atomics. A chunk may be compressed or encrypted, so a bit
+//! of the value has no fixed place in a stored record for an atomic to set,
+//! and the manifest holds the checksum of every chunk, which a chunk changed
+//! without being read would no longer match. Both commands also reply with
+//! the bits they replace, which takes a read anyway, and may extend the
+//! value, which rewrites its manifest.
+use crate::bitmap::operations::bits;
+use crate::bitmap::operations::{BitOp, BitRange, BitUnit, BitfieldOp, BitmapOperations};
+use crate::error::Error;
+use crate::gateway::RedisGateway;
+use crate::string::datamodel::StringDataModel;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+/// Range of BITCOUNT and BITPOS when none is given: the whole value.
+const WHOLE_VALUE: BitRange = BitRange {
+    start: 0,
+    end: None,
+    unit: BitUnit::Byte,
+};
+
+impl BitmapOperations for RedisGateway {
+    async fn setbit(&self, key: &[u8], offset: u64, bit: u8) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        let byte = offset / 8;
+        let update = |window: &mut Vec<u8>| bits::set_bit(window, offset % 8, bit);
+        match dm.update_range(key, byte..byte + 1, update).await {
+            Ok(previous) => Frame::Integer(previous as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn getbit(&self, key: &[u8], offset: u64) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        let byte = offset / 8;
+        match dm.read_range(key, |_| byte..byte + 1).await {
+            Ok((_, bytes)) => Frame::Integer(bits::get_bit(&bytes, offset % 8) as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn bitcount(&self, key: &[u8], range: Option<BitRange>) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        let range = range.unwrap_or(WHOLE_VALUE);
+        match dm.read_range(key, |len| byte_span(&range, len)).await {
+            Ok((len, bytes)) => {
+                let count = bits::resolve(&range, len).map_or(0, |(first, last)| {
+                    let base = first / 8 * 8;
+                    bits::count(&bytes, first - base, last - base)
+                });
+                Frame::Integer(count as i64)
+            }
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn bitpos(&self, key: &[u8], bit: u8, range: Option<BitRange>) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        let end_given = range.as_ref().is_some_and(|r| r.end.is_some());
+        let range = range.unwrap_or(WHOLE_VALUE);
+        let (len, bytes) = match dm.read_range(key, |len| byte_span(&range, len)).await {
+            Ok(read) => read,
+            Err(e) => return e.into(),
+        };
+        if len == 0 && bit == 0 {
+            // A missing key reads as zeros, unlike an empty string
+            match dm.exists(&[key]).await {
+                Ok(0) => return Frame::Integer(0),
+                Ok(_) => {}
+                Err(e) => return e.into(),
+            }
+        }
+        let Some((first, last)) = bits::resolve(&range, len) else {
+            return Frame::Integer(-1);
+        };
+        let base = first / 8 * 8;
+        match bits::position(&bytes, bit, first - base, last - base) {
+            Some(pos) => Frame::Integer((base + pos) as i64),
+            // Without an end, the value is taken as followed by clear bits
+            None if bit == 0 && !end_given => Frame::Integer(last as i64 + 1),
+            None => Frame::Integer(-1),
+        }
+    }
+
+    async fn bitop(&self, op: BitOp, destkey: &[u8], keys: &[&[u8]]) -> Frame {
+        if op == BitOp::Not && keys.len() != 1 {
+            return Error::Other("BITOP NOT must be called with a single source key.".to_string()).into();
+        }
+        let dm = StringDataModel::new(self.fdb.clone());
+        // Sources are read at a single version, as MGET does
+        let sources = match dm.mget(keys).await {
+            Ok(values) => values
+                .into_iter()
+                .map(Option::unwrap_or_default)
+                .collect::<Vec<_>>(),
+            Err(e) => return e.into(),
+        };
+        let result = bits::bitop(op, &sources);
+        let stored = if result.is_empty() {
+            dm.remove(&[destkey], false).await.map(|_| ())
+        } else {
+            dm.mset(&[(destkey, result.as_slice())]).await
+        };
+        match stored {
+            Ok(()) => Frame::Integer(result.len() as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn bitfield(&self, key: &[u8], ops: &[BitfieldOp]) -> Frame {
+        let (Some(start), Some(end)) = (
+            ops.iter().map(|op| op.bits().start).min(),
+            ops.iter().map(|op| op.bits().end).max(),
+        ) else {
+            return Frame::Array(Vec::new());
+        };
+        // Bytes holding every field, the first of them starting at bit `base`
+        let bytes = start / 8..end.div_ceil(8);
+        let base = bytes.start * 8;
+        let dm = StringDataModel::new(self.fdb.clone());
+        let replies = match ops.iter().filter(|op| op.writes()).map(|op| op.bits().end).max() {
+            Some(write_end) => {
+                // Writes extend the value up to their field, even those
+                // failing on overflow, as in Redis
+                let written = (write_end.div_ceil(8) - bytes.start) as usize;
+                let update = |window: &mut Vec<u8>| {
+                    if window.len() < written {
+                        window.resize(written, 0);
+                    }
+                    ops.iter()
+                        .map(|op| bits::apply(window, base, op))
+                        .collect::<Vec<_>>()
+                };
+                dm.update_range(key, bytes, update).await
+            }
+            None => dm
+                .read_range(key, |_| bytes.clone())
+                .await
+                .map(|(_, mut window)| {
+                    ops.iter()
+                        .map(|op| bits::apply(&mut window, base, op))
+                        .collect::<Vec<_>>()
+                }),
+        };
+        match replies {
+            Ok(replies) => Frame::Array(
+                replies
+                    .into_iter()
+                    .map(|r| r.map_or(Frame::Null, Frame::Integer))
+                    .collect(),
+            ),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// Bytes holding the bits `range` covers in a value of `len` bytes.
+fn byte_span(range: &BitRange, len: u64) -> std::ops::Range<u64> {
+    bits::resolve(range, len).map_or(0..0, |(first, last)| first / 8..last / 8 + 1)
+}
@@ -0,0 +1,188 @@
+use super::types::{BitOp, BitRange, BitUnit, BitfieldOp, BitfieldType, Overflow};
+
+/// Bits a bitmap holds at most, its value being capped at
+/// `fdb::MAX_VALUE_LEN` bytes.
+pub const MAX_BITS: u64 = fdb::MAX_VALUE_LEN as u64 * 8;
+
+/// Bit `offset` of `bytes`, the most significant bit of each byte coming
+/// first as in Redis. Bits past the end read as 0.
+pub fn get_bit(bytes: &[u8], offset: u64) -> u8 {
+    let byte = bytes.get((offset / 8) as usize).copied().unwrap_or(0);
+    (byte >> (7 - offset % 8)) & 1
+}
+
+/// Set bit `offset` of `bytes` to `bit`, zero-extending `bytes` up to it.
+/// Returns the previous bit.
+pub fn set_bit(bytes: &mut Vec<u8>, offset: u64, bit: u8) -> u8 {
+    let index = (offset / 8) as usize;
+    if bytes.len() <= index {
+        bytes.resize(index + 1, 0);
+    }
+    let mask = 1 << (7 - offset % 8);
+    let previous = (bytes[index] & mask != 0) as u8;
+    if bit == 1 {
+        bytes[index] |= mask;
+    } else {
+        bytes[index] &= !mask;
+    }
+    previous
+}
+
+/// Bits, both inclusive, that `range` covers in a value of `len` bytes, the
+/// way Redis resolves the ranges of BITCOUNT and BITPOS: negative ends count
+/// from the end of the value and are clamped to it. `None` when the range is
+/// empty.
+pub fn resolve(range: &BitRange, len: u64) -> Option<(u64, u64)> {
+    let total = match range.unit {
+        BitUnit::Byte => len as i64,
+        BitUnit::Bit => len as i64 * 8,
+    };
+    let (start, end) = (range.start, range.end.unwrap_or(-1));
+    if start < 0 && end < 0 && start > end {
+        return None;
+    }
+    let start = (if start < 0 { start + total } else { start }).max(0);
+    let end = (if end < 0 { end + total } else { end }).max(0).min(total - 1);
+    if start > end {
+        return None;
+    }
+    let (start, end) = (start as u64, end as u64);
+    Some(match range.unit {
+        BitUnit::Byte => (start * 8, end * 8 + 7),
+        BitUnit::Bit => (start, end),
+    })
+}
+
+/// Mask of the bits of byte `index` within bits `first..=last`.
+fn byte_mask(index: u64, first: u64, last: u64) -> u8 {
+    let lo = if index == first / 8 { first % 8 } else { 0 };
+    let hi = if index == last / 8 { last % 8 } else { 7 };
+    (0xFF >> lo) & (0xFF << (7 - hi))
+}
+
+/// Number of bits set within bits `first..=last` of `bytes`.
+pub fn count(bytes: &[u8], first: u64, last: u64) -> u64 {
+    (first / 8..=last / 8)
+        .map(|i| {
+            let byte = bytes.get(i as usize).copied().unwrap_or(0);
+            (byte & byte_mask(i, first, last)).count_ones() as u64
+        })
+        .sum()
+}
+
+/// Position of the first bit equal to `bit` within bits `first..=last` of
+/// `bytes`, bits past the end reading as 0.
+pub fn position(bytes: &[u8], bit: u8, first: u64, last: u64) -> Option<u64> {
+    (first / 8..=last / 8).find_map(|i| {
+        let byte = bytes.get(i as usize).copied().unwrap_or(0);
+        let byte = if bit == 1 { byte } else { !byte };
+        let matching = byte & byte_mask(i, first, last);
+        (matching != 0).then(|| i * 8 + matching.leading_zeros() as u64)
+    })
+}
+
+/// Combine `sources` with `op`, as long as the longest of them; shorter
+/// ones are zero-padded. NOT takes a single source.
+pub fn bitop(op: BitOp, sources: &[Vec<u8>]) -> Vec<u8> {
+    if op == BitOp::Not {
+        return sources
+            .first()
+            .map_or_else(Vec::new, |s| s.iter().map(|b| !b).collect());
+    }
+    let len = sources.iter().map(Vec::len).max().unwrap_or(0);
+    (0..len)
+        .map(|i| {
+            let mut bytes = sources.iter().map(|s| s.get(i).copied().unwrap_or(0));
+            let first = bytes.next().unwrap_or(0);
+            bytes.fold(first, |acc, b| match op {
+                BitOp::And => acc & b,
+                BitOp::Or => acc | b,
+                _ => acc ^ b,
+            })
+        })
+        .collect()
+}
+
+/// Value of field `ty` starting at bit `offset` of `bytes`.
+pub fn get_field(bytes: &[u8], ty: BitfieldType, offset: u64) -> i64 {
+    let raw = (0..ty.bits as u64).fold(0u64, |acc, i| (acc << 1) | get_bit(bytes, offset + i) as u64);
+    if ty.signed && ty.bits < 64 && (raw >> (ty.bits - 1)) & 1 == 1 {
+        // Sign-extend
+        (raw | (u64::MAX << ty.bits)) as i64
+    } else {
+        raw as i64
+    }
+}
+
+/// Write the low bits of `value` to field `ty` starting at bit `offset` of
+/// `bytes`, zero-extending `bytes` up to it.
+pub fn set_field(bytes: &mut Vec<u8>, ty: BitfieldType, offset: u64, value: i64) {
+    for i in 0..ty.bits as u64 {
+        let bit = ((value as u64) >> (ty.bits as u64 - 1 - i)) & 1;
+        set_bit(bytes, offset + i, bit as u8);
+    }
+}
+
+/// Fit `n` into field `ty` following `overflow`, as Redis does. `None`
+/// when `n` does not fit and `overflow` is FAIL.
+pub fn fit(ty: BitfieldType, n: i128, overflow: Overflow) -> Option<i64> {
+    let (min, max) = if ty.signed {
+        (-(1i128 << (ty.bits - 1)), (1i128 << (ty.bits - 1)) - 1)
+    } else {
+        (0, (1i128 << ty.bits) - 1)
+    };
+    if (min..=max).contains(&n) {
+        return Some(n as i64);
+    }
+    match overflow {
+        Overflow::Wrap => {
+            let modulus = 1i128 << ty.bits;
+            let wrapped = n.rem_euclid(modulus);
+            let wrapped = if ty.signed && wrapped > max {
+                wrapped - modulus
+            } else {
+                wrapped
+            };
+            Some(wrapped as i64)
+        }
+        Overflow::Sat => Some(n.clamp(min, max) as i64),
+        Overflow::Fail => None,
+    }
+}
+
+/// Run `op` against `bytes`, which hold the value from bit `base` on, and
+/// return its reply: the field for GET, its previous value for SET and its
+/// new value for INCRBY, or `None` when a FAIL overflow left it unchanged.
+pub fn apply(bytes: &mut Vec<u8>, base: u64, op: &BitfieldOp) -> Option<i64> {
+    match *op {
+        BitfieldOp::Get { ty, offset } => Some(get_field(bytes, ty, offset - base)),
+        BitfieldOp::Set {
+            ty,
+            offset,
+            value,
+            overflow,
+        } => {
+            // Unsigned fields take the value as Redis does, as a u64
+            let n = if ty.signed {
+                value as i128
+            } else {
+                value as u64 as i128
+            };
+            let value = fit(ty, n, overflow)?;
+            let previous = get_field(bytes, ty, offset - base);
+            set_field(bytes, ty, offset - base, value);
+            Some(previous)
+        }
+        BitfieldOp::IncrBy {
+            ty,
+            offset,
+            increment,
+            overflow,
+        } => {
+            let current = get_field(bytes, ty, offset - base);
+            let value = fit(ty, current as i128 + increment as i128, overflow)?;
+            set_field(bytes, ty, offset - base, value);
+            Some(value)
+        }
+    }
+}
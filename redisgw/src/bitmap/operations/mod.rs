@@ -0,0 +1,8 @@
+pub mod types;
+pub mod parse;
+pub mod traits;
+pub mod bits;
+
+pub use types::*;
+pub use traits::*;
+pub(crate) use parse::{parse_bit, parse_bit_offset, parse_bitcount_args, parse_bitfield_args, parse_bitop, parse_bitpos_args};
@@ -0,0 +1,174 @@
+use super::bits::MAX_BITS;
+use crate::bitmap::operations::{BitOp, BitRange, BitUnit, BitfieldOp, BitfieldType, Overflow};
+use crate::error::{Error, Result};
+
+fn parse_i64(value: &[u8]) -> Option<i64> {
+    std::str::from_utf8(value).ok()?.parse::<i64>().ok()
+}
+
+fn invalid_offset() -> Error {
+    Error::Other("bit offset is not an integer or out of range".to_string())
+}
+
+/// Parse the bit offset of SETBIT and GETBIT, which must address a bit of
+/// the longest value a key may hold.
+pub(crate) fn parse_bit_offset(value: &[u8]) -> Result<u64> {
+    match parse_i64(value) {
+        Some(n) if n >= 0 && (n as u64) < MAX_BITS => Ok(n as u64),
+        _ => Err(invalid_offset()),
+    }
+}
+
+/// Parse the bit SETBIT writes.
+pub(crate) fn parse_bit(value: &[u8]) -> Result<u8> {
+    match parse_i64(value) {
+        Some(n @ (0 | 1)) => Ok(n as u8),
+        _ => Err(Error::Other("bit is not an integer or out of range".to_string())),
+    }
+}
+
+/// Parse the range of BITCOUNT and BITPOS from `start`, `end` and the unit
+/// that may follow them.
+fn parse_range(start: &[u8], end: Option<&[u8]>, unit: Option<&[u8]>) -> Result<BitRange> {
+    let start = parse_i64(start).ok_or(Error::NotAnInteger)?;
+    let end = end
+        .map(|end| parse_i64(end).ok_or(Error::NotAnInteger))
+        .transpose()?;
+    let unit = match unit.map(|u| u.to_ascii_uppercase()).as_deref() {
+        None | Some(b"BYTE") => BitUnit::Byte,
+        Some(b"BIT") => BitUnit::Bit,
+        Some(_) => return Err(Error::Syntax),
+    };
+    Ok(BitRange { start, end, unit })
+}
+
+/// Parse the arguments of BITCOUNT following the key: nothing, or a start
+/// and an end optionally followed by BYTE or BIT.
+pub(crate) fn parse_bitcount_args(args: &[&[u8]]) -> Result<Option<BitRange>> {
+    match args {
+        [] => Ok(None),
+        [start, end] => parse_range(start, Some(*end), None).map(Some),
+        [start, end, unit] => parse_range(start, Some(*end), Some(*unit)).map(Some),
+        _ => Err(Error::Syntax),
+    }
+}
+
+/// Parse the arguments of BITPOS following the key: the bit looked for,
+/// then an optional start, end and BYTE or BIT.
+pub(crate) fn parse_bitpos_args(args: &[&[u8]]) -> Result<(u8, Option<BitRange>)> {
+    let Some((bit, range)) = args.split_first() else {
+        return Err(Error::Syntax);
+    };
+    let bit = match parse_i64(bit).ok_or(Error::NotAnInteger)? {
+        n @ (0 | 1) => n as u8,
+        _ => return Err(Error::Other("The bit argument must be 1 or 0.".to_string())),
+    };
+    let range = match range {
+        [] => None,
+        [start] => Some(parse_range(start, None, None)?),
+        [start, end] => Some(parse_range(start, Some(*end), None)?),
+        [start, end, unit] => Some(parse_range(start, Some(*end), Some(*unit))?),
+        _ => return Err(Error::Syntax),
+    };
+    Ok((bit, range))
+}
+
+/// Parse the operation of BITOP.
+pub(crate) fn parse_bitop(op: &[u8]) -> Result<BitOp> {
+    match op.to_ascii_uppercase().as_slice() {
+        b"AND" => Ok(BitOp::And),
+        b"OR" => Ok(BitOp::Or),
+        b"XOR" => Ok(BitOp::Xor),
+        b"NOT" => Ok(BitOp::Not),
+        _ => Err(Error::Syntax),
+    }
+}
+
+/// Parse a BITFIELD type such as `i8` or `u16`.
+fn parse_bitfield_type(value: &[u8]) -> Result<BitfieldType> {
+    let invalid = || {
+        Error::Other(
+            "Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is."
+                .to_string(),
+        )
+    };
+    let (signed, bits) = match value.split_first() {
+        Some((b'i' | b'I', bits)) => (true, bits),
+        Some((b'u' | b'U', bits)) => (false, bits),
+        _ => return Err(invalid()),
+    };
+    let bits = std::str::from_utf8(bits)
+        .ok()
+        .and_then(|s| s.parse::<u32>().ok())
+        .ok_or_else(invalid)?;
+    if bits == 0 || bits > 64 || (!signed && bits == 64) {
+        return Err(invalid());
+    }
+    Ok(BitfieldType { signed, bits })
+}
+
+/// Parse a BITFIELD offset: a bit offset, or `#n` for the n-th field of
+/// type `ty`.
+fn parse_bitfield_offset(value: &[u8], ty: BitfieldType) -> Result<u64> {
+    let offset = match value.strip_prefix(b"#") {
+        Some(index) => parse_i64(index).and_then(|n| n.checked_mul(ty.bits as i64)),
+        None => parse_i64(value),
+    };
+    match offset {
+        Some(n) if n >= 0 && (n as u64) < MAX_BITS => Ok(n as u64),
+        _ => Err(invalid_offset()),
+    }
+}
+
+/// Parse the subcommands of BITFIELD (GET/SET/INCRBY/OVERFLOW). Each
+/// OVERFLOW applies to the SET and INCRBY that follow it.
+pub(crate) fn parse_bitfield_args(args: &[&[u8]]) -> Result<Vec<BitfieldOp>> {
+    let mut ops = Vec::new();
+    let mut overflow = Overflow::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let subcommand = arg.to_ascii_uppercase();
+        if subcommand == b"OVERFLOW" {
+            let policy = args.next().ok_or(Error::Syntax)?;
+            overflow = match policy.to_ascii_uppercase().as_slice() {
+                b"WRAP" => Overflow::Wrap,
+                b"SAT" => Overflow::Sat,
+                b"FAIL" => Overflow::Fail,
+                _ => return Err(Error::Other("Invalid OVERFLOW type specified".to_string())),
+            };
+            continue;
+        }
+        let operands = match subcommand.as_slice() {
+            b"GET" => 2,
+            b"SET" | b"INCRBY" => 3,
+            _ => return Err(Error::Syntax),
+        };
+        let operands: Vec<&[u8]> = args.by_ref().take(operands).copied().collect();
+        let [ty, offset, rest @ ..] = operands.as_slice() else {
+            return Err(Error::Syntax);
+        };
+        let ty = parse_bitfield_type(ty)?;
+        let offset = parse_bitfield_offset(offset, ty)?;
+        let value = match rest {
+            [] if subcommand == b"GET" => None,
+            [value] => Some(parse_i64(value).ok_or(Error::NotAnInteger)?),
+            _ => return Err(Error::Syntax),
+        };
+        ops.push(match (subcommand.as_slice(), value) {
+            (b"SET", Some(value)) => BitfieldOp::Set {
+                ty,
+                offset,
+                value,
+                overflow,
+            },
+            (b"INCRBY", Some(increment)) => BitfieldOp::IncrBy {
+                ty,
+                offset,
+                increment,
+                overflow,
+            },
+            _ => BitfieldOp::Get { ty, offset },
+        });
+    }
+    Ok(ops)
+}
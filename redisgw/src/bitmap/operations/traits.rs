@@ -0,0 +1,21 @@
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+pub trait BitmapOperations {
+    /// Sets or clears the bit at an offset of the string value of a key.
+    fn setbit(&self, key: &[u8], offset: u64, bit: u8) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Returns the bit at an offset of the string value of a key.
+    fn getbit(&self, key: &[u8], offset: u64) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Counts the bits set in the string value of a key, or in a range of it.
+    fn bitcount(&self, key: &[u8], range: Option<super::types::BitRange>) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Finds the first bit set or cleared in the string value of a key, or in a range of it.
+    fn bitpos(&self, key: &[u8], bit: u8, range: Option<super::types::BitRange>) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Combines the string values of several keys bitwise and stores the result in a key.
+    fn bitop(&self, op: super::types::BitOp, destkey: &[u8], keys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Reads, writes and increments integer fields of the string value of a key.
+    fn bitfield(&self, key: &[u8], ops: &[super::types::BitfieldOp]) -> impl std::future::Future<Output = Frame> + Send;
+}
@@ -0,0 +1,87 @@
+/// Unit of the ranges given to BITCOUNT and BITPOS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BitUnit {
+    /// Offsets count bytes.
+    #[default]
+    Byte,
+    /// Offsets count bits.
+    Bit,
+}
+
+/// Range given to BITCOUNT and BITPOS, both ends inclusive and counted from
+/// the end of the value when negative.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitRange {
+    pub start: i64,
+    /// Last byte or bit; the end of the value when not given.
+    pub end: Option<i64>,
+    pub unit: BitUnit,
+}
+
+/// Operation of BITOP.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitOp {
+    And,
+    Or,
+    Xor,
+    Not,
+}
+
+/// Integer type of a BITFIELD field, such as `i8` or `u16`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BitfieldType {
+    pub signed: bool,
+    /// Width in bits: 1 to 64 when signed, 1 to 63 when not.
+    pub bits: u32,
+}
+
+/// What BITFIELD does when SET or INCRBY goes past the range of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Overflow {
+    /// Wrap around, as integers do in C.
+    #[default]
+    Wrap,
+    /// Saturate to the minimum or maximum of the field.
+    Sat,
+    /// Leave the field unchanged and reply with a null.
+    Fail,
+}
+
+/// Subcommand of BITFIELD, with the field it reads or writes starting at
+/// bit `offset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BitfieldOp {
+    /// Read the field.
+    Get { ty: BitfieldType, offset: u64 },
+    /// Write `value` to the field, replying with its previous value.
+    Set {
+        ty: BitfieldType,
+        offset: u64,
+        value: i64,
+        overflow: Overflow,
+    },
+    /// Add `increment` to the field, replying with its new value.
+    IncrBy {
+        ty: BitfieldType,
+        offset: u64,
+        increment: i64,
+        overflow: Overflow,
+    },
+}
+
+impl BitfieldOp {
+    /// Bits of the value the field spans.
+    pub fn bits(&self) -> std::ops::Range<u64> {
+        let (ty, offset) = match self {
+            BitfieldOp::Get { ty, offset }
+            | BitfieldOp::Set { ty, offset, .. }
+            | BitfieldOp::IncrBy { ty, offset, .. } => (ty, *offset),
+        };
+        offset..offset + ty.bits as u64
+    }
+
+    /// Whether the subcommand writes to the value.
+    pub fn writes(&self) -> bool {
+        !matches!(self, BitfieldOp::Get { .. })
+    }
+}
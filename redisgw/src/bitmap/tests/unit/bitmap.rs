@@ -0,0 +1,272 @@
+use crate::bitmap::operations::{
+    BitOp, BitRange, BitUnit, BitfieldOp, BitfieldType, BitmapOperations, Overflow, parse_bit, parse_bit_offset,
+    parse_bitcount_args, parse_bitfield_args, parse_bitpos_args,
+};
+use crate::gateway::RedisGateway;
+use crate::string::datamodel::SimpleDataPrefix;
+use crate::string::operations::{SetFlags, StringOperations};
+use fdb::FoundationDB;
+use fdb::codec::{Codec, Compression};
+use foundationdb_tuple::pack;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+fn range(start: i64, end: Option<i64>, unit: BitUnit) -> Option<BitRange> {
+    Some(BitRange { start, end, unit })
+}
+
+fn ints(values: &[Option<i64>]) -> Frame {
+    Frame::Array(values.iter().map(|v| v.map_or(Frame::Null, Frame::Integer)).collect())
+}
+
+#[tokio::test]
+async fn test_setbit_getbit() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    assert_eq!(gw.setbit(b"bits", 7, 1).await, Frame::Integer(0));
+    assert_eq!(gw.get(b"bits").await, Frame::BulkString(vec![0x01]));
+    assert_eq!(gw.getbit(b"bits", 0).await, Frame::Integer(0));
+    assert_eq!(gw.getbit(b"bits", 7).await, Frame::Integer(1));
+    assert_eq!(gw.getbit(b"bits", 100).await, Frame::Integer(0));
+    assert_eq!(gw.getbit(b"bits_missing", 3).await, Frame::Integer(0));
+
+    // Clearing replies with the previous bit; setting past the end extends
+    assert_eq!(gw.setbit(b"bits", 7, 0).await, Frame::Integer(1));
+    assert_eq!(gw.setbit(b"bits", 20, 1).await, Frame::Integer(0));
+    assert_eq!(gw.get(b"bits").await, Frame::BulkString(vec![0x00, 0x00, 0x08]));
+
+    // A counter is the string of its digits: "1" is 0b00110001
+    let _ = gw.incr(b"bits_counter").await;
+    assert_eq!(gw.getbit(b"bits_counter", 2).await, Frame::Integer(1));
+    assert_eq!(gw.getbit(b"bits_counter", 1).await, Frame::Integer(0));
+    assert_eq!(gw.setbit(b"bits_counter", 6, 1).await, Frame::Integer(0));
+    assert_eq!(gw.get(b"bits_counter").await, Frame::BulkString(b"3".to_vec()));
+}
+
+#[tokio::test]
+async fn test_setbit_rewrites_one_chunk() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+    let root = pack(&(SimpleDataPrefix::Data, b"bits_large".as_slice()));
+
+    let mut value = (0..350_000usize).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let _ = gw.set(b"bits_large", &value, SetFlags::default()).await;
+    let before = db.manifest(&root).await.expect("Unable to read manifest").expect("missing manifest");
+
+    // First bit of byte 250000, in the third chunk
+    assert_eq!(gw.setbit(b"bits_large", 2_000_000, 1).await, Frame::Integer(0));
+    assert_eq!(gw.getbit(b"bits_large", 2_000_000).await, Frame::Integer(1));
+    value[250_000] |= 0x80;
+    assert_eq!(gw.get(b"bits_large").await, Frame::BulkString(value.clone()));
+
+    let after = db.manifest(&root).await.expect("Unable to read manifest").expect("missing manifest");
+    assert_eq!(after.generation, before.generation);
+    assert_eq!(after.chunk_checksums[..2], before.chunk_checksums[..2]);
+    assert_ne!(after.chunk_checksums[2], before.chunk_checksums[2]);
+    assert_eq!(after.chunk_checksums[3], before.chunk_checksums[3]);
+
+    // Past the end, the value is zero-padded up to the bit
+    assert_eq!(gw.setbit(b"bits_large", 500_000 * 8 + 7, 1).await, Frame::Integer(0));
+    assert_eq!(gw.strlen(b"bits_large").await, Frame::Integer(500_001));
+    assert_eq!(db.verify(&root).await.expect("Unable to verify"), Some(500_001));
+
+    // Compressed values are rewritten whole
+    let compressed = FoundationDB::in_memory().with_compression(Compression {
+        codec: Codec::Zstd,
+        min_size: 0,
+    });
+    let gw = RedisGateway::new(compressed);
+    let mut value = b"compressible ".repeat(20_000);
+    let _ = gw.set(b"bits_zstd", &value, SetFlags::default()).await;
+    assert_eq!(gw.setbit(b"bits_zstd", 100_000 * 8 + 6, 0).await, Frame::Integer(1));
+    assert_eq!(gw.setbit(b"bits_zstd", 100_001 * 8, 1).await, Frame::Integer(0));
+    value[100_000] &= !0x02;
+    value[100_001] |= 0x80;
+    assert_eq!(gw.get(b"bits_zstd").await, Frame::BulkString(value));
+}
+
+#[tokio::test]
+async fn test_bitcount() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"bc", b"foobar", SetFlags::default()).await;
+    assert_eq!(gw.bitcount(b"bc", None).await, Frame::Integer(26));
+    assert_eq!(gw.bitcount(b"bc", range(0, Some(0), BitUnit::Byte)).await, Frame::Integer(4));
+    assert_eq!(gw.bitcount(b"bc", range(1, Some(1), BitUnit::Byte)).await, Frame::Integer(6));
+    assert_eq!(gw.bitcount(b"bc", range(-2, Some(-1), BitUnit::Byte)).await, Frame::Integer(7));
+    assert_eq!(gw.bitcount(b"bc", range(5, Some(30), BitUnit::Bit)).await, Frame::Integer(17));
+    assert_eq!(gw.bitcount(b"bc", range(3, Some(1), BitUnit::Byte)).await, Frame::Integer(0));
+    assert_eq!(gw.bitcount(b"bc", range(0, Some(100), BitUnit::Byte)).await, Frame::Integer(26));
+    assert_eq!(gw.bitcount(b"bc_missing", None).await, Frame::Integer(0));
+}
+
+#[tokio::test]
+async fn test_bitpos() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"bp", b"\xff\xf0\x00", SetFlags::default()).await;
+    assert_eq!(gw.bitpos(b"bp", 0, None).await, Frame::Integer(12));
+
+    let _ = gw.set(b"bp", b"\x00\xff\xf0", SetFlags::default()).await;
+    assert_eq!(gw.bitpos(b"bp", 1, range(0, None, BitUnit::Byte)).await, Frame::Integer(8));
+    assert_eq!(gw.bitpos(b"bp", 1, range(2, None, BitUnit::Byte)).await, Frame::Integer(16));
+    assert_eq!(gw.bitpos(b"bp", 1, range(2, Some(-1), BitUnit::Byte)).await, Frame::Integer(16));
+    assert_eq!(gw.bitpos(b"bp", 1, range(7, Some(15), BitUnit::Bit)).await, Frame::Integer(8));
+    assert_eq!(gw.bitpos(b"bp", 1, range(7, Some(-3), BitUnit::Bit)).await, Frame::Integer(8));
+    assert_eq!(gw.bitpos(b"bp", 0, range(12, Some(20), BitUnit::Bit)).await, Frame::Integer(20));
+
+    // Without an end, clear bits are found past the value; not within one
+    let _ = gw.set(b"bp", b"\xff\xff", SetFlags::default()).await;
+    assert_eq!(gw.bitpos(b"bp", 0, None).await, Frame::Integer(16));
+    assert_eq!(gw.bitpos(b"bp", 0, range(0, Some(-1), BitUnit::Byte)).await, Frame::Integer(-1));
+    let _ = gw.set(b"bp", b"\x00\x00", SetFlags::default()).await;
+    assert_eq!(gw.bitpos(b"bp", 1, None).await, Frame::Integer(-1));
+
+    assert_eq!(gw.bitpos(b"bp_missing", 0, None).await, Frame::Integer(0));
+    assert_eq!(gw.bitpos(b"bp_missing", 1, None).await, Frame::Integer(-1));
+}
+
+#[tokio::test]
+async fn test_bitop() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"bo1", b"foobar", SetFlags::default()).await;
+    let _ = gw.set(b"bo2", b"abcdef", SetFlags::default()).await;
+    assert_eq!(gw.bitop(BitOp::And, b"bo_dest", &[b"bo1", b"bo2"]).await, Frame::Integer(6));
+    assert_eq!(gw.get(b"bo_dest").await, Frame::BulkString(b"`bc`ab".to_vec()));
+    assert_eq!(gw.bitop(BitOp::Or, b"bo_dest", &[b"bo1", b"bo2"]).await, Frame::Integer(6));
+    assert_eq!(gw.get(b"bo_dest").await, Frame::BulkString(b"goofev".to_vec()));
+
+    // Shorter and missing sources are zero-padded
+    let _ = gw.set(b"bo3", b"\xff", SetFlags::default()).await;
+    let _ = gw.set(b"bo4", b"\x0f\x0f", SetFlags::default()).await;
+    assert_eq!(gw.bitop(BitOp::Xor, b"bo_dest", &[b"bo3", b"bo4"]).await, Frame::Integer(2));
+    assert_eq!(gw.get(b"bo_dest").await, Frame::BulkString(vec![0xf0, 0x0f]));
+    assert_eq!(gw.bitop(BitOp::And, b"bo_dest", &[b"bo3", b"bo_missing"]).await, Frame::Integer(1));
+    assert_eq!(gw.get(b"bo_dest").await, Frame::BulkString(vec![0x00]));
+    assert_eq!(gw.bitop(BitOp::Not, b"bo_dest", &[b"bo4"]).await, Frame::Integer(2));
+    assert_eq!(gw.get(b"bo_dest").await, Frame::BulkString(vec![0xf0, 0xf0]));
+
+    // An empty result deletes the destination
+    assert_eq!(gw.bitop(BitOp::Or, b"bo_dest", &[b"bo_missing"]).await, Frame::Integer(0));
+    assert_eq!(gw.get(b"bo_dest").await, Frame::Null);
+
+    let res = gw.bitop(BitOp::Not, b"bo_dest", &[b"bo1", b"bo2"]).await;
+    assert_eq!(res, Frame::Error("ERR BITOP NOT must be called with a single source key.".into()));
+}
+
+#[tokio::test]
+async fn test_bitfield() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+    let (int5, int8, uint2, uint4, uint8, int64) = (
+        BitfieldType { signed: true, bits: 5 },
+        BitfieldType { signed: true, bits: 8 },
+        BitfieldType { signed: false, bits: 2 },
+        BitfieldType { signed: false, bits: 4 },
+        BitfieldType { signed: false, bits: 8 },
+        BitfieldType { signed: true, bits: 64 },
+    );
+    let incr = |ty, offset, increment, overflow| BitfieldOp::IncrBy { ty, offset, increment, overflow };
+    let set = |ty, offset, value, overflow| BitfieldOp::Set { ty, offset, value, overflow };
+
+    let ops = [incr(int5, 100, 1, Overflow::Wrap), BitfieldOp::Get { ty: uint4, offset: 0 }];
+    assert_eq!(gw.bitfield(b"bf", &ops).await, ints(&[Some(1), Some(0)]));
+
+    // Wrapping and saturating counters side by side
+    let ops = [incr(uint2, 102, 1, Overflow::Wrap), incr(uint2, 104, 1, Overflow::Sat)];
+    assert_eq!(gw.bitfield(b"bf_overflow", &ops).await, ints(&[Some(1), Some(1)]));
+    assert_eq!(gw.bitfield(b"bf_overflow", &ops).await, ints(&[Some(2), Some(2)]));
+    assert_eq!(gw.bitfield(b"bf_overflow", &ops).await, ints(&[Some(3), Some(3)]));
+    assert_eq!(gw.bitfield(b"bf_overflow", &ops).await, ints(&[Some(0), Some(3)]));
+    let ops = [incr(uint2, 104, 1, Overflow::Fail)];
+    assert_eq!(gw.bitfield(b"bf_overflow", &ops).await, ints(&[None]));
+
+    // SET replies with the previous value; the same bits read per type
+    let ops = [set(int8, 0, -100, Overflow::Wrap), set(uint8, 8, 200, Overflow::Wrap)];
+    assert_eq!(gw.bitfield(b"bf_set", &ops).await, ints(&[Some(0), Some(0)]));
+    let ops = [
+        BitfieldOp::Get { ty: int8, offset: 0 },
+        BitfieldOp::Get { ty: uint8, offset: 0 },
+        BitfieldOp::Get { ty: uint8, offset: 8 },
+    ];
+    assert_eq!(gw.bitfield(b"bf_set", &ops).await, ints(&[Some(-100), Some(156), Some(200)]));
+    assert_eq!(gw.get(b"bf_set").await, Frame::BulkString(vec![156, 200]));
+
+    // Values out of range for the field
+    let ops = [
+        set(int8, 16, 200, Overflow::Wrap),
+        BitfieldOp::Get { ty: int8, offset: 16 },
+        set(int8, 16, 200, Overflow::Sat),
+        BitfieldOp::Get { ty: int8, offset: 16 },
+        set(int8, 16, 200, Overflow::Fail),
+        set(uint8, 16, -1, Overflow::Sat),
+        BitfieldOp::Get { ty: uint8, offset: 16 },
+    ];
+    let expected = [Some(0), Some(-56), Some(-56), Some(127), None, Some(127), Some(255)];
+    assert_eq!(gw.bitfield(b"bf_set", &ops).await, ints(&expected));
+    let ops = [set(int64, 32, i64::MAX, Overflow::Wrap), incr(int64, 32, 1, Overflow::Wrap)];
+    assert_eq!(gw.bitfield(b"bf_set", &ops).await, ints(&[Some(0), Some(i64::MIN)]));
+    let ops = [incr(int64, 32, -1, Overflow::Sat)];
+    assert_eq!(gw.bitfield(b"bf_set", &ops).await, ints(&[Some(i64::MIN)]));
+
+    // Reads never create the key; writes extend it, even failing ones
+    let ops = [BitfieldOp::Get { ty: uint8, offset: 0 }];
+    assert_eq!(gw.bitfield(b"bf_missing", &ops).await, ints(&[Some(0)]));
+    assert_eq!(gw.exists(&[b"bf_missing"]).await, Frame::Integer(0));
+    let ops = [set(uint2, 100, 8, Overflow::Fail)];
+    assert_eq!(gw.bitfield(b"bf_missing", &ops).await, ints(&[None]));
+    assert_eq!(gw.strlen(b"bf_missing").await, Frame::Integer(13));
+}
+
+#[test]
+fn test_bitmap_args() {
+    let not_an_integer = Frame::Error("ERR value is not an integer or out of range".into());
+    let bad_offset = Frame::Error("ERR bit offset is not an integer or out of range".into());
+    let syntax = Frame::Error("ERR syntax error".into());
+
+    assert_eq!(parse_bit_offset(b"4294967295").map_err(Frame::from), Ok(u32::MAX as u64));
+    assert_eq!(parse_bit_offset(b"4294967296").map_err(Frame::from), Err(bad_offset.clone()));
+    assert_eq!(parse_bit_offset(b"-1").map_err(Frame::from), Err(bad_offset.clone()));
+    let bad_bit = Frame::Error("ERR bit is not an integer or out of range".into());
+    assert_eq!(parse_bit(b"2").map_err(Frame::from), Err(bad_bit));
+
+    let args: [&[u8]; 3] = [b"1", b"-1", b"bit"];
+    assert_eq!(parse_bitcount_args(&args).map_err(Frame::from), Ok(range(1, Some(-1), BitUnit::Bit)));
+    assert_eq!(parse_bitcount_args(&args[..1]).map_err(Frame::from), Err(syntax.clone()));
+    let args: [&[u8]; 3] = [b"1", b"2", b"BYTES"];
+    assert_eq!(parse_bitcount_args(&args).map_err(Frame::from), Err(syntax.clone()));
+    let args: [&[u8]; 2] = [b"one", b"2"];
+    assert_eq!(parse_bitcount_args(&args).map_err(Frame::from), Err(not_an_integer.clone()));
+    let args: [&[u8]; 2] = [b"2", b"0"];
+    let bad_bitpos = Frame::Error("ERR The bit argument must be 1 or 0.".into());
+    assert_eq!(parse_bitpos_args(&args).map_err(Frame::from), Err(bad_bitpos));
+
+    let uint4 = BitfieldType { signed: false, bits: 4 };
+    let args: [&[u8]; 7] = [b"overflow", b"sat", b"incrby", b"u4", b"#2", b"3", b"GET"];
+    assert_eq!(parse_bitfield_args(&args[..6]).map_err(Frame::from), Ok(vec![BitfieldOp::IncrBy {
+        ty: uint4,
+        offset: 8,
+        increment: 3,
+        overflow: Overflow::Sat,
+    }]));
+    assert_eq!(parse_bitfield_args(&args).map_err(Frame::from), Err(syntax.clone()));
+    let bad_type = Frame::Error(
+        "ERR Invalid bitfield type. Use something like i16 u8. Note that u64 is not supported but i64 is.".into(),
+    );
+    let types: [&[u8]; 4] = [b"u64", b"i65", b"i0", b"x8"];
+    for ty in types {
+        let args: [&[u8]; 3] = [b"GET", ty, b"0"];
+        assert_eq!(parse_bitfield_args(&args).map_err(Frame::from), Err(bad_type.clone()));
+    }
+    let args: [&[u8]; 3] = [b"GET", b"u8", b"#-1"];
+    assert_eq!(parse_bitfield_args(&args).map_err(Frame::from), Err(bad_offset));
+    let args: [&[u8]; 2] = [b"OVERFLOW", b"CLAMP"];
+    let bad_overflow = Frame::Error("ERR Invalid OVERFLOW type specified".into());
+    assert_eq!(parse_bitfield_args(&args).map_err(Frame::from), Err(bad_overflow));
+    let args: [&[u8]; 4] = [b"SET", b"i8", b"0", b"x"];
+    assert_eq!(parse_bitfield_args(&args).map_err(Frame::from), Err(not_an_integer));
+}
@@ -1,4 +1,5 @@
 use crate::gateway::RedisGateway;
+use fdb::stream::ValueStream;
 use redis_protocol::resp2::types::OwnedFrame as Frame;
 use std::collections::HashMap;
 use std::future::Future;
@@ -15,6 +16,19 @@ pub type CmdHandler = dyn Fn(RedisGateway, Vec<Vec<u8>>) -> Pin<Box<dyn Future<O
 
 pub type CmdMap = HashMap<String, Arc<CmdHandler>>;
 
+/// Response to a command: a complete frame, or a bulk string streamed from
+/// storage as it is read.
+pub enum Reply {
+    Frame(Frame),
+    Bulk(ValueStream),
+}
+
+impl From<Frame> for Reply {
+    fn from(frame: Frame) -> Self {
+        Reply::Frame(frame)
+    }
+}
+
 /// Boxed future alias to reduce repetition in handler types.
 /// Use the `futures` crate generic boxed future specialized to our `Frame` type.
 pub type BoxFuture = futures::future::BoxFuture<'static, Frame>;
@@ -63,6 +77,27 @@ fn build_command_map() -> CmdMap {
         }
     }
 
+    // Register bitmap commands
+    if let Ok(bitmap_map) = std::panic::catch_unwind(crate::bitmap::commands::commands) {
+        for (k, h) in bitmap_map.into_iter() {
+            map.insert(k.to_ascii_uppercase(), h);
+        }
+    }
+
+    // Register HyperLogLog commands
+    if let Ok(hll_map) = std::panic::catch_unwind(crate::hyperloglog::commands::commands) {
+        for (k, h) in hll_map.into_iter() {
+            map.insert(k.to_ascii_uppercase(), h);
+        }
+    }
+
+    // Register stream commands
+    if let Ok(stream_map) = std::panic::catch_unwind(crate::stream::commands::commands) {
+        for (k, h) in stream_map.into_iter() {
+            map.insert(k.to_ascii_uppercase(), h);
+        }
+    }
+
     // Register list commands
     if let Ok(list_map) = std::panic::catch_unwind(crate::list::commands::commands) {
         for (k, h) in list_map.into_iter() {
@@ -87,6 +122,14 @@ impl CommandHandler {
         Self { gateway, map: Arc::new(map) }
     }
 
+    /// Like `handle`, but GET replies are streamed rather than buffered.
+    pub async fn dispatch(&self, cmd_str: &str, args: Vec<&[u8]>) -> Reply {
+        if cmd_str.eq_ignore_ascii_case("GET") && args.len() == 1 {
+            return self.gateway.get_stream(args[0]).await;
+        }
+        self.handle(cmd_str, args).await.into()
+    }
+
     pub async fn handle(&self, cmd_str: &str, args: Vec<&[u8]>) -> Frame {
         let key = cmd_str.to_ascii_uppercase();
         if let Some(h) = self.map.get(&key) {
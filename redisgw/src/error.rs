@@ -0,0 +1,87 @@
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+/// Errors surfaced by gateway commands.
+///
+/// Every error reaches the client through `From<Error> for Frame`, which picks
+/// the Redis error prefix and keeps storage internals out of the reply.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("no such key")]
+    NotFound,
+
+    #[error("Operation against a key holding the wrong kind of value")]
+    WrongType,
+
+    #[error("Key is not a valid HyperLogLog string value.")]
+    NotAHyperLogLog,
+
+    #[error("value is not an integer or out of range")]
+    NotAnInteger,
+
+    #[error("increment or decrement would overflow")]
+    Overflow,
+
+    #[error("value is not a valid float")]
+    NotAFloat,
+
+    #[error("increment would produce NaN or Infinity")]
+    NanOrInfinity,
+
+    #[error("syntax error")]
+    Syntax,
+
+    #[error("timeout acquiring key lock")]
+    LockTimeout,
+
+    #[error("{0}")]
+    Other(String),
+
+    #[error(transparent)]
+    Storage(#[from] fdb::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Redis error prefix sent to clients for this error.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Error::WrongType | Error::NotAHyperLogLog => "WRONGTYPE",
+            Error::LockTimeout => "BUSY",
+            Error::Storage(e) if e.is_retryable() => "TRYAGAIN",
+            _ => "ERR",
+        }
+    }
+
+    /// Message sent to clients after the prefix.
+    fn client_message(&self) -> String {
+        match self {
+            Error::Storage(fdb::Error::ValueTooLarge { .. }) => {
+                "string exceeds maximum allowed size (proto-max-bulk-len)".to_string()
+            }
+            Error::Storage(fdb::Error::CorruptValue(_)) => {
+                "value is incomplete or corrupted".to_string()
+            }
+            Error::Storage(e) if e.is_retryable() => {
+                "transaction conflict, please retry".to_string()
+            }
+            Error::Storage(_) => "internal storage error".to_string(),
+            e => e.to_string(),
+        }
+    }
+}
+
+impl From<Error> for Frame {
+    fn from(e: Error) -> Self {
+        if let Error::Storage(cause) = &e {
+            let mut msg = cause.to_string();
+            let mut source = std::error::Error::source(cause);
+            while let Some(s) = source {
+                msg = format!("{}: {}", msg, s);
+                source = s.source();
+            }
+            log::error!("storage error: {}", msg);
+        }
+        Frame::Error(format!("{} {}", e.prefix(), e.client_message()))
+    }
+}
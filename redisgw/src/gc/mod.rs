@@ -0,0 +1,316 @@
+//! Garbage collection of storage debris.
+//!
+//! Crashed or aborted writers can leave records nothing refers to any more:
+//! chunks of a generation whose manifest was never committed or has since
+//! been replaced, locks whose holder went away, and TTL records of deleted
+//! values. The collector sweeps each prefix in bounded transactions, checking
+//! liveness in the same transaction that clears a record so it never races a
+//! concurrent writer.
+use crate::error::{Error, Result};
+use crate::keys::StoredKey;
+use crate::migration::{self, FORMAT_VERSION, prefix_range};
+use crate::stats::{self, Stat, Update};
+use crate::string::datamodel::SimpleDataPrefix;
+use fdb::FoundationDB;
+use fdb::manifest::Manifest;
+use fdb::storage::Transaction;
+use fdb::transaction::Priority;
+use foundationdb::{FdbBindingError, RangeOption};
+use foundationdb_tuple::unpack;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Default number of records inspected per transaction.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// Default age below which an unreferenced generation is assumed to still be
+/// staged by a running writer.
+pub const DEFAULT_GRACE_PERIOD_MS: u64 = 10 * 60 * 1000;
+
+/// Default age after which a lock is considered abandoned. Writers renew
+/// the token of the lock they hold every ten seconds, so only the locks of
+/// writers gone for good get this old.
+pub const DEFAULT_STALE_LOCK_MS: u64 = 60 * 1000;
+
+/// Tuning of a collection run.
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// Number of records inspected per transaction.
+    pub batch_size: usize,
+    /// Report what would be removed without clearing anything.
+    pub dry_run: bool,
+    /// Minimum age of an unreferenced generation before it is collected.
+    pub grace_period_ms: u64,
+    /// Minimum age of a lock before it is collected.
+    pub stale_lock_ms: u64,
+}
+
+impl Default for GcOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: DEFAULT_BATCH_SIZE,
+            dry_run: false,
+            grace_period_ms: DEFAULT_GRACE_PERIOD_MS,
+            stale_lock_ms: DEFAULT_STALE_LOCK_MS,
+        }
+    }
+}
+
+/// Records found (and, unless dry-running, removed) by a collection run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcReport {
+    /// Chunks not referenced by the manifest of their value.
+    pub orphaned_chunks: usize,
+    /// Locks older than `stale_lock_ms`.
+    pub stale_locks: usize,
+    /// TTL records whose value or counter no longer exists.
+    pub dangling_ttls: usize,
+}
+
+impl fmt::Display for GcReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} orphaned chunks, {} stale locks, {} dangling TTL records",
+            self.orphaned_chunks, self.stale_locks, self.dangling_ttls
+        )
+    }
+}
+
+/// Sweep the whole keyspace once.
+///
+/// Refuses to run before `redisgw migrate` has brought the keyspace to
+/// `FORMAT_VERSION`, since older formats store live chunks without a manifest.
+pub async fn collect(fdb: &FoundationDB, options: &GcOptions) -> Result<GcReport> {
+    let version = migration::read_format_version(fdb).await?;
+    if version != Some(FORMAT_VERSION) {
+        return Err(Error::Other(format!(
+            "garbage collection requires on-disk format version {}, run `redisgw migrate` first",
+            FORMAT_VERSION
+        )));
+    }
+
+    let report = GcReport {
+        orphaned_chunks: sweep(fdb, SimpleDataPrefix::Data, options, orphaned_chunks).await?,
+        stale_locks: sweep(fdb, SimpleDataPrefix::Lock, options, stale_locks).await?,
+        dangling_ttls: sweep(fdb, SimpleDataPrefix::Ttl, options, dangling_ttls).await?,
+    };
+    if options.dry_run {
+        log::info!("garbage collection (dry run) found {}", report);
+    } else {
+        log::info!("garbage collection removed {}", report);
+        stats::record(
+            fdb,
+            &[
+                Update::Add(Stat::GcRuns, 1),
+                Update::Add(Stat::GcOrphanedChunks, report.orphaned_chunks as i64),
+                Update::Add(Stat::GcStaleLocks, report.stale_locks as i64),
+                Update::Add(Stat::GcDanglingTtls, report.dangling_ttls as i64),
+                Update::Max(Stat::GcLastRunMs, now_ms()),
+            ],
+        )
+        .await?;
+    }
+    Ok(report)
+}
+
+/// Run `collect` every `interval` until the task is dropped.
+/// Background runs use batch priority so they yield to client traffic.
+pub async fn run_periodically(fdb: FoundationDB, interval: Duration, options: GcOptions) {
+    let fdb = fdb.with_priority(Priority::Batch);
+    loop {
+        tokio::time::sleep(interval).await;
+        if let Err(e) = collect(&fdb, &options).await {
+            log::warn!("garbage collection failed: {}", e);
+        }
+    }
+}
+
+type Batch = Vec<(Vec<u8>, Vec<u8>)>;
+
+/// Walk `prefix` in transactions of at most `batch_size` records, letting
+/// `visit` inspect (and clear) each batch inside the transaction that read it.
+async fn sweep<F, Fut>(
+    fdb: &FoundationDB,
+    prefix: SimpleDataPrefix,
+    options: &GcOptions,
+    visit: F,
+) -> Result<usize>
+where
+    F: Fn(Transaction, Batch, GcOptions) -> Fut + Copy,
+    Fut: Future<Output = std::result::Result<usize, FdbBindingError>>,
+{
+    let batch_size = options.batch_size.max(1);
+    let (mut cursor, end) = prefix_range(prefix);
+    let mut found = 0;
+
+    loop {
+        let begin = cursor.clone();
+        let stop = end.clone();
+        let (count, last) = fdb
+            .run(|trx, _| {
+                let begin = begin.clone();
+                let stop = stop.clone();
+                let options = options.clone();
+                async move {
+                    let opt = RangeOption {
+                        limit: Some(batch_size),
+                        ..RangeOption::from((begin, stop))
+                    };
+                    let batch: Batch = trx.get_range(&opt, false).await?;
+                    let last = batch.last().map(|(k, _)| k.clone());
+                    let count = visit(trx, batch, options).await?;
+                    Ok((count, last))
+                }
+            })
+            .await
+            .map_err(fdb::Error::from)?;
+
+        found += count;
+        let Some(last) = last else {
+            break;
+        };
+        cursor = last;
+        cursor.push(0x00);
+    }
+
+    Ok(found)
+}
+
+/// Chunks whose generation differs from the one named by their manifest.
+///
+/// Generations younger than the grace period are left alone: they may belong
+/// to a large write that has not flipped its manifest yet.
+async fn orphaned_chunks(
+    trx: Transaction,
+    batch: Batch,
+    options: GcOptions,
+) -> std::result::Result<usize, FdbBindingError> {
+    let now = now_ms();
+    // Storage key of the value being walked and the generation it references
+    let mut current: Option<(StoredKey, Option<u64>)> = None;
+    let mut found = 0;
+
+    for (key, value) in &batch {
+        if let Ok((_, stored)) = unpack::<(u64, StoredKey)>(key) {
+            let live = Manifest::decode(value).map(|m| m.generation);
+            current = Some((stored, live));
+            continue;
+        }
+
+        let (stored, generation) =
+            if let Ok((_, stored, _)) = unpack::<(u64, StoredKey, i64)>(key) {
+                (stored, 0)
+            } else if let Ok((_, stored, _, generation, _)) =
+                unpack::<(u64, StoredKey, String, u64, i64)>(key)
+            {
+                (stored, generation)
+            } else {
+                continue;
+            };
+
+        // The batch may start in the middle of a value
+        if current.as_ref().is_none_or(|(k, _)| *k != stored) {
+            let root = stored.pack(SimpleDataPrefix::Data);
+            let live = trx
+                .get(&root, false)
+                .await?
+                .and_then(|m| Manifest::decode(&m))
+                .map(|m| m.generation);
+            current = Some((stored, live));
+        }
+
+        let live = current.as_ref().and_then(|(_, g)| *g);
+        if live == Some(generation) {
+            continue;
+        }
+        let age = now.saturating_sub(Manifest::generation_time_ms(generation));
+        if generation != 0 && age < options.grace_period_ms {
+            continue;
+        }
+
+        found += 1;
+        if !options.dry_run {
+            trx.clear(key);
+        }
+    }
+
+    Ok(found)
+}
+
+/// Locks whose `locked:<ms>` token is older than `stale_lock_ms`.
+async fn stale_locks(
+    trx: Transaction,
+    batch: Batch,
+    options: GcOptions,
+) -> std::result::Result<usize, FdbBindingError> {
+    let now = now_ms();
+    let mut found = 0;
+
+    for (key, value) in &batch {
+        let taken_at = std::str::from_utf8(value)
+            .ok()
+            .and_then(|s| s.strip_prefix("locked:"))
+            .and_then(|ms| ms.parse::<u64>().ok())
+            .unwrap_or(0);
+        if now.saturating_sub(taken_at) < options.stale_lock_ms {
+            continue;
+        }
+
+        found += 1;
+        if !options.dry_run {
+            trx.clear(key);
+        }
+    }
+
+    Ok(found)
+}
+
+/// TTL records with neither a value, a counter, a HyperLogLog nor a stream
+/// stored under the same key.
+async fn dangling_ttls(
+    trx: Transaction,
+    batch: Batch,
+    options: GcOptions,
+) -> std::result::Result<usize, FdbBindingError> {
+    let mut found = 0;
+
+    for (key, _) in &batch {
+        let Ok((_, stored)) = unpack::<(u64, StoredKey)>(key) else {
+            continue;
+        };
+        let root = stored.pack(SimpleDataPrefix::Data);
+        if trx.get(&root, false).await?.is_some() {
+            continue;
+        }
+        let counter = stored.pack(SimpleDataPrefix::Counter);
+        if trx.get(&counter, false).await?.is_some() {
+            continue;
+        }
+        let hll = stored.pack(SimpleDataPrefix::Hll);
+        if trx.get(&hll, false).await?.is_some() {
+            continue;
+        }
+        let stream = stored.pack(SimpleDataPrefix::Stream);
+        if trx.get(&stream, false).await?.is_some() {
+            continue;
+        }
+
+        found += 1;
+        if !options.dry_run {
+            trx.clear(key);
+        }
+    }
+
+    Ok(found)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+pub mod tests;
@@ -0,0 +1,144 @@
+use crate::gateway::RedisGateway;
+use crate::gc::{GcOptions, collect};
+use crate::migration::{FORMAT_VERSION, LEGACY_FORMAT_VERSION, write_format_version};
+use crate::string::datamodel::{SimpleDataPrefix, StringDataModel};
+use crate::string::operations::{SetFlags, StringOperations};
+use fdb::FoundationDB;
+use fdb::manifest::Manifest;
+use foundationdb_tuple::{Subspace, pack};
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+async fn write_raw(db: &FoundationDB, key: Vec<u8>, value: Vec<u8>) {
+    db.run(|trx, _| {
+        let key = key.clone();
+        let value = value.clone();
+        async move {
+            trx.set(&key, &value);
+            Ok(())
+        }
+    })
+    .await
+    .expect("Unable to write record");
+}
+
+async fn exists(db: &FoundationDB, key: Vec<u8>) -> bool {
+    db.run(|trx, _| {
+        let key = key.clone();
+        async move { Ok(trx.get(&key, false).await?.is_some()) }
+    })
+    .await
+    .expect("Unable to read record")
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
+
+#[tokio::test]
+async fn test_collect_debris() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+    write_format_version(&db, FORMAT_VERSION)
+        .await
+        .expect("Unable to write format version");
+
+    // A live value with a leftover legacy chunk and a generation being staged.
+    let key = b"gc_value".to_vec();
+    let _ = gw.set(&key, b"value", SetFlags::default()).await;
+    let root = pack(&(SimpleDataPrefix::Data, key.as_slice()));
+    let legacy_chunk = Subspace::from_bytes(root.clone()).pack(&(0,));
+    let staged_chunk =
+        Subspace::from_bytes(Manifest::generation_root(&root, Manifest::new_generation()))
+            .pack(&(0,));
+    write_raw(&db, legacy_chunk.clone(), b"old".to_vec()).await;
+    write_raw(&db, staged_chunk.clone(), b"new".to_vec()).await;
+
+    let stale_lock = pack(&(SimpleDataPrefix::Lock, b"gc_stale".as_slice()));
+    let fresh_lock = pack(&(SimpleDataPrefix::Lock, b"gc_fresh".as_slice()));
+    write_raw(&db, stale_lock.clone(), b"locked:0".to_vec()).await;
+    write_raw(&db, fresh_lock.clone(), format!("locked:{}", now_ms()).into_bytes()).await;
+
+    let dangling_ttl = pack(&(SimpleDataPrefix::Ttl, b"gc_missing".as_slice()));
+    write_raw(&db, dangling_ttl.clone(), (now_ms() + 60_000).to_be_bytes().to_vec()).await;
+
+    // A dry run reports without clearing.
+    let dry_run = GcOptions {
+        dry_run: true,
+        ..GcOptions::default()
+    };
+    let report = collect(&db, &dry_run).await.expect("dry run failed");
+    assert!(report.orphaned_chunks >= 1);
+    assert!(report.stale_locks >= 1);
+    assert!(report.dangling_ttls >= 1);
+    assert!(exists(&db, legacy_chunk.clone()).await);
+    assert!(exists(&db, stale_lock.clone()).await);
+    assert!(exists(&db, dangling_ttl.clone()).await);
+
+    collect(&db, &GcOptions::default())
+        .await
+        .expect("collection failed");
+    assert!(!exists(&db, legacy_chunk).await);
+    assert!(!exists(&db, stale_lock).await);
+    assert!(!exists(&db, dangling_ttl).await);
+    assert!(exists(&db, fresh_lock.clone()).await);
+    // Still within the grace period of its writer.
+    assert!(exists(&db, staged_chunk.clone()).await);
+    assert_eq!(gw.get(&key).await, Frame::BulkString(b"value".to_vec()));
+
+    let no_grace = GcOptions {
+        grace_period_ms: 0,
+        ..GcOptions::default()
+    };
+    collect(&db, &no_grace).await.expect("collection failed");
+    assert!(!exists(&db, staged_chunk).await);
+    assert_eq!(gw.get(&key).await, Frame::BulkString(b"value".to_vec()));
+
+    let _ = gw.del(&[key.as_slice()]).await;
+    let _ = db.delete(&fresh_lock).await;
+}
+
+#[tokio::test]
+async fn test_release_lock_taken_over() {
+    let db = FoundationDB::in_memory();
+    let dm = StringDataModel::new(db.clone());
+    let lock_key = pack(&(SimpleDataPrefix::Lock, b"gc_taken".as_slice()));
+
+    let lock = dm.acquire_lock(b"gc_taken", 1000).await.expect("lock failed");
+    // Collected as abandoned, then taken by another writer
+    let other = b"locked:1".to_vec();
+    write_raw(&db, lock_key.clone(), other.clone()).await;
+    dm.release_lock(lock).await.expect("release failed");
+    assert!(exists(&db, lock_key.clone()).await);
+
+    // Its own lock is released
+    db.run(|trx, _| {
+        let lock_key = lock_key.clone();
+        async move {
+            trx.clear(&lock_key);
+            Ok(())
+        }
+    })
+    .await
+    .expect("Unable to clear record");
+    let lock = dm.acquire_lock(b"gc_taken", 1000).await.expect("lock failed");
+    dm.release_lock(lock).await.expect("release failed");
+    assert!(!exists(&db, lock_key).await);
+}
+
+#[tokio::test]
+async fn test_refuse_outdated_format_version() {
+    let db = FoundationDB::in_memory();
+
+    write_format_version(&db, LEGACY_FORMAT_VERSION)
+        .await
+        .expect("Unable to write format version");
+    assert!(collect(&db, &GcOptions::default()).await.is_err());
+
+    write_format_version(&db, FORMAT_VERSION)
+        .await
+        .expect("Unable to restore format version");
+}
@@ -0,0 +1,42 @@
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+use crate::command::CmdMap;
+use crate::hyperloglog::operations::HyperLogLogOperations;
+
+crate::command_handler_static!(PFADD, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'PFADD' command".into());
+    }
+    let key = args[0].as_slice();
+    let elements: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    gw.pfadd(key, &elements).await
+});
+
+crate::command_handler_static!(PFCOUNT, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'PFCOUNT' command".into());
+    }
+    let keys: Vec<&[u8]> = args.iter().map(|v| v.as_slice()).collect();
+    gw.pfcount(&keys).await
+});
+
+crate::command_handler_static!(PFMERGE, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'PFMERGE' command".into());
+    }
+    let destkey = args[0].as_slice();
+    let sourcekeys: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    gw.pfmerge(destkey, &sourcekeys).await
+});
+
+/// Return handlers for HyperLogLog commands (PFADD, PFCOUNT, PFMERGE)
+pub fn commands() -> CmdMap {
+    let mut m: CmdMap = CmdMap::new();
+
+    m.insert("PFADD".to_string(), PFADD.clone());
+
+    m.insert("PFCOUNT".to_string(), PFCOUNT.clone());
+
+    m.insert("PFMERGE".to_string(), PFMERGE.clone());
+
+    m
+}
@@ -0,0 +1,49 @@
+use crate::gateway::RedisGateway;
+use crate::hyperloglog::operations::hll;
+use crate::hyperloglog::operations::{HyperLogLogOperations, Registers};
+use crate::string::datamodel::StringDataModel;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+// HyperLogLogs read as strings in the Redis encodings, but their registers
+// are stored one record each so that adds never conflict
+impl HyperLogLogOperations for RedisGateway {
+    async fn pfadd(&self, key: &[u8], elements: &[&[u8]]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        let raised = elements.iter().map(|e| hll::register_of(e)).collect::<Vec<_>>();
+        match dm.hll_add(key, &raised).await {
+            Ok(changed) => Frame::Integer(changed as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn pfcount(&self, keys: &[&[u8]]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.hll_read(keys).await {
+            Ok(read) => Frame::Integer(union(read).count() as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn pfmerge(&self, destkey: &[u8], sourcekeys: &[&[u8]]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        let merged = match dm.hll_read(sourcekeys).await {
+            Ok(read) => union(read),
+            Err(e) => return e.into(),
+        };
+        // The registers of the destination are raised in place, so it takes
+        // part in the union without being read
+        match dm.hll_merge(destkey, &merged).await {
+            Ok(()) => Frame::SimpleString("OK".to_string().into_bytes()),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// Union of the HyperLogLogs read, missing ones counting as empty.
+fn union(read: Vec<Option<Registers>>) -> Registers {
+    let mut merged = Registers::default();
+    for registers in read.iter().flatten() {
+        merged.merge(registers);
+    }
+    merged
+}
@@ -0,0 +1,6 @@
+pub mod commands;
+pub mod gateway;
+pub mod operations;
+
+#[cfg(test)]
+pub mod tests;
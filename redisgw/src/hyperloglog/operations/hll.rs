@@ -0,0 +1,262 @@
+/// Registers of a HyperLogLog, and bits of an element's hash that pick one.
+pub const REGISTERS: usize = 1 << P;
+const P: u32 = 14;
+/// Bits of the hash left to count the leading zeros of.
+const Q: u32 = 64 - P;
+
+/// Length of the header of a HyperLogLog string: the `HYLL` magic, the
+/// encoding, three unused bytes and the cached cardinality.
+pub const HEADER_LEN: usize = 16;
+/// Length of a dense HyperLogLog string, registers being 6 bits wide.
+pub const DENSE_LEN: usize = HEADER_LEN + (REGISTERS * 6).div_ceil(8);
+const DENSE: u8 = 0;
+const SPARSE: u8 = 1;
+
+/// Longest sparse string Redis keeps before switching to the dense encoding,
+/// `hll-sparse-max-bytes` by default.
+const SPARSE_MAX_LEN: usize = 3000;
+/// Largest register value the sparse encoding can hold.
+const SPARSE_MAX_VALUE: u8 = 32;
+/// Longest run of zero registers a ZERO and an XZERO opcode can hold.
+const ZERO_MAX_RUN: usize = 64;
+const XZERO_MAX_RUN: usize = 16384;
+/// Longest run of equal registers a VAL opcode can hold.
+const VAL_MAX_RUN: usize = 4;
+
+/// The registers of a HyperLogLog, one byte each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers(Vec<u8>);
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self(vec![0; REGISTERS])
+    }
+}
+
+impl Registers {
+    /// Value of register `index`.
+    pub fn get(&self, index: usize) -> u8 {
+        self.0[index]
+    }
+
+    /// Raise register `index` to `value`. Returns whether it changed.
+    pub fn raise(&mut self, index: usize, value: u8) -> bool {
+        let changed = value > self.0[index];
+        if changed {
+            self.0[index] = value;
+        }
+        changed
+    }
+
+    /// Raise every register to its value in `other`.
+    pub fn merge(&mut self, other: &Registers) {
+        for (register, &value) in self.0.iter_mut().zip(&other.0) {
+            *register = (*register).max(value);
+        }
+    }
+
+    /// Registers above zero, with their index.
+    pub fn raised(&self) -> impl Iterator<Item = (usize, u8)> + '_ {
+        self.0.iter().copied().enumerate().filter(|&(_, value)| value > 0)
+    }
+
+    /// Estimated number of distinct elements added, with the estimator
+    /// Redis uses so that both report the same counts.
+    pub fn count(&self) -> u64 {
+        let m = REGISTERS as f64;
+        let mut histogram = [0u32; 64];
+        for &value in &self.0 {
+            histogram[value as usize] += 1;
+        }
+        let mut z = m * tau((m - histogram[Q as usize + 1] as f64) / m);
+        for j in (1..=Q as usize).rev() {
+            z += histogram[j] as f64;
+            z *= 0.5;
+        }
+        z += m * sigma(histogram[0] as f64 / m);
+        (0.5 / std::f64::consts::LN_2 * m * m / z).round() as u64
+    }
+
+    /// The HyperLogLog as a Redis string: sparse while it is small enough
+    /// to be, dense otherwise.
+    /// The cached cardinality is flagged stale, so Redis computes it again.
+    pub fn encode(&self) -> Vec<u8> {
+        if self.0.iter().all(|&value| value <= SPARSE_MAX_VALUE) {
+            let sparse = self.encode_sparse();
+            if sparse.len() <= SPARSE_MAX_LEN {
+                return sparse;
+            }
+        }
+        self.encode_dense()
+    }
+
+    fn encode_sparse(&self) -> Vec<u8> {
+        let mut out = header(SPARSE);
+        let mut index = 0;
+        while index < REGISTERS {
+            let value = self.0[index];
+            let run = self.0[index..].iter().take_while(|&&v| v == value).count();
+            index += run;
+            if value == 0 {
+                let mut left = run;
+                while left > 0 {
+                    let len = left.min(XZERO_MAX_RUN);
+                    if len > ZERO_MAX_RUN {
+                        out.extend_from_slice(&[0x40 | ((len - 1) >> 8) as u8, (len - 1) as u8]);
+                    } else {
+                        out.push((len - 1) as u8);
+                    }
+                    left -= len;
+                }
+            } else {
+                let mut left = run;
+                while left > 0 {
+                    let len = left.min(VAL_MAX_RUN);
+                    out.push(0x80 | ((value - 1) << 2) | (len - 1) as u8);
+                    left -= len;
+                }
+            }
+        }
+        out
+    }
+
+    fn encode_dense(&self) -> Vec<u8> {
+        let mut out = header(DENSE);
+        out.resize(DENSE_LEN, 0);
+        let body = &mut out[HEADER_LEN..];
+        for (index, &value) in self.0.iter().enumerate() {
+            let (byte, shift) = (index * 6 / 8, index * 6 % 8);
+            body[byte] |= value << shift;
+            if shift > 2 {
+                body[byte + 1] |= value >> (8 - shift);
+            }
+        }
+        out
+    }
+
+    /// Registers of a HyperLogLog string in either Redis encoding, or `None`
+    /// when `bytes` is not one.
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < HEADER_LEN || &bytes[..4] != b"HYLL" {
+            return None;
+        }
+        let body = &bytes[HEADER_LEN..];
+        let mut registers = Self::default();
+        match bytes[4] {
+            DENSE if bytes.len() == DENSE_LEN => {
+                for (index, register) in registers.0.iter_mut().enumerate() {
+                    let (byte, shift) = (index * 6 / 8, index * 6 % 8);
+                    let next = body.get(byte + 1).copied().unwrap_or(0) as u16;
+                    *register = (((body[byte] as u16) >> shift | next << (8 - shift)) & 0x3f) as u8;
+                }
+            }
+            SPARSE => {
+                let (mut index, mut at) = (0, 0);
+                while at < body.len() {
+                    let op = body[at];
+                    if op & 0x80 != 0 {
+                        // VAL: a run of 1 to 4 registers holding 1 to 32
+                        let run = (op & 0x03) as usize + 1;
+                        let value = ((op >> 2) & 0x1f) + 1;
+                        registers.0.get_mut(index..index + run)?.fill(value);
+                        index += run;
+                        at += 1;
+                    } else if op & 0x40 != 0 {
+                        // XZERO: a run of 1 to 16384 zero registers
+                        let low = *body.get(at + 1)? as usize;
+                        index += (((op & 0x3f) as usize) << 8 | low) + 1;
+                        at += 2;
+                    } else {
+                        // ZERO: a run of 1 to 64 zero registers
+                        index += (op & 0x3f) as usize + 1;
+                        at += 1;
+                    }
+                }
+                if index != REGISTERS {
+                    return None;
+                }
+            }
+            _ => return None,
+        }
+        Some(registers)
+    }
+}
+
+/// Register `element` falls in and the value it raises it to: the position
+/// of the first set bit in the rest of its hash, as Redis computes them.
+pub fn register_of(element: &[u8]) -> (usize, u8) {
+    let hash = murmur_hash64a(element, 0xadc83b19);
+    let index = (hash & (REGISTERS as u64 - 1)) as usize;
+    let rest = (hash >> P) | (1 << Q);
+    (index, rest.trailing_zeros() as u8 + 1)
+}
+
+fn header(encoding: u8) -> Vec<u8> {
+    let mut out = Vec::with_capacity(DENSE_LEN);
+    out.extend_from_slice(b"HYLL");
+    out.extend_from_slice(&[encoding, 0, 0, 0]);
+    out.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0x80]);
+    out
+}
+
+/// MurmurHash64A, reading blocks as little-endian whatever the platform.
+fn murmur_hash64a(data: &[u8], seed: u64) -> u64 {
+    const M: u64 = 0xc6a4a7935bd1e995;
+    const R: u32 = 47;
+    let mut h = seed ^ (data.len() as u64).wrapping_mul(M);
+    let mut blocks = data.chunks_exact(8);
+    for block in &mut blocks {
+        let mut k = u64::from_le_bytes(block.try_into().expect("8-byte block"));
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        h ^= k;
+        h = h.wrapping_mul(M);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        for (i, &byte) in tail.iter().enumerate() {
+            h ^= (byte as u64) << (8 * i);
+        }
+        h = h.wrapping_mul(M);
+    }
+    h ^= h >> R;
+    h = h.wrapping_mul(M);
+    h ^= h >> R;
+    h
+}
+
+/// Helper of the estimator for registers left at zero, from Ertl's "New
+/// cardinality estimation algorithms for HyperLogLog sketches".
+fn sigma(mut x: f64) -> f64 {
+    if x == 1.0 {
+        return f64::INFINITY;
+    }
+    let (mut y, mut z) = (1.0, x);
+    loop {
+        x *= x;
+        let previous = z;
+        z += x * y;
+        y += y;
+        if z == previous {
+            return z;
+        }
+    }
+}
+
+/// Helper of the estimator for registers that saturated.
+fn tau(mut x: f64) -> f64 {
+    if x == 0.0 || x == 1.0 {
+        return 0.0;
+    }
+    let (mut y, mut z) = (1.0, 1.0 - x);
+    loop {
+        x = x.sqrt();
+        let previous = z;
+        y *= 0.5;
+        z -= (1.0 - x).powi(2) * y;
+        if z == previous {
+            return z / 3.0;
+        }
+    }
+}
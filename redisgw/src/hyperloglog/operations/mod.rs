@@ -0,0 +1,5 @@
+pub mod traits;
+pub mod hll;
+
+pub use traits::*;
+pub use hll::Registers;
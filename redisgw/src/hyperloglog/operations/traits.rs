@@ -0,0 +1,12 @@
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+pub trait HyperLogLogOperations {
+    /// Adds elements to the HyperLogLog stored at key.
+    fn pfadd(&self, key: &[u8], elements: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Returns the approximate number of distinct elements added to the HyperLogLogs at keys.
+    fn pfcount(&self, keys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Merges the HyperLogLogs at the source keys into the one at destkey.
+    fn pfmerge(&self, destkey: &[u8], sourcekeys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+}
@@ -0,0 +1,209 @@
+use crate::gateway::RedisGateway;
+use crate::hyperloglog::operations::hll::{self, DENSE_LEN};
+use crate::hyperloglog::operations::{HyperLogLogOperations, Registers};
+use crate::string::operations::{SetFlags, StringOperations};
+use fdb::FoundationDB;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+/// Redis's empty HyperLogLog: sparse, a single XZERO run over all registers.
+const EMPTY_HLL: &[u8] = b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff";
+
+fn wrong_type() -> Frame {
+    Frame::Error("WRONGTYPE Key is not a valid HyperLogLog string value.".into())
+}
+
+fn elements(prefix: &str, n: usize) -> Vec<Vec<u8>> {
+    (0..n).map(|i| format!("{}:{}", prefix, i).into_bytes()).collect()
+}
+
+fn refs(elements: &[Vec<u8>]) -> Vec<&[u8]> {
+    elements.iter().map(|e| e.as_slice()).collect()
+}
+
+fn bulk(frame: Frame) -> Vec<u8> {
+    match frame {
+        Frame::BulkString(bytes) => bytes,
+        other => panic!("expected a bulk string, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_pfadd_pfcount() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    assert_eq!(gw.pfadd(b"hll", &[b"a", b"b", b"c", b"d", b"e", b"f", b"g"]).await, Frame::Integer(1));
+    assert_eq!(gw.pfcount(&[b"hll"]).await, Frame::Integer(7));
+    // Nothing new: no register changes
+    assert_eq!(gw.pfadd(b"hll", &[b"a", b"g"]).await, Frame::Integer(0));
+    assert_eq!(gw.pfadd(b"hll", &[]).await, Frame::Integer(0));
+    assert_eq!(gw.pfcount(&[b"hll"]).await, Frame::Integer(7));
+
+    // Adding no element still creates the key
+    assert_eq!(gw.pfcount(&[b"hll_empty"]).await, Frame::Integer(0));
+    assert_eq!(gw.pfadd(b"hll_empty", &[]).await, Frame::Integer(1));
+    assert_eq!(gw.exists(&[b"hll_empty"]).await, Frame::Integer(1));
+    assert_eq!(gw.pfcount(&[b"hll_empty"]).await, Frame::Integer(0));
+
+    // Estimates stay within 1% on larger sets
+    let added = elements("element", 10_000);
+    for batch in added.chunks(1000) {
+        let _ = gw.pfadd(b"hll_large", &refs(batch)).await;
+    }
+    let Frame::Integer(count) = gw.pfcount(&[b"hll_large"]).await else {
+        panic!("expected an integer");
+    };
+    assert!((count - 10_000).abs() <= 100, "estimated {}", count);
+
+    let _ = gw.del(&[b"hll"]).await;
+    assert_eq!(gw.exists(&[b"hll"]).await, Frame::Integer(0));
+    assert_eq!(gw.pfcount(&[b"hll"]).await, Frame::Integer(0));
+}
+
+#[tokio::test]
+async fn test_pfmerge() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+    let ok = Frame::SimpleString(b"OK".to_vec());
+
+    let _ = gw.pfadd(b"hll1", &[b"foo", b"bar", b"zap", b"a"]).await;
+    let _ = gw.pfadd(b"hll2", &[b"a", b"b", b"c", b"foo"]).await;
+    assert_eq!(gw.pfmerge(b"hll3", &[b"hll1", b"hll2", b"hll_missing"]).await, ok);
+    assert_eq!(gw.pfcount(&[b"hll3"]).await, Frame::Integer(6));
+    // PFCOUNT of several keys counts their union without storing it
+    assert_eq!(gw.pfcount(&[b"hll1", b"hll2"]).await, Frame::Integer(6));
+    assert_eq!(gw.pfcount(&[b"hll1"]).await, Frame::Integer(4));
+
+    // The destination is part of the union
+    let _ = gw.pfadd(b"hll4", &[b"x"]).await;
+    assert_eq!(gw.pfmerge(b"hll4", &[b"hll1"]).await, ok);
+    assert_eq!(gw.pfcount(&[b"hll4"]).await, Frame::Integer(5));
+
+    // Without sources, the destination is created empty
+    assert_eq!(gw.pfmerge(b"hll5", &[]).await, ok);
+    assert_eq!(gw.exists(&[b"hll5"]).await, Frame::Integer(1));
+    assert_eq!(gw.pfcount(&[b"hll5"]).await, Frame::Integer(0));
+}
+
+#[tokio::test]
+async fn test_hll_string_form() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    // A small HyperLogLog reads as a sparse Redis string
+    let _ = gw.pfadd(b"hll_str", &[b"a", b"b", b"c"]).await;
+    let sparse = bulk(gw.get(b"hll_str").await);
+    assert_eq!(&sparse[..5], b"HYLL\x01");
+    assert_eq!(gw.strlen(b"hll_str").await, Frame::Integer(sparse.len() as i64));
+    assert_eq!(gw.getrange(b"hll_str", b"0", b"3").await, Frame::BulkString(b"HYLL".to_vec()));
+
+    // Copied as a string, as from Redis, it still counts and takes adds
+    let _ = gw.set(b"hll_copy", &sparse, SetFlags::default()).await;
+    assert_eq!(gw.pfcount(&[b"hll_copy"]).await, Frame::Integer(3));
+    assert_eq!(gw.pfadd(b"hll_copy", &[b"c"]).await, Frame::Integer(0));
+    assert_eq!(gw.pfadd(b"hll_copy", &[b"d"]).await, Frame::Integer(1));
+    assert_eq!(gw.pfcount(&[b"hll_copy", b"hll_str"]).await, Frame::Integer(4));
+
+    // Redis's own empty HyperLogLog
+    let _ = gw.set(b"hll_redis", EMPTY_HLL, SetFlags::default()).await;
+    assert_eq!(gw.pfcount(&[b"hll_redis"]).await, Frame::Integer(0));
+    assert_eq!(gw.pfmerge(b"hll_redis", &[b"hll_str"]).await, Frame::SimpleString(b"OK".to_vec()));
+    assert_eq!(gw.pfcount(&[b"hll_redis"]).await, Frame::Integer(3));
+
+    // Large ones switch to the dense encoding
+    let added = elements("dense", 5000);
+    let _ = gw.pfadd(b"hll_dense", &refs(&added)).await;
+    let dense = bulk(gw.get(b"hll_dense").await);
+    assert_eq!((dense.len(), dense[4]), (DENSE_LEN, 0));
+    let _ = gw.set(b"hll_dense_copy", &dense, SetFlags::default()).await;
+    assert_eq!(gw.pfcount(&[b"hll_dense_copy"]).await, gw.pfcount(&[b"hll_dense"]).await);
+}
+
+#[tokio::test]
+async fn test_hll_wrong_type() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"hll_plain", b"not a hyperloglog", SetFlags::default()).await;
+    let _ = gw.incr(b"hll_counter").await;
+    let mut truncated = EMPTY_HLL.to_vec();
+    truncated[17] = 0xfe;
+    let _ = gw.set(b"hll_truncated", &truncated, SetFlags::default()).await;
+    let keys: [&[u8]; 3] = [b"hll_plain", b"hll_counter", b"hll_truncated"];
+    for key in keys {
+        assert_eq!(gw.pfadd(key, &[b"a"]).await, wrong_type());
+        assert_eq!(gw.pfcount(&[key]).await, wrong_type());
+        assert_eq!(gw.pfmerge(key, &[]).await, wrong_type());
+    }
+    assert_eq!(gw.get(b"hll_plain").await, Frame::BulkString(b"not a hyperloglog".to_vec()));
+    let _ = gw.pfadd(b"hll", &[b"a"]).await;
+    assert_eq!(gw.pfcount(&[b"hll", b"hll_plain"]).await, wrong_type());
+    assert_eq!(gw.pfmerge(b"hll_dest", &[b"hll", b"hll_counter"]).await, wrong_type());
+    assert_eq!(gw.exists(&[b"hll_dest"]).await, Frame::Integer(0));
+
+    // A HyperLogLog is no number, and any write of a string replaces it
+    assert_eq!(gw.incr(b"hll").await, Frame::Error("ERR value is not an integer or out of range".into()));
+    assert_eq!(gw.incr_by_float(b"hll", b"1").await, Frame::Error("ERR value is not a valid float".into()));
+    let _ = gw.set(b"hll", b"plain", SetFlags::default()).await;
+    assert_eq!(gw.get(b"hll").await, Frame::BulkString(b"plain".to_vec()));
+    assert_eq!(gw.pfcount(&[b"hll"]).await, wrong_type());
+}
+
+#[tokio::test]
+async fn test_concurrent_pfadd() {
+    use futures::future::join_all;
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    let added = elements("concurrent", 2000);
+    let tasks: Vec<_> = added
+        .chunks(100)
+        .map(|batch| {
+            let gw = RedisGateway::new(db.clone());
+            let batch = batch.to_vec();
+            tokio::spawn(async move {
+                for pair in batch.chunks(2) {
+                    let _ = gw.pfadd(b"hll_conc", &refs(pair)).await;
+                }
+            })
+        })
+        .collect();
+    let _ = join_all(tasks).await;
+
+    // No add is lost: the registers match those of a single sequential add
+    let mut expected = Registers::default();
+    for element in &added {
+        let (index, value) = hll::register_of(element);
+        expected.raise(index, value);
+    }
+    assert_eq!(gw.get(b"hll_conc").await, Frame::BulkString(expected.encode()));
+    assert_eq!(gw.pfcount(&[b"hll_conc"]).await, Frame::Integer(expected.count() as i64));
+}
+
+#[test]
+fn test_registers_encoding() {
+    let empty = Registers::default();
+    assert_eq!(Registers::decode(EMPTY_HLL), Some(empty.clone()));
+    assert_eq!(empty.count(), 0);
+
+    // Values up to 32 fit the sparse encoding, larger ones need the dense one
+    let mut registers = Registers::default();
+    registers.raise(0, 32);
+    registers.raise(1, 32);
+    registers.raise(16383, 1);
+    let sparse = registers.encode();
+    assert_eq!(&sparse[16..], b"\xfd\x7f\xfc\x80");
+    assert_eq!(Registers::decode(&sparse), Some(registers.clone()));
+    assert!(registers.raise(100, 33));
+    assert!(!registers.raise(100, 2));
+    let dense = registers.encode();
+    assert_eq!(dense.len(), DENSE_LEN);
+    assert_eq!(Registers::decode(&dense), Some(registers));
+
+    // Runs must cover exactly every register, and dense strings be complete
+    assert_eq!(Registers::decode(b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff\x00"), None);
+    assert_eq!(Registers::decode(b"HYLL\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f"), None);
+    assert_eq!(Registers::decode(&dense[..DENSE_LEN - 1]), None);
+    assert_eq!(Registers::decode(b"HYLL\x02\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x7f\xff"), None);
+    assert_eq!(Registers::decode(b"hyll"), None);
+}
@@ -0,0 +1,225 @@
+//! Encoding of Redis keys into FoundationDB keys.
+//!
+//! FoundationDB rejects keys over 10,000 bytes, while Redis accepts keys of up
+//! to 512MB. Keys short enough are tuple-packed as is; longer keys are stored
+//! under the SHA-256 digest of the key and a collision slot. Each slot records
+//! the length and SHA-512 digest of the key owning it, so lookups can tell
+//! colliding keys apart without reading them back; the full key is kept in a
+//! metadata value of its own, written along with the slot unless too large
+//! for one transaction.
+//!
+//! Slots of a digest are allocated in order and never released, so a lookup
+//! can stop at the first free slot, and a key resolved once keeps its slot.
+use crate::error::Result;
+use crate::string::datamodel::SimpleDataPrefix;
+use fdb::FoundationDB;
+use foundationdb_tuple::{PackResult, TupleDepth, TuplePack, TupleUnpack, VersionstampOffset, pack};
+use sha2::{Digest, Sha256, Sha512};
+use std::io::Write;
+use std::sync::Mutex;
+
+/// Largest packed key element stored inline. Leaves room under FoundationDB's
+/// 10,000 byte key limit for the prefix and the suffixes of chunk keys.
+pub const MAX_INLINE_KEY_SIZE: usize = 9_000;
+
+/// Longest key whose full form is written in the transaction claiming its
+/// slot, well within FoundationDB's 10MB limit on a transaction.
+const MAX_NAME_IN_CLAIM: usize = 1_000_000;
+
+/// Redis key as it appears in the tuple of every record stored for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoredKey {
+    /// The key itself, packed as bytes.
+    Inline(Vec<u8>),
+    /// A long key, packed as the nested tuple `(digest, slot)`.
+    Hashed { digest: Vec<u8>, slot: u64 },
+}
+
+impl StoredKey {
+    /// Whether `key` fits inline.
+    pub fn fits_inline(key: &[u8]) -> bool {
+        // Bytes are packed between a type code and a terminator, with every
+        // 0x00 escaped as 0x00 0xFF
+        let zeros = key.iter().filter(|&&b| b == 0).count();
+        key.len() + zeros + 2 <= MAX_INLINE_KEY_SIZE
+    }
+
+    /// FoundationDB key of the record stored for this key under `prefix`.
+    pub fn pack(&self, prefix: SimpleDataPrefix) -> Vec<u8> {
+        pack(&(prefix, self))
+    }
+}
+
+impl TuplePack for StoredKey {
+    fn pack<W: Write>(
+        &self,
+        w: &mut W,
+        tuple_depth: TupleDepth,
+    ) -> std::io::Result<VersionstampOffset> {
+        match self {
+            StoredKey::Inline(key) => key.as_slice().pack(w, tuple_depth),
+            StoredKey::Hashed { digest, slot } => (digest.as_slice(), *slot).pack(w, tuple_depth),
+        }
+    }
+}
+
+impl<'de> TupleUnpack<'de> for StoredKey {
+    fn unpack(input: &'de [u8], tuple_depth: TupleDepth) -> PackResult<(&'de [u8], Self)> {
+        if let Ok((rest, key)) = Vec::<u8>::unpack(input, tuple_depth) {
+            return Ok((rest, StoredKey::Inline(key)));
+        }
+        let (rest, (digest, slot)) = <(Vec<u8>, u64)>::unpack(input, tuple_depth)?;
+        Ok((rest, StoredKey::Hashed { digest, slot }))
+    }
+}
+
+/// Metadata record telling which key owns `slot` of `digest`, by its
+/// fingerprint.
+pub(crate) fn slot_key(digest: &[u8], slot: u64) -> Vec<u8> {
+    pack(&(SimpleDataPrefix::Meta, "long_key", digest, slot))
+}
+
+/// Metadata record holding the full key assigned to `slot` of `digest`.
+pub(crate) fn name_key(digest: &[u8], slot: u64) -> Vec<u8> {
+    pack(&(SimpleDataPrefix::Meta, "long_key_name", digest, slot))
+}
+
+pub(crate) fn digest(key: &[u8]) -> Vec<u8> {
+    Sha256::digest(key).to_vec()
+}
+
+/// Length of `key` followed by its SHA-512 digest: two keys sharing a SHA-256
+/// digest are told apart by it.
+pub(crate) fn fingerprint(key: &[u8]) -> Vec<u8> {
+    [(key.len() as u64).to_be_bytes().as_slice(), &Sha512::digest(key)].concat()
+}
+
+/// Storage key of `key`, or `None` for a long key that was never written.
+pub async fn lookup(fdb: &FoundationDB, key: &[u8]) -> Result<Option<StoredKey>> {
+    if StoredKey::fits_inline(key) {
+        return Ok(Some(StoredKey::Inline(key.to_vec())));
+    }
+    let (digest, fingerprint) = (&digest(key), &fingerprint(key));
+    let slot = fdb
+        .run(move |trx, _| async move {
+            let mut slot = 0;
+            loop {
+                match trx.get(&slot_key(digest, slot), false).await? {
+                    Some(owner) if &owner == fingerprint => return Ok(Some(slot)),
+                    // Another key with the same digest
+                    Some(_) => slot += 1,
+                    None => return Ok(None),
+                }
+            }
+        })
+        .await
+        .map_err(fdb::Error::from)?;
+    Ok(slot.map(|slot| StoredKey::Hashed {
+        digest: digest.clone(),
+        slot,
+    }))
+}
+
+/// Storage key of `key`, assigning a slot to a long key written for the first time.
+pub async fn resolve(fdb: &FoundationDB, key: &[u8]) -> Result<StoredKey> {
+    if StoredKey::fits_inline(key) {
+        return Ok(StoredKey::Inline(key.to_vec()));
+    }
+    let (digest, fingerprint) = (&digest(key), &fingerprint(key));
+    let named = key.len() <= MAX_NAME_IN_CLAIM;
+
+    // Run at most once: a replay would find the slot it claimed taken and
+    // leave the key without its full form
+    let (slot, claimed) = fdb
+        .run_once(move |trx| async move {
+            let mut slot = 0;
+            loop {
+                let record = slot_key(digest, slot);
+                match trx.get(&record, false).await? {
+                    Some(owner) if &owner == fingerprint => return Ok((slot, false)),
+                    Some(_) => slot += 1,
+                    // A concurrent writer claiming the slot first makes this
+                    // transaction conflict and look again
+                    None => {
+                        trx.set(&record, fingerprint);
+                        if named {
+                            fdb.set_in_trx(&trx, &name_key(digest, slot), key).await?;
+                        }
+                        return Ok((slot, true));
+                    }
+                }
+            }
+        })
+        .await?;
+    // Larger keys get their full form once the slot is theirs, again on a
+    // later write when that one failed
+    if !named {
+        let name = name_key(digest, slot);
+        if claimed || fdb.value_len(&name).await?.is_none() {
+            fdb.set(&name, key).await?;
+        }
+    }
+    Ok(StoredKey::Hashed {
+        digest: digest.clone(),
+        slot,
+    })
+}
+
+/// Redis key stored as `stored`, or `None` when its slot is unassigned or
+/// the full form of a key too long to be written along with its slot is
+/// not written yet.
+pub async fn user_key(fdb: &FoundationDB, stored: &StoredKey) -> Result<Option<Vec<u8>>> {
+    match stored {
+        StoredKey::Inline(key) => Ok(Some(key.clone())),
+        StoredKey::Hashed { digest, slot } => Ok(fdb.get(&name_key(digest, *slot)).await?),
+    }
+}
+
+/// Storage keys of the long keys a command looked up, so that it hashes and
+/// looks up each of them once however many records it touches.
+#[derive(Default)]
+pub struct Resolved {
+    keys: Mutex<Vec<(Vec<u8>, StoredKey)>>,
+}
+
+impl Resolved {
+    /// `lookup`, remembering the storage key found.
+    pub async fn lookup(&self, fdb: &FoundationDB, key: &[u8]) -> Result<Option<StoredKey>> {
+        if let Some(stored) = self.cached(key) {
+            return Ok(Some(stored));
+        }
+        let stored = lookup(fdb, key).await?;
+        if let Some(stored) = &stored {
+            self.remember(key, stored);
+        }
+        Ok(stored)
+    }
+
+    /// `resolve`, remembering the storage key assigned.
+    pub async fn resolve(&self, fdb: &FoundationDB, key: &[u8]) -> Result<StoredKey> {
+        if let Some(stored) = self.cached(key) {
+            return Ok(stored);
+        }
+        let stored = resolve(fdb, key).await?;
+        self.remember(key, &stored);
+        Ok(stored)
+    }
+
+    fn cached(&self, key: &[u8]) -> Option<StoredKey> {
+        if StoredKey::fits_inline(key) {
+            return Some(StoredKey::Inline(key.to_vec()));
+        }
+        let keys = self.keys.lock().expect("resolved keys lock");
+        keys.iter().find(|(k, _)| k.as_slice() == key).map(|(_, stored)| stored.clone())
+    }
+
+    fn remember(&self, key: &[u8], stored: &StoredKey) {
+        if let StoredKey::Hashed { .. } = stored {
+            let mut keys = self.keys.lock().expect("resolved keys lock");
+            keys.push((key.to_vec(), stored.clone()));
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod tests;
@@ -0,0 +1,207 @@
+use crate::gateway::RedisGateway;
+use crate::keys::{self, MAX_INLINE_KEY_SIZE, StoredKey};
+use crate::string::datamodel::SimpleDataPrefix;
+use crate::string::operations::{SetFlags, SetTTL, StringOperations};
+use fdb::FoundationDB;
+use foundationdb_tuple::{Subspace, unpack};
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+/// FoundationDB's key size limit.
+const FDB_MAX_KEY_SIZE: usize = 10_000;
+
+/// Longest suffix appended to a value root by its chunk keys.
+fn longest_chunk_key(root: &[u8]) -> Vec<u8> {
+    Subspace::from_bytes(root.to_vec()).pack(&("g", u64::MAX, u32::MAX as i64))
+}
+
+#[test]
+fn test_inline_boundary() {
+    // Bytes are packed with a type code and a terminator
+    let longest = vec![b'k'; MAX_INLINE_KEY_SIZE - 2];
+    assert!(StoredKey::fits_inline(&longest));
+    assert!(!StoredKey::fits_inline(&[longest.as_slice(), b"k"].concat()));
+
+    // 0x00 is escaped on two bytes
+    let zeros = vec![0u8; (MAX_INLINE_KEY_SIZE - 2) / 2];
+    assert!(StoredKey::fits_inline(&zeros));
+    assert!(!StoredKey::fits_inline(&[zeros.as_slice(), &[0]].concat()));
+
+    for key in [longest, zeros] {
+        let stored = StoredKey::Inline(key);
+        for prefix in [SimpleDataPrefix::Data, SimpleDataPrefix::Ttl, SimpleDataPrefix::Lock] {
+            let root = stored.pack(prefix);
+            assert!(longest_chunk_key(&root).len() <= FDB_MAX_KEY_SIZE);
+        }
+    }
+}
+
+#[test]
+fn test_stored_key_tuples() {
+    let inline = StoredKey::Inline(b"key".to_vec());
+    let hashed = StoredKey::Hashed {
+        digest: keys::digest(b"key"),
+        slot: 3,
+    };
+    for stored in [&inline, &hashed] {
+        let root = stored.pack(SimpleDataPrefix::Data);
+        let (_, unpacked) = unpack::<(u64, StoredKey)>(&root).expect("unpack root");
+        assert_eq!(&unpacked, stored);
+
+        let chunk = longest_chunk_key(&root);
+        let (_, unpacked, _, _, _) =
+            unpack::<(u64, StoredKey, String, u64, i64)>(&chunk).expect("unpack chunk");
+        assert_eq!(&unpacked, stored);
+    }
+
+    // Hashed keys never collide with inline ones and sort after them
+    let inline_root = inline.pack(SimpleDataPrefix::Data);
+    let hashed_root = hashed.pack(SimpleDataPrefix::Data);
+    assert!(!hashed_root.starts_with(&inline_root));
+    assert!(inline_root < hashed_root);
+    assert!(hashed_root.len() < 100);
+}
+
+#[tokio::test]
+async fn test_long_keys_around_fdb_limit() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    for len in [MAX_INLINE_KEY_SIZE - 2, MAX_INLINE_KEY_SIZE - 1, 9_999, 10_000, 10_001, 1_000_000] {
+        let key = format!("long_key_{}_", len).into_bytes();
+        let key = [key.as_slice(), &vec![b'x'; len - key.len()]].concat();
+        let value = format!("value of {}", len).into_bytes();
+
+        assert_eq!(gw.get(&key).await, Frame::Null, "len {}", len);
+        assert_eq!(gw.getdel(&key).await, Frame::Null, "len {}", len);
+        if !StoredKey::fits_inline(&key) {
+            // Nothing was written, not even a slot
+            assert_eq!(keys::lookup(&db, &key).await.expect("lookup"), None);
+        }
+        let res = gw.set(&key, &value, SetFlags::default()).await;
+        assert_eq!(res, Frame::SimpleString(b"OK".to_vec()), "len {}", len);
+        assert_eq!(gw.get(&key).await, Frame::BulkString(value.clone()), "len {}", len);
+
+        let stored = keys::lookup(&db, &key)
+            .await
+            .expect("lookup failed")
+            .expect("missing key");
+        assert_eq!(
+            matches!(stored, StoredKey::Inline(_)),
+            StoredKey::fits_inline(&key),
+            "len {}",
+            len
+        );
+        assert_eq!(
+            keys::user_key(&db, &stored).await.expect("user_key"),
+            Some(key.clone())
+        );
+
+        // Large values and TTLs are stored under the same encoding
+        let large = vec![7u8; 250_000];
+        let flags = SetFlags {
+            method: None,
+            ttl: Some(SetTTL::Ex(60)),
+            get: true,
+        };
+        assert_eq!(gw.set(&key, &large, flags).await, Frame::BulkString(value));
+        assert_eq!(gw.get(&key).await, Frame::BulkString(large), "len {}", len);
+
+        assert_eq!(gw.del(&[key.as_slice()]).await, Frame::Integer(1), "len {}", len);
+        assert_eq!(gw.get(&key).await, Frame::Null, "len {}", len);
+    }
+}
+
+#[tokio::test]
+async fn test_digest_collision() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    let key = [b"collision_".as_slice(), &vec![b'c'; 20_000]].concat();
+    let other = [b"collision_".as_slice(), &vec![b'o'; 20_000]].concat();
+
+    // Pretend another key with the same digest and length already took the
+    // first slot
+    let digest = keys::digest(&key);
+    let claimed = (keys::slot_key(&digest, 0), keys::fingerprint(&other));
+    db.run(|trx, _| {
+        let claimed = claimed.clone();
+        async move {
+            trx.set(&claimed.0, &claimed.1);
+            Ok(())
+        }
+    })
+    .await
+    .expect("Unable to claim slot");
+
+    assert_eq!(keys::lookup(&db, &key).await.expect("lookup"), None);
+    let _ = gw.set(&key, b"mine", SetFlags::default()).await;
+    let stored = keys::lookup(&db, &key).await.expect("lookup");
+    assert_eq!(
+        stored,
+        Some(StoredKey::Hashed {
+            digest: digest.clone(),
+            slot: 1
+        })
+    );
+    assert_eq!(gw.get(&key).await, Frame::BulkString(b"mine".to_vec()));
+    assert_eq!(
+        keys::Resolved::default().lookup(&db, &key).await.expect("lookup"),
+        stored
+    );
+
+    // Slots record a fingerprint, the full key is kept apart
+    let slot_1 = keys::slot_key(&digest, 1);
+    let owner = db
+        .run(|trx, _| {
+            let slot_1 = slot_1.clone();
+            async move { Ok(trx.get(&slot_1, false).await?) }
+        })
+        .await
+        .expect("Unable to read slot");
+    assert_eq!(owner, Some(keys::fingerprint(&key)));
+    assert_eq!(
+        keys::user_key(&db, stored.as_ref().expect("missing key")).await.expect("user_key"),
+        Some(key.clone())
+    );
+
+    // The owner of slot 0 is a different key with nothing stored
+    let slot_0 = StoredKey::Hashed { digest: digest.clone(), slot: 0 };
+    assert_eq!(
+        db.get(&slot_0.pack(SimpleDataPrefix::Data)).await.expect("get"),
+        None
+    );
+
+    let _ = gw.del(&[key.as_slice()]).await;
+    let _ = db.delete(&keys::name_key(&digest, 1)).await;
+    let _ = db
+        .run(|trx, _| {
+            let digest = digest.clone();
+            async move {
+                for slot in [0, 1] {
+                    trx.clear(&keys::slot_key(&digest, slot));
+                }
+                Ok(())
+            }
+        })
+        .await;
+}
+
+#[tokio::test]
+async fn test_name_of_huge_key() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    // Too long for its full form to share the transaction claiming its slot
+    let key = vec![b'h'; 1_500_000];
+    let _ = gw.set(&key, b"huge", SetFlags::default()).await;
+    let stored = keys::lookup(&db, &key).await.expect("lookup").expect("missing key");
+    assert_eq!(keys::user_key(&db, &stored).await.expect("user_key"), Some(key.clone()));
+
+    // A full form lost before it was written is written by the next write
+    let digest = keys::digest(&key);
+    db.delete(&keys::name_key(&digest, 0)).await.expect("delete");
+    assert_eq!(keys::user_key(&db, &stored).await.expect("user_key"), None);
+    let _ = gw.set(&key, b"again", SetFlags::default()).await;
+    assert_eq!(keys::user_key(&db, &stored).await.expect("user_key"), Some(key.clone()));
+    assert_eq!(gw.get(&key).await, Frame::BulkString(b"again".to_vec()));
+}
@@ -1,10 +1,20 @@
 pub mod command;
 pub mod connection;
+pub mod error;
 pub mod string;
+pub mod bitmap;
+pub mod hyperloglog;
+pub mod stream;
 pub mod list;
 pub mod set;
 pub mod gateway;
+pub mod gc;
+pub mod keys;
+pub mod migration;
+pub mod reencrypt;
 pub mod server;
+pub mod stats;
+pub mod verify;
 
 #[cfg(test)]
 pub(crate) use crate::tests::e2e::util::with_e2e_server;
@@ -12,4 +22,5 @@ pub(crate) use crate::tests::e2e::util::with_e2e_server;
 #[cfg(test)]
 mod tests {
 	pub mod e2e;
+	pub mod unit;
 }
@@ -1,9 +1,19 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use fdb::FoundationDB;
+use fdb::codec::{self, Codec, Compression};
+use fdb::crypto::Keyring;
+use fdb::storage::Engine;
+use fdb::transaction::{self, TransactionOptions};
 use foundationdb::Database;
 use redisgw::gateway::RedisGateway;
+use redisgw::gc::{self, GcOptions};
+use redisgw::migration;
+use redisgw::reencrypt;
 use redisgw::server::Server;
+use redisgw::stats;
+use redisgw::verify;
 use std::sync::Arc;
+use std::time::Duration;
 
 const APP_TITLE: &str = "redisgw";
 
@@ -21,6 +31,75 @@ pub struct Config {
     /// Port on which to start the server
     #[clap(long = "fdb-path", env)]
     fdb_path: Option<String>,
+
+    /// Where data is stored: fdb, or memory for tests and local development
+    #[clap(long, env, default_value_t = Engine::FoundationDB)]
+    storage: Engine,
+
+    /// Compression applied to stored values: none, zstd or lz4
+    #[clap(long, env, default_value_t = Codec::None)]
+    compression: Codec,
+
+    /// Values shorter than this many bytes are stored uncompressed
+    #[clap(long = "compression-min-size", env, default_value_t = codec::DEFAULT_MIN_SIZE)]
+    compression_min_size: usize,
+
+    /// File of encryption keys; values are encrypted with its highest key id
+    #[clap(long = "encryption-key-file", env)]
+    encryption_key_file: Option<String>,
+
+    /// Milliseconds after which a transaction is cancelled, 0 for no limit
+    #[clap(long = "transaction-timeout-ms", env, default_value_t = 0)]
+    transaction_timeout_ms: u64,
+
+    /// Retries allowed per transaction before its error is returned
+    #[clap(long = "retry-limit", env, default_value_t = transaction::DEFAULT_RETRY_LIMIT)]
+    retry_limit: u32,
+
+    /// Longest backoff in milliseconds between two retries of a transaction
+    #[clap(long = "max-retry-delay-ms", env, default_value_t = 1000)]
+    max_retry_delay_ms: u64,
+
+    /// Seconds between background garbage collection runs, 0 to disable
+    #[clap(long = "gc-interval", env, default_value_t = 300)]
+    gc_interval: u64,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Upgrade stored data to the current on-disk format
+    Migrate {
+        /// Number of records rewritten per transaction
+        #[clap(long, default_value_t = migration::DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+    },
+    /// Remove orphaned chunks, stale locks and dangling TTL records
+    Gc {
+        /// Report what would be removed without removing it
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Number of records inspected per transaction
+        #[clap(long, default_value_t = gc::DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+    },
+    /// Read back every stored value and report corrupt or incomplete ones
+    Verify {
+        /// Number of values located per transaction
+        #[clap(long, default_value_t = verify::DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+    },
+    /// Print the statistics shared by the gateways of the cluster
+    Stats,
+    /// Rewrite every stored value under the active encryption key
+    Reencrypt {
+        /// Number of values located per transaction
+        #[clap(long, default_value_t = reencrypt::DEFAULT_BATCH_SIZE)]
+        batch_size: usize,
+    },
 }
 
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
@@ -28,9 +107,107 @@ async fn main() -> std::io::Result<()> {
     env_logger::init();
     let config = Config::parse();
 
-    let _network = unsafe { foundationdb::boot() };
-    let db = Database::new(config.fdb_path.as_deref()).expect("Failed to run Database");
-    let fdb = FoundationDB::new(Arc::new(db));
+    let (_network, fdb) = match config.storage {
+        Engine::FoundationDB => {
+            let network = unsafe { foundationdb::boot() };
+            let db = Database::new(config.fdb_path.as_deref()).expect("Failed to run Database");
+            (Some(network), FoundationDB::new(Arc::new(db)))
+        }
+        Engine::Memory => {
+            log::warn!("storing data in memory, it is lost when the gateway stops");
+            (None, FoundationDB::in_memory())
+        }
+    };
+    let mut fdb = fdb
+        .with_compression(Compression {
+            codec: config.compression,
+            min_size: config.compression_min_size,
+        })
+        .with_transaction_options(TransactionOptions {
+            timeout: (config.transaction_timeout_ms > 0)
+                .then(|| Duration::from_millis(config.transaction_timeout_ms)),
+            retry_limit: Some(config.retry_limit),
+            max_retry_delay: Some(Duration::from_millis(config.max_retry_delay_ms)),
+            ..TransactionOptions::default()
+        });
+    if let Some(path) = &config.encryption_key_file {
+        let keyring = Keyring::load(path).map_err(std::io::Error::other)?;
+        fdb = fdb.with_encryption(keyring);
+    }
+
+    match config.command {
+        Some(Command::Migrate { batch_size }) => {
+            let steps = migration::migrate(&fdb, batch_size)
+                .await
+                .map_err(std::io::Error::other)?;
+            println!(
+                "On-disk format is at version {} ({} migration steps applied)",
+                migration::FORMAT_VERSION,
+                steps.len()
+            );
+            return Ok(());
+        }
+        Some(Command::Gc {
+            dry_run,
+            batch_size,
+        }) => {
+            let options = GcOptions {
+                dry_run,
+                batch_size,
+                ..GcOptions::default()
+            };
+            let report = gc::collect(&fdb, &options)
+                .await
+                .map_err(std::io::Error::other)?;
+            let verb = if dry_run { "Found" } else { "Removed" };
+            println!("{} {}", verb, report);
+            return Ok(());
+        }
+        Some(Command::Verify { batch_size }) => {
+            let report = verify::verify(&fdb, batch_size)
+                .await
+                .map_err(std::io::Error::other)?;
+            for value in &report.corrupt {
+                println!(
+                    "{}: {}",
+                    String::from_utf8_lossy(&value.key),
+                    value.reason
+                );
+            }
+            println!("{}", report);
+            if !report.corrupt.is_empty() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Stats) => {
+            let stats = stats::read(&fdb).await.map_err(std::io::Error::other)?;
+            print!("{}", stats);
+            return Ok(());
+        }
+        Some(Command::Reencrypt { batch_size }) => {
+            let report = reencrypt::reencrypt(&fdb, batch_size)
+                .await
+                .map_err(std::io::Error::other)?;
+            println!("{}", report);
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let version = migration::ensure_format_version(&fdb)
+        .await
+        .map_err(std::io::Error::other)?;
+    if version >= migration::MANIFESTS_SINCE {
+        fdb = fdb.without_legacy_values();
+    }
+    if config.gc_interval > 0 {
+        tokio::spawn(gc::run_periodically(
+            fdb.clone(),
+            Duration::from_secs(config.gc_interval),
+            GcOptions::default(),
+        ));
+    }
     let gw = RedisGateway::new(fdb);
     let server = Server::new(format!("{}:{}", config.ip, config.port), gw);
     let res = server.start().await;
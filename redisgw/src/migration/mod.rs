@@ -0,0 +1,194 @@
+//! On-disk format versioning of the gateway keyspace.
+//!
+//! The format version is stored in a single metadata record. The server
+//! checks it at startup and refuses to run against data written by a newer
+//! gateway; older data is upgraded online by `redisgw migrate`, which runs
+//! each migration step in bounded transactions.
+use crate::error::{Error, Result};
+use crate::string::datamodel::SimpleDataPrefix;
+use fdb::FoundationDB;
+use foundationdb::RangeOption;
+use foundationdb_tuple::{Subspace, pack};
+
+mod v1;
+mod v2;
+
+/// Format written by gateways that predate the version record.
+pub const LEGACY_FORMAT_VERSION: u64 = 0;
+
+/// Format written by this gateway.
+/// - v1: TTL records are stored inline instead of as a chunked value.
+/// - v2: string values carry a manifest listing their chunks.
+/// - v3: chunks may hold a compressed value (manifest revision 3).
+/// - v4: manifests carry the checksum of every chunk (manifest revision 4).
+/// - v5: chunks may be encrypted (manifest revision 5).
+/// - v6: keys over the FoundationDB key limit are stored under their digest,
+///   with the fingerprint of the key in a `long_key` metadata record and
+///   the full key in a `long_key_name` one.
+/// - v7: integers are stored as atomically added counter records.
+/// - v8: HyperLogLogs are stored as one record per register.
+/// - v9: streams are stored as a marker record with one record per entry.
+pub const FORMAT_VERSION: u64 = 9;
+
+/// Format from which every later step only adds kinds of records that older
+/// data does not need. Such a keyspace is stamped with `FORMAT_VERSION` at
+/// startup, so that a gateway predating the new records refuses it instead
+/// of missing them.
+const ADDITIVE_SINCE: u64 = 2;
+
+/// Format from which every value has a manifest, so that reads need not look
+/// for values written without one.
+pub const MANIFESTS_SINCE: u64 = 2;
+
+/// Default number of records rewritten per migration transaction.
+pub const DEFAULT_BATCH_SIZE: usize = 500;
+
+fn format_version_key() -> Vec<u8> {
+    pack(&(SimpleDataPrefix::Meta, "format_version"))
+}
+
+fn decode_version(bytes: &[u8]) -> Result<u64> {
+    let arr: [u8; 8] = bytes
+        .try_into()
+        .map_err(|_| Error::Other(format!("invalid format version record: {:?}", bytes)))?;
+    Ok(u64::from_be_bytes(arr))
+}
+
+/// Range covering every record stored under `prefix`.
+pub(crate) fn prefix_range(prefix: SimpleDataPrefix) -> (Vec<u8>, Vec<u8>) {
+    Subspace::all().subspace(&(prefix,)).range()
+}
+
+/// Ranges of the records that tell a keyspace holds data.
+fn data_ranges() -> Vec<(Vec<u8>, Vec<u8>)> {
+    vec![
+        prefix_range(SimpleDataPrefix::Data),
+        prefix_range(SimpleDataPrefix::Ttl),
+        prefix_range(SimpleDataPrefix::Counter),
+        prefix_range(SimpleDataPrefix::Hll),
+        prefix_range(SimpleDataPrefix::Stream),
+        Subspace::all().subspace(&(SimpleDataPrefix::Meta, "long_key")).range(),
+    ]
+}
+
+/// Read the stored format version, if any.
+pub async fn read_format_version(fdb: &FoundationDB) -> Result<Option<u64>> {
+    let raw = fdb
+        .run(|trx, _| async move {
+            Ok(trx.get(&format_version_key(), false).await?)
+        })
+        .await
+        .map_err(fdb::Error::from)?;
+    raw.map(|v| decode_version(&v)).transpose()
+}
+
+/// Overwrite the stored format version.
+pub async fn write_format_version(fdb: &FoundationDB, version: u64) -> Result<()> {
+    fdb.run(move |trx, _| async move {
+        trx.set(&format_version_key(), &version.to_be_bytes());
+        Ok(())
+    })
+    .await
+    .map_err(|e| fdb::Error::from(e).into())
+}
+
+/// Check the stored format version at startup, writing it when missing.
+///
+/// A keyspace without a version record is stamped with `FORMAT_VERSION` when
+/// empty and with `LEGACY_FORMAT_VERSION` otherwise; one at `ADDITIVE_SINCE`
+/// or later is stamped with `FORMAT_VERSION`. Returns the version in use, or
+/// an error when the data was written by a newer gateway.
+pub async fn ensure_format_version(fdb: &FoundationDB) -> Result<u64> {
+    let raw = fdb
+        .run(|trx, _| async move {
+            let key = format_version_key();
+            if let Some(v) = trx.get(&key, false).await? {
+                return Ok(v);
+            }
+
+            let mut has_data = false;
+            for (begin, end) in data_ranges() {
+                let opt = RangeOption {
+                    limit: Some(1),
+                    ..RangeOption::from((begin, end))
+                };
+                if !trx.get_range(&opt, false).await?.is_empty() {
+                    has_data = true;
+                    break;
+                }
+            }
+
+            let version = if has_data {
+                LEGACY_FORMAT_VERSION
+            } else {
+                FORMAT_VERSION
+            };
+            trx.set(&key, &version.to_be_bytes());
+            Ok(version.to_be_bytes().to_vec())
+        })
+        .await
+        .map_err(fdb::Error::from)?;
+
+    let mut version = decode_version(&raw)?;
+    if version > FORMAT_VERSION {
+        return Err(Error::Other(format!(
+            "on-disk format version {} is newer than supported version {}",
+            version, FORMAT_VERSION
+        )));
+    }
+    if (ADDITIVE_SINCE..FORMAT_VERSION).contains(&version) {
+        write_format_version(fdb, FORMAT_VERSION).await?;
+        log::info!("on-disk format version {} stamped as {}", version, FORMAT_VERSION);
+        version = FORMAT_VERSION;
+    }
+    if version < FORMAT_VERSION {
+        log::warn!(
+            "on-disk format version {} is older than {}, run `redisgw migrate` to upgrade",
+            version,
+            FORMAT_VERSION
+        );
+    }
+    Ok(version)
+}
+
+/// Outcome of a single migration step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationStep {
+    /// Format version reached by this step.
+    pub version: u64,
+    /// Number of records rewritten.
+    pub migrated: usize,
+}
+
+/// Upgrade the keyspace to `FORMAT_VERSION`.
+///
+/// Each step rewrites at most `batch_size` records per transaction, so the
+/// gateway can keep serving while it runs; the version record is bumped once
+/// a step completes.
+pub async fn migrate(fdb: &FoundationDB, batch_size: usize) -> Result<Vec<MigrationStep>> {
+    let batch_size = batch_size.max(1);
+    let mut version = ensure_format_version(fdb).await?;
+    let mut steps = Vec::new();
+
+    while version < FORMAT_VERSION {
+        let migrated = match version {
+            0 => v1::inline_ttl_records(fdb, batch_size).await?,
+            1 => v2::write_manifests(fdb, batch_size).await?,
+            // Later steps only add kinds of records
+            _ => 0,
+        };
+        version += 1;
+        write_format_version(fdb, version).await?;
+        log::info!(
+            "migrated to format version {} ({} records rewritten)",
+            version,
+            migrated
+        );
+        steps.push(MigrationStep { version, migrated });
+    }
+
+    Ok(steps)
+}
+
+#[cfg(test)]
+pub mod tests;
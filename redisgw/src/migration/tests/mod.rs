@@ -0,0 +1,4 @@
+#[cfg(test)]
+mod unit {
+    pub mod migrate;
+}
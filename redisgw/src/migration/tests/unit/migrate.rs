@@ -0,0 +1,136 @@
+use crate::gateway::RedisGateway;
+use crate::migration::{
+    FORMAT_VERSION, LEGACY_FORMAT_VERSION, ensure_format_version, migrate, read_format_version,
+    write_format_version,
+};
+use crate::string::datamodel::SimpleDataPrefix;
+use crate::string::operations::{SetFlags, StringOperations};
+use fdb::FoundationDB;
+use foundationdb_tuple::{Subspace, pack};
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Write `value` as a single bare chunk below `root`, as format v0 did.
+async fn write_legacy(db: &FoundationDB, root: Vec<u8>, value: Vec<u8>) {
+    let chunk_key = Subspace::from_bytes(root).pack(&(0,));
+    db.run(|trx, _| {
+        let chunk_key = chunk_key.clone();
+        let value = value.clone();
+        async move {
+            trx.set(&chunk_key, &value);
+            Ok(())
+        }
+    })
+    .await
+    .expect("Unable to write legacy record");
+}
+
+fn now_ms() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
+}
+
+#[tokio::test]
+async fn test_migrate_from_legacy_layout() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    // Write values the way format v0 did: bare chunks, TTLs included.
+    let live = b"migrate_live".to_vec();
+    let expired = b"migrate_expired".to_vec();
+    for (key, ttl) in [(&live, now_ms() + 60_000), (&expired, now_ms() - 1)] {
+        let data_key = pack(&(SimpleDataPrefix::Data, key.as_slice()));
+        let ttl_key = pack(&(SimpleDataPrefix::Ttl, key.as_slice()));
+        write_legacy(&db, data_key, b"value".to_vec()).await;
+        write_legacy(&db, ttl_key, ttl.to_be_bytes().to_vec()).await;
+    }
+    write_format_version(&db, LEGACY_FORMAT_VERSION)
+        .await
+        .expect("Unable to write format version");
+
+    // Legacy TTLs are honoured before the migration runs.
+    assert_eq!(gw.get(&expired).await, Frame::Null);
+
+    // A handle told every value has a manifest does not look for others
+    let migrated = RedisGateway::new(db.clone().without_legacy_values());
+    assert_eq!(migrated.get(&live).await, Frame::Null);
+
+    let steps = migrate(&db, 1).await.expect("migration failed");
+    assert_eq!(steps.last().map(|s| s.version), Some(FORMAT_VERSION));
+    let version = read_format_version(&db)
+        .await
+        .expect("Unable to read version");
+    assert_eq!(version, Some(FORMAT_VERSION));
+
+    let ttl_key = pack(&(SimpleDataPrefix::Ttl, live.as_slice()));
+    let legacy_key = Subspace::from_bytes(ttl_key.clone()).pack(&(0,));
+    let (inline, legacy) = db
+        .run(|trx, _| {
+            let ttl_key = ttl_key.clone();
+            let legacy_key = legacy_key.clone();
+            async move {
+                let inline = trx.get(&ttl_key, false).await?;
+                let legacy = trx.get(&legacy_key, false).await?;
+                Ok((inline, legacy))
+            }
+        })
+        .await
+        .expect("Unable to read ttl records");
+    assert_eq!(inline.map(|v| v.len()), Some(16));
+    assert!(legacy.is_none());
+
+    let data_key = pack(&(SimpleDataPrefix::Data, live.as_slice()));
+    let manifest = db.manifest(&data_key).await.expect("Unable to read manifest");
+    assert_eq!(manifest.map(|m| m.total_len), Some(5));
+
+    assert_eq!(gw.get(&live).await, Frame::BulkString(b"value".to_vec()));
+    assert_eq!(migrated.get(&live).await, Frame::BulkString(b"value".to_vec()));
+    let _ = gw.del(&[live.as_slice()]).await;
+
+    // Running it again is a no-op.
+    let steps = migrate(&db, 1).await.expect("second migration failed");
+    assert!(steps.is_empty());
+}
+
+#[tokio::test]
+async fn test_refuse_newer_format_version() {
+    let db = FoundationDB::in_memory();
+
+    write_format_version(&db, FORMAT_VERSION + 1)
+        .await
+        .expect("Unable to write format version");
+    assert!(ensure_format_version(&db).await.is_err());
+    assert!(migrate(&db, 10).await.is_err());
+
+    write_format_version(&db, FORMAT_VERSION)
+        .await
+        .expect("Unable to restore format version");
+    let version = ensure_format_version(&db)
+        .await
+        .expect("Unable to check format version");
+    assert_eq!(version, FORMAT_VERSION);
+}
+
+#[tokio::test]
+async fn test_stamp_additive_format_versions() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    // Formats past v2 only add records: a v2 keyspace is stamped at startup,
+    // so that older gateways refuse it once newer records may be written.
+    let _ = gw.set(b"stamped", b"value", SetFlags::default()).await;
+    write_format_version(&db, 2)
+        .await
+        .expect("Unable to write format version");
+    let version = ensure_format_version(&db)
+        .await
+        .expect("Unable to check format version");
+    assert_eq!(version, FORMAT_VERSION);
+    let stored = read_format_version(&db)
+        .await
+        .expect("Unable to read version");
+    assert_eq!(stored, Some(FORMAT_VERSION));
+    assert_eq!(gw.get(b"stamped").await, Frame::BulkString(b"value".to_vec()));
+}
@@ -0,0 +1,57 @@
+//! v0 -> v1: store TTL records inline.
+//!
+//! v0 wrote TTLs through the chunked value path, leaving the 16-byte
+//! big-endian expiry at `(Ttl, key, 0)`. v1 stores it directly at `(Ttl, key)`.
+use crate::error::Result;
+use crate::migration::prefix_range;
+use crate::string::datamodel::SimpleDataPrefix;
+use fdb::FoundationDB;
+use foundationdb::RangeOption;
+use foundationdb_tuple::{pack, unpack};
+
+pub(crate) async fn inline_ttl_records(fdb: &FoundationDB, batch_size: usize) -> Result<usize> {
+    let (mut cursor, end) = prefix_range(SimpleDataPrefix::Ttl);
+    let mut migrated = 0;
+
+    loop {
+        let begin = cursor.clone();
+        let stop = end.clone();
+        let (moved, last) = fdb
+            .run(move |trx, _| {
+                let begin = begin.clone();
+                let stop = stop.clone();
+                async move {
+                    let opt = RangeOption {
+                        limit: Some(batch_size),
+                        ..RangeOption::from((begin, stop))
+                    };
+                    let kvs = trx.get_range(&opt, false).await?;
+                    let mut moved = 0;
+                    let mut last = None;
+                    for (raw, value) in kvs {
+                        // Inline records are 2-tuples and do not match
+                        if let Ok((_, key, _)) = unpack::<(u64, Vec<u8>, i64)>(&raw) {
+                            trx.set(&pack(&(SimpleDataPrefix::Ttl, key.as_slice())), &value);
+                            trx.clear(&raw);
+                            moved += 1;
+                        }
+                        last = Some(raw);
+                    }
+                    Ok((moved, last))
+                }
+            })
+            .await
+            .map_err(fdb::Error::from)?;
+
+        migrated += moved;
+        match last {
+            Some(mut key) => {
+                key.push(0x00);
+                cursor = key;
+            }
+            None => break,
+        }
+    }
+
+    Ok(migrated)
+}
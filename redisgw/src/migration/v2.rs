@@ -0,0 +1,66 @@
+//! v1 -> v2: give every string value a manifest.
+//!
+//! v1 values are bare chunks at `(Data, key, i)`; v2 adds a manifest at
+//! `(Data, key)` so reads fetch exactly the chunks it lists.
+use crate::error::Result;
+use crate::migration::prefix_range;
+use crate::string::datamodel::SimpleDataPrefix;
+use fdb::FoundationDB;
+use foundationdb::RangeOption;
+use foundationdb_tuple::{pack, unpack};
+
+pub(crate) async fn write_manifests(fdb: &FoundationDB, batch_size: usize) -> Result<usize> {
+    let (mut cursor, end) = prefix_range(SimpleDataPrefix::Data);
+    let mut migrated = 0;
+
+    loop {
+        let begin = cursor.clone();
+        let stop = end.clone();
+        let keys = fdb
+            .run(move |trx, _| {
+                let begin = begin.clone();
+                let stop = stop.clone();
+                async move {
+                    let opt = RangeOption {
+                        limit: Some(batch_size),
+                        ..RangeOption::from((begin, stop))
+                    };
+                    let kvs = trx.get_range(&opt, true).await?;
+                    Ok(kvs.into_iter().map(|(key, _)| key).collect::<Vec<_>>())
+                }
+            })
+            .await
+            .map_err(fdb::Error::from)?;
+
+        let Some(last) = keys.last() else {
+            break;
+        };
+        cursor = last.clone();
+        cursor.push(0x00);
+
+        // Collect the value roots of the chunks seen in this batch
+        let mut roots: Vec<Vec<u8>> = Vec::new();
+        for key in &keys {
+            if let Ok((_, user_key, _)) = unpack::<(u64, Vec<u8>, i64)>(key) {
+                let root = pack(&(SimpleDataPrefix::Data, user_key.as_slice()));
+                if roots.last() != Some(&root) {
+                    roots.push(root);
+                }
+            }
+        }
+
+        for root in roots {
+            if fdb.adopt_legacy_value(&root).await? {
+                migrated += 1;
+            }
+            // Skip the remaining chunks of this value
+            let mut after = root;
+            after.push(0xFF);
+            if after > cursor {
+                cursor = after;
+            }
+        }
+    }
+
+    Ok(migrated)
+}
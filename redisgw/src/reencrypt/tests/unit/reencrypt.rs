@@ -0,0 +1,64 @@
+use crate::gateway::RedisGateway;
+use crate::reencrypt::reencrypt_range;
+use crate::string::datamodel::SimpleDataPrefix;
+use crate::string::operations::{SetFlags, StringOperations};
+use fdb::FoundationDB;
+use fdb::crypto::{Cipher, Keyring};
+use foundationdb_tuple::pack;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+const OLD_KEY: &str = "1 aes-256-gcm 000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+const NEW_KEY: &str =
+    "2 chacha20-poly1305 1f1e1d1c1b1a191817161514131211100f0e0d0c0b0a09080706050403020100";
+
+#[tokio::test]
+async fn test_reencrypt_rotates_keys() {
+    let db = FoundationDB::in_memory();
+    let keyring = |text: &str| Keyring::parse(text).expect("Unable to parse keyring");
+    let old = db.clone().with_encryption(keyring(OLD_KEY));
+    let gw = RedisGateway::new(old);
+
+    let small = b"reencrypt_small".to_vec();
+    let large = b"reencrypt_large".to_vec();
+    let large_value = vec![3u8; 250_000];
+    let _ = gw.set(&small, b"secret", SetFlags::default()).await;
+    let _ = gw.set(&large, &large_value, SetFlags::default()).await;
+
+    // Only walk the keys of this test
+    let mut begin = pack(&(SimpleDataPrefix::Data,));
+    begin.push(0x01);
+    begin.extend_from_slice(b"reencrypt_");
+    let mut end = begin.clone();
+    end.push(0xFF);
+
+    let rotated = db.clone()
+        .with_encryption(keyring(&format!("{}\n{}", OLD_KEY, NEW_KEY)));
+    let report = reencrypt_range(&rotated, begin.clone(), end.clone(), 1)
+        .await
+        .expect("reencrypt failed");
+    assert_eq!((report.checked, report.rewritten), (2, 2));
+
+    for key in [&small, &large] {
+        let root = pack(&(SimpleDataPrefix::Data, key.as_slice()));
+        let manifest = rotated
+            .manifest(&root)
+            .await
+            .expect("Unable to read manifest")
+            .expect("missing manifest");
+        assert_eq!((manifest.cipher, manifest.key_id), (Cipher::ChaCha20Poly1305, 2));
+    }
+
+    // the old key can be retired
+    let gw = RedisGateway::new(db.clone().with_encryption(keyring(NEW_KEY)));
+    assert_eq!(gw.get(&small).await, Frame::BulkString(b"secret".to_vec()));
+    assert_eq!(gw.get(&large).await, Frame::BulkString(large_value));
+
+    // a second pass has nothing left to rewrite
+    let again = reencrypt_range(&rotated, begin, end, 10)
+        .await
+        .expect("reencrypt failed");
+    assert_eq!((again.checked, again.rewritten), (2, 0));
+
+    let _ = gw.del(&[small.as_slice()]).await;
+    let _ = gw.del(&[large.as_slice()]).await;
+}
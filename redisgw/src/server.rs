@@ -1,5 +1,6 @@
-use crate::command::CommandHandler;
+use crate::command::{CommandHandler, Reply};
 use crate::gateway::RedisGateway;
+use futures::StreamExt;
 use redis_protocol::resp2::{
     decode::decode,
     encode::encode,
@@ -81,14 +82,43 @@ impl Server {
 
             for frame in frames {
                 let response = Self::process_command(&frame, &handler).await;
-                let mut out = vec![0u8; response.encode_len(false)];
-                let _ = encode(&mut out, &response, false);
+                if Self::write_reply(&mut socket, response).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Write `reply` to the socket, streaming bulk values chunk by chunk.
+    /// A streamed value that fails midway cannot be turned into an error
+    /// reply any more, so the connection is closed instead.
+    async fn write_reply(socket: &mut TcpStream, reply: Reply) -> std::io::Result<()> {
+        match reply {
+            Reply::Frame(frame) => {
+                let mut out = vec![0u8; frame.encode_len(false)];
+                let _ = encode(&mut out, &frame, false);
                 let _ = socket.write_all(&out).await;
+                Ok(())
+            }
+            Reply::Bulk(mut stream) => {
+                socket
+                    .write_all(format!("${}\r\n", stream.len()).as_bytes())
+                    .await?;
+                while let Some(chunk) = stream.next().await {
+                    match chunk {
+                        Ok(chunk) => socket.write_all(&chunk).await?,
+                        Err(e) => {
+                            log::error!("aborting streamed reply: {}", e);
+                            return Err(std::io::Error::other(e));
+                        }
+                    }
+                }
+                socket.write_all(b"\r\n").await
             }
         }
     }
 
-    async fn process_command(frame: &Frame, handler: &CommandHandler) -> Frame {
+    async fn process_command(frame: &Frame, handler: &CommandHandler) -> Reply {
         match frame {
             Frame::Array(arr) if !arr.is_empty() => {
                 if let Frame::BulkString(cmd) = &arr[0] {
@@ -103,13 +133,13 @@ impl Server {
                         .collect();
 
                     handler
-                        .handle(std::str::from_utf8(cmd).unwrap_or(""), args)
+                        .dispatch(std::str::from_utf8(cmd).unwrap_or(""), args)
                         .await
                 } else {
-                    Frame::Error("ERR invalid command".into())
+                    Frame::Error("ERR invalid command".into()).into()
                 }
             }
-            _ => Frame::Error("ERR invalid command".into()),
+            _ => Frame::Error("ERR invalid command".into()).into(),
         }
     }
 }
@@ -0,0 +1,114 @@
+//! Gateway statistics kept in FoundationDB.
+//!
+//! Counters are shared by every gateway of a cluster and updated with atomic
+//! mutations, so concurrent updates never conflict and never read the counter.
+use crate::error::Result;
+use crate::string::datamodel::SimpleDataPrefix;
+use fdb::FoundationDB;
+use fdb::atomic::{self, Mutation};
+use fdb::scan::ScanOptions;
+use foundationdb_tuple::Subspace;
+use futures::TryStreamExt;
+use std::fmt;
+
+/// A statistic maintained by the gateway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stat {
+    /// Completed garbage collection runs.
+    GcRuns,
+    /// Orphaned chunks removed by garbage collection.
+    GcOrphanedChunks,
+    /// Stale locks removed by garbage collection.
+    GcStaleLocks,
+    /// Dangling TTL records removed by garbage collection.
+    GcDanglingTtls,
+    /// Time of the latest garbage collection run, in ms since the epoch.
+    GcLastRunMs,
+}
+
+impl Stat {
+    pub const ALL: [Stat; 5] = [
+        Stat::GcRuns,
+        Stat::GcOrphanedChunks,
+        Stat::GcStaleLocks,
+        Stat::GcDanglingTtls,
+        Stat::GcLastRunMs,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stat::GcRuns => "gc_runs",
+            Stat::GcOrphanedChunks => "gc_orphaned_chunks",
+            Stat::GcStaleLocks => "gc_stale_locks",
+            Stat::GcDanglingTtls => "gc_dangling_ttls",
+            Stat::GcLastRunMs => "gc_last_run_ms",
+        }
+    }
+
+    fn key(self) -> Vec<u8> {
+        subspace().pack(&(self.name(),))
+    }
+}
+
+/// A change to a statistic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Update {
+    /// Add to a counter.
+    Add(Stat, i64),
+    /// Raise a high-water mark, such as a timestamp.
+    Max(Stat, u64),
+}
+
+fn subspace() -> Subspace {
+    Subspace::all().subspace(&(SimpleDataPrefix::Meta, "stats"))
+}
+
+/// Apply `updates` in a single transaction.
+pub async fn record(fdb: &FoundationDB, updates: &[Update]) -> Result<()> {
+    let mutations = updates
+        .iter()
+        .map(|update| match *update {
+            Update::Add(stat, delta) => (stat.key(), Mutation::Add(delta)),
+            Update::Max(stat, value) => (stat.key(), Mutation::Max(value)),
+        })
+        .collect::<Vec<_>>();
+    Ok(fdb.mutate_many(&mutations).await?)
+}
+
+/// Current value of every statistic, in declaration order.
+pub async fn read(fdb: &FoundationDB) -> Result<Stats> {
+    let (begin, end) = subspace().range();
+    let records = fdb
+        .scan_stream(&begin, &end, ScanOptions::default())
+        .try_collect::<Vec<_>>()
+        .await?;
+    let value = |stat: Stat| {
+        records
+            .iter()
+            .find(|(key, _)| *key == stat.key())
+            .map_or(0, |(_, value)| atomic::decode_i64(value))
+    };
+    Ok(Stats(Stat::ALL.iter().map(|&stat| (stat, value(stat))).collect()))
+}
+
+/// Snapshot of the statistics.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats(pub Vec<(Stat, i64)>);
+
+impl Stats {
+    pub fn get(&self, stat: Stat) -> i64 {
+        self.0.iter().find(|(s, _)| *s == stat).map_or(0, |(_, v)| *v)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (stat, value) in &self.0 {
+            writeln!(f, "{}:{}", stat.name(), value)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub mod tests;
@@ -0,0 +1,32 @@
+use crate::stats::{self, Stat, Update};
+use fdb::FoundationDB;
+
+#[tokio::test]
+async fn test_record_and_read_stats() {
+    let db = FoundationDB::in_memory();
+    let before = stats::read(&db).await.expect("Unable to read stats");
+    assert_eq!(before.0.len(), Stat::ALL.len());
+
+    // concurrent updates of the same counters never conflict
+    let updates = (0..10).map(|i| {
+        let db = db.clone();
+        async move {
+            stats::record(
+                &db,
+                &[
+                    Update::Add(Stat::GcStaleLocks, 2),
+                    Update::Max(Stat::GcLastRunMs, i),
+                ],
+            )
+            .await
+        }
+    });
+    futures::future::try_join_all(updates)
+        .await
+        .expect("Unable to record stats");
+
+    let after = stats::read(&db).await.expect("Unable to read stats");
+    assert!(after.get(Stat::GcStaleLocks) >= before.get(Stat::GcStaleLocks) + 20);
+    assert!(after.get(Stat::GcLastRunMs) >= 9);
+    assert!(after.to_string().contains("gc_stale_locks:"));
+}
@@ -0,0 +1,104 @@
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+use crate::command::CmdMap;
+use crate::stream::operations::{
+    StreamOperations, parse_range_bound, parse_range_count, parse_stream_id, parse_xadd_args,
+    parse_xtrim_args,
+};
+
+crate::command_handler_static!(XADD, |gw, args| async move {
+    if args.len() < 4 {
+        return Frame::Error("ERR wrong number of arguments for 'XADD' command".into());
+    }
+    let key = args[0].as_slice();
+    let rest: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    match parse_xadd_args(&rest) {
+        Ok((xadd_args, fields)) => gw.xadd(key, &xadd_args, &fields).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(XRANGE, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'XRANGE' command".into());
+    }
+    let key = args[0].as_slice();
+    let rest: Vec<&[u8]> = args.iter().skip(3).map(|v| v.as_slice()).collect();
+    let parsed = parse_range_bound(&args[1], true).and_then(|start| {
+        let end = parse_range_bound(&args[2], false)?;
+        Ok((start, end, parse_range_count(&rest)?))
+    });
+    match parsed {
+        Ok((start, end, count)) => gw.xrange(key, start, end, count).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(XREVRANGE, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'XREVRANGE' command".into());
+    }
+    let key = args[0].as_slice();
+    let rest: Vec<&[u8]> = args.iter().skip(3).map(|v| v.as_slice()).collect();
+    let parsed = parse_range_bound(&args[1], false).and_then(|end| {
+        let start = parse_range_bound(&args[2], true)?;
+        Ok((end, start, parse_range_count(&rest)?))
+    });
+    match parsed {
+        Ok((end, start, count)) => gw.xrevrange(key, end, start, count).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(XLEN, |gw, args| async move {
+    if args.len() != 1 {
+        return Frame::Error("ERR wrong number of arguments for 'XLEN' command".into());
+    }
+    gw.xlen(&args[0]).await
+});
+
+crate::command_handler_static!(XDEL, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'XDEL' command".into());
+    }
+    let key = args[0].as_slice();
+    let ids = args
+        .iter()
+        .skip(1)
+        .map(|v| parse_stream_id(v))
+        .collect::<Result<Vec<_>, _>>();
+    match ids {
+        Ok(ids) => gw.xdel(key, &ids).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(XTRIM, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'XTRIM' command".into());
+    }
+    let key = args[0].as_slice();
+    let rest: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    match parse_xtrim_args(&rest) {
+        Ok(trim) => gw.xtrim(key, &trim).await,
+        Err(e) => e.into(),
+    }
+});
+
+/// Return handlers for stream commands (XADD, XRANGE, XREVRANGE, XLEN, XDEL, XTRIM)
+pub fn commands() -> CmdMap {
+    let mut m: CmdMap = CmdMap::new();
+
+    m.insert("XADD".to_string(), XADD.clone());
+
+    m.insert("XRANGE".to_string(), XRANGE.clone());
+
+    m.insert("XREVRANGE".to_string(), XREVRANGE.clone());
+
+    m.insert("XLEN".to_string(), XLEN.clone());
+
+    m.insert("XDEL".to_string(), XDEL.clone());
+
+    m.insert("XTRIM".to_string(), XTRIM.clone());
+
+    m
+}
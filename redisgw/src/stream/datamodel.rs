@@ -0,0 +1,607 @@
+//! Storage of streams.
+//!
+//! A stream is a marker record holding the last id it generated, with below
+//! it one record per entry, keyed by the entry id in big-endian so that
+//! ranges of ids are ranges of keys, and a counter of its entries.
+//!
+//! XADD takes the sequence number of the ids it generates from the commit
+//! versionstamp, writing the entry with FoundationDB's versionstamped key:
+//! adds to a stream within the same millisecond neither read nor write a
+//! common record, so they never conflict, and their ids follow the order
+//! they committed in. The add that moves a stream to a later millisecond
+//! writes the marker, which every add reads; the others record the id they
+//! generated in a tail record that only explicit ids are checked against.
+//!
+//! Sequence numbers are stored on the 10 bytes of a versionstamp, those of
+//! explicit ids on the last 8. A versionstamp only fits the 8 bytes of a
+//! Redis id while the commit version stays below 2^48; versions advance a
+//! million per second but jump on recoveries, so a cluster may get there.
+//! Past that, XADD generates the id following the last one instead, which
+//! makes adds to the same stream conflict again.
+use crate::error::{Error, Result};
+use crate::keys::StoredKey;
+use crate::stream::operations::{AddId, RangeBound, StreamEntry, StreamId, Trim, TrimStrategy, XAddArgs};
+use crate::string::datamodel::{SimpleDataPrefix, StringDataModel};
+use fdb::FoundationDB;
+use fdb::atomic::{self, Mutation, VERSIONSTAMP_LEN};
+use fdb::storage::Transaction;
+use foundationdb::{FdbBindingError, FdbError, RangeOption};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Tags following the marker in the keys of the other records of a stream.
+const ENTRY: u8 = 0x01;
+const LENGTH: u8 = 0x02;
+const TAIL: u8 = 0x03;
+
+/// Length of a stored id: its milliseconds on 8 bytes, then its sequence
+/// number on the 10 of a versionstamp.
+const ID_LEN: usize = 8 + VERSIONSTAMP_LEN;
+
+/// Largest entry once encoded, FoundationDB's limit on the size of a value.
+const MAX_ENTRY_LEN: usize = 100_000;
+
+/// Most entries trimmed at once with `~` when no LIMIT is given, as in Redis
+/// with its default node size.
+const APPROXIMATE_TRIM_LIMIT: u64 = 10_000;
+
+/// Most entries a trim reads and removes per transaction, which keeps it
+/// within FoundationDB's limits however many entries it removes in all.
+const TRIM_BATCH: u64 = 1_000;
+
+/// Read version from which a versionstamp may no longer fit 64 bits: the
+/// commit version is below 2^48 for the stamp to fit, and at most the five
+/// seconds of versions a transaction lives past its read version.
+const LAST_STAMPED_VERSION: i64 = (1 << 48) - 5_000_000;
+
+pub struct StreamDataModel {
+    pub fdb: FoundationDB,
+    /// Expires the values streams share their keys with, and resolves the
+    /// keys once per command for both.
+    strings: StringDataModel,
+}
+
+impl StreamDataModel {
+    pub fn new(fdb: FoundationDB) -> Self {
+        Self {
+            strings: StringDataModel::new(fdb.clone()),
+            fdb,
+        }
+    }
+
+    /// Storage key of `key`, once an expired value stored for it is gone;
+    /// `None` when nothing was ever stored for it.
+    async fn existing_key(&self, key: &[u8]) -> Result<Option<StoredKey>> {
+        self.strings.expire_if_due(key).await?;
+        self.strings.existing_key(key).await
+    }
+
+    /// Add an entry holding `fields` to the stream at `key`, creating the
+    /// stream unless `args.nomkstream`. Returns the id of the entry, or
+    /// `None` when the stream was missing and not created.
+    ///
+    /// Trimming, when asked for, runs in transactions of its own right
+    /// after the add, so that adds do not conflict over the length of the
+    /// stream: readers may briefly see it past its threshold.
+    pub async fn add(
+        &self,
+        key: &[u8],
+        args: &XAddArgs,
+        fields: &[(&[u8], &[u8])],
+    ) -> Result<Option<StreamId>> {
+        let stored = match self.existing_key(key).await? {
+            Some(stored) => stored,
+            None if args.nomkstream => return Ok(None),
+            None => self.strings.stored_key(key).await?,
+        };
+        let entry = encode_fields(fields);
+        if entry.len() > MAX_ENTRY_LEN {
+            return Err(Error::Other(format!(
+                "stream entry of {} bytes exceeds the maximum of {} bytes",
+                entry.len(),
+                MAX_ENTRY_LEN
+            )));
+        }
+        let stream = StreamKeys::new(&stored);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let (stored, stream, entry, id, nomkstream) = (&stored, &stream, &entry, args.id, args.nomkstream);
+
+        // Run at most once: a replay would add the entry twice
+        let (placed, stamp) = self
+            .fdb
+            .run_once_versionstamped(move |trx| async move {
+                if other_type_in_trx(&trx, stored).await? {
+                    return Ok(Err(Refused::WrongType));
+                }
+                let top = trx
+                    .get(&stream.marker, false)
+                    .await?
+                    .and_then(|t| StoredId::decode(&t));
+                if top.is_none() && nomkstream {
+                    return Ok(Ok(None));
+                }
+                let placement = match place_in_trx(&trx, stream, id, top, now).await? {
+                    Ok(placement) => placement,
+                    Err(refused) => return Ok(Err(refused)),
+                };
+                match placement {
+                    Placement::Stamped { ms, advance } => {
+                        let placeholder = StoredId { ms, seq: 0 };
+                        let entry_key = stream.entry(placeholder);
+                        let offset = entry_key.len() - VERSIONSTAMP_LEN;
+                        atomic::set_versionstamped_key(&trx, &entry_key, offset, entry).map_err(custom)?;
+                        let record = if advance {
+                            stream.marker.clone()
+                        } else {
+                            stream.tail()
+                        };
+                        atomic::set_versionstamped_value(&trx, &record, &placeholder.encode(), 8)
+                            .map_err(custom)?;
+                    }
+                    Placement::Fixed(id) => {
+                        trx.set(&stream.entry(id), entry);
+                        trx.set(&stream.marker, &id.encode());
+                    }
+                }
+                Mutation::Add(1).apply(&trx, &stream.length());
+                Ok(Ok(Some(placement)))
+            })
+            .await?;
+
+        let Some(placement) = placed.map_err(Error::from)? else {
+            return Ok(None);
+        };
+        let id = match placement {
+            Placement::Stamped { ms, .. } => StoredId {
+                ms,
+                seq: seq_of(&stamp),
+            },
+            Placement::Fixed(id) => id,
+        };
+        if let Some(trim) = &args.trim {
+            self.trim(key, trim).await?;
+        }
+        Ok(Some(id.id()))
+    }
+
+    /// Entries of the stream at `key` with ids from `start` to `end`, in the
+    /// order of their ids or in reverse, at most `count` of them.
+    pub async fn range(
+        &self,
+        key: &[u8],
+        start: RangeBound,
+        end: RangeBound,
+        count: Option<usize>,
+        reverse: bool,
+    ) -> Result<Vec<StreamEntry>> {
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(Vec::new());
+        };
+        let stream = StreamKeys::new(&stored);
+        let (begin, end) = (stream.bound(start, false), stream.bound(end, true));
+        let (stored, begin, end) = (&stored, &begin, &end);
+        let read = self
+            .fdb
+            .run(move |trx, _| async move {
+                if other_type_in_trx(&trx, stored).await? {
+                    return Ok(Err(Refused::WrongType));
+                }
+                // A limit of zero reads the whole range
+                if begin >= end || count == Some(0) {
+                    return Ok(Ok(Vec::new()));
+                }
+                let mut range = RangeOption::from((begin.clone(), end.clone()));
+                range.limit = count;
+                range.reverse = reverse;
+                Ok(Ok(trx.get_range(&range, false).await?))
+            })
+            .await
+            .map_err(fdb::Error::from)?
+            .map_err(Error::from)?;
+
+        let entries = read.into_iter().filter_map(|(key, value)| {
+            let id = StoredId::decode(key.get(stream.marker.len() + 1..)?)?;
+            Some(StreamEntry {
+                id: id.id(),
+                fields: decode_fields(&value)?,
+            })
+        });
+        Ok(entries.collect())
+    }
+
+    /// Number of entries of the stream at `key`, zero when it is missing.
+    pub async fn length(&self, key: &[u8]) -> Result<u64> {
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(0);
+        };
+        let length_key = StreamKeys::new(&stored).length();
+        let (stored, length_key) = (&stored, &length_key);
+        let read = self
+            .fdb
+            .run(move |trx, _| async move {
+                if other_type_in_trx(&trx, stored).await? {
+                    return Ok(Err(Refused::WrongType));
+                }
+                Ok(Ok(trx.get(length_key, false).await?))
+            })
+            .await
+            .map_err(fdb::Error::from)?
+            .map_err(Error::from)?;
+        Ok(read.map_or(0, |l| atomic::decode_i64(&l).max(0) as u64))
+    }
+
+    /// Remove the entries of the stream at `key` with ids in `ids`. Returns
+    /// how many there were. The last id of the stream is kept, so that ids
+    /// are never given twice.
+    pub async fn delete(&self, key: &[u8], ids: &[StreamId]) -> Result<u64> {
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(0);
+        };
+        let stream = StreamKeys::new(&stored);
+        let mut ids = ids.to_vec();
+        ids.sort();
+        ids.dedup();
+        let entry_keys = ids
+            .into_iter()
+            .map(|id| stream.entry(id.into()))
+            .collect::<Vec<_>>();
+        let (stored, stream, entry_keys) = (&stored, &stream, &entry_keys);
+
+        // Run at most once: a replay would find the entries gone and count
+        // them as missing
+        let res = self
+            .fdb
+            .run_once(move |trx| async move {
+                if other_type_in_trx(&trx, stored).await? {
+                    return Ok(Err(Refused::WrongType));
+                }
+                let reads = entry_keys.iter().map(|k| trx.get(k, false)).collect::<Vec<_>>();
+                let found = futures::future::try_join_all(reads).await?;
+                let mut removed = 0;
+                for (entry_key, _) in entry_keys.iter().zip(found).filter(|(_, f)| f.is_some()) {
+                    trx.clear(entry_key);
+                    removed += 1;
+                }
+                if removed > 0 {
+                    Mutation::Add(-removed).apply(&trx, &stream.length());
+                }
+                Ok(Ok(removed as u64))
+            })
+            .await;
+
+        res?.map_err(Error::from)
+    }
+
+    /// Remove the oldest entries of the stream at `key` as `trim` asks.
+    /// Returns how many were removed.
+    ///
+    /// With `~`, at most `APPROXIMATE_TRIM_LIMIT` entries or the LIMIT given
+    /// are removed at once; entries are otherwise trimmed exactly. Entries
+    /// go in batches of `TRIM_BATCH`, each in a transaction of its own, so
+    /// readers may see a long trim halfway.
+    pub async fn trim(&self, key: &[u8], trim: &Trim) -> Result<u64> {
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(0);
+        };
+        let stream = StreamKeys::new(&stored);
+        let limit = match (trim.approximate, trim.limit) {
+            (true, None) => Some(APPROXIMATE_TRIM_LIMIT),
+            (true, Some(0)) | (false, _) => None,
+            (true, Some(limit)) => Some(limit),
+        };
+        let mut removed = 0;
+        loop {
+            let batch = limit.map_or(TRIM_BATCH, |limit| (limit - removed).min(TRIM_BATCH));
+            if batch == 0 {
+                return Ok(removed);
+            }
+            match self.trim_batch(&stored, &stream, trim.strategy, batch).await? {
+                Ok(n) => {
+                    removed += n;
+                    if n < batch {
+                        return Ok(removed);
+                    }
+                }
+                Err(refused) if removed == 0 => return Err(refused.into()),
+                // Replaced by another type of value since the first batch
+                Err(_) => return Ok(removed),
+            }
+        }
+    }
+
+    /// Remove at most `batch` of the entries `strategy` trims, in a single
+    /// transaction. Returns how many were removed.
+    async fn trim_batch(
+        &self,
+        stored: &StoredKey,
+        stream: &StreamKeys,
+        strategy: TrimStrategy,
+        batch: u64,
+    ) -> Result<std::result::Result<u64, Refused>> {
+        // Run at most once: a replay would find the entries gone and report
+        // none removed
+        let res = self
+            .fdb
+            .run_once(move |trx| async move {
+                if other_type_in_trx(&trx, stored).await? {
+                    return Ok(Err(Refused::WrongType));
+                }
+                let (begin, entries_end) = stream.entries();
+                let (end, wanted) = match strategy {
+                    TrimStrategy::MaxLen(max) => {
+                        let length = trx.get(&stream.length(), false).await?;
+                        let length = length.map_or(0, |l| atomic::decode_i64(&l).max(0) as u64);
+                        (entries_end, length.saturating_sub(max).min(batch))
+                    }
+                    TrimStrategy::MinId(id) => (stream.entry(id.into()), batch),
+                };
+                if wanted == 0 {
+                    return Ok(Ok(0));
+                }
+                let mut range = RangeOption::from((begin.clone(), end));
+                range.limit = Some(wanted as usize);
+                let removed = trx.get_range(&range, false).await?;
+                let Some((last, _)) = removed.last() else {
+                    return Ok(Ok(0));
+                };
+                let mut past_last = last.clone();
+                past_last.push(0x00);
+                trx.clear_range(&begin, &past_last);
+                Mutation::Add(-(removed.len() as i64)).apply(&trx, &stream.length());
+                Ok(Ok(removed.len() as u64))
+            })
+            .await;
+
+        Ok(res?)
+    }
+}
+
+/// Why a stream command left the stream untouched.
+#[derive(Clone, Copy)]
+enum Refused {
+    WrongType,
+    IdZero,
+    IdNotGreater,
+}
+
+impl From<Refused> for Error {
+    fn from(refused: Refused) -> Self {
+        match refused {
+            Refused::WrongType => Error::WrongType,
+            Refused::IdZero => Error::Other("The ID specified in XADD must be greater than 0-0".to_string()),
+            Refused::IdNotGreater => Error::Other(
+                "The ID specified in XADD is equal or smaller than the target stream top item".to_string(),
+            ),
+        }
+    }
+}
+
+/// Where XADD puts an entry.
+#[derive(Clone, Copy)]
+enum Placement {
+    /// At millisecond `ms`, with the commit versionstamp as sequence number.
+    /// `advance` when `ms` is past the last id of the stream, which the
+    /// marker then records; otherwise the tail record does.
+    Stamped { ms: u64, advance: bool },
+    /// At the given id, which the marker records.
+    Fixed(StoredId),
+}
+
+/// Id as stored, with a sequence number as wide as a versionstamp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct StoredId {
+    ms: u64,
+    seq: u128,
+}
+
+impl StoredId {
+    fn encode(&self) -> [u8; ID_LEN] {
+        let mut out = [0; ID_LEN];
+        out[..8].copy_from_slice(&self.ms.to_be_bytes());
+        out[8..].copy_from_slice(&self.seq.to_be_bytes()[16 - VERSIONSTAMP_LEN..]);
+        out
+    }
+
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != ID_LEN {
+            return None;
+        }
+        let (ms, seq) = bytes.split_at(8);
+        Some(Self {
+            ms: u64::from_be_bytes(ms.try_into().ok()?),
+            seq: seq_of(seq),
+        })
+    }
+
+    /// The id as Redis knows it. Sequence numbers are only stamped while they
+    /// fit 64 bits, see [`stamps_fit`], so none saturates.
+    fn id(&self) -> StreamId {
+        StreamId {
+            ms: self.ms,
+            seq: u64::try_from(self.seq).unwrap_or(u64::MAX),
+        }
+    }
+}
+
+impl From<StreamId> for StoredId {
+    fn from(id: StreamId) -> Self {
+        Self {
+            ms: id.ms,
+            seq: id.seq as u128,
+        }
+    }
+}
+
+/// Sequence number stored on the 10 bytes of `bytes`, such as a versionstamp.
+fn seq_of(bytes: &[u8]) -> u128 {
+    let mut wide = [0; 16];
+    wide[16 - VERSIONSTAMP_LEN..].copy_from_slice(bytes);
+    u128::from_be_bytes(wide)
+}
+
+/// Whether the versionstamp of a transaction reading at `read_version` fits
+/// the 64 bits of a sequence number.
+pub(crate) fn stamps_fit(read_version: i64) -> bool {
+    read_version < LAST_STAMPED_VERSION
+}
+
+/// Keys of the records of a stream.
+struct StreamKeys {
+    /// The marker, holding the last id.
+    marker: Vec<u8>,
+}
+
+impl StreamKeys {
+    fn new(stored: &StoredKey) -> Self {
+        Self {
+            marker: stored.pack(SimpleDataPrefix::Stream),
+        }
+    }
+
+    fn tagged(&self, tag: u8) -> Vec<u8> {
+        [self.marker.as_slice(), &[tag]].concat()
+    }
+
+    fn entry(&self, id: StoredId) -> Vec<u8> {
+        [self.marker.as_slice(), &[ENTRY], &id.encode()].concat()
+    }
+
+    /// Range of the keys of the entries.
+    fn entries(&self) -> (Vec<u8>, Vec<u8>) {
+        (self.tagged(ENTRY), self.tagged(ENTRY + 1))
+    }
+
+    /// Counter of the entries.
+    fn length(&self) -> Vec<u8> {
+        self.tagged(LENGTH)
+    }
+
+    /// Last id generated without writing the marker.
+    fn tail(&self) -> Vec<u8> {
+        self.tagged(TAIL)
+    }
+
+    /// First key of a range starting at `bound`, or the key past the end of
+    /// one ending at it.
+    fn bound(&self, bound: RangeBound, end: bool) -> Vec<u8> {
+        match bound {
+            RangeBound::Min => self.entries().0,
+            RangeBound::Max => self.entries().1,
+            RangeBound::Id(id) if end => [self.entry(id.into()), vec![0x00]].concat(),
+            RangeBound::Id(id) => self.entry(id.into()),
+        }
+    }
+}
+
+/// Where XADD puts an entry with `id`, given the last id `top` recorded by
+/// the marker and the time `now`, read inside `trx`.
+async fn place_in_trx(
+    trx: &Transaction,
+    stream: &StreamKeys,
+    id: AddId,
+    top: Option<StoredId>,
+    now: u64,
+) -> std::result::Result<std::result::Result<Placement, Refused>, FdbError> {
+    let ms = match id {
+        AddId::Explicit(id) if id == StreamId::default() => return Ok(Err(Refused::IdZero)),
+        AddId::Explicit(id) => {
+            let last = last_id_in_trx(trx, stream, top).await?;
+            let id = StoredId::from(id);
+            if last.is_some_and(|last| id <= last) {
+                return Ok(Err(Refused::IdNotGreater));
+            }
+            return Ok(Ok(Placement::Fixed(id)));
+        }
+        AddId::Auto => top.map_or(now, |top| now.max(top.ms)),
+        AddId::AutoSeq(ms) => ms,
+    };
+    let read_version = trx.get_read_version().await?;
+    let stamped = stamps_fit(read_version);
+    let top = match top {
+        Some(top) if ms <= top.ms => top,
+        _ if stamped => return Ok(Ok(Placement::Stamped { ms, advance: true })),
+        // 0-0 is not a valid id
+        _ => return Ok(Ok(Placement::Fixed(StoredId { ms, seq: u128::from(ms == 0) }))),
+    };
+    if ms < top.ms {
+        return Ok(Err(Refused::IdNotGreater));
+    }
+    // The commit version is past the read version, and so the versionstamp
+    // past every id generated before, unless an explicit id was given a
+    // larger sequence number
+    if stamped && top.seq >> 16 <= read_version as u128 {
+        return Ok(Ok(Placement::Stamped { ms, advance: false }));
+    }
+    let last = last_id_in_trx(trx, stream, Some(top)).await?.unwrap_or(top);
+    let next = match (last.id().next(), id) {
+        (Some(next), AddId::AutoSeq(ms)) if next.ms != ms => None,
+        (next, _) => next,
+    };
+    Ok(next
+        .map(|next| Placement::Fixed(next.into()))
+        .ok_or(Refused::IdNotGreater))
+}
+
+/// Last id generated for a stream: the larger of `top`, recorded by the
+/// marker, and the id of the tail record, read inside `trx`.
+async fn last_id_in_trx(
+    trx: &Transaction,
+    stream: &StreamKeys,
+    top: Option<StoredId>,
+) -> std::result::Result<Option<StoredId>, FdbError> {
+    let tail = trx
+        .get(&stream.tail(), false)
+        .await?
+        .and_then(|t| StoredId::decode(&t));
+    Ok(top.max(tail))
+}
+
+/// Whether the key `stored` holds a value of another type than a stream,
+/// read inside `trx`.
+async fn other_type_in_trx(trx: &Transaction, stored: &StoredKey) -> std::result::Result<bool, FdbError> {
+    let (value, counter, hll) = futures::try_join!(
+        trx.get(&stored.pack(SimpleDataPrefix::Data), false),
+        trx.get(&stored.pack(SimpleDataPrefix::Counter), false),
+        trx.get(&stored.pack(SimpleDataPrefix::Hll), false),
+    )?;
+    Ok(value.is_some() || counter.is_some() || hll.is_some())
+}
+
+/// Clear the stream at `marker` along with its entries.
+pub(crate) fn clear_stream_in_trx(trx: &Transaction, marker: &[u8]) {
+    let mut end = marker.to_vec();
+    end.push(0xFF);
+    trx.clear_range(marker, &end);
+}
+
+fn custom(e: fdb::Error) -> FdbBindingError {
+    FdbBindingError::CustomError(Box::new(e))
+}
+
+/// Field/value pairs of an entry as a single record, each field and value
+/// preceded by its length as a big-endian `u32`.
+fn encode_fields(fields: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for part in fields.iter().flat_map(|&(field, value)| [field, value]) {
+        out.extend_from_slice(&(part.len() as u32).to_be_bytes());
+        out.extend_from_slice(part);
+    }
+    out
+}
+
+fn decode_fields(mut bytes: &[u8]) -> Option<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut next = || {
+        let (len, rest) = bytes.split_first_chunk::<4>()?;
+        let len = u32::from_be_bytes(*len) as usize;
+        let part = rest.get(..len)?.to_vec();
+        bytes = &rest[len..];
+        Some(part)
+    };
+    let mut fields = Vec::new();
+    while let Some(field) = next() {
+        fields.push((field, next()?));
+    }
+    Some(fields)
+}
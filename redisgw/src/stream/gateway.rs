@@ -0,0 +1,74 @@
+use crate::gateway::RedisGateway;
+use crate::stream::datamodel::StreamDataModel;
+use crate::stream::operations::{RangeBound, StreamEntry, StreamId, StreamOperations, Trim, XAddArgs};
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+// Entry ids generated by XADD come from the commit versionstamp, so that
+// adds to a stream never conflict with each other
+impl StreamOperations for RedisGateway {
+    async fn xadd(&self, key: &[u8], args: &XAddArgs, fields: &[(&[u8], &[u8])]) -> Frame {
+        let dm = StreamDataModel::new(self.fdb.clone());
+        match dm.add(key, args, fields).await {
+            Ok(Some(id)) => Frame::BulkString(id.to_string().into_bytes()),
+            Ok(None) => Frame::Null,
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn xrange(&self, key: &[u8], start: RangeBound, end: RangeBound, count: Option<usize>) -> Frame {
+        let dm = StreamDataModel::new(self.fdb.clone());
+        match dm.range(key, start, end, count, false).await {
+            Ok(entries) => entries_frame(entries),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn xrevrange(&self, key: &[u8], end: RangeBound, start: RangeBound, count: Option<usize>) -> Frame {
+        let dm = StreamDataModel::new(self.fdb.clone());
+        match dm.range(key, start, end, count, true).await {
+            Ok(entries) => entries_frame(entries),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn xlen(&self, key: &[u8]) -> Frame {
+        let dm = StreamDataModel::new(self.fdb.clone());
+        match dm.length(key).await {
+            Ok(length) => Frame::Integer(length as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn xdel(&self, key: &[u8], ids: &[StreamId]) -> Frame {
+        let dm = StreamDataModel::new(self.fdb.clone());
+        match dm.delete(key, ids).await {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn xtrim(&self, key: &[u8], trim: &Trim) -> Frame {
+        let dm = StreamDataModel::new(self.fdb.clone());
+        match dm.trim(key, trim).await {
+            Ok(removed) => Frame::Integer(removed as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+/// Entries as XRANGE replies them: each an array of its id and of its
+/// fields and values.
+fn entries_frame(entries: Vec<StreamEntry>) -> Frame {
+    let entries = entries.into_iter().map(|entry| {
+        let fields = entry
+            .fields
+            .into_iter()
+            .flat_map(|(field, value)| [Frame::BulkString(field), Frame::BulkString(value)])
+            .collect();
+        Frame::Array(vec![
+            Frame::BulkString(entry.id.to_string().into_bytes()),
+            Frame::Array(fields),
+        ])
+    });
+    Frame::Array(entries.collect())
+}
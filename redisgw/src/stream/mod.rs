@@ -0,0 +1,7 @@
+pub mod commands;
+pub mod gateway;
+pub mod operations;
+pub(crate) mod datamodel;
+
+#[cfg(test)]
+pub mod tests;
@@ -0,0 +1,7 @@
+pub mod types;
+pub mod parse;
+pub mod traits;
+
+pub use types::*;
+pub use traits::*;
+pub(crate) use parse::{parse_range_bound, parse_range_count, parse_stream_id, parse_xadd_args, parse_xtrim_args};
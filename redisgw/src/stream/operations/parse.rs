@@ -0,0 +1,179 @@
+use crate::error::{Error, Result};
+use crate::stream::operations::{AddId, RangeBound, StreamId, Trim, TrimStrategy, XAddArgs};
+
+fn parse_i64(value: &[u8]) -> Option<i64> {
+    std::str::from_utf8(value).ok()?.parse::<i64>().ok()
+}
+
+/// Parse an unsigned number written with digits only: Rust would also take
+/// a leading `+`.
+fn parse_u64(value: &[u8]) -> Option<u64> {
+    if value.is_empty() || !value.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    std::str::from_utf8(value).ok()?.parse::<u64>().ok()
+}
+
+fn invalid_id() -> Error {
+    Error::Other("Invalid stream ID specified as stream command argument".to_string())
+}
+
+/// Parse an id `ms-seq`, or `ms` alone, which then takes `seq` as its
+/// sequence number.
+fn parse_id(value: &[u8], seq: u64) -> Result<StreamId> {
+    let (ms, given) = match value.iter().position(|&b| b == b'-') {
+        Some(at) => (&value[..at], Some(&value[at + 1..])),
+        None => (value, None),
+    };
+    let ms = parse_u64(ms).ok_or_else(invalid_id)?;
+    let seq = match given {
+        Some(seq) => parse_u64(seq).ok_or_else(invalid_id)?,
+        None => seq,
+    };
+    Ok(StreamId { ms, seq })
+}
+
+/// Parse an id given to XDEL or MINID; `ms` alone stands for `ms-0`.
+pub(crate) fn parse_stream_id(value: &[u8]) -> Result<StreamId> {
+    parse_id(value, 0)
+}
+
+/// Parse the id given to XADD: `*`, `ms-*` or an id.
+fn parse_add_id(value: &[u8]) -> Result<AddId> {
+    if value == b"*" {
+        return Ok(AddId::Auto);
+    }
+    if let Some(ms) = value.strip_suffix(b"-*") {
+        return parse_u64(ms).map(AddId::AutoSeq).ok_or_else(invalid_id);
+    }
+    parse_stream_id(value).map(AddId::Explicit)
+}
+
+/// Parse a bound of XRANGE or XREVRANGE: `-`, `+` or an id, excluded when
+/// prefixed with `(`. An id without a sequence number covers the whole
+/// millisecond.
+pub(crate) fn parse_range_bound(value: &[u8], start: bool) -> Result<RangeBound> {
+    match value {
+        b"-" => return Ok(RangeBound::Min),
+        b"+" => return Ok(RangeBound::Max),
+        _ => {}
+    }
+    let (exclusive, value) = match value.strip_prefix(b"(") {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let id = parse_id(value, if start { 0 } else { u64::MAX })?;
+    if !exclusive {
+        return Ok(RangeBound::Id(id));
+    }
+    let moved = if start { id.next() } else { id.prev() };
+    moved.map(RangeBound::Id).ok_or_else(|| {
+        Error::Other(format!(
+            "invalid {} ID for the interval",
+            if start { "start" } else { "end" }
+        ))
+    })
+}
+
+/// Parse the arguments of XRANGE and XREVRANGE following the bounds:
+/// nothing, or COUNT and the most entries to return.
+pub(crate) fn parse_range_count(args: &[&[u8]]) -> Result<Option<usize>> {
+    match args {
+        [] => Ok(None),
+        [option, count] if option.eq_ignore_ascii_case(b"COUNT") => {
+            let count = parse_i64(count).ok_or(Error::NotAnInteger)?;
+            Ok(Some(count.max(0) as usize))
+        }
+        _ => Err(Error::Syntax),
+    }
+}
+
+/// Parse the options of XADD that come before its id, NOMKSTREAM and the
+/// trimming ones, or the options of XTRIM. Returns them with the number of
+/// arguments they took; XTRIM must have no other argument.
+fn parse_options(args: &[&[u8]], xadd: bool) -> Result<(bool, Option<Trim>, usize)> {
+    let mut nomkstream = false;
+    let mut strategy = None;
+    let mut approximate = false;
+    let mut limit = None;
+    let mut at = 0;
+    while at < args.len() {
+        let option = args[at].to_ascii_uppercase();
+        let operand = args.get(at + 1).copied();
+        match (option.as_slice(), operand) {
+            (b"NOMKSTREAM", _) if xadd => {
+                nomkstream = true;
+                at += 1;
+            }
+            (b"MAXLEN" | b"MINID", Some(mut threshold)) => {
+                at += 2;
+                if threshold == b"~" || threshold == b"=" {
+                    approximate = threshold == b"~";
+                    threshold = args.get(at).copied().ok_or(Error::Syntax)?;
+                    at += 1;
+                }
+                let parsed = if option == b"MAXLEN" {
+                    match parse_i64(threshold).ok_or(Error::NotAnInteger)? {
+                        n if n < 0 => {
+                            return Err(Error::Other("The MAXLEN argument must be >= 0.".to_string()));
+                        }
+                        n => TrimStrategy::MaxLen(n as u64),
+                    }
+                } else {
+                    TrimStrategy::MinId(parse_stream_id(threshold)?)
+                };
+                if strategy.is_some_and(|s| std::mem::discriminant(&s) != std::mem::discriminant(&parsed)) {
+                    return Err(Error::Other(
+                        "syntax error, MAXLEN and MINID options at the same time are not compatible"
+                            .to_string(),
+                    ));
+                }
+                strategy = Some(parsed);
+            }
+            (b"LIMIT", Some(count)) => {
+                match parse_i64(count).ok_or(Error::NotAnInteger)? {
+                    n if n < 0 => return Err(Error::Other("The LIMIT argument must be >= 0.".to_string())),
+                    n => limit = Some(n as u64),
+                }
+                at += 2;
+            }
+            // The id of XADD
+            _ if xadd => break,
+            _ => return Err(Error::Syntax),
+        }
+    }
+    if limit.is_some() && !approximate {
+        return Err(Error::Other(
+            "syntax error, LIMIT cannot be used without the special ~ option".to_string(),
+        ));
+    }
+    let trim = strategy.map(|strategy| Trim {
+        strategy,
+        approximate,
+        limit,
+    });
+    Ok((nomkstream, trim, at))
+}
+
+/// Parse the arguments of XADD following the key: its options, the id and
+/// the field/value pairs of the entry.
+pub(crate) fn parse_xadd_args<'a>(args: &[&'a [u8]]) -> Result<(XAddArgs, Vec<(&'a [u8], &'a [u8])>)> {
+    let (nomkstream, trim, taken) = parse_options(args, true)?;
+    let rest = &args[taken..];
+    if rest.len() < 3 || rest.len().is_multiple_of(2) {
+        return Err(Error::Other(
+            "wrong number of arguments for 'XADD' command".to_string(),
+        ));
+    }
+    let id = parse_add_id(rest[0])?;
+    let fields = rest[1..].chunks(2).map(|pair| (pair[0], pair[1])).collect();
+    Ok((XAddArgs { nomkstream, trim, id }, fields))
+}
+
+/// Parse the arguments of XTRIM following the key.
+pub(crate) fn parse_xtrim_args(args: &[&[u8]]) -> Result<Trim> {
+    let (_, trim, _) = parse_options(args, false)?;
+    trim.ok_or_else(|| {
+        Error::Other("syntax error, XTRIM must be called with a trimming strategy".to_string())
+    })
+}
@@ -0,0 +1,21 @@
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+pub trait StreamOperations {
+    /// Appends an entry to the stream stored at key, creating it when missing.
+    fn xadd(&self, key: &[u8], args: &super::types::XAddArgs, fields: &[(&[u8], &[u8])]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Returns the entries of the stream stored at key with ids from start to end.
+    fn xrange(&self, key: &[u8], start: super::types::RangeBound, end: super::types::RangeBound, count: Option<usize>) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Returns the entries of the stream stored at key with ids from end down to start.
+    fn xrevrange(&self, key: &[u8], end: super::types::RangeBound, start: super::types::RangeBound, count: Option<usize>) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Returns the number of entries of the stream stored at key.
+    fn xlen(&self, key: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Removes the entries with the given ids from the stream stored at key.
+    fn xdel(&self, key: &[u8], ids: &[super::types::StreamId]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Removes the oldest entries of the stream stored at key.
+    fn xtrim(&self, key: &[u8], trim: &super::types::Trim) -> impl std::future::Future<Output = Frame> + Send;
+}
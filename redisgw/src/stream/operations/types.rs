@@ -0,0 +1,96 @@
+use std::fmt;
+
+/// Id of a stream entry: a time in milliseconds and a sequence number
+/// telling apart the entries of that millisecond.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct StreamId {
+    pub ms: u64,
+    pub seq: u64,
+}
+
+impl StreamId {
+    /// The id right after this one, if any.
+    pub fn next(&self) -> Option<StreamId> {
+        match self.seq.checked_add(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_add(1)?,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// The id right before this one, if any.
+    pub fn prev(&self) -> Option<StreamId> {
+        match self.seq.checked_sub(1) {
+            Some(seq) => Some(StreamId { ms: self.ms, seq }),
+            None => Some(StreamId {
+                ms: self.ms.checked_sub(1)?,
+                seq: u64::MAX,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for StreamId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.ms, self.seq)
+    }
+}
+
+/// Id XADD gives the entry it adds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddId {
+    /// `*`: generated from the current time.
+    Auto,
+    /// `ms-*`: at the given millisecond, with a generated sequence number.
+    AutoSeq(u64),
+    /// The given id, which must be past the last one of the stream.
+    Explicit(StreamId),
+}
+
+/// Which entries XTRIM, or XADD after adding, removes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrimStrategy {
+    /// The oldest entries past the given number of entries.
+    MaxLen(u64),
+    /// The entries with ids below the given one.
+    MinId(StreamId),
+}
+
+/// Trimming asked for with MAXLEN or MINID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trim {
+    pub strategy: TrimStrategy,
+    /// `~`: trimming may stop short of the threshold.
+    pub approximate: bool,
+    /// Most entries removed at once, only given with `~`; zero for no limit.
+    pub limit: Option<u64>,
+}
+
+/// Options of XADD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XAddArgs {
+    /// Do not create the stream when it is missing.
+    pub nomkstream: bool,
+    pub trim: Option<Trim>,
+    pub id: AddId,
+}
+
+/// Bound of XRANGE and XREVRANGE, inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeBound {
+    /// `-`: the first entry.
+    Min,
+    /// `+`: the last entry.
+    Max,
+    Id(StreamId),
+}
+
+/// An entry of a stream, with its field/value pairs in the order they were
+/// given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StreamEntry {
+    pub id: StreamId,
+    pub fields: Vec<(Vec<u8>, Vec<u8>)>,
+}
@@ -0,0 +1,420 @@
+use crate::gateway::RedisGateway;
+use crate::hyperloglog::operations::HyperLogLogOperations;
+use crate::stream::datamodel::stamps_fit;
+use crate::stream::operations::{
+    RangeBound, StreamId, StreamOperations, parse_range_bound, parse_range_count, parse_stream_id,
+    parse_xadd_args, parse_xtrim_args,
+};
+use crate::string::operations::{SetFlags, StringOperations};
+use fdb::FoundationDB;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+fn wrong_type() -> Frame {
+    Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".into())
+}
+
+fn not_greater() -> Frame {
+    Frame::Error("ERR The ID specified in XADD is equal or smaller than the target stream top item".into())
+}
+
+/// XADD with its arguments following the key, as a client sends them.
+async fn xadd(gw: &RedisGateway, key: &[u8], args: &[&[u8]]) -> Frame {
+    let (args, fields) = parse_xadd_args(args).expect("XADD arguments");
+    gw.xadd(key, &args, &fields).await
+}
+
+/// XTRIM with its arguments following the key.
+async fn xtrim(gw: &RedisGateway, key: &[u8], args: &[&[u8]]) -> Frame {
+    gw.xtrim(key, &parse_xtrim_args(args).expect("XTRIM arguments"))
+        .await
+}
+
+fn id(frame: Frame) -> StreamId {
+    match frame {
+        Frame::BulkString(bytes) => parse_stream_id(&bytes).expect("stream id"),
+        other => panic!("expected an id, got {:?}", other),
+    }
+}
+
+fn bound(value: &[u8], start: bool) -> RangeBound {
+    parse_range_bound(value, start).expect("range bound")
+}
+
+/// Ids of the entries XRANGE or XREVRANGE replied.
+fn ids(frame: Frame) -> Vec<String> {
+    let Frame::Array(entries) = frame else {
+        panic!("expected an array, got {:?}", frame);
+    };
+    entries
+        .into_iter()
+        .map(|entry| match entry {
+            Frame::Array(mut parts) => match parts.remove(0) {
+                Frame::BulkString(id) => String::from_utf8(id).expect("utf-8 id"),
+                other => panic!("expected an id, got {:?}", other),
+            },
+            other => panic!("expected an entry, got {:?}", other),
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_xadd_ids() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    // Generated ids keep increasing
+    let first = id(xadd(&gw, b"s", &[b"*", b"f", b"v"]).await);
+    let second = id(xadd(&gw, b"s", &[b"*", b"f", b"v"]).await);
+    assert!(second > first, "{} then {}", first, second);
+
+    // Explicit ids must be past the last one
+    let past = format!("{}-0", second.ms + 1000);
+    assert_eq!(
+        xadd(&gw, b"s", &[past.as_bytes(), b"f", b"v"]).await,
+        Frame::BulkString(past.clone().into_bytes())
+    );
+    assert_eq!(
+        xadd(&gw, b"s", &[past.as_bytes(), b"f", b"v"]).await,
+        not_greater()
+    );
+    assert_eq!(xadd(&gw, b"s", &[b"1-1", b"f", b"v"]).await, not_greater());
+    let third = id(xadd(&gw, b"s", &[b"*", b"f", b"v"]).await);
+    assert!(third > parse_stream_id(past.as_bytes()).unwrap());
+    assert_eq!(gw.xlen(b"s").await, Frame::Integer(4));
+
+    // `ms-*` generates a sequence number at that millisecond only
+    assert_eq!(
+        xadd(&gw, b"t", &[b"5-3", b"f", b"v"]).await,
+        Frame::BulkString(b"5-3".to_vec())
+    );
+    let seq = id(xadd(&gw, b"t", &[b"5-*", b"f", b"v"]).await);
+    assert!(seq.ms == 5 && seq.seq > 3, "{}", seq);
+    assert_eq!(xadd(&gw, b"t", &[b"4-*", b"f", b"v"]).await, not_greater());
+    assert_eq!(id(xadd(&gw, b"t", &[b"6-*", b"f", b"v"]).await).ms, 6);
+
+    assert_eq!(
+        xadd(&gw, b"u", &[b"0-0", b"f", b"v"]).await,
+        Frame::Error("ERR The ID specified in XADD must be greater than 0-0".into())
+    );
+    assert_eq!(id(xadd(&gw, b"u", &[b"0-*", b"f", b"v"]).await).ms, 0);
+
+    // NOMKSTREAM adds nothing to a missing stream
+    assert_eq!(
+        xadd(&gw, b"missing", &[b"NOMKSTREAM", b"*", b"f", b"v"]).await,
+        Frame::Null
+    );
+    assert_eq!(gw.exists(&[b"missing"]).await, Frame::Integer(0));
+    assert!(matches!(
+        xadd(&gw, b"s", &[b"NOMKSTREAM", b"*", b"f", b"v"]).await,
+        Frame::BulkString(_)
+    ));
+}
+
+#[tokio::test]
+async fn test_xrange_xrevrange() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    for entry in [b"1-1", b"1-2", b"2-1", b"3-0"] {
+        let _ = xadd(&gw, b"s", &[entry, b"name", entry, b"kind", b"test"]).await;
+    }
+
+    assert_eq!(
+        gw.xrange(b"s", bound(b"1-2", true), bound(b"1-2", false), None)
+            .await,
+        Frame::Array(vec![Frame::Array(vec![
+            Frame::BulkString(b"1-2".to_vec()),
+            Frame::Array(vec![
+                Frame::BulkString(b"name".to_vec()),
+                Frame::BulkString(b"1-2".to_vec()),
+                Frame::BulkString(b"kind".to_vec()),
+                Frame::BulkString(b"test".to_vec()),
+            ]),
+        ])])
+    );
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, None).await),
+        ["1-1", "1-2", "2-1", "3-0"]
+    );
+    // An id without a sequence number covers its whole millisecond
+    assert_eq!(
+        ids(gw.xrange(b"s", bound(b"1", true), bound(b"2", false), None).await),
+        ["1-1", "1-2", "2-1"]
+    );
+    assert_eq!(
+        ids(gw
+            .xrange(b"s", bound(b"(1-1", true), bound(b"(3-0", false), None)
+            .await),
+        ["1-2", "2-1"]
+    );
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, Some(2)).await),
+        ["1-1", "1-2"]
+    );
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, Some(0)).await),
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        ids(gw.xrange(b"s", bound(b"3", true), bound(b"1", false), None).await),
+        Vec::<String>::new()
+    );
+
+    assert_eq!(
+        ids(gw.xrevrange(b"s", RangeBound::Max, RangeBound::Min, None).await),
+        ["3-0", "2-1", "1-2", "1-1"]
+    );
+    assert_eq!(
+        ids(gw
+            .xrevrange(b"s", bound(b"2", false), bound(b"1-2", true), Some(1))
+            .await),
+        ["2-1"]
+    );
+
+    assert_eq!(
+        gw.xrange(b"missing", RangeBound::Min, RangeBound::Max, None)
+            .await,
+        Frame::Array(vec![])
+    );
+}
+
+#[tokio::test]
+async fn test_xlen_xdel() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    assert_eq!(gw.xlen(b"s").await, Frame::Integer(0));
+    for entry in [b"1-1", b"1-2", b"2-1"] {
+        let _ = xadd(&gw, b"s", &[entry, b"f", b"v"]).await;
+    }
+    assert_eq!(gw.xlen(b"s").await, Frame::Integer(3));
+
+    let deleted = [
+        StreamId { ms: 1, seq: 2 },
+        StreamId { ms: 1, seq: 2 },
+        StreamId { ms: 9, seq: 0 },
+    ];
+    assert_eq!(gw.xdel(b"s", &deleted).await, Frame::Integer(1));
+    assert_eq!(gw.xlen(b"s").await, Frame::Integer(2));
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, None).await),
+        ["1-1", "2-1"]
+    );
+
+    // The last id outlives its entry
+    assert_eq!(
+        gw.xdel(b"s", &[StreamId { ms: 2, seq: 1 }]).await,
+        Frame::Integer(1)
+    );
+    assert_eq!(xadd(&gw, b"s", &[b"2-1", b"f", b"v"]).await, not_greater());
+    assert_eq!(
+        gw.xdel(b"missing", &[StreamId { ms: 1, seq: 1 }]).await,
+        Frame::Integer(0)
+    );
+}
+
+#[tokio::test]
+async fn test_xtrim() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    for ms in 1..=10 {
+        let _ = xadd(&gw, b"s", &[format!("{}-0", ms).as_bytes(), b"f", b"v"]).await;
+    }
+    assert_eq!(xtrim(&gw, b"s", &[b"MAXLEN", b"8"]).await, Frame::Integer(2));
+    assert_eq!(
+        xtrim(&gw, b"s", &[b"MAXLEN", b"=", b"8"]).await,
+        Frame::Integer(0)
+    );
+    assert_eq!(xtrim(&gw, b"s", &[b"MINID", b"5"]).await, Frame::Integer(2));
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, bound(b"5", false), None).await),
+        ["5-0"]
+    );
+    // `~` stops at its LIMIT
+    assert_eq!(
+        xtrim(&gw, b"s", &[b"MAXLEN", b"~", b"0", b"LIMIT", b"2"]).await,
+        Frame::Integer(2)
+    );
+    assert_eq!(gw.xlen(b"s").await, Frame::Integer(4));
+    assert_eq!(
+        xtrim(&gw, b"s", &[b"MINID", b"~", b"9", b"LIMIT", b"0"]).await,
+        Frame::Integer(2)
+    );
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, None).await),
+        ["9-0", "10-0"]
+    );
+
+    // XADD trims once the entry is added
+    assert_eq!(
+        xadd(&gw, b"s", &[b"MAXLEN", b"1", b"11-0", b"f", b"v"]).await,
+        Frame::BulkString(b"11-0".to_vec())
+    );
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, None).await),
+        ["11-0"]
+    );
+    assert_eq!(
+        xtrim(&gw, b"missing", &[b"MAXLEN", b"0"]).await,
+        Frame::Integer(0)
+    );
+}
+
+#[tokio::test]
+async fn test_xtrim_in_batches() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    // More entries than a trim removes per transaction
+    for ms in 1..=2_500 {
+        let _ = xadd(&gw, b"s", &[format!("{}-0", ms).as_bytes(), b"f", b"v"]).await;
+    }
+    assert_eq!(xtrim(&gw, b"s", &[b"MINID", b"1201"]).await, Frame::Integer(1_200));
+    assert_eq!(xtrim(&gw, b"s", &[b"MAXLEN", b"100"]).await, Frame::Integer(1_200));
+    assert_eq!(gw.xlen(b"s").await, Frame::Integer(100));
+    assert_eq!(
+        ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, Some(1)).await),
+        ["2401-0"]
+    );
+}
+
+#[tokio::test]
+async fn test_stream_wrong_type() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"str", b"plain", SetFlags::default()).await;
+    let _ = gw.incr(b"counter").await;
+    assert_eq!(xadd(&gw, b"str", &[b"*", b"f", b"v"]).await, wrong_type());
+    assert_eq!(xadd(&gw, b"counter", &[b"*", b"f", b"v"]).await, wrong_type());
+    assert_eq!(gw.xlen(b"str").await, wrong_type());
+    assert_eq!(
+        gw.xrange(b"str", RangeBound::Min, RangeBound::Max, None).await,
+        wrong_type()
+    );
+
+    let _ = xadd(&gw, b"s", &[b"1-1", b"f", b"v"]).await;
+    assert_eq!(gw.get(b"s").await, wrong_type());
+    assert_eq!(gw.incr(b"s").await, wrong_type());
+    assert_eq!(gw.pfadd(b"s", &[b"a"]).await, wrong_type());
+    assert_eq!(gw.exists(&[b"s"]).await, Frame::Integer(1));
+
+    // SET replaces the stream, DEL drops it with its entries
+    let _ = gw.set(b"s", b"plain", SetFlags::default()).await;
+    assert_eq!(gw.get(b"s").await, Frame::BulkString(b"plain".to_vec()));
+    assert_eq!(gw.xlen(b"s").await, wrong_type());
+    let _ = gw.del(&[b"s"]).await;
+    let _ = xadd(&gw, b"s2", &[b"1-1", b"f", b"v"]).await;
+    assert_eq!(gw.del(&[b"s2"]).await, Frame::Integer(1));
+    assert_eq!(gw.exists(&[b"s2"]).await, Frame::Integer(0));
+    assert_eq!(gw.xlen(b"s2").await, Frame::Integer(0));
+    assert_eq!(
+        xadd(&gw, b"s2", &[b"1-1", b"f", b"v"]).await,
+        Frame::BulkString(b"1-1".to_vec())
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_xadd() {
+    use futures::future::join_all;
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    let tasks: Vec<_> = (0..20)
+        .map(|_| {
+            let gw = RedisGateway::new(db.clone());
+            tokio::spawn(async move {
+                let mut added = Vec::new();
+                for _ in 0..5 {
+                    added.push(id(xadd(&gw, b"s", &[b"*", b"f", b"v"]).await));
+                }
+                added
+            })
+        })
+        .collect();
+    let mut added: Vec<_> = join_all(tasks)
+        .await
+        .into_iter()
+        .flat_map(|t| t.expect("task"))
+        .collect();
+    added.sort();
+    added.dedup();
+
+    // Every add got an id of its own, in the order the stream holds them
+    assert_eq!(added.len(), 100);
+    assert_eq!(gw.xlen(b"s").await, Frame::Integer(100));
+    let stored = ids(gw.xrange(b"s", RangeBound::Min, RangeBound::Max, None).await);
+    assert_eq!(stored, added.iter().map(|id| id.to_string()).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_stamps_fit_boundary() {
+    // A transaction commits at most five seconds of versions past its read
+    // version, and the batch order takes the last two bytes of the stamp
+    let last = (1_i64 << 48) - 5_000_001;
+    assert!(stamps_fit(last));
+    assert!(!stamps_fit(last + 1));
+    let stamp = (((last + 5_000_000) as u128) << 16) | 0xFFFF;
+    assert_eq!(u64::try_from(stamp), Ok(u64::MAX));
+    assert!(u64::try_from(stamp + 1).is_err());
+}
+
+#[test]
+fn test_stream_parse_errors() {
+    let invalid_id = Frame::Error("ERR Invalid stream ID specified as stream command argument".into());
+    let syntax = Frame::Error("ERR syntax error".into());
+
+    assert_eq!(
+        parse_stream_id(b"1-x").map_err(Frame::from),
+        Err(invalid_id.clone())
+    );
+    assert_eq!(
+        parse_stream_id(b"+1").map_err(Frame::from),
+        Err(invalid_id.clone())
+    );
+    assert_eq!(
+        parse_xadd_args(&[b"1-*-1", b"f", b"v"])
+            .map(|_| ())
+            .map_err(Frame::from),
+        Err(invalid_id)
+    );
+    assert_eq!(
+        parse_xadd_args(&[b"*", b"f"]).map(|_| ()).map_err(Frame::from),
+        Err(Frame::Error(
+            "ERR wrong number of arguments for 'XADD' command".into()
+        ))
+    );
+    assert_eq!(
+        parse_xadd_args(&[b"MAXLEN", b"-1", b"*", b"f", b"v"])
+            .map(|_| ())
+            .map_err(Frame::from),
+        Err(Frame::Error("ERR The MAXLEN argument must be >= 0.".into()))
+    );
+    assert_eq!(
+        parse_xtrim_args(&[b"MAXLEN", b"1", b"LIMIT", b"1"]).map_err(Frame::from),
+        Err(Frame::Error(
+            "ERR syntax error, LIMIT cannot be used without the special ~ option".into()
+        ))
+    );
+    assert_eq!(
+        parse_xtrim_args(&[b"MAXLEN", b"1", b"MINID", b"1"]).map_err(Frame::from),
+        Err(Frame::Error(
+            "ERR syntax error, MAXLEN and MINID options at the same time are not compatible".into()
+        ))
+    );
+    assert_eq!(
+        parse_xtrim_args(&[b"MAXLEN", b"1", b"EXTRA"]).map_err(Frame::from),
+        Err(syntax.clone())
+    );
+    assert!(parse_range_bound(b"(0-0", true).is_ok());
+    assert_eq!(
+        parse_range_bound(b"(0-0", false).map_err(Frame::from),
+        Err(Frame::Error("ERR invalid end ID for the interval".into()))
+    );
+    assert_eq!(
+        parse_range_count(&[b"COUNT", b"2"]).map_err(Frame::from),
+        Ok(Some(2))
+    );
+    assert_eq!(parse_range_count(&[b"COUNT"]).map_err(Frame::from), Err(syntax));
+}
@@ -1,6 +1,6 @@
 use redis_protocol::resp2::types::OwnedFrame as Frame;
 use crate::command::CmdMap;
-use crate::string::operations::{StringOperations, parse_set_extra_args};
+use crate::string::operations::{StringOperations, parse_getex_args, parse_lcs_args, parse_set_extra_args};
 
 crate::command_handler_static!(SET, |gw, args| async move {
     // expecting at least key and value
@@ -11,8 +11,10 @@ crate::command_handler_static!(SET, |gw, args| async move {
     let val = args[1].as_slice();
 
     let extra_refs: Vec<&[u8]> = args.iter().skip(2).map(|v| v.as_slice()).collect();
-    let flags = parse_set_extra_args(&extra_refs);
-    gw.set(key, val, flags).await
+    match parse_set_extra_args(&extra_refs) {
+        Ok(flags) => gw.set(key, val, flags).await,
+        Err(e) => e.into(),
+    }
 });
 
 crate::command_handler_static!(GET, |gw, args| async move {
@@ -27,8 +29,32 @@ crate::command_handler_static!(DEL, |gw, args| async move {
     if args.is_empty() {
         return Frame::Error("ERR wrong number of arguments for 'DEL' command".into());
     }
-    let key = args[0].as_slice();
-    gw.del(key).await
+    let keys: Vec<&[u8]> = args.iter().map(|v| v.as_slice()).collect();
+    gw.del(&keys).await
+});
+
+crate::command_handler_static!(EXISTS, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'EXISTS' command".into());
+    }
+    let keys: Vec<&[u8]> = args.iter().map(|v| v.as_slice()).collect();
+    gw.exists(&keys).await
+});
+
+crate::command_handler_static!(TOUCH, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'TOUCH' command".into());
+    }
+    let keys: Vec<&[u8]> = args.iter().map(|v| v.as_slice()).collect();
+    gw.touch(&keys).await
+});
+
+crate::command_handler_static!(UNLINK, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'UNLINK' command".into());
+    }
+    let keys: Vec<&[u8]> = args.iter().map(|v| v.as_slice()).collect();
+    gw.unlink(&keys).await
 });
 
 crate::command_handler_static!(GETDEL, |gw, args| async move {
@@ -39,6 +65,53 @@ crate::command_handler_static!(GETDEL, |gw, args| async move {
     gw.getdel(key).await
 });
 
+crate::command_handler_static!(GETEX, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'GETEX' command".into());
+    }
+    let key = args[0].as_slice();
+
+    let extra_refs: Vec<&[u8]> = args.iter().skip(1).map(|v| v.as_slice()).collect();
+    match parse_getex_args(&extra_refs) {
+        Ok(ttl) => gw.getex(key, ttl).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(GETSET, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'GETSET' command".into());
+    }
+    let key = args[0].as_slice();
+    let val = args[1].as_slice();
+    gw.getset(key, val).await
+});
+
+crate::command_handler_static!(SETNX, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'SETNX' command".into());
+    }
+    let key = args[0].as_slice();
+    let val = args[1].as_slice();
+    gw.setnx(key, val).await
+});
+
+crate::command_handler_static!(SETEX, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'SETEX' command".into());
+    }
+    let key = args[0].as_slice();
+    gw.setex(key, args[1].as_slice(), args[2].as_slice()).await
+});
+
+crate::command_handler_static!(PSETEX, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'PSETEX' command".into());
+    }
+    let key = args[0].as_slice();
+    gw.psetex(key, args[1].as_slice(), args[2].as_slice()).await
+});
+
 crate::command_handler_static!(INCR, |gw, args| async move {
     if args.is_empty() {
         return Frame::Error("ERR wrong number of arguments for 'INCR' command".into());
@@ -73,6 +146,97 @@ crate::command_handler_static!(DECRBY, |gw, args| async move {
     gw.decr_by(key, dec).await
 });
 
+crate::command_handler_static!(INCRBYFLOAT, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'INCRBYFLOAT' command".into());
+    }
+    let key = args[0].as_slice();
+    let inc = args[1].as_slice();
+    gw.incr_by_float(key, inc).await
+});
+
+crate::command_handler_static!(APPEND, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'APPEND' command".into());
+    }
+    let key = args[0].as_slice();
+    let val = args[1].as_slice();
+    gw.append(key, val).await
+});
+
+crate::command_handler_static!(STRLEN, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'STRLEN' command".into());
+    }
+    let key = args[0].as_slice();
+    gw.strlen(key).await
+});
+
+crate::command_handler_static!(GETRANGE, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'GETRANGE' command".into());
+    }
+    let key = args[0].as_slice();
+    gw.getrange(key, args[1].as_slice(), args[2].as_slice()).await
+});
+
+crate::command_handler_static!(SUBSTR, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'SUBSTR' command".into());
+    }
+    let key = args[0].as_slice();
+    gw.getrange(key, args[1].as_slice(), args[2].as_slice()).await
+});
+
+crate::command_handler_static!(SETRANGE, |gw, args| async move {
+    if args.len() < 3 {
+        return Frame::Error("ERR wrong number of arguments for 'SETRANGE' command".into());
+    }
+    let key = args[0].as_slice();
+    gw.setrange(key, args[1].as_slice(), args[2].as_slice()).await
+});
+
+crate::command_handler_static!(LCS, |gw, args| async move {
+    if args.len() < 2 {
+        return Frame::Error("ERR wrong number of arguments for 'LCS' command".into());
+    }
+    let extra_refs: Vec<&[u8]> = args.iter().skip(2).map(|v| v.as_slice()).collect();
+    match parse_lcs_args(&extra_refs) {
+        Ok(flags) => gw.lcs(args[0].as_slice(), args[1].as_slice(), flags).await,
+        Err(e) => e.into(),
+    }
+});
+
+crate::command_handler_static!(MGET, |gw, args| async move {
+    if args.is_empty() {
+        return Frame::Error("ERR wrong number of arguments for 'MGET' command".into());
+    }
+    let keys: Vec<&[u8]> = args.iter().map(|v| v.as_slice()).collect();
+    gw.mget(&keys).await
+});
+
+crate::command_handler_static!(MSET, |gw, args| async move {
+    if args.is_empty() || args.len() % 2 == 1 {
+        return Frame::Error("ERR wrong number of arguments for 'MSET' command".into());
+    }
+    let pairs: Vec<(&[u8], &[u8])> = args
+        .chunks(2)
+        .map(|p| (p[0].as_slice(), p[1].as_slice()))
+        .collect();
+    gw.mset(&pairs).await
+});
+
+crate::command_handler_static!(MSETNX, |gw, args| async move {
+    if args.is_empty() || args.len() % 2 == 1 {
+        return Frame::Error("ERR wrong number of arguments for 'MSETNX' command".into());
+    }
+    let pairs: Vec<(&[u8], &[u8])> = args
+        .chunks(2)
+        .map(|p| (p[0].as_slice(), p[1].as_slice()))
+        .collect();
+    gw.msetnx(&pairs).await
+});
+
 /// Return handlers for string-related commands (SET, GET)
 pub fn commands() -> CmdMap {
     let mut m: CmdMap = CmdMap::new();
@@ -83,8 +247,24 @@ pub fn commands() -> CmdMap {
 
     m.insert("DEL".to_string(), DEL.clone());
 
+    m.insert("EXISTS".to_string(), EXISTS.clone());
+
+    m.insert("TOUCH".to_string(), TOUCH.clone());
+
+    m.insert("UNLINK".to_string(), UNLINK.clone());
+
     m.insert("GETDEL".to_string(), GETDEL.clone());
 
+    m.insert("GETEX".to_string(), GETEX.clone());
+
+    m.insert("GETSET".to_string(), GETSET.clone());
+
+    m.insert("SETNX".to_string(), SETNX.clone());
+
+    m.insert("SETEX".to_string(), SETEX.clone());
+
+    m.insert("PSETEX".to_string(), PSETEX.clone());
+
     m.insert("INCR".to_string(), INCR.clone());
 
     m.insert("DECR".to_string(), DECR.clone());
@@ -93,5 +273,25 @@ pub fn commands() -> CmdMap {
 
     m.insert("DECRBY".to_string(), DECRBY.clone());
 
+    m.insert("INCRBYFLOAT".to_string(), INCRBYFLOAT.clone());
+
+    m.insert("APPEND".to_string(), APPEND.clone());
+
+    m.insert("STRLEN".to_string(), STRLEN.clone());
+
+    m.insert("GETRANGE".to_string(), GETRANGE.clone());
+
+    m.insert("SUBSTR".to_string(), SUBSTR.clone());
+
+    m.insert("SETRANGE".to_string(), SETRANGE.clone());
+
+    m.insert("LCS".to_string(), LCS.clone());
+
+    m.insert("MGET".to_string(), MGET.clone());
+
+    m.insert("MSET".to_string(), MSET.clone());
+
+    m.insert("MSETNX".to_string(), MSETNX.clone());
+
     m
 }
@@ -1,18 +1,40 @@
-use crate::string::operations::{SetFlags, SetMethod, SetTTL};
+use crate::error::{Error, Result};
+use crate::hyperloglog::operations::hll::{self, Registers};
+use crate::keys::{self, StoredKey};
+use crate::stream::datamodel::clear_stream_in_trx;
+use crate::string::operations::{GetExTTL, SetFlags, SetMethod, SetTTL};
 use fdb::FoundationDB;
-use foundationdb_tuple::{TupleDepth, TuplePack, VersionstampOffset, pack};
-use foundationdb::RangeOption;
+use fdb::manifest::Manifest;
+use fdb::storage::Transaction;
+use fdb::stream::ValueStream;
+use foundationdb::{FdbBindingError, FdbError, RangeOption};
+use foundationdb::options::MutationType;
+use foundationdb_tuple::{TupleDepth, TuplePack, VersionstampOffset};
 use foundationdb_tuple::Subspace;
-use futures_util::stream::StreamExt;
-use futures_util::TryStreamExt;
+use std::collections::HashSet;
 use std::io::Write;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum SimpleDataPrefix {
+    Meta = 10,
     Data = 11,
     Ttl = 12,
     Lock = 13,
+    /// Integers written by the INCR family, as a little-endian `i128` that
+    /// FoundationDB's atomic add can update without reading it.
+    Counter = 14,
+    /// Marks a HyperLogLog, whose registers are stored below this key one
+    /// byte each, so that FoundationDB's byte max can raise them without
+    /// reading them. Only registers above zero have a record.
+    Hll = 15,
+    /// Marks a stream and holds the last id it generated; its entries and
+    /// the records kept with them are stored below this key.
+    Stream = 16,
 }
 
 impl TuplePack for SimpleDataPrefix {
@@ -25,13 +47,58 @@ impl TuplePack for SimpleDataPrefix {
     }
 }
 
+/// Chunks a value may have for UNLINK to clear it inline; larger values
+/// are cleared in the background.
+const UNLINK_INLINE_CHUNKS: u64 = 100;
+
+/// Longest wait on a held lock before trying to take it again.
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How often a held lock gets a fresh token, well within the age at which
+/// garbage collection takes a lock for abandoned.
+const LOCK_RENEW_INTERVAL: Duration = Duration::from_secs(10);
+
+/// A per-key lock taken with `StringDataModel::acquire_lock`. Its token is
+/// renewed until it is released, or until it is dropped.
+pub struct HeldLock {
+    lock_key: Vec<u8>,
+    stop: oneshot::Sender<()>,
+    /// Renews the token, and returns the last one once stopped.
+    renewal: JoinHandle<Vec<u8>>,
+}
+
+/// What `StringDataModel::set` did.
+pub struct SetOutcome {
+    /// Whether the value was written; NX and XX may have refused it.
+    pub written: bool,
+    /// Value held before the write, when `SetFlags::get` asked for it.
+    pub previous: Option<Vec<u8>>,
+}
+
+/// Storage of string values, and of the counters, HyperLogLogs and streams
+/// sharing their keys. Gateways build one per command: the long keys it
+/// resolves are kept for the rest of the command.
 pub struct StringDataModel {
     pub fdb: FoundationDB,
+    resolved: keys::Resolved,
 }
 
 impl StringDataModel {
     pub fn new(fdb: FoundationDB) -> Self {
-        Self { fdb }
+        Self {
+            fdb,
+            resolved: keys::Resolved::default(),
+        }
+    }
+
+    /// Storage key of `key`, assigning one to a long key written for the first time.
+    pub(crate) async fn stored_key(&self, key: &[u8]) -> Result<StoredKey> {
+        self.resolved.resolve(&self.fdb, key).await
+    }
+
+    /// Storage key of `key`, or `None` when nothing was ever stored for it.
+    pub(crate) async fn existing_key(&self, key: &[u8]) -> Result<Option<StoredKey>> {
+        self.resolved.lookup(&self.fdb, key).await
     }
 
     pub async fn set(
@@ -39,14 +106,12 @@ impl StringDataModel {
         key: &[u8],
         value: &[u8],
         flags: SetFlags,
-    ) -> Result<Option<Vec<u8>>, String> {
-        let packed_key = pack(&(SimpleDataPrefix::Data, key));
+    ) -> Result<SetOutcome> {
+        let packed_key = self.stored_key(key).await?.pack(SimpleDataPrefix::Data);
         let mut old_val = None;
 
         let existing = if flags.get || flags.method.is_some() {
-            self.get(key)
-                .await
-                .map_err(|e| format!("FoundationDB set error: {:?}", e))?
+            self.get(key).await?
         } else {
             None
         };
@@ -55,270 +120,1205 @@ impl StringDataModel {
             old_val = existing.clone();
         }
 
-        if let Some(method) = &flags.method {
-            match method {
-                SetMethod::NX => {
-                    if existing.is_some() {
-                        return Ok(None);
-                    }
-                }
-                SetMethod::XX => {
-                    if existing.is_none() {
-                        return Ok(None);
-                    }
-                }
-            }
+        let refused = match &flags.method {
+            Some(SetMethod::NX) => existing.is_some(),
+            Some(SetMethod::XX) => existing.is_none(),
+            None => false,
+        };
+        if refused {
+            return Ok(SetOutcome {
+                written: false,
+                previous: old_val,
+            });
         }
 
         // Acquire per-key lock to avoid concurrent large writes
-        self.acquire_lock(key, 5000).await?;
-        let set_res = self.fdb.set(&packed_key, value).await;
+        let lock = self.acquire_lock(key, 5000).await?;
+        let set_res = match parse_counter(value) {
+            Some(n) => self.write_counter(key, n).await,
+            None => self.write_value(key, &packed_key, value).await,
+        };
         if let Err(e) = set_res {
-            let _ = self.release_lock(key).await; // best-effort
-            return Err(format!("FoundationDB set error: {:?}", e));
-        }
-
-        if let Some(ttl_option) = &flags.ttl {
-            let ttl = ttl_option
-                .unix_epoch_in_ms()
-                .map_err(|e| format!("FoundationDB set_ttl error: {:?}", e))?;
-            if *ttl_option != SetTTL::KeepTTL {
-                self.set_ttl(key, ttl)
-                    .await
-                    .map_err(|e| format!("FoundationDB set_ttl error: {:?}", e))?;
+            let _ = self.release_lock(lock).await; // best-effort
+            return Err(e);
+        }
+
+        match &flags.ttl {
+            Some(SetTTL::KeepTTL) => {}
+            Some(ttl_option) => {
+                let ttl = ttl_option.unix_epoch_in_ms().map_err(Error::Other)?;
+                self.set_ttl(key, ttl).await?;
             }
+            // The TTL of the value replaced goes with it, as in Redis
+            None => self.persist(key).await?,
         }
 
         // Release lock
-        self.release_lock(key).await?;
+        self.release_lock(lock).await?;
+
+        Ok(SetOutcome {
+            written: true,
+            previous: old_val,
+        })
+    }
+
+    /// Store `n` as a counter, dropping any string value, HyperLogLog or
+    /// stream it replaces.
+    async fn write_counter(&self, key: &[u8], n: i64) -> Result<()> {
+        let stored = self.stored_key(key).await?;
+        let packed_key = stored.pack(SimpleDataPrefix::Data);
+        let counter_key = stored.pack(SimpleDataPrefix::Counter);
+        let hll_key = stored.pack(SimpleDataPrefix::Hll);
+        let stream_key = stored.pack(SimpleDataPrefix::Stream);
+        self.fdb
+            .run(move |trx, _| {
+                let packed_key = packed_key.clone();
+                let counter_key = counter_key.clone();
+                let hll_key = hll_key.clone();
+                let stream_key = stream_key.clone();
+                async move {
+                    FoundationDB::clear_in_trx(&trx, &packed_key).await?;
+                    clear_hll_in_trx(&trx, &hll_key);
+                    clear_stream_in_trx(&trx, &stream_key);
+                    trx.set(&counter_key, &(n as i128).to_le_bytes());
+                    Ok(())
+                }
+            })
+            .await
+            .map_err(|e| fdb::Error::from(e).into())
+    }
 
-        Ok(old_val)
+    /// Store a string value, dropping any counter, HyperLogLog or stream it
+    /// replaces in the transaction that writes it, or that flips its manifest
+    /// once a value too large for one transaction is staged.
+    async fn write_value(&self, key: &[u8], packed_key: &[u8], value: &[u8]) -> Result<()> {
+        let stored = self.stored_key(key).await?;
+        let counter_key = stored.pack(SimpleDataPrefix::Counter);
+        let hll_key = stored.pack(SimpleDataPrefix::Hll);
+        let stream_key = stored.pack(SimpleDataPrefix::Stream);
+        let (counter_key, hll_key, stream_key) = (&counter_key, &hll_key, &stream_key);
+        self.fdb
+            .set_many(&[(packed_key.to_vec(), value.to_vec())], move |trx| async move {
+                trx.clear(counter_key);
+                clear_hll_in_trx(&trx, hll_key);
+                clear_stream_in_trx(&trx, stream_key);
+                Ok(true)
+            })
+            .await?;
+        Ok(())
     }
 
-    pub async fn set_ttl(&self, key: &[u8], ttl: u128) -> Result<(), String> {
-        let packed_key = pack(&(SimpleDataPrefix::Ttl, key));
+    /// Read the counter stored for `stored`, if any.
+    async fn read_counter(&self, stored: &StoredKey) -> Result<Option<i128>> {
+        let counter_key = stored.pack(SimpleDataPrefix::Counter);
+        self.fdb
+            .run(move |trx, _| {
+                let counter_key = counter_key.clone();
+                async move { Ok(trx.get(&counter_key, false).await?.map(|v| decode_counter(&v))) }
+            })
+            .await
+            .map_err(|e| fdb::Error::from(e).into())
+    }
+
+    /// Value held by the counter or the HyperLogLog stored for `stored`, in
+    /// the string form GET returns: the decimal form of the counter, the
+    /// Redis encoding of the HyperLogLog. A stream has no string form.
+    async fn read_derived(&self, stored: &StoredKey) -> Result<Option<Vec<u8>>> {
+        if let Some(n) = self.read_counter(stored).await? {
+            return Ok(Some(n.to_string().into_bytes()));
+        }
+        let hll_key = stored.pack(SimpleDataPrefix::Hll);
+        let stream_key = stored.pack(SimpleDataPrefix::Stream);
+        let (registers, stream) = self
+            .fdb
+            .run(move |trx, _| {
+                let hll_key = hll_key.clone();
+                let stream_key = stream_key.clone();
+                async move {
+                    let registers = read_registers_in_trx(&trx, &hll_key).await?;
+                    Ok((registers, trx.get(&stream_key, false).await?))
+                }
+            })
+            .await
+            .map_err(fdb::Error::from)?;
+        if stream.is_some() {
+            return Err(Error::WrongType);
+        }
+        Ok(registers.map(|r| r.encode()))
+    }
+
+    /// Drop the HyperLogLog or the stream stored for `stored`, if any.
+    async fn clear_hll_and_stream(&self, stored: &StoredKey) -> Result<()> {
+        let hll_key = stored.pack(SimpleDataPrefix::Hll);
+        let stream_key = stored.pack(SimpleDataPrefix::Stream);
+        self.fdb
+            .run(move |trx, _| {
+                let hll_key = hll_key.clone();
+                let stream_key = stream_key.clone();
+                async move {
+                    clear_hll_in_trx(&trx, &hll_key);
+                    clear_stream_in_trx(&trx, &stream_key);
+                    Ok(())
+                }
+            })
+            .await
+            .map_err(|e| fdb::Error::from(e).into())
+    }
+
+    pub async fn set_ttl(&self, key: &[u8], ttl: u128) -> Result<()> {
+        let packed_key = self.stored_key(key).await?.pack(SimpleDataPrefix::Ttl);
         let ttl_bytes = ttl.to_be_bytes();
         self.fdb
-            .set(&packed_key, &ttl_bytes)
+            .run(move |trx, _| {
+                let packed_key = packed_key.clone();
+                async move {
+                    // Drop any record left in the chunked v0 layout before writing inline
+                    let mut end = packed_key.clone();
+                    end.push(0xFF);
+                    trx.clear_range(&packed_key, &end);
+                    trx.set(&packed_key, &ttl_bytes);
+                    Ok(())
+                }
+            })
             .await
-            .map_err(|e| format!("FoundationDB set_ttl error: {:?}", e))
+            .map_err(|e| fdb::Error::from(e).into())
+    }
+
+    /// Remove the TTL of `key`, if any.
+    pub async fn persist(&self, key: &[u8]) -> Result<()> {
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(());
+        };
+        let ttl_key = stored.pack(SimpleDataPrefix::Ttl);
+        self.fdb
+            .run(move |trx, _| {
+                let ttl_key = ttl_key.clone();
+                async move {
+                    clear_ttl_in_trx(&trx, &ttl_key);
+                    Ok(())
+                }
+            })
+            .await
+            .map_err(|e| fdb::Error::from(e).into())
+    }
+
+    /// Read the raw TTL record of `key`.
+    /// TTLs are stored inline under the TTL key; format v0 stored them as a
+    /// single chunk below it, which is still read until `redisgw migrate` ran.
+    pub async fn get_ttl(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        match self.existing_key(key).await? {
+            Some(stored) => self.read_ttl(&stored).await,
+            None => Ok(None),
+        }
+    }
+
+    async fn read_ttl(&self, stored: &StoredKey) -> Result<Option<Vec<u8>>> {
+        let packed_key = stored.pack(SimpleDataPrefix::Ttl);
+        let legacy_key = Subspace::from_bytes(packed_key.clone()).pack(&(0,));
+        self.fdb
+            .run(move |trx, _| {
+                let packed_key = packed_key.clone();
+                let legacy_key = legacy_key.clone();
+                async move {
+                    if let Some(ttl) = trx.get(&packed_key, false).await? {
+                        return Ok(Some(ttl));
+                    }
+                    Ok(trx.get(&legacy_key, false).await?)
+                }
+            })
+            .await
+            .map_err(|e| fdb::Error::from(e).into())
     }
 
     /// Acquire a per-key lock to prevent concurrent writers for large objects.
-    /// This function retries with backoff until timeout_ms is reached.
-    pub async fn acquire_lock(&self, key: &[u8], timeout_ms: u64) -> Result<(), String> {
-        use tokio::time::{sleep, Instant};
+    /// While the lock is held, waits for it to change until timeout_ms is reached.
+    /// The lock holds a `locked:<ms>` token, renewed every
+    /// `LOCK_RENEW_INTERVAL` while the write lasts.
+    pub async fn acquire_lock(&self, key: &[u8], timeout_ms: u64) -> Result<HeldLock> {
+        use tokio::time::{Instant, timeout};
 
         let start = Instant::now();
-        let mut backoff = 10u64; // ms
-        let lock_key = pack(&(SimpleDataPrefix::Lock, key));
+        let lock_key = self.stored_key(key).await?.pack(SimpleDataPrefix::Lock);
 
         while start.elapsed().as_millis() as u64 <= timeout_ms {
+            let token = lock_token();
             // Try to create the lock in a short transaction: read then set if absent.
             let lk = lock_key.clone();
+            let tk = token.clone();
             let db = self.fdb.clone();
             let res = db
-                .database
-                .run(move |trx, _| {
+                .run(move |trx, maybe_committed| {
                     let lk = lk.clone();
+                    let token = tk.clone();
                     async move {
-                        let existing = trx.get(&lk, false).await?;
-                        if existing.is_some() {
-                            // someone holds the lock
-                            return Ok(false);
+                        if let Some(existing) = trx.get(&lk, false).await? {
+                            // An attempt whose commit outcome was unknown took it,
+                            // otherwise someone else holds the lock
+                            return Ok(bool::from(maybe_committed) && existing == token);
                         }
-                        // set a simple token (timestamp) to mark the lock
-                        let token = match SystemTime::now().duration_since(UNIX_EPOCH) {
-                            Ok(d) => format!("locked:{}", d.as_millis()),
-                            Err(_) => "locked:0".to_string(),
-                        };
-                        trx.set(&lk, token.as_bytes());
+                        trx.set(&lk, &token);
                         Ok(true)
                     }
                 })
                 .await;
 
             match res {
-                Ok(true) => return Ok(()),
+                Ok(true) => {
+                    let (stop, stopped) = oneshot::channel();
+                    let renewal = tokio::spawn(renew_lock(self.fdb.clone(), lock_key.clone(), token, stopped));
+                    return Ok(HeldLock {
+                        lock_key,
+                        stop,
+                        renewal,
+                    });
+                }
                 Ok(false) => {
-                    sleep(std::time::Duration::from_millis(backoff)).await;
-                    backoff = (backoff * 2).min(500);
+                    // The lock may be released before the watch is armed, so
+                    // the wait is capped and the lock tried again regardless
+                    let remaining = Duration::from_millis(timeout_ms).saturating_sub(start.elapsed());
+                    let released = self.fdb.watch(&lock_key);
+                    if let Ok(Err(e)) = timeout(remaining.min(LOCK_POLL_INTERVAL), released).await {
+                        return Err(e.into());
+                    }
                     continue;
                 }
-                Err(e) => return Err(format!("FoundationDB error while acquiring lock: {:?}", e)),
+                Err(e) => return Err(fdb::Error::from(e).into()),
             }
         }
 
-        Err("timeout acquiring key lock".to_string())
+        Err(Error::LockTimeout)
     }
 
-    /// Release the per-key lock acquired with `acquire_lock`.
-    pub async fn release_lock(&self, key: &[u8]) -> Result<(), String> {
-        let lock_key = pack(&(SimpleDataPrefix::Lock, key));
-        // Perform a short transaction to clear the lock key to avoid ambiguity with other delete helpers
-        let lk = lock_key.clone();
+    /// Release a lock acquired with `acquire_lock`, unless it was taken for
+    /// abandoned and someone else holds it by now.
+    pub async fn release_lock(&self, lock: HeldLock) -> Result<()> {
+        let _ = lock.stop.send(());
+        let token = lock
+            .renewal
+            .await
+            .map_err(|e| Error::Other(format!("lock renewal failed: {}", e)))?;
+        let lk = lock.lock_key;
         let db = self.fdb.clone();
         let res = db
-            .database
             .run(move |trx, _| {
                 let lk = lk.clone();
+                let token = token.clone();
                 async move {
-                    trx.clear(&lk);
+                    if trx.get(&lk, false).await?.is_some_and(|t| t == token) {
+                        trx.clear(&lk);
+                    }
                     Ok(())
                 }
             })
             .await;
 
-        res.map_err(|e| format!("FoundationDB release_lock error: {:?}", e))
+        res.map_err(|e| fdb::Error::from(e).into())
     }
 
-    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, String> {
-        let packed_key = pack(&(SimpleDataPrefix::Data, key));
+    pub async fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(None);
+        };
+        let packed_key = stored.pack(SimpleDataPrefix::Data);
         // Read value and TTL (if any). If TTL exists and is expired, delete both and return None.
-        let value = self
-            .fdb
-            .get(&packed_key)
-            .await
-            .map_err(|e| format!("FoundationDB get error: {:?}", e))?;
+        let value = match self.fdb.get(&packed_key).await? {
+            Some(value) => Some(value),
+            None => self.read_derived(&stored).await?,
+        };
 
         // If there's no value, nothing to do.
         if value.is_none() {
             return Ok(None);
         }
 
-        // Check TTL
-        let packed_ttl_key = pack(&(SimpleDataPrefix::Ttl, key));
-        let ttl_bytes_opt = self
-            .fdb
-            .get(&packed_ttl_key)
-            .await
-            .map_err(|e| format!("FoundationDB get ttl error: {:?}", e))?;
-
-        if let Some(ttl_bytes) = ttl_bytes_opt {
-            // TTL stored as big-endian u128
-            if ttl_bytes.len() == 16 {
-                let mut arr = [0u8; 16];
-                arr.copy_from_slice(&ttl_bytes[..16]);
-                let ttl = u128::from_be_bytes(arr);
-
-                let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
-                    Ok(d) => d.as_millis(),
-                    Err(_) => 0u128,
-                };
+        if self.expire_if_due(key).await? {
+            return Ok(None);
+        }
 
-                if ttl != 0 && ttl <= now {
-                    // expired -> delete both keys
-                    let _ = self
-                        .fdb
-                        .delete(&packed_key)
-                        .await
-                        .map_err(|e| format!("FoundationDB delete expired error: {:?}", e))?;
-                    let _ = self
-                        .fdb
-                        .delete(&packed_ttl_key)
-                        .await
-                        .map_err(|e| format!("FoundationDB delete expired ttl error: {:?}", e))?;
-                    return Ok(None);
-                }
-            }
+        Ok(value)
+    }
+
+    /// Open the value stored at `key` for reading chunk by chunk.
+    pub async fn get_stream(&self, key: &[u8]) -> Result<Option<ValueStream>> {
+        if self.expire_if_due(key).await? {
+            return Ok(None);
         }
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(None);
+        };
+        if let Some(stream) = self.fdb.get_stream(&stored.pack(SimpleDataPrefix::Data)).await? {
+            return Ok(Some(stream));
+        }
+        Ok(self.read_derived(&stored).await?.map(ValueStream::from_bytes))
+    }
 
+    /// Read the value at `key` as GET does, then change its expiry when
+    /// `ttl` asks for it.
+    pub async fn getex(&self, key: &[u8], ttl: Option<GetExTTL>) -> Result<Option<Vec<u8>>> {
+        let value = self.get(key).await?;
+        match ttl.filter(|_| value.is_some()) {
+            Some(GetExTTL::Set(ttl)) => {
+                let at = ttl.unix_epoch_in_ms().map_err(Error::Other)?;
+                self.set_ttl(key, at).await?;
+            }
+            Some(GetExTTL::Persist) => self.persist(key).await?,
+            None => {}
+        }
         Ok(value)
     }
 
-    pub async fn delete(&self, key: &[u8]) -> Result<i64, String> {
-        // Acquire lock before deleting
-        self.acquire_lock(key, 5000).await?;
-        let packed_key = pack(&(SimpleDataPrefix::Data, key));
-        let packed_ttl_key = pack(&(SimpleDataPrefix::Ttl, key));
-        let r1 = self.fdb.delete(&packed_key).await;
-        let r2 = self.fdb.delete(&packed_ttl_key).await;
-        // best-effort release
-        let _ = self.release_lock(key).await;
+    /// Length of the value at `key`, read from its manifest, or of the
+    /// string form of its counter or HyperLogLog.
+    pub async fn strlen(&self, key: &[u8]) -> Result<u64> {
+        if self.expire_if_due(key).await? {
+            return Ok(0);
+        }
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(0);
+        };
+        if let Some(len) = self.fdb.value_len(&stored.pack(SimpleDataPrefix::Data)).await? {
+            return Ok(len);
+        }
+        Ok(self.read_derived(&stored).await?.map_or(0, |v| v.len() as u64))
+    }
+
+    /// Bytes `start` to `end` of the value at `key`, both inclusive and
+    /// counted from its end when negative. Only the chunks holding them are read.
+    pub async fn get_range(&self, key: &[u8], start: i64, end: i64) -> Result<Vec<u8>> {
+        let (_, bytes) = self.read_range(key, |len| byte_range(len, start, end)).await?;
+        Ok(bytes)
+    }
 
-        if let Err(e) = r1 {
-            return Err(format!("FoundationDB delete error: {:?}", e));
+    /// Bytes of the value at `key` that `range` picks given its length,
+    /// returned with that length. Only the chunks holding them are read; a
+    /// counter or a HyperLogLog reads as its string form and a missing key
+    /// as empty.
+    pub async fn read_range<F>(&self, key: &[u8], range: F) -> Result<(u64, Vec<u8>)>
+    where
+        F: Fn(u64) -> Range<u64>,
+    {
+        if self.expire_if_due(key).await? {
+            return Ok((0, Vec::new()));
         }
-        if let Err(e) = r2 {
-            return Err(format!("FoundationDB delete ttl error: {:?}", e));
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok((0, Vec::new()));
+        };
+        // Length the range was last picked for
+        let seen = AtomicU64::new(0);
+        let picked = |len: u64| {
+            seen.store(len, Ordering::Relaxed);
+            range(len)
+        };
+        if let Some(bytes) = self.fdb.get_range(&stored.pack(SimpleDataPrefix::Data), picked).await? {
+            return Ok((seen.into_inner(), bytes));
         }
+        let Some(value) = self.read_derived(&stored).await? else {
+            return Ok((0, Vec::new()));
+        };
+        let wanted = range(value.len() as u64);
+        let end = (wanted.end as usize).min(value.len());
+        let bytes = value[(wanted.start as usize).min(end)..end].to_vec();
+        Ok((value.len() as u64, bytes))
+    }
 
-        Ok(1)
+    /// Overwrite the value at `key` from byte `offset` with `value`, as
+    /// SETRANGE does. Returns the new length.
+    pub async fn set_range(&self, key: &[u8], offset: u64, value: &[u8]) -> Result<u64> {
+        if value.is_empty() {
+            return self.strlen(key).await;
+        }
+        self.write_range(key, Some(offset), value).await
     }
 
-    /// Atomically add `delta` to integer value stored at `key`.
-    /// Returns new value on success, or Err(String) on parse/FDB error.
-    pub async fn atomic_add(&self, key: &[u8], delta: i64) -> Result<i64, String> {
-        let packed_key = pack(&(SimpleDataPrefix::Data, key));
-        let pk = packed_key.clone();
-        let db = self.fdb.clone();
-        // Acquire lock to serialize potentially large transactional writes
-        self.acquire_lock(key, 5000).await?;
-        let release_key = key.to_vec();
+    /// Append `value` to the value at `key`, creating it when missing.
+    /// Returns the new length.
+    pub async fn append(&self, key: &[u8], value: &[u8]) -> Result<u64> {
+        self.write_range(key, None, value).await
+    }
 
-        let res = db
-            .database
-            .run(move |trx, _| {
-                let key = pk.clone();
-                async move {
-                    // range scan for chunks under packed key
-                    let mut end = key.clone();
-                    end.push(0xFF);
-                    let range = RangeOption::from((key.clone(), end));
-                    let stream = trx.get_ranges_keyvalues(range, false);
-                    let records = stream
-                        .map(|r| match r {
-                            Ok(v) => Ok((v.key().to_vec(), v.value().to_vec())),
-                            Err(e) => Err(e),
-                        })
-                        .try_collect::<Vec<(Vec<u8>, Vec<u8>)>>()
-                        .await?;
-
-                    // reconstruct current value
-                    let mut current = Vec::new();
-                    for (_k, v) in &records {
-                        current.extend_from_slice(v);
+    /// Write `value` into the string at `key` from byte `offset`, or at its
+    /// end when `offset` is `None`, rewriting only the chunks it overlaps.
+    /// The TTL of the key is kept; a counter or a HyperLogLog becomes the
+    /// string GET returns for it first.
+    async fn write_range(&self, key: &[u8], offset: Option<u64>, value: &[u8]) -> Result<u64> {
+        let end = offset.unwrap_or(0).saturating_add(value.len() as u64);
+        if end > fdb::MAX_VALUE_LEN as u64 {
+            return Err(fdb::Error::ValueTooLarge {
+                size: end as usize,
+                max: fdb::MAX_VALUE_LEN,
+            }
+            .into());
+        }
+        let (packed_key, lock) = self.lock_for_write(key).await?;
+        let written = match offset {
+            Some(offset) => self.fdb.set_range(&packed_key, offset, value).await,
+            None => self.fdb.append(&packed_key, value).await,
+        };
+        self.unlock_after_write(lock, written.map_err(Error::from)).await
+    }
+
+    /// Update bytes `range` of the string at `key` in one go, as
+    /// `FoundationDB::update_range` does, and return what `update` returned.
+    /// The TTL of the key is kept; a counter or a HyperLogLog becomes the
+    /// string GET returns for it first.
+    pub async fn update_range<F, T>(&self, key: &[u8], range: Range<u64>, update: F) -> Result<T>
+    where
+        F: Fn(&mut Vec<u8>) -> T,
+        T: Clone,
+    {
+        let (packed_key, lock) = self.lock_for_write(key).await?;
+        let updated = self.fdb.update_range(&packed_key, range, update).await;
+        self.unlock_after_write(lock, updated.map_err(Error::from)).await
+    }
+
+    /// Take the lock of `key` ahead of a partial write and return its data
+    /// key, once an expired value is gone and a counter or a HyperLogLog has
+    /// become the string GET returns for it.
+    /// Writes too large for one transaction rewrite the whole value, which
+    /// must not race with other writers.
+    async fn lock_for_write(&self, key: &[u8]) -> Result<(Vec<u8>, HeldLock)> {
+        self.expire_if_due(key).await?;
+        let stored = self.stored_key(key).await?;
+        let packed_key = stored.pack(SimpleDataPrefix::Data);
+
+        let lock = self.acquire_lock(key, 5000).await?;
+        let converted = async {
+            let has_value = self.fdb.value_len(&packed_key).await?.is_some();
+            let derived = if has_value {
+                None
+            } else {
+                self.read_derived(&stored).await?
+            };
+            if let Some(value) = derived {
+                self.write_value(key, &packed_key, &value).await?;
+            }
+            Ok::<_, Error>(())
+        }
+        .await;
+        if let Err(e) = converted {
+            let _ = self.release_lock(lock).await; // best-effort
+            return Err(e);
+        }
+        Ok((packed_key, lock))
+    }
+
+    /// Release the lock taken by `lock_for_write`, passing `result` on.
+    async fn unlock_after_write<T>(&self, lock: HeldLock, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            let _ = self.release_lock(lock).await; // best-effort
+            return result;
+        }
+        self.release_lock(lock).await?;
+        result
+    }
+
+    /// Delete `key` when its TTL has passed. Returns whether it expired.
+    pub(crate) async fn expire_if_due(&self, key: &[u8]) -> Result<bool> {
+        let Some(stored) = self.existing_key(key).await? else {
+            return Ok(false);
+        };
+        let packed_key = stored.pack(SimpleDataPrefix::Data);
+        let packed_ttl_key = stored.pack(SimpleDataPrefix::Ttl);
+        let Some(ttl_bytes) = self.read_ttl(&stored).await? else {
+            return Ok(false);
+        };
+
+        if is_expired(&ttl_bytes) {
+            // expired -> delete value, counter, HyperLogLog, stream and TTL
+            self.fdb.delete(&packed_key).await?;
+            self.fdb.delete(&stored.pack(SimpleDataPrefix::Counter)).await?;
+            self.clear_hll_and_stream(&stored).await?;
+            self.fdb.delete(&packed_ttl_key).await?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Read the values of `keys` as GET would, all at a single read version.
+    /// Expired keys read as missing; they are left for GET to delete.
+    /// The registers of HyperLogLogs are read afterwards, at a version of
+    /// their own.
+    pub async fn mget(&self, keys: &[&[u8]]) -> Result<Vec<Option<Vec<u8>>>> {
+        let mut stored = Vec::with_capacity(keys.len());
+        for key in keys {
+            stored.push(self.existing_key(key).await?);
+        }
+        let found = stored.iter().flatten().collect::<Vec<_>>();
+        let data_keys = found
+            .iter()
+            .map(|s| s.pack(SimpleDataPrefix::Data))
+            .collect::<Vec<_>>();
+        // Counter, HyperLogLog, TTL and v0 TTL records of each key, in that order
+        let records = found
+            .iter()
+            .flat_map(|s| {
+                let [ttl_key, legacy_ttl_key] = ttl_keys(s);
+                [
+                    s.pack(SimpleDataPrefix::Counter),
+                    s.pack(SimpleDataPrefix::Hll),
+                    ttl_key,
+                    legacy_ttl_key,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let (values, records) = self.fdb.get_many(&data_keys, &records).await?;
+
+        let mut found = values.into_iter().zip(records.chunks(4));
+        let mut read = Vec::with_capacity(stored.len());
+        for s in &stored {
+            let Some(s) = s else {
+                read.push(None);
+                continue;
+            };
+            let Some((value, [counter, hll, ttl, legacy_ttl])) = found.next() else {
+                read.push(None);
+                continue;
+            };
+            if ttl.as_ref().or(legacy_ttl.as_ref()).is_some_and(|t| is_expired(t)) {
+                read.push(None);
+                continue;
+            }
+            read.push(match (value, counter) {
+                (Some(value), _) => Some(value),
+                (None, Some(c)) => Some(decode_counter(c).to_string().into_bytes()),
+                (None, None) if hll.is_some() => self.read_derived(s).await?,
+                (None, None) => None,
+            });
+        }
+        Ok(read)
+    }
+
+    /// Set every pair at once, as SET would one by one: counters,
+    /// HyperLogLogs, streams and TTLs of the keys are dropped in the same
+    /// transaction.
+    pub async fn mset(&self, pairs: &[(&[u8], &[u8])]) -> Result<()> {
+        self.write_many(pairs, false).await?;
+        Ok(())
+    }
+
+    /// Set every pair at once unless one of the keys exists, in which case
+    /// none is set. Returns whether the pairs were set.
+    pub async fn msetnx(&self, pairs: &[(&[u8], &[u8])]) -> Result<bool> {
+        self.write_many(pairs, true).await
+    }
+
+    /// Count the keys of `keys` that exist, a key given twice counting twice.
+    pub async fn exists(&self, keys: &[&[u8]]) -> Result<i64> {
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            if let Some(stored) = self.existing_key(key).await? {
+                found.push(stored);
+            }
+        }
+        // Manifest, counter, HyperLogLog, stream, TTL and v0 TTL records of
+        // each key, in that order
+        let records = found
+            .iter()
+            .flat_map(|s| {
+                let [ttl_key, legacy_ttl_key] = ttl_keys(s);
+                [
+                    s.pack(SimpleDataPrefix::Data),
+                    s.pack(SimpleDataPrefix::Counter),
+                    s.pack(SimpleDataPrefix::Hll),
+                    s.pack(SimpleDataPrefix::Stream),
+                    ttl_key,
+                    legacy_ttl_key,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let (_, records) = self.fdb.get_many(&[], &records).await?;
+        let live = records.chunks(6).filter(|r| match r {
+            [value, counter, hll, stream, ttl, legacy_ttl] => {
+                let expired = ttl.as_ref().or(legacy_ttl.as_ref()).is_some_and(|t| is_expired(t));
+                (value.is_some() || counter.is_some() || hll.is_some() || stream.is_some()) && !expired
+            }
+            _ => false,
+        });
+        Ok(live.count() as i64)
+    }
+
+    /// Remove every key of `keys` in a single transaction and return how
+    /// many of them existed.
+    ///
+    /// With `background`, values of more than `UNLINK_INLINE_CHUNKS` chunks
+    /// are only detached in that transaction, and their chunks cleared
+    /// afterwards by a background task.
+    pub async fn remove(&self, keys: &[&[u8]], background: bool) -> Result<i64> {
+        let mut seen = HashSet::new();
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            if !seen.insert(*key) {
+                continue;
+            }
+            if let Some(stored) = self.existing_key(key).await? {
+                found.push((
+                    stored.pack(SimpleDataPrefix::Data),
+                    stored.pack(SimpleDataPrefix::Counter),
+                    stored.pack(SimpleDataPrefix::Hll),
+                    stored.pack(SimpleDataPrefix::Stream),
+                    ttl_keys(&stored),
+                ));
+            }
+        }
+        if found.is_empty() {
+            return Ok(0);
+        }
+
+        let max_chunks = if background { UNLINK_INLINE_CHUNKS } else { u64::MAX };
+        let found = &found;
+        // Run at most once: a replay after an unknown commit outcome would
+        // find the keys gone and count them as missing
+        let (removed, detached) = self
+            .fdb
+            .run_once(move |trx| async move {
+                let mut removed = 0;
+                let mut detached = Vec::new();
+                for (data_key, counter_key, hll_key, stream_key, ttl_keys) in found {
+                    if live_in_trx(&trx, data_key, counter_key, hll_key, stream_key, ttl_keys).await? {
+                        removed += 1;
+                    }
+                    if let Some(manifest) = FoundationDB::detach_in_trx(&trx, data_key, max_chunks).await? {
+                        detached.push((data_key.clone(), manifest));
                     }
+                    trx.clear(counter_key);
+                    clear_hll_in_trx(&trx, hll_key);
+                    clear_stream_in_trx(&trx, stream_key);
+                    clear_ttl_in_trx(&trx, &ttl_keys[0]);
+                }
+                Ok((removed, detached))
+            })
+            .await?;
 
-                    let mut n: i64 = 0;
-                    if !current.is_empty() {
-                        match std::str::from_utf8(&current) {
-                            Ok(s) => match s.parse::<i64>() {
-                                Ok(num) => n = num,
-                                Err(_) => {
-                                    // signal parse failure by returning (false, 0)
-                                    return Ok((false, 0i64));
-                                }
-                            },
-                            Err(_) => {
-                                return Ok((false, 0i64));
-                            }
+        if !detached.is_empty() {
+            let fdb = self.fdb.clone();
+            tokio::spawn(async move {
+                for (key, manifest) in detached {
+                    if let Err(e) = fdb.clear_detached(&key, &manifest).await {
+                        log::warn!("unable to clear unlinked value: {}", e);
+                    }
+                }
+            });
+        }
+        Ok(removed)
+    }
+
+    async fn write_many(&self, pairs: &[(&[u8], &[u8])], only_absent: bool) -> Result<bool> {
+        let mut writes = Vec::with_capacity(pairs.len());
+        let mut records = Vec::with_capacity(pairs.len());
+        for (key, value) in pairs {
+            let stored = self.stored_key(key).await?;
+            let data_key = stored.pack(SimpleDataPrefix::Data);
+            writes.push((data_key.clone(), value.to_vec()));
+            records.push((
+                data_key,
+                stored.pack(SimpleDataPrefix::Counter),
+                stored.pack(SimpleDataPrefix::Hll),
+                stored.pack(SimpleDataPrefix::Stream),
+                ttl_keys(&stored),
+            ));
+        }
+
+        let records = &records;
+        let written = self
+            .fdb
+            .set_many(&writes, move |trx| async move {
+                if only_absent {
+                    for (data_key, counter_key, hll_key, stream_key, ttl_keys) in records {
+                        if live_in_trx(&trx, data_key, counter_key, hll_key, stream_key, ttl_keys).await? {
+                            return Ok(false);
                         }
                     }
+                }
+                for (_, counter_key, hll_key, stream_key, [ttl_key, _]) in records {
+                    trx.clear(counter_key);
+                    clear_hll_in_trx(&trx, hll_key);
+                    clear_stream_in_trx(&trx, stream_key);
+                    clear_ttl_in_trx(&trx, ttl_key);
+                }
+                Ok(true)
+            })
+            .await?;
+        Ok(written)
+    }
 
-                    let new_n = n.wrapping_add(delta);
+    /// Add `delta` to the integer stored at `key` and return the new value.
+    ///
+    /// Counters are bumped with FoundationDB's atomic add and read at snapshot
+    /// isolation, so concurrent increments never conflict and none is lost.
+    /// The reply and the i64 range check are computed from that snapshot:
+    /// under contention two callers may see the same value, while the stored
+    /// total stays exact.
+    /// A string value holding an integer is turned into a counter on the way,
+    /// in a transaction that conflicts only with writers of that value.
+    pub async fn atomic_add(&self, key: &[u8], delta: i64) -> Result<i64> {
+        let stored = self.stored_key(key).await?;
+        let packed_key = stored.pack(SimpleDataPrefix::Data);
+        let counter_key = stored.pack(SimpleDataPrefix::Counter);
+        let hll_key = stored.pack(SimpleDataPrefix::Hll);
+        let stream_key = stored.pack(SimpleDataPrefix::Stream);
+        let trx_fdb = self.fdb.clone();
 
-                    // clear old chunks
-                    for (k, _) in &records {
-                        trx.clear(k);
+        // Run at most once: replaying an add whose commit outcome was unknown
+        // would count it twice
+        let res = self
+            .fdb
+            .run_once(move |trx| {
+                let packed_key = packed_key.clone();
+                let counter_key = counter_key.clone();
+                let hll_key = hll_key.clone();
+                let stream_key = stream_key.clone();
+                let fdb = trx_fdb.clone();
+                async move {
+                    if let Some(current) = fdb.get_in_trx(&trx, &packed_key).await? {
+                        let Some(n) = std::str::from_utf8(&current)
+                            .ok()
+                            .and_then(|s| s.parse::<i64>().ok())
+                        else {
+                            return Ok(Err(Refused::NotAnInteger));
+                        };
+                        let Some(new_n) = n.checked_add(delta) else {
+                            return Ok(Err(Refused::Overflow));
+                        };
+                        FoundationDB::clear_in_trx(&trx, &packed_key).await?;
+                        trx.set(&counter_key, &(new_n as i128).to_le_bytes());
+                        return Ok(Ok(new_n));
+                    }
+
+                    let (current, hll, stream) = futures::try_join!(
+                        trx.get(&counter_key, true),
+                        trx.get(&hll_key, false),
+                        trx.get(&stream_key, false),
+                    )?;
+                    if stream.is_some() {
+                        return Ok(Err(Refused::WrongType));
+                    }
+                    if hll.is_some() {
+                        return Ok(Err(Refused::NotAnInteger));
                     }
+                    let current = current.map(|v| decode_counter(&v)).unwrap_or(0);
+                    let Ok(n) = i64::try_from(current) else {
+                        return Ok(Err(Refused::NotAnInteger));
+                    };
+                    let Some(new_n) = n.checked_add(delta) else {
+                        return Ok(Err(Refused::Overflow));
+                    };
+                    // The operand sets the width of the sum, so it spans the whole record
+                    trx.atomic_op(&counter_key, &(delta as i128).to_le_bytes(), MutationType::Add);
+                    Ok(Ok(new_n))
+                }
+            })
+            .await;
+
+        res?.map_err(Error::from)
+    }
 
-                    // write new single chunk at index 0
-                    let subspace = Subspace::from_bytes(key.clone());
-                    let chunk_key = subspace.pack(&(0,));
-                    trx.set(&chunk_key, new_n.to_string().as_bytes());
+    /// Add `delta` to the float stored at `key` and return the new value, in
+    /// the form it is stored: as a string, formatted the way Redis does.
+    /// The TTL of the key is kept.
+    pub async fn incr_by_float(&self, key: &[u8], delta: f64) -> Result<Vec<u8>> {
+        self.expire_if_due(key).await?;
+        let stored = self.stored_key(key).await?;
+        let packed_key = stored.pack(SimpleDataPrefix::Data);
+        let counter_key = stored.pack(SimpleDataPrefix::Counter);
+        let hll_key = stored.pack(SimpleDataPrefix::Hll);
+        let stream_key = stored.pack(SimpleDataPrefix::Stream);
+        let (packed_key, counter_key, hll_key, stream_key, fdb) =
+            (&packed_key, &counter_key, &hll_key, &stream_key, &self.fdb);
 
-                    Ok((true, new_n))
+        // Run at most once: a replay would add `delta` twice
+        let res = self
+            .fdb
+            .run_once(move |trx| async move {
+                let current = match fdb.get_in_trx(&trx, packed_key).await? {
+                    Some(value) => Some(value),
+                    None => trx
+                        .get(counter_key, false)
+                        .await?
+                        .map(|c| decode_counter(&c).to_string().into_bytes()),
+                };
+                let n = match current {
+                    Some(value) => match parse_float(&value) {
+                        Some(n) => n,
+                        None => return Ok(Err(Refused::NotAFloat)),
+                    },
+                    None if trx.get(hll_key, false).await?.is_some() => return Ok(Err(Refused::NotAFloat)),
+                    None if trx.get(stream_key, false).await?.is_some() => return Ok(Err(Refused::WrongType)),
+                    None => 0.0,
+                };
+                let sum = n + delta;
+                if !sum.is_finite() {
+                    return Ok(Err(Refused::NanOrInfinity));
                 }
+                let formatted = format_float(sum).into_bytes();
+                trx.clear(counter_key);
+                fdb.set_in_trx(&trx, packed_key, &formatted).await?;
+                Ok(Ok(formatted))
             })
             .await;
-        // Release lock
-        let _ = self.release_lock(&release_key).await;
 
-        match res {
-            Ok((true, v)) => Ok(v),
-            Ok((false, _)) => Err("Value is not a valid integer".to_string()),
-            Err(e) => Err(format!("FoundationDB error: {:?}", e)),
+        res?.map_err(Error::from)
+    }
+
+    /// Raise registers of the HyperLogLog at `key` to the values in
+    /// `raised`, creating it when missing. Returns whether it was created or
+    /// a register changed.
+    ///
+    /// Registers are raised with FoundationDB's byte max and read at snapshot
+    /// isolation, so concurrent adds never conflict and none is lost; as for
+    /// counters, the reply is computed from that snapshot.
+    /// A string holding a HyperLogLog, as SET writes one copied from Redis,
+    /// becomes registers on the way; any other value is refused.
+    pub async fn hll_add(&self, key: &[u8], raised: &[(usize, u8)]) -> Result<bool> {
+        self.raise_registers(key, raised, true).await
+    }
+
+    /// Raise every register of the HyperLogLog at `key` to its value in
+    /// `registers`, creating it when missing, as `hll_add` does without
+    /// reading the registers it raises.
+    pub async fn hll_merge(&self, key: &[u8], registers: &Registers) -> Result<()> {
+        let raised = registers.raised().collect::<Vec<_>>();
+        self.raise_registers(key, &raised, false).await?;
+        Ok(())
+    }
+
+    async fn raise_registers(&self, key: &[u8], raised: &[(usize, u8)], compare: bool) -> Result<bool> {
+        self.expire_if_due(key).await?;
+        let stored = self.stored_key(key).await?;
+        let packed_key = stored.pack(SimpleDataPrefix::Data);
+        let counter_key = stored.pack(SimpleDataPrefix::Counter);
+        let hll_key = stored.pack(SimpleDataPrefix::Hll);
+        let stream_key = stored.pack(SimpleDataPrefix::Stream);
+        let registers = Subspace::from_bytes(hll_key.clone());
+        let (packed_key, counter_key, hll_key, stream_key, registers, fdb) =
+            (&packed_key, &counter_key, &hll_key, &stream_key, &registers, &self.fdb);
+
+        // Run at most once: a replay would find the registers raised and
+        // report nothing changed
+        let res = self
+            .fdb
+            .run_once(move |trx| async move {
+                if trx.get(stream_key, false).await?.is_some() {
+                    return Ok(Err(Refused::WrongType));
+                }
+                if trx.get(counter_key, false).await?.is_some() {
+                    return Ok(Err(Refused::NotAHyperLogLog));
+                }
+                let converted = match read_hll_string_in_trx(fdb, &trx, packed_key).await? {
+                    Ok(converted) => converted,
+                    Err(refused) => return Ok(Err(refused)),
+                };
+                let existed = converted.is_some() || trx.get(hll_key, true).await?.is_some();
+                if let Some(converted) = &converted {
+                    FoundationDB::clear_in_trx(&trx, packed_key).await?;
+                    for (index, value) in converted.raised() {
+                        trx.set(&registers.pack(&(index as u64,)), &[value]);
+                    }
+                }
+
+                let changed = if !existed {
+                    true
+                } else if !compare {
+                    false
+                } else if let Some(converted) = &converted {
+                    raised.iter().any(|&(index, value)| value > converted.get(index))
+                } else {
+                    let reads = raised
+                        .iter()
+                        .map(|&(index, _)| trx.get(&registers.pack(&(index as u64,)), true))
+                        .collect::<Vec<_>>();
+                    let current = futures::future::try_join_all(reads).await?;
+                    raised.iter().zip(current).any(|(&(_, value), current)| {
+                        value > current.and_then(|c| c.first().copied()).unwrap_or(0)
+                    })
+                };
+
+                trx.set(hll_key, &[]);
+                for &(index, value) in raised {
+                    trx.atomic_op(&registers.pack(&(index as u64,)), &[value], MutationType::ByteMax);
+                }
+                Ok(Ok(changed))
+            })
+            .await;
+
+        res?.map_err(Error::from)
+    }
+
+    /// Registers of the HyperLogLogs at `keys`, all read at a single read
+    /// version; `None` for missing keys. Refused when one of the keys holds
+    /// a value that is not a HyperLogLog.
+    pub async fn hll_read(&self, keys: &[&[u8]]) -> Result<Vec<Option<Registers>>> {
+        let mut stored = Vec::with_capacity(keys.len());
+        for key in keys {
+            self.expire_if_due(key).await?;
+            stored.push(self.existing_key(key).await?);
+        }
+        let (stored, fdb) = (&stored, &self.fdb);
+        let res = self
+            .fdb
+            .run(move |trx, _| async move {
+                let mut read = Vec::with_capacity(stored.len());
+                for s in stored {
+                    let Some(s) = s else {
+                        read.push(None);
+                        continue;
+                    };
+                    match read_hll_string_in_trx(fdb, &trx, &s.pack(SimpleDataPrefix::Data)).await? {
+                        Ok(Some(registers)) => {
+                            read.push(Some(registers));
+                            continue;
+                        }
+                        Ok(None) => {}
+                        Err(refused) => return Ok(Err(refused)),
+                    }
+                    if trx
+                        .get(&s.pack(SimpleDataPrefix::Counter), false)
+                        .await?
+                        .is_some()
+                    {
+                        return Ok(Err(Refused::NotAHyperLogLog));
+                    }
+                    if trx.get(&s.pack(SimpleDataPrefix::Stream), false).await?.is_some() {
+                        return Ok(Err(Refused::WrongType));
+                    }
+                    read.push(read_registers_in_trx(&trx, &s.pack(SimpleDataPrefix::Hll)).await?);
+                }
+                Ok(Ok(read))
+            })
+            .await
+            .map_err(fdb::Error::from)?;
+
+        res.map_err(Error::from)
+    }
+}
+
+/// Why `atomic_add`, `incr_by_float` or the HyperLogLog commands left a
+/// value untouched.
+#[derive(Clone, Copy)]
+enum Refused {
+    WrongType,
+    NotAnInteger,
+    Overflow,
+    NotAFloat,
+    NanOrInfinity,
+    NotAHyperLogLog,
+}
+
+impl From<Refused> for Error {
+    fn from(refused: Refused) -> Self {
+        match refused {
+            Refused::WrongType => Error::WrongType,
+            Refused::NotAnInteger => Error::NotAnInteger,
+            Refused::Overflow => Error::Overflow,
+            Refused::NotAFloat => Error::NotAFloat,
+            Refused::NanOrInfinity => Error::NanOrInfinity,
+            Refused::NotAHyperLogLog => Error::NotAHyperLogLog,
+        }
+    }
+}
+
+/// Parse `value` as a counter when it is the canonical decimal form of an
+/// `i64`, so that GET returns exactly the bytes that were SET.
+fn parse_counter(value: &[u8]) -> Option<i64> {
+    let n = std::str::from_utf8(value).ok()?.parse::<i64>().ok()?;
+    (n.to_string().as_bytes() == value).then_some(n)
+}
+
+/// Parse `value` as a finite float, as INCRBYFLOAT accepts it.
+pub(crate) fn parse_float(value: &[u8]) -> Option<f64> {
+    let s = std::str::from_utf8(value).ok()?;
+    // Rust accepts "inf" and "nan", and Redis no surrounding whitespace
+    if s.trim() != s {
+        return None;
+    }
+    s.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+/// Format `n` as Redis replies to INCRBYFLOAT: plain decimal notation,
+/// without an exponent or trailing zeros.
+pub(crate) fn format_float(n: f64) -> String {
+    // Redis prints a long double with 17 decimals, digits it carries a few
+    // more of, which hides the rounding error of the addition: 0.2 + 0.1 is
+    // 0.3. An f64 carries three digits fewer, so it keeps its 15 significant
+    // digits, along with every digit of its integral part
+    let magnitude = n.abs().log10().floor() as i32;
+    let decimals = (f64::DIGITS as i32 - 1 - magnitude).clamp(0, 17) as usize;
+    let mut formatted = format!("{:.*}", decimals, n);
+    if formatted.contains('.') {
+        formatted.truncate(formatted.trim_end_matches('0').trim_end_matches('.').len());
+    }
+    if formatted == "-0" {
+        formatted.remove(0);
+    }
+    formatted
+}
+
+/// Bytes of a `len` bytes long value that GETRANGE returns for `start` and
+/// `end`, both inclusive and counted from the end of the value when negative.
+fn byte_range(len: u64, start: i64, end: i64) -> Range<u64> {
+    let len = len as i64;
+    if start < 0 && end < 0 && start > end {
+        return 0..0;
+    }
+    let from_end = |i: i64| if i < 0 { (i + len).max(0) } else { i };
+    let (start, end) = (from_end(start), from_end(end).min(len - 1));
+    if start > end {
+        return 0..0;
+    }
+    start as u64..end as u64 + 1
+}
+
+/// Token a lock holds: the time it was taken or last renewed.
+fn lock_token() -> Vec<u8> {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => format!("locked:{}", d.as_millis()).into_bytes(),
+        Err(_) => b"locked:0".to_vec(),
+    }
+}
+
+/// Give the lock at `lock_key` a fresh token every `LOCK_RENEW_INTERVAL`
+/// for as long as it holds `token`, until `stopped`. Returns the token it
+/// holds last.
+async fn renew_lock(
+    fdb: FoundationDB,
+    lock_key: Vec<u8>,
+    mut token: Vec<u8>,
+    mut stopped: oneshot::Receiver<()>,
+) -> Vec<u8> {
+    loop {
+        // A dropped lock stops its renewal too
+        tokio::select! {
+            _ = &mut stopped => return token,
+            _ = tokio::time::sleep(LOCK_RENEW_INTERVAL) => {}
         }
+        let fresh = lock_token();
+        let (lk, held, tk) = (&lock_key, &token, &fresh);
+        let renewed = fdb
+            .run(move |trx, maybe_committed| async move {
+                let current = trx.get(lk, false).await?;
+                if bool::from(maybe_committed) && current.as_ref() == Some(tk) {
+                    return Ok(true);
+                }
+                if current.as_ref() != Some(held) {
+                    return Ok(false);
+                }
+                trx.set(lk, tk);
+                Ok(true)
+            })
+            .await;
+        match renewed {
+            Ok(true) => token = fresh,
+            // Taken for abandoned: someone else may hold it now
+            Ok(false) => return token,
+            Err(e) => log::warn!("unable to renew lock: {}", e),
+        }
+    }
+}
+
+/// Keys of the TTL record of `stored` and of the record the v0 layout kept
+/// below it.
+fn ttl_keys(stored: &StoredKey) -> [Vec<u8>; 2] {
+    let ttl_key = stored.pack(SimpleDataPrefix::Ttl);
+    let legacy_ttl_key = Subspace::from_bytes(ttl_key.clone()).pack(&(0,));
+    [ttl_key, legacy_ttl_key]
+}
+
+/// Clear the TTL record at `ttl_key` and any record left below it in the v0 layout.
+fn clear_ttl_in_trx(trx: &Transaction, ttl_key: &[u8]) {
+    let mut end = ttl_key.to_vec();
+    end.push(0xFF);
+    trx.clear_range(ttl_key, &end);
+}
+
+/// Clear the HyperLogLog at `hll_key` along with its registers.
+fn clear_hll_in_trx(trx: &Transaction, hll_key: &[u8]) {
+    let mut end = hll_key.to_vec();
+    end.push(0xFF);
+    trx.clear_range(hll_key, &end);
+}
+
+/// Registers of the HyperLogLog at `hll_key`, read inside `trx`, or `None`
+/// when there is none.
+async fn read_registers_in_trx(
+    trx: &Transaction,
+    hll_key: &[u8],
+) -> std::result::Result<Option<Registers>, FdbError> {
+    if trx.get(hll_key, false).await?.is_none() {
+        return Ok(None);
+    }
+    let subspace = Subspace::from_bytes(hll_key.to_vec());
+    let records = trx.get_range(&RangeOption::from(subspace.range()), false).await?;
+    let raised = records.iter().filter_map(|(key, value)| {
+        let (index,) = subspace.unpack::<(u64,)>(key).ok()?;
+        Some((index as usize, *value.first()?))
+    });
+    let mut registers = Registers::default();
+    for (index, value) in raised.filter(|&(index, _)| index < hll::REGISTERS) {
+        registers.raise(index, value);
     }
+    Ok(Some(registers))
+}
+
+/// HyperLogLog held as a string at `data_key`, read inside `trx`: `None`
+/// when there is no string there, refused when the string is not a
+/// HyperLogLog in one of the Redis encodings.
+async fn read_hll_string_in_trx(
+    fdb: &FoundationDB,
+    trx: &Transaction,
+    data_key: &[u8],
+) -> std::result::Result<std::result::Result<Option<Registers>, Refused>, FdbBindingError> {
+    let Some(raw) = trx.get(data_key, false).await? else {
+        return Ok(Ok(None));
+    };
+    // Longer strings cannot be HyperLogLogs and are not read
+    if Manifest::decode(&raw).is_some_and(|m| m.value_len > hll::DENSE_LEN as u64) {
+        return Ok(Err(Refused::NotAHyperLogLog));
+    }
+    let value = fdb.get_in_trx(trx, data_key).await?.unwrap_or_default();
+    Ok(Registers::decode(&value)
+        .map(Some)
+        .ok_or(Refused::NotAHyperLogLog))
+}
+
+/// Whether a key holds a value, a counter, a HyperLogLog or a stream that
+/// has not expired, read inside `trx`.
+async fn live_in_trx(
+    trx: &Transaction,
+    data_key: &[u8],
+    counter_key: &[u8],
+    hll_key: &[u8],
+    stream_key: &[u8],
+    [ttl_key, legacy_ttl_key]: &[Vec<u8>; 2],
+) -> std::result::Result<bool, FdbError> {
+    let (value, counter, hll, stream, ttl, legacy_ttl) = futures::try_join!(
+        trx.get(data_key, false),
+        trx.get(counter_key, false),
+        trx.get(hll_key, false),
+        trx.get(stream_key, false),
+        trx.get(ttl_key, false),
+        trx.get(legacy_ttl_key, false),
+    )?;
+    let expired = ttl.or(legacy_ttl).is_some_and(|t| is_expired(&t));
+    Ok((value.is_some() || counter.is_some() || hll.is_some() || stream.is_some()) && !expired)
+}
+
+/// Whether a TTL record, a big-endian `u128` of unix milliseconds, has passed.
+/// Zero means no expiry.
+fn is_expired(ttl_bytes: &[u8]) -> bool {
+    let Ok(arr) = <[u8; 16]>::try_from(ttl_bytes) else {
+        return false;
+    };
+    let ttl = u128::from_be_bytes(arr);
+    let now = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis(),
+        Err(_) => 0u128,
+    };
+    ttl != 0 && ttl <= now
+}
+
+/// Decode a counter record. Records are 16 bytes; missing bytes read as zero.
+fn decode_counter(bytes: &[u8]) -> i128 {
+    let mut buf = [0u8; 16];
+    let len = bytes.len().min(16);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    i128::from_le_bytes(buf)
 }
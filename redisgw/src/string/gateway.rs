@@ -1,5 +1,8 @@
-use crate::string::datamodel::StringDataModel;
-use crate::string::operations::{SetFlags, StringOperations};
+use crate::command::Reply;
+use crate::error::Error;
+use crate::string::datamodel::{StringDataModel, parse_float};
+use crate::string::operations::lcs;
+use crate::string::operations::{GetExTTL, LcsFlags, SetFlags, SetMethod, StringOperations, parse_expire};
 use redis_protocol::resp2::types::OwnedFrame as Frame;
 
 // Implement StringOperations for RedisGateway in the feature-local module
@@ -8,10 +11,12 @@ use crate::gateway::RedisGateway;
 impl StringOperations for RedisGateway {
     async fn set(&self, key: &[u8], value: &[u8], extra_args: SetFlags) -> Frame {
         let dm = StringDataModel::new(self.fdb.clone());
+        let get = extra_args.get;
         match dm.set(key, value, extra_args).await {
-            Ok(Some(val)) => Frame::BulkString(val),
-            Ok(None) => Frame::SimpleString("OK".to_string().into_bytes()),
-            Err(e) => Frame::Error(e.to_string()),
+            Ok(outcome) if get => outcome.previous.map_or(Frame::Null, Frame::BulkString),
+            Ok(outcome) if outcome.written => Frame::SimpleString("OK".to_string().into_bytes()),
+            Ok(_) => Frame::Null,
+            Err(e) => e.into(),
         }
     }
 
@@ -20,33 +25,98 @@ impl StringOperations for RedisGateway {
         match dm.get(key).await {
             Ok(Some(val)) => Frame::BulkString(val),
             Ok(None) => Frame::Null,
-            Err(e) => Frame::Error(e.to_string()),
+            Err(e) => e.into(),
         }
     }
 
-    async fn del(&self, key: &[u8]) -> Frame {
+    async fn del(&self, keys: &[&[u8]]) -> Frame {
         let dm = StringDataModel::new(self.fdb.clone());
-        let val = match dm.get(key).await {
-            Ok(v) => v,
-            Err(e) => return Frame::Error(e.to_string()),
-        };
-        if val.is_none() {
-            return Frame::Integer(0i64);
+        match dm.remove(keys, false).await {
+            Ok(n) => Frame::Integer(n),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn exists(&self, keys: &[&[u8]]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.exists(keys).await {
+            Ok(n) => Frame::Integer(n),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn touch(&self, keys: &[&[u8]]) -> Frame {
+        self.exists(keys).await
+    }
+
+    async fn unlink(&self, keys: &[&[u8]]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.remove(keys, true).await {
+            Ok(n) => Frame::Integer(n),
+            Err(e) => e.into(),
         }
+    }
+
+    async fn getex(&self, key: &[u8], ttl: Option<GetExTTL>) -> Frame {
         let dm = StringDataModel::new(self.fdb.clone());
-        match dm.delete(key).await {
-            Ok(val) => Frame::Integer(val),
-            Err(e) => Frame::Error(e.to_string()),
+        match dm.getex(key, ttl).await {
+            Ok(Some(val)) => Frame::BulkString(val),
+            Ok(None) => Frame::Null,
+            Err(e) => e.into(),
         }
     }
 
+    async fn getset(&self, key: &[u8], value: &[u8]) -> Frame {
+        let flags = SetFlags {
+            get: true,
+            ..SetFlags::default()
+        };
+        self.set(key, value, flags).await
+    }
+
+    async fn setnx(&self, key: &[u8], value: &[u8]) -> Frame {
+        let flags = SetFlags {
+            method: Some(SetMethod::NX),
+            ..SetFlags::default()
+        };
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.set(key, value, flags).await {
+            Ok(outcome) => Frame::Integer(outcome.written as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn setex(&self, key: &[u8], seconds: &[u8], value: &[u8]) -> Frame {
+        let ttl = match parse_expire("EX", seconds, "setex") {
+            Ok(ttl) => ttl,
+            Err(e) => return e.into(),
+        };
+        let flags = SetFlags {
+            ttl: Some(ttl),
+            ..SetFlags::default()
+        };
+        self.set(key, value, flags).await
+    }
+
+    async fn psetex(&self, key: &[u8], milliseconds: &[u8], value: &[u8]) -> Frame {
+        let ttl = match parse_expire("PX", milliseconds, "psetex") {
+            Ok(ttl) => ttl,
+            Err(e) => return e.into(),
+        };
+        let flags = SetFlags {
+            ttl: Some(ttl),
+            ..SetFlags::default()
+        };
+        self.set(key, value, flags).await
+    }
+
     async fn getdel(&self, key: &[u8]) -> Frame {
         let dm = StringDataModel::new(self.fdb.clone());
         let val = match dm.get(key).await {
             Ok(v) => v,
-            Err(e) => return Frame::Error(e.to_string()),
+            Err(e) => return e.into(),
         };
-        if let Err(e) = dm.delete(key).await { return Frame::Error(e.to_string()) }
+        if let Err(e) = dm.remove(&[key], false).await { return e.into() }
         match val {
             Some(val) => Frame::BulkString(val),
             None => Frame::Null,
@@ -57,7 +127,7 @@ impl StringOperations for RedisGateway {
         let dm = StringDataModel::new(self.fdb.clone());
         match dm.atomic_add(key, 1).await {
             Ok(n) => Frame::Integer(n),
-            Err(e) => Frame::Error(e),
+            Err(e) => e.into(),
         }
     }
 
@@ -65,7 +135,7 @@ impl StringOperations for RedisGateway {
         let dm = StringDataModel::new(self.fdb.clone());
         match dm.atomic_add(key, -1).await {
             Ok(n) => Frame::Integer(n),
-            Err(e) => Frame::Error(e),
+            Err(e) => e.into(),
         }
     }
 
@@ -74,13 +144,13 @@ impl StringOperations for RedisGateway {
             .ok()
             .and_then(|s| s.parse::<i64>().ok())
         {
-            None => return Frame::Error("ERR value is not an integer or out of range".into()),
+            None => return Error::NotAnInteger.into(),
             Some(i) => i,
         };
         let dm = StringDataModel::new(self.fdb.clone());
         match dm.atomic_add(key, int).await {
             Ok(n) => Frame::Integer(n),
-            Err(e) => Frame::Error(e),
+            Err(e) => e.into(),
         }
     }
 
@@ -89,33 +159,162 @@ impl StringOperations for RedisGateway {
             .ok()
             .and_then(|s| s.parse::<i64>().ok())
         {
-            None => return Frame::Error("ERR value is not an integer or out of range".into()),
+            None => return Error::NotAnInteger.into(),
             Some(i) => i,
         };
+        let Some(delta) = int.checked_neg() else {
+            return Error::Overflow.into();
+        };
         let dm = StringDataModel::new(self.fdb.clone());
-        match dm.atomic_add(key, -int).await {
+        match dm.atomic_add(key, delta).await {
             Ok(n) => Frame::Integer(n),
-            Err(e) => Frame::Error(e),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn incr_by_float(&self, key: &[u8], increment: &[u8]) -> Frame {
+        let Some(delta) = parse_float(increment) else {
+            return Error::NotAFloat.into();
+        };
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.incr_by_float(key, delta).await {
+            Ok(val) => Frame::BulkString(val),
+            Err(e) => e.into(),
         }
     }
 
     async fn append(&self, key: &[u8], value: &[u8]) -> Frame {
         let dm = StringDataModel::new(self.fdb.clone());
-        let current = match dm.get(key).await {
-            Ok(v) => v,
-            Err(e) => return Frame::Error(e.to_string()),
+        match dm.append(key, value).await {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn strlen(&self, key: &[u8]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.strlen(key).await {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn getrange(&self, key: &[u8], start: &[u8], end: &[u8]) -> Frame {
+        let (Some(start), Some(end)) = (parse_i64(start), parse_i64(end)) else {
+            return Error::NotAnInteger.into();
         };
-        let mut new_value = Vec::new();
-        if let Some(existing) = current {
-            new_value.extend_from_slice(&existing);
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.get_range(key, start, end).await {
+            Ok(bytes) => Frame::BulkString(bytes),
+            Err(e) => e.into(),
         }
-        new_value.extend_from_slice(value);
-        let len = new_value.len();
-        let dm2 = StringDataModel::new(self.fdb.clone());
-        if let Err(e) = dm2.set(key, &new_value, SetFlags::default()).await
-        {
-            return Frame::Error(e.to_string());
+    }
+
+    async fn setrange(&self, key: &[u8], offset: &[u8], value: &[u8]) -> Frame {
+        let Some(offset) = parse_i64(offset) else {
+            return Error::NotAnInteger.into();
+        };
+        let Ok(offset) = u64::try_from(offset) else {
+            return Error::Other("offset is out of range".to_string()).into();
+        };
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.set_range(key, offset, value).await {
+            Ok(len) => Frame::Integer(len as i64),
+            Err(e) => e.into(),
         }
-        Frame::Integer(len as i64)
     }
+
+    async fn lcs(&self, key1: &[u8], key2: &[u8], flags: LcsFlags) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        let (a, b) = match (dm.get(key1).await, dm.get(key2).await) {
+            (Ok(a), Ok(b)) => (a.unwrap_or_default(), b.unwrap_or_default()),
+            (Err(e), _) | (_, Err(e)) => return e.into(),
+        };
+        if lcs::table_size(a.len(), b.len()) > fdb::MAX_VALUE_LEN as u64 {
+            return Error::Other(
+                "Insufficient memory, transient memory for LCS exceeds proto-max-bulk-len".to_string(),
+            )
+            .into();
+        }
+        // The table is filled off the runtime, being up to MAX_VALUE_LEN bytes
+        let found = match tokio::task::spawn_blocking(move || lcs::lcs(&a, &b)).await {
+            Ok(found) => found,
+            Err(e) => return Error::Other(e.to_string()).into(),
+        };
+
+        let len = Frame::Integer(found.subsequence.len() as i64);
+        if flags.len {
+            return len;
+        }
+        if !flags.idx {
+            return Frame::BulkString(found.subsequence);
+        }
+        let matches = found
+            .matches
+            .iter()
+            .filter(|m| m.length() >= flags.min_match_len)
+            .map(|m| {
+                let range = |(start, end): (u64, u64)| {
+                    Frame::Array(vec![Frame::Integer(start as i64), Frame::Integer(end as i64)])
+                };
+                let mut entry = vec![range(m.a), range(m.b)];
+                if flags.with_match_len {
+                    entry.push(Frame::Integer(m.length() as i64));
+                }
+                Frame::Array(entry)
+            })
+            .collect();
+        Frame::Array(vec![
+            Frame::BulkString(b"matches".to_vec()),
+            Frame::Array(matches),
+            Frame::BulkString(b"len".to_vec()),
+            len,
+        ])
+    }
+
+    async fn mget(&self, keys: &[&[u8]]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.mget(keys).await {
+            Ok(values) => Frame::Array(
+                values
+                    .into_iter()
+                    .map(|v| v.map_or(Frame::Null, Frame::BulkString))
+                    .collect(),
+            ),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn mset(&self, pairs: &[(&[u8], &[u8])]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.mset(pairs).await {
+            Ok(()) => Frame::SimpleString("OK".to_string().into_bytes()),
+            Err(e) => e.into(),
+        }
+    }
+
+    async fn msetnx(&self, pairs: &[(&[u8], &[u8])]) -> Frame {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.msetnx(pairs).await {
+            Ok(written) => Frame::Integer(written as i64),
+            Err(e) => e.into(),
+        }
+    }
+}
+
+impl RedisGateway {
+    /// GET whose value is streamed to the client instead of buffered.
+    pub async fn get_stream(&self, key: &[u8]) -> Reply {
+        let dm = StringDataModel::new(self.fdb.clone());
+        match dm.get_stream(key).await {
+            Ok(Some(stream)) => Reply::Bulk(stream),
+            Ok(None) => Frame::Null.into(),
+            Err(e) => Frame::from(e).into(),
+        }
+    }
+}
+
+/// Parse a command argument holding a decimal `i64`.
+fn parse_i64(arg: &[u8]) -> Option<i64> {
+    std::str::from_utf8(arg).ok()?.parse().ok()
 }
@@ -1,7 +1,7 @@
 pub mod commands;
 pub mod gateway;
 pub mod operations;
-mod datamodel;
+pub(crate) mod datamodel;
 
 #[cfg(test)]
 pub mod tests;
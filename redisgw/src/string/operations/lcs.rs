@@ -0,0 +1,88 @@
+/// A run of bytes common to both strings, as inclusive byte ranges.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LcsMatch {
+    pub a: (u64, u64),
+    pub b: (u64, u64),
+}
+
+impl LcsMatch {
+    pub fn length(&self) -> u64 {
+        self.a.1 - self.a.0 + 1
+    }
+}
+
+/// Longest common subsequence of two strings.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Lcs {
+    pub subsequence: Vec<u8>,
+    /// Runs making up the subsequence, from the last to the first as Redis
+    /// lists them.
+    pub matches: Vec<LcsMatch>,
+}
+
+/// Bytes of the table `lcs` fills for strings of these lengths, which Redis
+/// caps at `proto-max-bulk-len`.
+pub fn table_size(a_len: usize, b_len: usize) -> u64 {
+    (a_len as u64 + 1) * (b_len as u64 + 1) * std::mem::size_of::<u32>() as u64
+}
+
+/// Compute the longest common subsequence of `a` and `b` with the dynamic
+/// programming table Redis uses, walking it back the same way so ties
+/// resolve to the same subsequence and matches.
+pub fn lcs(a: &[u8], b: &[u8]) -> Lcs {
+    let width = b.len() + 1;
+    // table[i * width + j] is the LCS length of a[..i] and b[..j]
+    let mut table = vec![0u32; (a.len() + 1) * width];
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            table[i * width + j] = if a[i - 1] == b[j - 1] {
+                table[(i - 1) * width + j - 1] + 1
+            } else {
+                table[(i - 1) * width + j].max(table[i * width + j - 1])
+            };
+        }
+    }
+
+    let mut subsequence = vec![0u8; table[a.len() * width + b.len()] as usize];
+    let mut matches = Vec::new();
+    let (mut i, mut j, mut idx) = (a.len(), b.len(), subsequence.len());
+    // Run being walked back, if any
+    let mut run: Option<LcsMatch> = None;
+    while i > 0 && j > 0 {
+        let mut emit = false;
+        if a[i - 1] == b[j - 1] {
+            subsequence[idx - 1] = a[i - 1];
+            match &mut run {
+                None => {
+                    run = Some(LcsMatch {
+                        a: (i as u64 - 1, i as u64 - 1),
+                        b: (j as u64 - 1, j as u64 - 1),
+                    })
+                }
+                Some(m) if m.a.0 == i as u64 && m.b.0 == j as u64 => {
+                    m.a.0 -= 1;
+                    m.b.0 -= 1;
+                }
+                Some(_) => emit = true,
+            }
+            // Nothing matches before the first byte of either string
+            if run.as_ref().is_some_and(|m| m.a.0 == 0 || m.b.0 == 0) {
+                emit = true;
+            }
+            idx -= 1;
+            i -= 1;
+            j -= 1;
+        } else {
+            if table[(i - 1) * width + j] > table[i * width + j - 1] {
+                i -= 1;
+            } else {
+                j -= 1;
+            }
+            emit = run.is_some();
+        }
+        if emit {
+            matches.extend(run.take());
+        }
+    }
+    Lcs { subsequence, matches }
+}
@@ -1,7 +1,8 @@
 pub mod types;
 pub mod parse;
 pub mod traits;
+pub mod lcs;
 
 pub use types::*;
 pub use traits::*;
-pub(crate) use parse::parse_set_extra_args;
+pub(crate) use parse::{parse_expire, parse_getex_args, parse_lcs_args, parse_set_extra_args};
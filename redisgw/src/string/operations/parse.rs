@@ -1,47 +1,145 @@
-use crate::string::operations::{SetFlags, SetMethod, SetTTL};
+use crate::error::{Error, Result};
+use crate::string::operations::{GetExTTL, LcsFlags, SetFlags, SetMethod, SetTTL};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 /// Parse extra arguments for the SET command (NX/XX/GET/EX/PX/EXAT/PXAT/KEEPTTL)
-/// Returns a `SetFlags` value representing parsed options.
-pub(crate) fn parse_set_extra_args(extra_args: &[&[u8]]) -> SetFlags {
-    if extra_args.is_empty() {
-        return SetFlags::default();
-    }
-
+/// Returns a `SetFlags` value representing parsed options, or the error Redis
+/// replies with: unknown or conflicting options are a syntax error, checked
+/// before the expiry itself.
+pub(crate) fn parse_set_extra_args(extra_args: &[&[u8]]) -> Result<SetFlags> {
     let mut method: Option<SetMethod> = None;
-    let mut ttl: Option<SetTTL> = None;
+    // The last expiry option with its value; repeating an option is allowed,
+    // mixing two is not
+    let mut expiry: Option<(String, Option<&[u8]>)> = None;
     let mut get: bool = false;
 
-    let mut args = extra_args.iter().peekable();
+    let mut args = extra_args.iter();
     while let Some(arg) = args.next() {
-        let s = match std::str::from_utf8(arg) {
-            Ok(s) => s.to_ascii_uppercase(),
-            Err(_) => continue,
-        };
-        match s.as_str() {
-            "NX" => method = Some(SetMethod::NX),
-            "XX" => method = Some(SetMethod::XX),
-            "GET" => get = true,
-            "EX" | "PX" | "EXAT" | "PXAT" => {
-                let next = match args.next() {
-                    Some(n) => n,
-                    None => continue,
+        let option = std::str::from_utf8(arg)
+            .map_err(|_| Error::Syntax)?
+            .to_ascii_uppercase();
+        match option.as_str() {
+            "NX" | "XX" => {
+                let requested = if option == "NX" {
+                    SetMethod::NX
+                } else {
+                    SetMethod::XX
                 };
-                let n = match std::str::from_utf8(next).ok().and_then(|s| s.parse::<u64>().ok()) {
-                    Some(val) => val,
-                    None => continue,
+                if method.as_ref().is_some_and(|m| *m != requested) {
+                    return Err(Error::Syntax);
+                }
+                method = Some(requested);
+            }
+            "GET" => get = true,
+            "KEEPTTL" | "EX" | "PX" | "EXAT" | "PXAT" => {
+                let value = match option.as_str() {
+                    "KEEPTTL" => None,
+                    _ => Some(*args.next().ok_or(Error::Syntax)?),
                 };
-                ttl = Some(match s.as_str() {
-                    "EX" => SetTTL::Ex(n),
-                    "PX" => SetTTL::Px(n),
-                    "EXAT" => SetTTL::ExAt(n),
-                    "PXAT" => SetTTL::PxAt(n),
-                    _ => unreachable!(),
-                });
+                if expiry.as_ref().is_some_and(|(seen, _)| *seen != option) {
+                    return Err(Error::Syntax);
+                }
+                expiry = Some((option, value));
             }
-            "KEEPTTL" => ttl = Some(SetTTL::KeepTTL),
-            _ => {}
+            _ => return Err(Error::Syntax),
         }
     }
 
-    SetFlags { method, ttl, get }
+    let ttl = match expiry {
+        None => None,
+        Some((_, None)) => Some(SetTTL::KeepTTL),
+        Some((option, Some(value))) => Some(parse_expire(&option, value, "set")?),
+    };
+    Ok(SetFlags { method, ttl, get })
+}
+
+/// Parse the expiry given to `command` with `option`, one of EX, PX, EXAT
+/// and PXAT. Expiries must be positive and fit in an `i64` of milliseconds
+/// once made absolute.
+pub(crate) fn parse_expire(option: &str, value: &[u8], command: &str) -> Result<SetTTL> {
+    let n = std::str::from_utf8(value)
+        .ok()
+        .and_then(|s| s.parse::<i64>().ok())
+        .ok_or(Error::NotAnInteger)?;
+    let invalid = || Error::Other(format!("invalid expire time in '{}' command", command));
+    if n <= 0 {
+        return Err(invalid());
+    }
+    let ms = match option {
+        "EX" | "EXAT" => n.checked_mul(1000).ok_or_else(invalid)?,
+        _ => n,
+    };
+    if matches!(option, "EX" | "PX") {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0);
+        ms.checked_add(now).ok_or_else(invalid)?;
+    }
+    let n = n as u64;
+    Ok(match option {
+        "EX" => SetTTL::Ex(n),
+        "PX" => SetTTL::Px(n),
+        "EXAT" => SetTTL::ExAt(n),
+        _ => SetTTL::PxAt(n),
+    })
+}
+
+/// Parse the options of GETEX (EX/PX/EXAT/PXAT/PERSIST).
+/// Returns `None` when the expiry is left as is.
+pub(crate) fn parse_getex_args(extra_args: &[&[u8]]) -> Result<Option<GetExTTL>> {
+    // The last option with its value; repeating an option is allowed,
+    // mixing two is not
+    let mut expiry: Option<(String, Option<&[u8]>)> = None;
+    let mut args = extra_args.iter();
+    while let Some(arg) = args.next() {
+        let option = std::str::from_utf8(arg)
+            .map_err(|_| Error::Syntax)?
+            .to_ascii_uppercase();
+        let value = match option.as_str() {
+            "PERSIST" => None,
+            "EX" | "PX" | "EXAT" | "PXAT" => Some(*args.next().ok_or(Error::Syntax)?),
+            _ => return Err(Error::Syntax),
+        };
+        if expiry.as_ref().is_some_and(|(seen, _)| *seen != option) {
+            return Err(Error::Syntax);
+        }
+        expiry = Some((option, value));
+    }
+    Ok(match expiry {
+        None => None,
+        Some((_, None)) => Some(GetExTTL::Persist),
+        Some((option, Some(value))) => Some(GetExTTL::Set(parse_expire(&option, value, "getex")?)),
+    })
+}
+
+/// Parse the options of LCS (LEN/IDX/MINMATCHLEN/WITHMATCHLEN).
+pub(crate) fn parse_lcs_args(extra_args: &[&[u8]]) -> Result<LcsFlags> {
+    let mut flags = LcsFlags::default();
+    let mut args = extra_args.iter();
+    while let Some(arg) = args.next() {
+        let option = std::str::from_utf8(arg)
+            .map_err(|_| Error::Syntax)?
+            .to_ascii_uppercase();
+        match option.as_str() {
+            "LEN" => flags.len = true,
+            "IDX" => flags.idx = true,
+            "WITHMATCHLEN" => flags.with_match_len = true,
+            "MINMATCHLEN" => {
+                let value = args.next().ok_or(Error::Syntax)?;
+                let n = std::str::from_utf8(value)
+                    .ok()
+                    .and_then(|s| s.parse::<i64>().ok())
+                    .ok_or(Error::NotAnInteger)?;
+                flags.min_match_len = n.max(0) as u64;
+            }
+            _ => return Err(Error::Syntax),
+        }
+    }
+    if flags.len && flags.idx {
+        return Err(Error::Other(
+            "If you want both the length and indexes, please just use IDX.".to_string(),
+        ));
+    }
+    Ok(flags)
 }
@@ -7,8 +7,32 @@ pub trait StringOperations {
     /// Gets the value of a key.
     fn get(&self, key: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
 
-    /// Deletes a key.
-    fn del(&self, key: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+    /// Deletes keys, returning how many existed.
+    fn del(&self, keys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Counts the keys that exist, a key given twice counting twice.
+    fn exists(&self, keys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Counts the keys that exist, as access times are not tracked.
+    fn touch(&self, keys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Deletes keys like `del`, clearing large values in the background.
+    fn unlink(&self, keys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Gets the value of a key and changes its expiry.
+    fn getex(&self, key: &[u8], ttl: Option<super::types::GetExTTL>) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Sets the value of a key, returning its previous value.
+    fn getset(&self, key: &[u8], value: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Sets the value of a key, only if it does not exist.
+    fn setnx(&self, key: &[u8], value: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Sets the value of a key, expiring it after the given seconds.
+    fn setex(&self, key: &[u8], seconds: &[u8], value: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Sets the value of a key, expiring it after the given milliseconds.
+    fn psetex(&self, key: &[u8], milliseconds: &[u8], value: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
 
     /// Gets the value of a key and deletes it.
     fn getdel(&self, key: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
@@ -25,6 +49,30 @@ pub trait StringOperations {
     /// Decrements the integer value of a key by the given amount.
     fn decr_by(&self, key: &[u8], decrement: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
 
+    /// Increments the float value of a key by the given amount.
+    fn incr_by_float(&self, key: &[u8], increment: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
     /// Appends a value to a key.
     fn append(&self, key: &[u8], value: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Gets the length of the value of a key.
+    fn strlen(&self, key: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Gets a substring of the value of a key, between two inclusive offsets.
+    fn getrange(&self, key: &[u8], start: &[u8], end: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Overwrites part of the value of a key from the given offset.
+    fn setrange(&self, key: &[u8], offset: &[u8], value: &[u8]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Finds the longest common subsequence of the values of two keys.
+    fn lcs(&self, key1: &[u8], key2: &[u8], flags: super::types::LcsFlags) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Gets the values of several keys at once.
+    fn mget(&self, keys: &[&[u8]]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Sets several keys at once.
+    fn mset(&self, pairs: &[(&[u8], &[u8])]) -> impl std::future::Future<Output = Frame> + Send;
+
+    /// Sets several keys at once, only if none of them exists.
+    fn msetnx(&self, pairs: &[(&[u8], &[u8])]) -> impl std::future::Future<Output = Frame> + Send;
 }
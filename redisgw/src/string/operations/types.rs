@@ -45,3 +45,25 @@ pub enum SetMethod {
     /// Only set the key if it already exists.
     XX,
 }
+
+/// Expiry change requested by GETEX.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GetExTTL {
+    /// Set a new expiry, as SET does.
+    Set(SetTTL),
+    /// Remove the expiry.
+    Persist,
+}
+
+/// Options of the LCS command.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LcsFlags {
+    /// Reply with the length of the common subsequence only.
+    pub len: bool,
+    /// Reply with the ranges of the matches.
+    pub idx: bool,
+    /// Shortest match listed with `idx`.
+    pub min_match_len: u64,
+    /// List the length of each match with `idx`.
+    pub with_match_len: bool,
+}
@@ -45,3 +45,92 @@ async fn test_e2e_set_get_via_tcp() {
     drop(stream);
     srv_handle.abort();
 }
+
+#[tokio::test]
+async fn test_e2e_get_streams_large_value() {
+    crate::with_e2e_server!(srv_handle, stream);
+    let mut stream = stream.expect("stream");
+
+    // Several chunks long, so GET is streamed chunk by chunk
+    let value = (0..1_000_000u32).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let req_set = Frame::Array(vec![
+        Frame::BulkString(b"SET".to_vec()),
+        Frame::BulkString(b"e2e_large_key".to_vec()),
+        Frame::BulkString(value.clone()),
+    ]);
+    let mut out = vec![0u8; req_set.encode_len(false)];
+    let _ = encode(&mut out, &req_set, false);
+    stream.write_all(&out).await.expect("write set");
+
+    let mut buf = vec![0u8; 1024];
+    let n = stream.read(&mut buf).await.expect("read");
+    let (frame, _used) = decode(&buf[..n]).expect("decode").expect("frame");
+    assert!(matches!(frame, Frame::SimpleString(s) if s == b"OK".to_vec()));
+
+    let req_get = Frame::Array(vec![
+        Frame::BulkString(b"GET".to_vec()),
+        Frame::BulkString(b"e2e_large_key".to_vec()),
+    ]);
+    let mut out = vec![0u8; req_get.encode_len(false)];
+    let _ = encode(&mut out, &req_get, false);
+    stream.write_all(&out).await.expect("write get");
+
+    // Read until the whole bulk string has arrived
+    let mut resp = Vec::new();
+    let frame = loop {
+        let n = stream.read(&mut buf).await.expect("read2");
+        assert!(n > 0, "connection closed mid-reply");
+        resp.extend_from_slice(&buf[..n]);
+        if let Some((frame, _used)) = decode(&resp).expect("decode2") {
+            break frame;
+        }
+    };
+    assert!(matches!(frame, Frame::BulkString(s) if s == value));
+
+    drop(stream);
+    srv_handle.abort();
+}
+
+#[tokio::test]
+async fn test_e2e_set_rejects_bad_options() {
+    crate::with_e2e_server!(srv_handle, stream);
+    let mut stream = stream.expect("stream");
+
+    let mut buf = vec![0u8; 1024];
+    let cases = [
+        ("NX XX", "ERR syntax error"),
+        ("EX 0", "ERR invalid expire time in 'set' command"),
+        ("FOO", "ERR syntax error"),
+    ];
+    for (options, expected) in cases {
+        let mut req = vec![
+            Frame::BulkString(b"SET".to_vec()),
+            Frame::BulkString(b"e2e_bad_options".to_vec()),
+            Frame::BulkString(b"v".to_vec()),
+        ];
+        req.extend(options.split_whitespace().map(|o| Frame::BulkString(o.as_bytes().to_vec())));
+        let req = Frame::Array(req);
+        let mut out = vec![0u8; req.encode_len(false)];
+        let _ = encode(&mut out, &req, false);
+        stream.write_all(&out).await.expect("write set");
+
+        let n = stream.read(&mut buf).await.expect("read");
+        let (frame, _used) = decode(&buf[..n]).expect("decode").expect("frame");
+        assert!(matches!(frame, Frame::Error(ref e) if e == expected), "{:?}", frame);
+    }
+
+    // nothing was written
+    let req_get = Frame::Array(vec![
+        Frame::BulkString(b"GET".to_vec()),
+        Frame::BulkString(b"e2e_bad_options".to_vec()),
+    ]);
+    let mut out = vec![0u8; req_get.encode_len(false)];
+    let _ = encode(&mut out, &req_get, false);
+    stream.write_all(&out).await.expect("write get");
+    let n = stream.read(&mut buf).await.expect("read2");
+    let (frame, _used) = decode(&buf[..n]).expect("decode2").expect("frame2");
+    assert!(matches!(frame, Frame::Null));
+
+    drop(stream);
+    srv_handle.abort();
+}
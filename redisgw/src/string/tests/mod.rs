@@ -5,6 +5,7 @@ mod e2e {
 
 #[cfg(test)]
 mod unit {
+    pub mod parse;
     pub mod string;
     pub mod ttl;
 }
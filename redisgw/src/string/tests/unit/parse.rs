@@ -0,0 +1,85 @@
+use crate::string::operations::{SetFlags, SetMethod, SetTTL, parse_set_extra_args};
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+/// Parse the space separated SET options in `options`.
+fn parse(options: &str) -> Result<SetFlags, Frame> {
+    let args: Vec<&[u8]> = options.split_whitespace().map(str::as_bytes).collect();
+    parse_set_extra_args(&args).map_err(Frame::from)
+}
+
+fn flags(method: Option<SetMethod>, ttl: Option<SetTTL>, get: bool) -> SetFlags {
+    SetFlags { method, ttl, get }
+}
+
+#[test]
+fn test_parse_set_extra_args_accepted() {
+    let pxat_max = format!("PXAT {}", i64::MAX);
+    let cases = [
+        ("", SetFlags::default()),
+        ("nx", flags(Some(SetMethod::NX), None, false)),
+        ("XX", flags(Some(SetMethod::XX), None, false)),
+        ("NX NX", flags(Some(SetMethod::NX), None, false)),
+        ("GET", flags(None, None, true)),
+        ("NX GET", flags(Some(SetMethod::NX), None, true)),
+        ("GET XX", flags(Some(SetMethod::XX), None, true)),
+        ("EX 10", flags(None, Some(SetTTL::Ex(10)), false)),
+        ("px 1500", flags(None, Some(SetTTL::Px(1500)), false)),
+        (
+            "EXAT 1700000000",
+            flags(None, Some(SetTTL::ExAt(1_700_000_000)), false),
+        ),
+        (
+            pxat_max.as_str(),
+            flags(None, Some(SetTTL::PxAt(i64::MAX as u64)), false),
+        ),
+        ("EX 10 EX 20", flags(None, Some(SetTTL::Ex(20)), false)),
+        ("KEEPTTL", flags(None, Some(SetTTL::KeepTTL), false)),
+        (
+            "XX PX 100 GET",
+            flags(Some(SetMethod::XX), Some(SetTTL::Px(100)), true),
+        ),
+    ];
+    for (options, expected) in cases {
+        assert_eq!(parse(options), Ok(expected), "options {:?}", options);
+    }
+}
+
+#[test]
+fn test_parse_set_extra_args_rejected() {
+    let syntax = Frame::Error("ERR syntax error".into());
+    let invalid = Frame::Error("ERR invalid expire time in 'set' command".into());
+    let not_an_integer = Frame::Error("ERR value is not an integer or out of range".into());
+    let (ex_max, px_max, exat_max) = (
+        format!("EX {}", i64::MAX),
+        format!("PX {}", i64::MAX),
+        format!("EXAT {}", i64::MAX),
+    );
+    let cases = [
+        ("FOO", &syntax),
+        ("NX XX", &syntax),
+        ("XX NX", &syntax),
+        ("EX", &syntax),
+        ("EX 10 PX 100", &syntax),
+        ("KEEPTTL EX 10", &syntax),
+        ("PXAT 100 KEEPTTL", &syntax),
+        // options are checked before the expiry
+        ("EX abc FOO", &syntax),
+        ("EX 0", &invalid),
+        ("PX -100", &invalid),
+        ("EXAT 0", &invalid),
+        (ex_max.as_str(), &invalid),
+        (px_max.as_str(), &invalid),
+        (exat_max.as_str(), &invalid),
+        ("EX abc", &not_an_integer),
+        ("PX 1.5", &not_an_integer),
+    ];
+    for (options, expected) in cases {
+        assert_eq!(parse(options), Err(expected.clone()), "options {:?}", options);
+    }
+
+    let non_utf8: &[u8] = b"\xff";
+    assert_eq!(
+        parse_set_extra_args(&[non_utf8]).map_err(Frame::from),
+        Err(syntax)
+    );
+}
@@ -1,21 +1,23 @@
 use crate::gateway::RedisGateway;
-use crate::string::operations::{StringOperations, SetFlags, SetTTL, SetMethod};
+use crate::string::operations::{StringOperations, SetFlags, SetTTL, SetMethod, GetExTTL, LcsFlags};
 use fdb::FoundationDB;
-use fdb_testcontainer::get_db_once;
 use redis_protocol::resp2::types::OwnedFrame as Frame;
 use tokio::time::{sleep, Duration};
 use futures::future::join_all;
+use crate::string::datamodel::SimpleDataPrefix;
+use foundationdb::RangeOption;
+use foundationdb_tuple::pack;
+use fdb::codec::{Codec, Compression};
 
 #[tokio::test]
 async fn test_insert_record() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db);
 
     let _ = gw.set(b"key", b"value", SetFlags::default()).await;
     let result = gw.get(b"key").await;
     assert_eq!(result, Frame::BulkString(b"value".to_vec()));
-    let _ = gw.del(b"key").await;
+    let _ = gw.del(&[b"key"]).await;
     let result = gw.get(b"key").await;
     assert_eq!(result, Frame::Null);
 
@@ -28,8 +30,7 @@ async fn test_insert_record() {
 
 #[tokio::test]
 async fn test_increment_decrement_record() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gateway = RedisGateway::new(db);
     let key = b"counter";
     gateway.set(key, b"0", SetFlags::default()).await;
@@ -55,13 +56,12 @@ async fn test_increment_decrement_record() {
     let val = gateway.decr(key).await;
     assert_eq!(val, Frame::Integer(99));
 
-    gateway.del(key).await;
+    gateway.del(&[key]).await;
 }
 
 #[tokio::test]
 async fn test_increment_decrement_by_record() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gateway = RedisGateway::new(db);
     let key = b"counter";
     gateway.set(key, b"0", SetFlags::default()).await;
@@ -77,23 +77,73 @@ async fn test_increment_decrement_by_record() {
     assert_eq!(val, Frame::Integer(-1));
 }
 
+#[tokio::test]
+async fn test_counter_rendering() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    // A fresh counter and an integer literal both read back as decimal
+    let _ = gw.del(&[b"ctr_fresh"]).await;
+    assert_eq!(gw.incr_by(b"ctr_fresh", b"-42").await, Frame::Integer(-42));
+    assert_eq!(gw.get(b"ctr_fresh").await, Frame::BulkString(b"-42".to_vec()));
+    assert_eq!(gw.del(&[b"ctr_fresh"]).await, Frame::Integer(1));
+    assert_eq!(gw.get(b"ctr_fresh").await, Frame::Null);
+
+    let _ = gw.set(b"ctr_lit", b"9000", SetFlags::default()).await;
+    assert_eq!(gw.get(b"ctr_lit").await, Frame::BulkString(b"9000".to_vec()));
+    assert_eq!(gw.incr(b"ctr_lit").await, Frame::Integer(9001));
+
+    // A string value replaces the counter, and a non-canonical integer stays a string
+    let _ = gw.set(b"ctr_lit", b"007", SetFlags::default()).await;
+    assert_eq!(gw.get(b"ctr_lit").await, Frame::BulkString(b"007".to_vec()));
+    assert_eq!(gw.incr(b"ctr_lit").await, Frame::Integer(8));
+    assert_eq!(gw.get(b"ctr_lit").await, Frame::BulkString(b"8".to_vec()));
+
+    let _ = gw.set(b"ctr_lit", b"abc", SetFlags::default()).await;
+    assert_eq!(gw.get(b"ctr_lit").await, Frame::BulkString(b"abc".to_vec()));
+    assert_eq!(
+        gw.incr(b"ctr_lit").await,
+        Frame::Error("ERR value is not an integer or out of range".into())
+    );
+    let _ = gw.del(&[b"ctr_lit"]).await;
+}
+
+#[tokio::test]
+async fn test_counter_overflow() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+    let overflow = Frame::Error("ERR increment or decrement would overflow".into());
+
+    let max = i64::MAX.to_string();
+    let _ = gw.set(b"ctr_max", max.as_bytes(), SetFlags::default()).await;
+    assert_eq!(gw.incr(b"ctr_max").await, overflow);
+    // The failed increment leaves the value untouched
+    assert_eq!(gw.get(b"ctr_max").await, Frame::BulkString(max.into_bytes()));
+
+    let _ = gw.set(b"ctr_min", b"-1", SetFlags::default()).await;
+    assert_eq!(gw.decr_by(b"ctr_min", i64::MAX.to_string().as_bytes()).await, Frame::Integer(i64::MIN));
+    assert_eq!(gw.decr(b"ctr_min").await, overflow);
+    assert_eq!(gw.decr_by(b"ctr_min", i64::MIN.to_string().as_bytes()).await, overflow);
+
+    let _ = gw.del(&[b"ctr_max"]).await;
+    let _ = gw.del(&[b"ctr_min"]).await;
+}
+
 #[tokio::test]
 async fn test_set_overwrite() {
-    let _guard = fdb_testcontainer::get_db_once().await;
-    let db = fdb::FoundationDB::new(_guard.clone());
+    let db = fdb::FoundationDB::in_memory();
     let gw = crate::gateway::RedisGateway::new(db);
 
     let _ = gw.set(b"ow", b"v1", SetFlags::default()).await;
     let _ = gw.set(b"ow", b"v2", SetFlags::default()).await;
     let res = gw.get(b"ow").await;
     assert_eq!(res, Frame::BulkString(b"v2".to_vec()));
-    let _ = gw.del(b"ow").await;
+    let _ = gw.del(&[b"ow"]).await;
 }
 
 #[tokio::test]
 async fn test_set_nx_xx() {
-    let _guard = fdb_testcontainer::get_db_once().await;
-    let db = fdb::FoundationDB::new(_guard.clone());
+    let db = fdb::FoundationDB::in_memory();
     let gw = crate::gateway::RedisGateway::new(db);
 
     // NX: only set when not exists
@@ -104,7 +154,7 @@ async fn test_set_nx_xx() {
     let res = gw.get(b"nxkey").await;
     assert_eq!(res, Frame::BulkString(b"v1".to_vec()));
 
-    let _ = gw.del(b"nxkey").await;
+    let _ = gw.del(&[b"nxkey"]).await;
 
     // XX: only set when exists
     let flags_xx = SetFlags { method: Some(SetMethod::XX), ttl: None, get: false };
@@ -118,60 +168,139 @@ async fn test_set_nx_xx() {
     let _ = gw.set(b"xxkey", b"v1", flags_xx).await;
     let res = gw.get(b"xxkey").await;
     assert_eq!(res, Frame::BulkString(b"v1".to_vec()));
-    let _ = gw.del(b"xxkey").await;
+    let _ = gw.del(&[b"xxkey"]).await;
 }
 
 #[tokio::test]
 async fn test_del_multiple() {
-    let _guard = fdb_testcontainer::get_db_once().await;
-    let db = fdb::FoundationDB::new(_guard.clone());
+    let db = fdb::FoundationDB::in_memory();
     let gw = crate::gateway::RedisGateway::new(db);
 
     let _ = gw.set(b"d1", b"a", SetFlags::default()).await;
     let _ = gw.set(b"d2", b"b", SetFlags::default()).await;
     let _ = gw.set(b"d3", b"c", SetFlags::default()).await;
 
-    // del returns integer frame with number of deleted keys
-    let res = gw.del(b"d1").await;
-    // del currently implemented to return Integer(1) on success
-    assert!(matches!(res, Frame::Integer(_)));
-    let _ = gw.del(b"d2").await;
-    let _ = gw.del(b"d3").await;
+    let _ = gw.incr(b"d_counter").await;
+
+    // del returns the number of keys that existed, each counted once
+    let res = gw.del(&[b"d1", b"d2", b"d_missing", b"d1", b"d_counter"]).await;
+    assert_eq!(res, Frame::Integer(3));
+    assert_eq!(gw.get(b"d1").await, Frame::Null);
+    assert_eq!(gw.get(b"d_counter").await, Frame::Null);
+    assert_eq!(gw.get(b"d3").await, Frame::BulkString(b"c".to_vec()));
+    assert_eq!(gw.del(&[b"d1", b"d2"]).await, Frame::Integer(0));
+
+    // An expired key is not counted
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(50)), get: false };
+    let _ = gw.set(b"d_expiring", b"v", flags).await;
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(gw.del(&[b"d3", b"d_expiring"]).await, Frame::Integer(1));
+}
+
+#[tokio::test]
+async fn test_exists_touch() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"e1", b"a", SetFlags::default()).await;
+    let _ = gw.incr(b"e_counter").await;
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(50)), get: false };
+    let _ = gw.set(b"e_expiring", b"v", flags).await;
+
+    // A key given twice counts twice, as in Redis
+    let keys: [&[u8]; 5] = [b"e1", b"e1", b"e_counter", b"e_missing", b"e_expiring"];
+    assert_eq!(gw.exists(&keys).await, Frame::Integer(4));
+    assert_eq!(gw.touch(&keys).await, Frame::Integer(4));
+    sleep(Duration::from_millis(100)).await;
+    assert_eq!(gw.exists(&keys).await, Frame::Integer(3));
+    let _ = gw.del(&[b"e1"]).await;
+    assert_eq!(gw.exists(&keys).await, Frame::Integer(1));
+}
+
+#[tokio::test]
+async fn test_unlink() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    // Large enough to be cleared in the background
+    let large = vec![b'u'; 15 * 1000 * 1000];
+    let _ = gw.set(b"u_large", &large, SetFlags::default()).await;
+    let _ = gw.set(b"u_small", b"small", SetFlags::default()).await;
+    let manifest = db
+        .manifest(&pack(&(SimpleDataPrefix::Data, b"u_large".as_slice())))
+        .await
+        .expect("Unable to read manifest")
+        .expect("missing manifest");
+
+    let res = gw.unlink(&[b"u_large", b"u_small", b"u_missing"]).await;
+    assert_eq!(res, Frame::Integer(2));
+    assert_eq!(gw.exists(&[b"u_large", b"u_small"]).await, Frame::Integer(0));
+    assert_eq!(gw.get(b"u_large").await, Frame::Null);
+
+    // The chunks are gone once the background task is done
+    let root = pack(&(SimpleDataPrefix::Data, b"u_large".as_slice()));
+    let (begin, end) = manifest.chunk_range(&root);
+    let mut left = usize::MAX;
+    for _ in 0..50 {
+        left = db
+            .run(|trx, _| {
+                let range = RangeOption::from((begin.clone(), end.clone()));
+                async move { Ok(trx.get_range(&range, false).await?.len()) }
+            })
+            .await
+            .expect("Unable to scan chunks");
+        if left == 0 {
+            break;
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(left, 0);
 }
 
 #[tokio::test]
 async fn test_concurrent_incr() {
     use futures::future::join_all;
-    let _guard = fdb_testcontainer::get_db_once().await;
-    let db = fdb::FoundationDB::new(_guard.clone());
+    let db = fdb::FoundationDB::in_memory();
     let gw = crate::gateway::RedisGateway::new(db.clone());
     let key = b"conc_counter";
     let _ = gw.set(key, b"0", SetFlags::default()).await;
 
+    // Increments never conflict, so none needs a retry
     let tasks: Vec<_> = (0..20)
         .map(|_| {
-            let gw = crate::gateway::RedisGateway::new(db.clone());
+            let gw = crate::gateway::RedisGateway::new(db.clone().with_retry_limit(Some(0)));
             let key = key.to_vec();
             tokio::spawn(async move {
+                let mut replies = Vec::new();
                 for _ in 0..10 {
-                    let _ = gw.incr(&key,).await;
+                    replies.push(gw.incr(&key).await);
                 }
+                replies
             })
         })
         .collect();
 
-    let _ = join_all(tasks).await;
+    let replies: Vec<i64> = join_all(tasks)
+        .await
+        .into_iter()
+        .flat_map(|t| t.expect("task"))
+        .map(|r| match r {
+            Frame::Integer(n) => n,
+            other => panic!("expected an integer, got {:?}", other),
+        })
+        .collect();
+    // Replies come from a snapshot, so concurrent ones may repeat
+    assert!(replies.iter().all(|n| (1..=200).contains(n)));
 
     let res = gw.get(key).await;
     // expect 200 increments
     assert_eq!(res, Frame::BulkString(b"200".to_vec()));
-    let _ = gw.del(key).await;
+    let _ = gw.del(&[key]).await;
 }
 
 #[tokio::test]
 async fn test_set_with_ttl_ex_px() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db);
 
     let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(100)), get: false };
@@ -188,8 +317,7 @@ async fn test_set_with_ttl_ex_px() {
 
 #[tokio::test]
 async fn test_keep_ttl_preserved_on_set() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db);
 
     // set initial key with 300ms TTL
@@ -213,8 +341,7 @@ async fn test_keep_ttl_preserved_on_set() {
 
 #[tokio::test]
 async fn test_append_empty_and_nonempty() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db);
 
     // append to missing key creates it
@@ -233,8 +360,7 @@ async fn test_append_empty_and_nonempty() {
 
 #[tokio::test]
 async fn test_set_get_with_getflag_returns_old_value() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db);
 
     // initial set
@@ -256,8 +382,7 @@ async fn test_set_get_with_getflag_returns_old_value() {
 // of the writers' payloads.
 #[tokio::test]
 async fn test_concurrent_large_writes() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db.clone());
 
     let key = b"concurrent_big_key";
@@ -301,3 +426,417 @@ async fn test_concurrent_large_writes() {
         other => panic!("unexpected frame returned: {:?}", other),
     }
 }
+
+#[tokio::test]
+async fn test_mget_mset() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let res = gw.mset(&[(b"m1", b"one"), (b"m2", b"two"), (b"m1", b"uno")]).await;
+    assert_eq!(res, Frame::SimpleString(b"OK".to_vec()));
+    let _ = gw.incr(b"m_counter").await;
+    let res = gw.mget(&[b"m1", b"m2", b"m_missing", b"m_counter"]).await;
+    assert_eq!(
+        res,
+        Frame::Array(vec![
+            Frame::BulkString(b"uno".to_vec()),
+            Frame::BulkString(b"two".to_vec()),
+            Frame::Null,
+            Frame::BulkString(b"1".to_vec()),
+        ])
+    );
+
+    // MSET replaces counters and clears TTLs, like SET
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(100)), get: false };
+    let _ = gw.set(b"m2", b"short-lived", flags.clone()).await;
+    let _ = gw.set(b"m3", b"short-lived", flags).await;
+    let _ = gw.mset(&[(b"m2", b"kept"), (b"m_counter", b"text")]).await;
+    let _ = gw.set(b"m3", b"kept", SetFlags::default()).await;
+    sleep(Duration::from_millis(200)).await;
+    let res = gw.mget(&[b"m2", b"m_counter", b"m3"]).await;
+    assert_eq!(
+        res,
+        Frame::Array(vec![
+            Frame::BulkString(b"kept".to_vec()),
+            Frame::BulkString(b"text".to_vec()),
+            Frame::BulkString(b"kept".to_vec()),
+        ])
+    );
+
+    // Expired keys read as missing
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(50)), get: false };
+    let _ = gw.set(b"m1", b"gone", flags).await;
+    sleep(Duration::from_millis(100)).await;
+    let res = gw.mget(&[b"m1"]).await;
+    assert_eq!(res, Frame::Array(vec![Frame::Null]));
+}
+
+#[tokio::test]
+async fn test_mset_large_values() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    // Too large together for one transaction: staged, then flipped at once
+    let a = vec![b'a'; 6 * 1000 * 1000];
+    let b = vec![b'b'; 6 * 1000 * 1000];
+    let _ = gw.set(b"big_a", b"old", SetFlags::default()).await;
+    let res = gw.mset(&[(b"big_a", &a), (b"big_b", &b)]).await;
+    assert_eq!(res, Frame::SimpleString(b"OK".to_vec()));
+    let res = gw.mget(&[b"big_a", b"big_b"]).await;
+    assert_eq!(res, Frame::Array(vec![Frame::BulkString(a), Frame::BulkString(b)]));
+}
+
+#[tokio::test]
+async fn test_msetnx() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let res = gw.msetnx(&[(b"nx1", b"one"), (b"nx2", b"two")]).await;
+    assert_eq!(res, Frame::Integer(1));
+
+    // One existing key, even a counter, and nothing is written
+    let _ = gw.incr(b"nx_counter").await;
+    let res = gw.msetnx(&[(b"nx3", b"three"), (b"nx_counter", b"x")]).await;
+    assert_eq!(res, Frame::Integer(0));
+    let res = gw.msetnx(&[(b"nx3", b"three"), (b"nx1", b"x")]).await;
+    assert_eq!(res, Frame::Integer(0));
+    let res = gw.mget(&[b"nx1", b"nx3", b"nx_counter"]).await;
+    assert_eq!(
+        res,
+        Frame::Array(vec![
+            Frame::BulkString(b"one".to_vec()),
+            Frame::Null,
+            Frame::BulkString(b"1".to_vec()),
+        ])
+    );
+
+    // An expired key no longer counts as existing
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(50)), get: false };
+    let _ = gw.set(b"nx_expiring", b"v", flags).await;
+    sleep(Duration::from_millis(100)).await;
+    let res = gw.msetnx(&[(b"nx_expiring", b"fresh"), (b"nx3", b"three")]).await;
+    assert_eq!(res, Frame::Integer(1));
+    assert_eq!(gw.get(b"nx_expiring").await, Frame::BulkString(b"fresh".to_vec()));
+}
+
+/// Value spanning several chunks, no two neighbouring bytes alike.
+fn patterned(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+#[tokio::test]
+async fn test_strlen_getrange() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"gr", b"Hello World", SetFlags::default()).await;
+    assert_eq!(gw.strlen(b"gr").await, Frame::Integer(11));
+    assert_eq!(gw.strlen(b"gr_missing").await, Frame::Integer(0));
+
+    // Offsets as Redis reads them: inclusive, negative from the end, clamped
+    let cases: [(&[u8], &[u8], &[u8]); 7] = [
+        (b"0", b"4", b"Hello"),
+        (b"-5", b"-1", b"World"),
+        (b"6", b"100", b"World"),
+        (b"0", b"-1", b"Hello World"),
+        (b"-100", b"2", b"Hel"),
+        (b"5", b"3", b""),
+        (b"-1", b"-5", b""),
+    ];
+    for (start, end, expected) in cases {
+        assert_eq!(
+            gw.getrange(b"gr", start, end).await,
+            Frame::BulkString(expected.to_vec()),
+            "{:?}..{:?}",
+            start,
+            end
+        );
+    }
+    assert_eq!(gw.getrange(b"gr_missing", b"0", b"-1").await, Frame::BulkString(Vec::new()));
+    assert!(matches!(gw.getrange(b"gr", b"a", b"1").await, Frame::Error(_)));
+
+    // Counters read as their decimal form
+    let _ = gw.incr_by(b"gr_counter", b"12345").await;
+    assert_eq!(gw.strlen(b"gr_counter").await, Frame::Integer(5));
+    assert_eq!(gw.getrange(b"gr_counter", b"1", b"2").await, Frame::BulkString(b"23".to_vec()));
+}
+
+#[tokio::test]
+async fn test_getrange_large_value() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let value = patterned(350_000);
+    let _ = gw.set(b"gr_large", &value, SetFlags::default()).await;
+    assert_eq!(gw.strlen(b"gr_large").await, Frame::Integer(350_000));
+
+    // Within a chunk, across chunk boundaries, and up to the end
+    for (start, end) in [(10, 20), (99_990, 100_009), (50_000, 250_000), (349_990, 349_999)] {
+        let res = gw
+            .getrange(b"gr_large", start.to_string().as_bytes(), end.to_string().as_bytes())
+            .await;
+        assert_eq!(res, Frame::BulkString(value[start..=end].to_vec()));
+    }
+
+    // Compressed values are read whole, then sliced
+    let compressed = FoundationDB::in_memory().with_compression(Compression {
+        codec: Codec::Zstd,
+        min_size: 0,
+    });
+    let gw = RedisGateway::new(compressed);
+    let value = b"compressible ".repeat(20_000);
+    let _ = gw.set(b"gr_zstd", &value, SetFlags::default()).await;
+    assert_eq!(gw.strlen(b"gr_zstd").await, Frame::Integer(value.len() as i64));
+    let res = gw.getrange(b"gr_zstd", b"100000", b"100012").await;
+    assert_eq!(res, Frame::BulkString(value[100_000..=100_012].to_vec()));
+}
+
+#[tokio::test]
+async fn test_setrange() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"sr", b"Hello World", SetFlags::default()).await;
+    assert_eq!(gw.setrange(b"sr", b"6", b"Redis").await, Frame::Integer(11));
+    assert_eq!(gw.get(b"sr").await, Frame::BulkString(b"Hello Redis".to_vec()));
+
+    // Missing keys and gaps are zero-padded
+    assert_eq!(gw.setrange(b"sr_pad", b"3", b"abc").await, Frame::Integer(6));
+    assert_eq!(gw.get(b"sr_pad").await, Frame::BulkString(b"\0\0\0abc".to_vec()));
+    assert_eq!(gw.setrange(b"sr_pad", b"8", b"z").await, Frame::Integer(9));
+    assert_eq!(gw.get(b"sr_pad").await, Frame::BulkString(b"\0\0\0abc\0\0z".to_vec()));
+
+    // An empty value only reports the length, without creating the key
+    assert_eq!(gw.setrange(b"sr", b"100", b"").await, Frame::Integer(11));
+    assert_eq!(gw.setrange(b"sr_none", b"0", b"").await, Frame::Integer(0));
+    assert_eq!(gw.exists(&[b"sr_none"]).await, Frame::Integer(0));
+
+    assert!(matches!(gw.setrange(b"sr", b"-1", b"x").await, Frame::Error(_)));
+    assert!(matches!(gw.setrange(b"sr", b"536870912", b"x").await, Frame::Error(_)));
+
+    // A counter becomes a string; the TTL is kept
+    let _ = gw.incr_by(b"sr_counter", b"100").await;
+    assert_eq!(gw.setrange(b"sr_counter", b"1", b"9").await, Frame::Integer(3));
+    assert_eq!(gw.get(b"sr_counter").await, Frame::BulkString(b"190".to_vec()));
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(100)), get: false };
+    let _ = gw.set(b"sr_expiring", b"value", flags).await;
+    let _ = gw.setrange(b"sr_expiring", b"0", b"V").await;
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(gw.get(b"sr_expiring").await, Frame::Null);
+}
+
+#[tokio::test]
+async fn test_setrange_rewrites_affected_chunks() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+    let root = pack(&(SimpleDataPrefix::Data, b"sr_large".as_slice()));
+
+    let mut value = patterned(350_000);
+    let _ = gw.set(b"sr_large", &value, SetFlags::default()).await;
+    let before = db.manifest(&root).await.expect("Unable to read manifest").expect("missing manifest");
+
+    // Across the boundary of the first two chunks, then past the end
+    let patch = vec![b'x'; 20];
+    assert_eq!(gw.setrange(b"sr_large", b"99990", &patch).await, Frame::Integer(350_000));
+    value[99_990..100_010].copy_from_slice(&patch);
+    assert_eq!(gw.setrange(b"sr_large", b"400000", b"end").await, Frame::Integer(400_003));
+    value.resize(400_000, 0);
+    value.extend_from_slice(b"end");
+    assert_eq!(gw.get(b"sr_large").await, Frame::BulkString(value.clone()));
+
+    // Patched in place: same generation, the untouched chunks left as they were
+    let after = db.manifest(&root).await.expect("Unable to read manifest").expect("missing manifest");
+    assert_eq!(after.generation, before.generation);
+    assert_eq!(after.chunk_count, 5);
+    assert_eq!(after.chunk_checksums[2], before.chunk_checksums[2]);
+    assert_eq!(db.verify(&root).await.expect("Unable to verify"), Some(400_003));
+}
+
+#[tokio::test]
+async fn test_append_large_value() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+    let root = pack(&(SimpleDataPrefix::Data, b"ap_large".as_slice()));
+
+    let mut value = patterned(250_000);
+    let _ = gw.set(b"ap_large", &value, SetFlags::default()).await;
+    let before = db.manifest(&root).await.expect("Unable to read manifest").expect("missing manifest");
+
+    let tail = patterned(60_000);
+    assert_eq!(gw.append(b"ap_large", &tail).await, Frame::Integer(310_000));
+    value.extend_from_slice(&tail);
+    assert_eq!(gw.get(b"ap_large").await, Frame::BulkString(value));
+
+    let after = db.manifest(&root).await.expect("Unable to read manifest").expect("missing manifest");
+    assert_eq!(after.generation, before.generation);
+    assert_eq!(after.chunk_checksums[..2], before.chunk_checksums[..2]);
+    assert_eq!(after.chunk_count, 4);
+
+    // Appending to a counter or an empty key
+    let _ = gw.incr(b"ap_counter").await;
+    assert_eq!(gw.append(b"ap_counter", b"0").await, Frame::Integer(2));
+    assert_eq!(gw.get(b"ap_counter").await, Frame::BulkString(b"10".to_vec()));
+    assert_eq!(gw.append(b"ap_empty", b"").await, Frame::Integer(0));
+    assert_eq!(gw.exists(&[b"ap_empty"]).await, Frame::Integer(1));
+}
+
+#[tokio::test]
+async fn test_getex() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    assert_eq!(gw.getex(b"getex", None).await, Frame::Null);
+    let _ = gw.set(b"getex", b"v", SetFlags::default()).await;
+
+    // a new expiry is set while the value is returned
+    let res = gw.getex(b"getex", Some(GetExTTL::Set(SetTTL::Px(100)))).await;
+    assert_eq!(res, Frame::BulkString(b"v".to_vec()));
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(gw.get(b"getex").await, Frame::Null);
+
+    // PERSIST removes the expiry
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(100)), get: false };
+    let _ = gw.set(b"getex", b"v", flags).await;
+    let res = gw.getex(b"getex", Some(GetExTTL::Persist)).await;
+    assert_eq!(res, Frame::BulkString(b"v".to_vec()));
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(gw.get(b"getex").await, Frame::BulkString(b"v".to_vec()));
+}
+
+#[tokio::test]
+async fn test_getset_setnx() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    assert_eq!(gw.getset(b"gs", b"v1").await, Frame::Null);
+    assert_eq!(gw.getset(b"gs", b"v2").await, Frame::BulkString(b"v1".to_vec()));
+    assert_eq!(gw.get(b"gs").await, Frame::BulkString(b"v2".to_vec()));
+
+    // GETSET discards the TTL of the value it replaces
+    let flags = SetFlags { method: None, ttl: Some(SetTTL::Px(100)), get: false };
+    let _ = gw.set(b"gs_ttl", b"v1", flags).await;
+    assert_eq!(gw.getset(b"gs_ttl", b"v2").await, Frame::BulkString(b"v1".to_vec()));
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(gw.get(b"gs_ttl").await, Frame::BulkString(b"v2".to_vec()));
+
+    assert_eq!(gw.setnx(b"nx", b"v1").await, Frame::Integer(1));
+    assert_eq!(gw.setnx(b"nx", b"v2").await, Frame::Integer(0));
+    assert_eq!(gw.get(b"nx").await, Frame::BulkString(b"v1".to_vec()));
+
+    // SET NX replies with a null when the key exists
+    let flags = SetFlags { method: Some(SetMethod::NX), ttl: None, get: false };
+    assert_eq!(gw.set(b"nx", b"v3", flags).await, Frame::Null);
+}
+
+#[tokio::test]
+async fn test_setex_psetex() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let ok = Frame::SimpleString(b"OK".to_vec());
+    assert_eq!(gw.setex(b"ex", b"100", b"v").await, ok);
+    assert_eq!(gw.psetex(b"px", b"100", b"v").await, ok);
+    assert_eq!(gw.get(b"px").await, Frame::BulkString(b"v".to_vec()));
+    sleep(Duration::from_millis(200)).await;
+    assert_eq!(gw.get(b"px").await, Frame::Null);
+    assert_eq!(gw.get(b"ex").await, Frame::BulkString(b"v".to_vec()));
+
+    let res = gw.setex(b"ex", b"0", b"v").await;
+    assert_eq!(res, Frame::Error("ERR invalid expire time in 'setex' command".into()));
+    let res = gw.psetex(b"px", b"-5", b"v").await;
+    assert_eq!(res, Frame::Error("ERR invalid expire time in 'psetex' command".into()));
+    let res = gw.setex(b"ex", b"ten", b"v").await;
+    assert_eq!(res, Frame::Error("ERR value is not an integer or out of range".into()));
+}
+
+#[tokio::test]
+async fn test_incr_by_float() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    assert_eq!(gw.incr_by_float(b"f", b"1.5").await, Frame::BulkString(b"1.5".to_vec()));
+
+    let _ = gw.set(b"f", b"10.50", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"f", b"0.1").await, Frame::BulkString(b"10.6".to_vec()));
+    assert_eq!(gw.incr_by_float(b"f", b"-5").await, Frame::BulkString(b"5.6".to_vec()));
+
+    let _ = gw.set(b"f", b"5.0e3", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"f", b"2.0e2").await, Frame::BulkString(b"5200".to_vec()));
+    assert_eq!(gw.get(b"f").await, Frame::BulkString(b"5200".to_vec()));
+
+    // counters are read back as floats
+    let _ = gw.set(b"c", b"3", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"c", b"0.25").await, Frame::BulkString(b"3.25".to_vec()));
+
+    let not_a_float = Frame::Error("ERR value is not a valid float".into());
+    assert_eq!(gw.incr_by_float(b"f", b"abc").await, not_a_float);
+    assert_eq!(gw.incr_by_float(b"f", b"inf").await, not_a_float);
+    let _ = gw.set(b"s", b"text", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"s", b"1").await, not_a_float);
+
+    // the rounding error of the addition does not show
+    let _ = gw.set(b"f", b"0.2", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"f", b"0.1").await, Frame::BulkString(b"0.3".to_vec()));
+    let _ = gw.set(b"f", b"0.7", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"f", b"0.1").await, Frame::BulkString(b"0.8".to_vec()));
+
+    // large values are written out in full, without an exponent
+    let _ = gw.set(b"f", b"1e17", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"f", b"0").await, Frame::BulkString(b"100000000000000000".to_vec()));
+    assert_eq!(
+        gw.incr_by_float(b"f", b"1.5e20").await,
+        Frame::BulkString(b"150100000000000000000".to_vec())
+    );
+    let _ = gw.set(b"f", b"-1e-3", SetFlags::default()).await;
+    assert_eq!(gw.incr_by_float(b"f", b"1e-5").await, Frame::BulkString(b"-0.00099".to_vec()));
+
+    let _ = gw.set(b"f", b"1.7e308", SetFlags::default()).await;
+    let res = gw.incr_by_float(b"f", b"1.7e308").await;
+    assert_eq!(res, Frame::Error("ERR increment would produce NaN or Infinity".into()));
+}
+
+#[tokio::test]
+async fn test_lcs() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db);
+
+    let _ = gw.set(b"key1", b"ohmytext", SetFlags::default()).await;
+    let _ = gw.set(b"key2", b"mynewtext", SetFlags::default()).await;
+
+    let res = gw.lcs(b"key1", b"key2", LcsFlags::default()).await;
+    assert_eq!(res, Frame::BulkString(b"mytext".to_vec()));
+    let flags = LcsFlags { len: true, ..LcsFlags::default() };
+    assert_eq!(gw.lcs(b"key1", b"key2", flags).await, Frame::Integer(6));
+
+    let range = |start, end| Frame::Array(vec![Frame::Integer(start), Frame::Integer(end)]);
+    let flags = LcsFlags { idx: true, ..LcsFlags::default() };
+    let res = gw.lcs(b"key1", b"key2", flags).await;
+    assert_eq!(
+        res,
+        Frame::Array(vec![
+            Frame::BulkString(b"matches".to_vec()),
+            Frame::Array(vec![
+                Frame::Array(vec![range(4, 7), range(5, 8)]),
+                Frame::Array(vec![range(2, 3), range(0, 1)]),
+            ]),
+            Frame::BulkString(b"len".to_vec()),
+            Frame::Integer(6),
+        ])
+    );
+
+    let flags = LcsFlags { idx: true, min_match_len: 4, with_match_len: true, ..LcsFlags::default() };
+    let res = gw.lcs(b"key1", b"key2", flags).await;
+    assert_eq!(
+        res,
+        Frame::Array(vec![
+            Frame::BulkString(b"matches".to_vec()),
+            Frame::Array(vec![Frame::Array(vec![range(4, 7), range(5, 8), Frame::Integer(4)])]),
+            Frame::BulkString(b"len".to_vec()),
+            Frame::Integer(6),
+        ])
+    );
+
+    // missing keys are empty strings
+    let res = gw.lcs(b"key1", b"missing", LcsFlags::default()).await;
+    assert_eq!(res, Frame::BulkString(Vec::new()));
+}
@@ -2,13 +2,11 @@ use crate::gateway::RedisGateway;
 use crate::string::operations::{StringOperations, SetFlags, SetTTL};
 use std::time::Duration;
 use fdb::FoundationDB;
-use fdb_testcontainer::get_db_once;
 use redis_protocol::resp2::types::OwnedFrame as Frame;
 
 #[tokio::test]
 async fn test_set_with_ttl() {
-    let _guard = get_db_once().await;
-    let db = FoundationDB::new(_guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db);
 
     let flags = SetFlags {
@@ -1,4 +1,3 @@
-use fdb_testcontainer::get_db_once;
 use fdb::FoundationDB;
 use crate::gateway::RedisGateway;
 use crate::server::Server;
@@ -14,12 +13,10 @@ macro_rules! with_e2e_server {
 #[cfg(test)]
 pub(crate) use with_e2e_server;
 
-/// Start a test FDB instance and spawn a RedisGateway Server bound to an ephemeral port.
-/// Returns (server_handle, stream). The testcontainer guard is captured by the
-/// spawned server task to keep the DB alive while the server runs.
+/// Spawn a RedisGateway Server over an in-memory store, bound to an ephemeral port.
+/// Returns (server_handle, stream).
 pub async fn spawn_test_server() -> (tokio::task::JoinHandle<()>, Option<tokio::net::TcpStream>) {
-    let guard = get_db_once().await;
-    let db = FoundationDB::new(guard.clone());
+    let db = FoundationDB::in_memory();
     let gw = RedisGateway::new(db);
 
     // Reserve an ephemeral port
@@ -28,10 +25,9 @@ pub async fn spawn_test_server() -> (tokio::task::JoinHandle<()>, Option<tokio::
     drop(listener);
     let bind_addr = format!("127.0.0.1:{}", addr.port());
 
-    // Start server in background; move `guard` into the task so it remains alive.
+    // Start server in background
     let server = Server::new(bind_addr.clone(), gw);
     let srv = tokio::spawn(async move {
-        let _guard = guard; // keep guard alive while server runs
         let _ = server.start().await;
     });
 
@@ -0,0 +1,33 @@
+use crate::error::Error;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+#[test]
+fn test_error_prefixes() {
+    assert_eq!(Error::WrongType.prefix(), "WRONGTYPE");
+    assert_eq!(Error::NotAHyperLogLog.prefix(), "WRONGTYPE");
+    assert_eq!(Error::LockTimeout.prefix(), "BUSY");
+    assert_eq!(Error::NotAnInteger.prefix(), "ERR");
+    assert_eq!(Error::Syntax.prefix(), "ERR");
+}
+
+#[test]
+fn test_error_frames() {
+    assert_eq!(
+        Frame::from(Error::NotAnInteger),
+        Frame::Error("ERR value is not an integer or out of range".into())
+    );
+    assert_eq!(
+        Frame::from(Error::WrongType),
+        Frame::Error("WRONGTYPE Operation against a key holding the wrong kind of value".into())
+    );
+    assert_eq!(
+        Frame::from(Error::NotAHyperLogLog),
+        Frame::Error("WRONGTYPE Key is not a valid HyperLogLog string value.".into())
+    );
+
+    let too_large = Error::from(fdb::Error::ValueTooLarge { size: 2, max: 1 });
+    assert_eq!(
+        Frame::from(too_large),
+        Frame::Error("ERR string exceeds maximum allowed size (proto-max-bulk-len)".into())
+    );
+}
@@ -0,0 +1,215 @@
+use crate::gateway::RedisGateway;
+use crate::gc::{GcOptions, collect};
+use crate::migration::{FORMAT_VERSION, write_format_version};
+use crate::string::operations::{SetFlags, StringOperations};
+use fdb::FoundationDB;
+use fdb::storage::fault::{FaultConfig, FaultInjector};
+use futures::future::join_all;
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+use std::sync::Arc;
+use std::time::Duration;
+
+const SEEDS: u64 = 8;
+
+/// Faults frequent enough that every command meets some, retried without limit.
+fn flaky(seed: u64) -> FaultConfig {
+    FaultConfig {
+        seed,
+        not_committed: 0.15,
+        transaction_too_old: 0.1,
+        commit_unknown_result: 0.15,
+        max_latency: Duration::from_millis(1),
+        crash_at_step: None,
+    }
+}
+
+/// A gateway over `db` whose transactions go through `injector`.
+fn faulty_gateway(db: &FoundationDB, injector: &Arc<FaultInjector>) -> RedisGateway {
+    RedisGateway::new(db.clone().with_retry_limit(None).with_faults(injector.clone()))
+}
+
+/// Value too large for one transaction, so `set` stages it in several.
+fn large_value(byte: u8) -> Vec<u8> {
+    vec![byte; 10 * 1000 * 1000]
+}
+
+#[tokio::test]
+async fn test_commands_under_faults() {
+    let long_key = vec![b'k'; 20_000];
+    let mut injected = Vec::new();
+    for seed in 0..SEEDS {
+        let db = FoundationDB::in_memory();
+        let injector = Arc::new(FaultInjector::new(flaky(seed)));
+        let gw = faulty_gateway(&db, &injector);
+
+        let _ = gw.set(b"key", b"value", SetFlags::default()).await;
+        assert_eq!(gw.get(b"key").await, Frame::BulkString(b"value".to_vec()));
+        assert_eq!(gw.append(b"key", b"-tail").await, Frame::Integer(10));
+        assert_eq!(gw.get(b"key").await, Frame::BulkString(b"value-tail".to_vec()));
+
+        // Every increment counts exactly once, even when its commit outcome was unknown
+        for i in 1..=20 {
+            assert_eq!(gw.incr(b"counter").await, Frame::Integer(i));
+        }
+        assert_eq!(gw.incr_by(b"counter", b"5").await, Frame::Integer(25));
+        assert_eq!(gw.decr_by(b"counter", b"30").await, Frame::Integer(-5));
+
+        let _ = gw.set(&long_key, b"long", SetFlags::default()).await;
+        assert_eq!(gw.get(&long_key).await, Frame::BulkString(b"long".to_vec()));
+
+        assert_eq!(gw.getdel(b"key").await, Frame::BulkString(b"value-tail".to_vec()));
+        assert_eq!(gw.get(b"key").await, Frame::Null);
+        assert_eq!(gw.del(&[long_key.as_slice()]).await, Frame::Integer(1));
+        assert_eq!(gw.del(&[long_key.as_slice()]).await, Frame::Integer(0));
+
+        // The records are the same when read without faults
+        let plain = RedisGateway::new(db.clone());
+        assert_eq!(plain.get(b"key").await, Frame::Null);
+        assert_eq!(plain.get(&long_key).await, Frame::Null);
+        assert_eq!(plain.get(b"counter").await, Frame::BulkString(b"-5".to_vec()));
+
+        injected.push(injector.counts());
+    }
+    assert!(injected.iter().any(|c| c.not_committed > 0));
+    assert!(injected.iter().any(|c| c.transaction_too_old > 0));
+    assert!(injected.iter().any(|c| c.applied_unknown_result > 0));
+    assert!(
+        injected
+            .iter()
+            .any(|c| c.commit_unknown_result > c.applied_unknown_result)
+    );
+}
+
+#[tokio::test]
+async fn test_concurrent_writers_under_faults() {
+    for seed in 0..SEEDS {
+        let db = FoundationDB::in_memory();
+        let injector = Arc::new(FaultInjector::new(flaky(seed)));
+        let gw = faulty_gateway(&db, &injector);
+
+        // Writers of the same key queue on its lock; faults while it is
+        // taken or released must not leave it held
+        let values = (0..4u8).map(|i| vec![b'a' + i; 64]).collect::<Vec<_>>();
+        let replies = join_all(values.iter().map(|v| gw.set(b"shared", v, SetFlags::default()))).await;
+        for reply in replies {
+            assert_eq!(reply, Frame::SimpleString(b"OK".to_vec()));
+        }
+        let Frame::BulkString(stored) = gw.get(b"shared").await else {
+            panic!("seed {}: value lost", seed);
+        };
+        assert!(values.contains(&stored), "seed {}", seed);
+
+        let increments = join_all((0..16).map(|_| gw.incr(b"hits"))).await;
+        assert!(increments.iter().all(|r| matches!(r, Frame::Integer(_))));
+        assert_eq!(gw.get(b"hits").await, Frame::BulkString(b"16".to_vec()));
+    }
+}
+
+#[tokio::test]
+async fn test_large_value_under_faults() {
+    let db = FoundationDB::in_memory();
+    let injector = Arc::new(FaultInjector::new(flaky(42)));
+    let gw = faulty_gateway(&db, &injector);
+
+    let value = large_value(b'x');
+    assert_eq!(
+        gw.set(b"large", &value, SetFlags::default()).await,
+        Frame::SimpleString(b"OK".to_vec())
+    );
+    assert_eq!(gw.get(b"large").await, Frame::BulkString(value));
+    assert_eq!(
+        gw.append(b"large", b"!").await,
+        Frame::Integer(10 * 1000 * 1000 + 1)
+    );
+}
+
+#[tokio::test]
+async fn test_replaced_generations_collected_under_faults() {
+    let no_grace = GcOptions {
+        grace_period_ms: 0,
+        ..GcOptions::default()
+    };
+    for seed in 0..4 {
+        let db = FoundationDB::in_memory();
+        write_format_version(&db, FORMAT_VERSION)
+            .await
+            .expect("Unable to write format version");
+        let injector = Arc::new(FaultInjector::new(FaultConfig {
+            seed,
+            commit_unknown_result: 0.5,
+            ..FaultConfig::default()
+        }));
+        let gw = faulty_gateway(&db, &injector);
+        for byte in [b'a', b'b', b'c'] {
+            assert_eq!(
+                gw.set(b"large", &large_value(byte), SetFlags::default()).await,
+                Frame::SimpleString(b"OK".to_vec())
+            );
+        }
+
+        // Each set cleared the generation it replaced, even when it found its
+        // manifest written after an unknown commit outcome
+        let report = collect(&db, &no_grace).await.expect("collection failed");
+        assert_eq!(report.orphaned_chunks, 0, "seed {}", seed);
+        assert_eq!(
+            RedisGateway::new(db.clone()).get(b"large").await,
+            Frame::BulkString(large_value(b'c'))
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_crash_during_large_set() {
+    let old = b"old".to_vec();
+    let new = large_value(b'n');
+
+    // Count the steps of a set that runs to completion
+    let injector = Arc::new(FaultInjector::new(FaultConfig::default()));
+    let db = FoundationDB::in_memory();
+    let _ = faulty_gateway(&db, &injector)
+        .set(b"key", &new, SetFlags::default())
+        .await;
+    let steps = injector.steps();
+    assert!(steps > 4);
+
+    let no_grace = GcOptions {
+        grace_period_ms: 0,
+        stale_lock_ms: 0,
+        ..GcOptions::default()
+    };
+    for crash_at_step in (1..=steps).step_by((steps as usize / 16).max(1)) {
+        let db = FoundationDB::in_memory();
+        write_format_version(&db, FORMAT_VERSION)
+            .await
+            .expect("Unable to write format version");
+        let plain = RedisGateway::new(db.clone());
+        let _ = plain.set(b"key", &old, SetFlags::default()).await;
+
+        let injector = Arc::new(FaultInjector::new(FaultConfig {
+            crash_at_step: Some(crash_at_step),
+            ..FaultConfig::default()
+        }));
+        let reply = faulty_gateway(&db, &injector)
+            .set(b"key", &new, SetFlags::default())
+            .await;
+        assert!(injector.crashed(), "step {}: {:?}", crash_at_step, reply);
+        assert!(matches!(reply, Frame::Error(_)), "step {}", crash_at_step);
+
+        // Readers see the old value or the new one, never a mix of both
+        let Frame::BulkString(seen) = plain.get(b"key").await else {
+            panic!("step {}: value lost", crash_at_step);
+        };
+        assert!(seen == old || seen == new, "step {}", crash_at_step);
+
+        // Recovery clears the staged chunks and the lock the writer left behind
+        collect(&db, &no_grace).await.expect("collection failed");
+        let report = collect(&db, &no_grace).await.expect("collection failed");
+        assert_eq!(report.orphaned_chunks, 0, "step {}", crash_at_step);
+        assert_eq!(report.stale_locks, 0, "step {}", crash_at_step);
+        assert_eq!(plain.get(b"key").await, Frame::BulkString(seen));
+        assert_eq!(
+            plain.set(b"key", b"after", SetFlags::default()).await,
+            Frame::SimpleString(b"OK".to_vec())
+        );
+    }
+}
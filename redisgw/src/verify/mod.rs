@@ -0,0 +1,132 @@
+//! Scrub of stored values.
+//!
+//! Walks every value of the keyspace and reads it back through its manifest,
+//! checking each chunk and the whole value against their checksums. Values
+//! are visited one key selector at a time so their chunks are never scanned,
+//! and each value is streamed so the scrub never holds one in memory.
+use crate::error::Result;
+use crate::keys::{self, StoredKey};
+use crate::migration::prefix_range;
+use crate::string::datamodel::SimpleDataPrefix;
+use fdb::FoundationDB;
+use foundationdb::KeySelector;
+use foundationdb_tuple::unpack;
+use std::fmt;
+
+/// Default number of values located per transaction.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// A value that failed verification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptValue {
+    pub key: Vec<u8>,
+    pub reason: String,
+}
+
+/// Outcome of a scrub.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Number of values read back.
+    pub checked: usize,
+    /// Values that are incomplete or failed their checksums.
+    pub corrupt: Vec<CorruptValue>,
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} values checked, {} corrupt",
+            self.checked,
+            self.corrupt.len()
+        )
+    }
+}
+
+/// Read back every stored value, reporting those that fail verification.
+pub async fn verify(fdb: &FoundationDB, batch_size: usize) -> Result<VerifyReport> {
+    let batch_size = batch_size.max(1);
+    let (mut cursor, end) = prefix_range(SimpleDataPrefix::Data);
+    let mut report = VerifyReport::default();
+
+    loop {
+        let (keys, next) = locate_values(fdb, cursor, end.clone(), batch_size).await?;
+        for stored in keys {
+            match fdb.verify(&stored.pack(SimpleDataPrefix::Data)).await {
+                Ok(Some(_)) => report.checked += 1,
+                // Deleted or rewritten since it was located
+                Ok(None) | Err(fdb::Error::ValueChanged) => {}
+                Err(fdb::Error::CorruptValue(reason)) => {
+                    // A long key whose full form is missing goes by its storage key
+                    let key = match keys::user_key(fdb, &stored).await? {
+                        Some(key) => key,
+                        None => format!("{:?}", stored).into_bytes(),
+                    };
+                    log::error!("value {:?} is corrupt: {}", String::from_utf8_lossy(&key), reason);
+                    report.checked += 1;
+                    report.corrupt.push(CorruptValue { key, reason });
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+        match next {
+            Some(next) => cursor = next,
+            None => break,
+        }
+    }
+
+    Ok(report)
+}
+
+/// Locate up to `limit` values from `cursor`, returning their storage keys and
+/// where to resume. Chunks without a manifest are skipped: they are either
+/// being staged or left for garbage collection.
+pub(crate) async fn locate_values(
+    fdb: &FoundationDB,
+    cursor: Vec<u8>,
+    end: Vec<u8>,
+    limit: usize,
+) -> Result<(Vec<StoredKey>, Option<Vec<u8>>)> {
+    let located = fdb
+        .run(|trx, _| {
+            let mut cursor = cursor.clone();
+            let end = end.clone();
+            async move {
+                let mut keys = Vec::new();
+                for _ in 0..limit {
+                    let selector = KeySelector::first_greater_or_equal(cursor.clone());
+                    let key = trx.get_key(&selector, true).await?;
+                    if key >= end {
+                        return Ok((keys, None));
+                    }
+
+                    let stored = if let Ok((_, stored)) = unpack::<(u64, StoredKey)>(&key) {
+                        keys.push(stored.clone());
+                        stored
+                    } else if let Ok((_, stored, _)) = unpack::<(u64, StoredKey, i64)>(&key) {
+                        stored
+                    } else if let Ok((_, stored, _, _, _)) =
+                        unpack::<(u64, StoredKey, String, u64, i64)>(&key)
+                    {
+                        stored
+                    } else {
+                        let mut next = key;
+                        next.push(0x00);
+                        cursor = next;
+                        continue;
+                    };
+
+                    // Skip past the chunks of this value
+                    cursor = stored.pack(SimpleDataPrefix::Data);
+                    cursor.push(0xFF);
+                }
+                Ok((keys, Some(cursor)))
+            }
+        })
+        .await
+        .map_err(fdb::Error::from)?;
+    Ok(located)
+}
+
+#[cfg(test)]
+pub mod tests;
@@ -0,0 +1,58 @@
+use crate::gateway::RedisGateway;
+use crate::string::datamodel::SimpleDataPrefix;
+use crate::string::operations::{SetFlags, StringOperations};
+use crate::verify::verify;
+use fdb::FoundationDB;
+use foundationdb_tuple::{Subspace, pack};
+use redis_protocol::resp2::types::OwnedFrame as Frame;
+
+#[tokio::test]
+async fn test_verify_reports_corrupt_values() {
+    let db = FoundationDB::in_memory();
+    let gw = RedisGateway::new(db.clone());
+
+    let healthy = b"verify_healthy".to_vec();
+    let damaged = b"verify_damaged".to_vec();
+    let value = vec![7u8; 250_000];
+    let _ = gw.set(&healthy, &value, SetFlags::default()).await;
+    let _ = gw.set(&damaged, &value, SetFlags::default()).await;
+
+    // Flip the second chunk of one value behind its manifest's back
+    let root = pack(&(SimpleDataPrefix::Data, damaged.as_slice()));
+    let manifest = db
+        .manifest(&root)
+        .await
+        .expect("Unable to read manifest")
+        .expect("missing manifest");
+    let chunk_key = Subspace::from_bytes(manifest.chunk_root(&root)).pack(&(1,));
+    db.run(|trx, _| {
+        let chunk_key = chunk_key.clone();
+        async move {
+            trx.set(&chunk_key, &[8u8; 100_000]);
+            Ok(())
+        }
+    })
+    .await
+    .expect("Unable to overwrite chunk");
+
+    let before = fdb::metrics::corrupt_values();
+    let res = gw.get(&damaged).await;
+    assert_eq!(
+        res,
+        Frame::Error("ERR value is incomplete or corrupted".into())
+    );
+    assert!(fdb::metrics::corrupt_values() > before);
+
+    let report = verify(&db, 10).await.expect("verify failed");
+    assert!(report.checked >= 2);
+    let corrupt = report
+        .corrupt
+        .iter()
+        .map(|c| c.key.clone())
+        .collect::<Vec<_>>();
+    assert!(corrupt.contains(&damaged));
+    assert!(!corrupt.contains(&healthy));
+
+    let _ = gw.del(&[healthy.as_slice()]).await;
+    let _ = db.delete(&root).await;
+}